@@ -1,29 +1,33 @@
-use alloc::{sync::Arc, vec};
+use alloc::{string::String, sync::Arc, vec};
 use core::cmp::min;
 
+use config::PATH_MAX;
 use constants::{
-    io::{
-        FileStat, FsStat, InodeMode, IoVec, MountFlags, OpenFlags, Renameat2Flags, SeekFrom,
-        StatFlags,
-    },
+    io::{FileStat, InodeMode, IoVec, MountFlags, OpenFlags, Renameat2Flags, SeekFrom, StatFlags},
     AlienResult, LinuxErrno, AT_FDCWD,
 };
 use gmanager::ManagerError;
 use log::{info, warn};
 use syscall_table::syscall_func;
-use vfs::{kfile::KernelFile, system_root_fs};
+use vfs::kfile::KernelFile;
 use vfscore::{
-    path::VfsPath,
+    error::VfsError,
     utils::{VfsFileStat, VfsFsStat, VfsNodeType, VfsRenameFlag},
 };
 
 use super::im2vim;
 use crate::{
-    fs::{syscontext_for_vfs, user_path_at},
-    task::current_task,
+    fs::{
+        check_fsize_limit, inode_permission, is_subpath, parent_path, path_in_root,
+        syscontext_for_vfs, update_inode_attr, user_path_at, MAY_EXEC, MAY_READ, MAY_WRITE,
+    },
+    mm::uaccess::{UserPtr, UserSlice},
+    task::{all_tasks, current_task, Capabilities},
 };
 
 /// 用于将一个设备(通常是存储设备)挂载到一个已经存在的目录上，可以挂载文件系统。
+///
+/// 挂载记录在当前进程所在的 mount 命名空间的挂载表中，只对该命名空间中的进程可见。
 #[syscall_func(40)]
 pub fn sys_mount(
     source: *const u8,
@@ -33,9 +37,14 @@ pub fn sys_mount(
     data: *const u8,
 ) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let source = task.transfer_str(source);
-    let dir = task.transfer_str(dir);
-    let fs_type = task.transfer_str(fs_type);
+    // 只有需要设备的文件系统才使用 source，其它文件系统允许其为空指针
+    let source = if source.is_null() {
+        String::new()
+    } else {
+        task.strncpy_from_user(source, PATH_MAX)?
+    };
+    let dir = task.strncpy_from_user(dir, PATH_MAX)?;
+    let fs_type = task.strncpy_from_user(fs_type, PATH_MAX)?;
     assert!(data.is_null());
     let flags = MountFlags::from_bits(flags as u32).unwrap();
     info!(
@@ -43,12 +52,11 @@ pub fn sys_mount(
         source, dir, fs_type, flags, data
     );
     let find = vfs::system_support_fs(&fs_type).ok_or(LinuxErrno::EINVAL)?;
-    let path = VfsPath::new(vfs::system_root_fs(), system_root_fs());
     let fs_root = match find.fs_name() {
         name @ ("tmpfs" | "ramfs" | "fat32") => {
             let fs = vfs::system_support_fs(name).unwrap();
             let dev = if name.eq("fat32") {
-                let dev = path.join(source)?.open(None)?;
+                let dev = user_path_at(AT_FDCWD, &source)?.open(None)?;
                 Some(dev.inode()?)
             } else {
                 None
@@ -58,18 +66,27 @@ pub fn sys_mount(
         }
         _ => return Err(LinuxErrno::EINVAL),
     };
-    path.join(dir)?.mount(fs_root, flags.bits())?;
+    let mount_point = user_path_at(AT_FDCWD, &dir)?.open(None)?;
+    if mount_point.inode()?.inode_type() != VfsNodeType::Dir {
+        return Err(LinuxErrno::ENOTDIR);
+    }
+    let mnt = task.access_inner().ns.mnt.clone();
+    mnt.mount(mount_point, fs_root);
     Ok(0)
 }
 
 /// 用于取消一个目录上的文件挂载(卸载一个文件系统)。
+///
+/// 只能卸载在当前进程所在的 mount 命名空间中挂载的文件系统，`dir` 不是这样的挂载点时返回 `EINVAL`，
+/// 其中还挂载有其它文件系统时返回 `EBUSY`。系统启动时挂载的文件系统由所有命名空间共享，不能被卸载。
 #[syscall_func(39)]
 pub fn sys_umount(dir: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let dir = process.transfer_str(dir);
+    let dir = process.strncpy_from_user(dir, PATH_MAX)?;
     info!("umount dir:{:?}", dir);
-    let path = VfsPath::new(vfs::system_root_fs(), system_root_fs());
-    path.join(dir)?.umount()?;
+    let root = user_path_at(AT_FDCWD, &dir)?.open(None)?;
+    let mnt = process.access_inner().ns.mnt.clone();
+    mnt.umount(&root)?;
     Ok(0)
 }
 
@@ -86,14 +103,43 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flag: usize, mode: u32) -> Alie
     }
     .map(|x| im2vim(x));
     let process = current_task().unwrap();
-    let path_str = process.transfer_str(path);
+    let path_str = process.strncpy_from_user(path, PATH_MAX)?;
     let path = user_path_at(dirfd, &path_str)?;
     warn!(
         "open file: dirfd:[{}], {:?},flag:{:?}, mode:{:?}",
         dirfd, path, flag, file_mode
     );
 
-    let dentry = path.open(file_mode)?;
+    let cred = process.access_inner().cred.clone();
+    let dentry = match path.open(None) {
+        Ok(dentry) => {
+            if flag.contains(OpenFlags::O_CREAT) && flag.contains(OpenFlags::O_EXCL) {
+                return Err(LinuxErrno::EEXIST);
+            }
+            let mut mask = if flag.contains(OpenFlags::O_RDWR) {
+                MAY_READ | MAY_WRITE
+            } else if flag.contains(OpenFlags::O_WRONLY) {
+                MAY_WRITE
+            } else {
+                MAY_READ
+            };
+            if flag.contains(OpenFlags::O_TRUNC) {
+                mask |= MAY_WRITE;
+            }
+            inode_permission(&cred, &dentry.inode()?, mask)?;
+            dentry
+        }
+        Err(VfsError::NoEntry) if flag.contains(OpenFlags::O_CREAT) => {
+            // 创建文件需要拥有对父目录的写与搜索权限
+            let parent = user_path_at(dirfd, parent_path(&path_str))?.open(None)?;
+            inode_permission(&cred, &parent.inode()?, MAY_WRITE | MAY_EXEC)?;
+            let dentry = path.open(file_mode)?;
+            // 新创建的文件属于创建者，文件系统不支持时忽略
+            let _ = update_inode_attr(&dentry.inode()?, None, Some(cred.fsuid), Some(cred.fsgid));
+            dentry
+        }
+        Err(e) => return Err(e.into()),
+    };
     let file = KernelFile::new(dentry, flag);
 
     let fd = process.add_file(Arc::new(file));
@@ -140,20 +186,12 @@ pub fn sys_getdents(fd: usize, buf: *mut u8, len: usize) -> AlienResult<isize> {
     info!("[getdents] fd: {}, buf size: {}", fd, len);
     let process = current_task().unwrap();
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let user_bufs = process.transfer_buffer(buf, len);
+    let user_buf = UserSlice::<u8>::new(buf as usize, len);
     let mut buf = vec![0u8; len];
     let len = file.readdir(buf.as_mut_slice())?;
     info!("[getdents]: read len: {:?}", len);
-    let mut offset = 0;
     // copy dirent_buf to user space
-    for user_buf in user_bufs {
-        let copy_len = user_buf.len(); // user_bufs len is equal to buf len
-        user_buf.copy_from_slice(&buf[offset..offset + copy_len]);
-        offset += copy_len;
-        if offset >= len {
-            break;
-        }
-    }
+    process.write_user_slice(user_buf, &buf[..len])?;
     Ok(len as _)
 }
 
@@ -169,7 +207,7 @@ pub fn sys_getdents(fd: usize, buf: *mut u8, len: usize) -> AlienResult<isize> {
 #[syscall_func(45)]
 pub fn sys_truncate(path: usize, len: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let path = process.transfer_str(path as *const u8);
+    let path = process.strncpy_from_user(path as *const u8, PATH_MAX)?;
     let path = user_path_at(AT_FDCWD, &path)?;
     path.truncate(len as u64)?;
     Ok(0)
@@ -203,7 +241,7 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     info!("read file: {:?}, len:{:?}", fd, len);
-    let mut buf = process.transfer_buffer(buf, len);
+    let mut buf = process.writable_buffers(UserSlice::new(buf as usize, len))?;
 
     let mut count = 0;
     for b in buf.iter_mut() {
@@ -227,9 +265,10 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> AlienResult<isize> {
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let mut buf = task.transfer_buffer(buf, len);
+    let len = check_fsize_limit(&file, None, len)?;
+    let buf = task.readable_buffers(UserSlice::new(buf as usize, len))?;
     let mut count = 0;
-    for b in buf.iter_mut() {
+    for b in buf.iter() {
         let w = file.write(b)?;
         count += w;
         if w != b.len() {
@@ -243,28 +282,15 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> AlienResult<isize> {
 /// 一个系统调用，用于获取当前工作目录。
 /// 获取的工作目录将直接保存在 `buf` 所指向的缓冲区中，`len` 用于指明 `buf` 的长度。
 ///
-/// 获取当前目录成功后，返回 `buf` 的首地址。当 `buf` 为空指针时，会导致函数 panic。
+/// 获取当前目录成功后，返回 `buf` 的首地址。当 `buf` 不是有效的用户地址时，返回 `EFAULT`。
 #[syscall_func(17)]
-pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> AlienResult<isize> {
     info!("getcwd: {:?}, len: {:?}", buf, len);
-    assert!(!buf.is_null());
     let task = current_task().unwrap();
     let cwd = task.access_inner().cwd();
-
-    let mut buf = task.transfer_buffer(buf, len);
-    let mut count = 0;
-    let path = cwd.cwd.path();
-    let mut cwd = path.as_bytes();
-    buf.iter_mut().for_each(|buf| {
-        // fill buf
-        if !cwd.is_empty() {
-            let min = min(cwd.len(), buf.len());
-            buf[..min].copy_from_slice(&cwd[..min]);
-            count += min;
-            cwd = &cwd[min..];
-        }
-    });
-    buf[0].as_ptr() as isize
+    let path = path_in_root(&cwd.cwd, &cwd.root);
+    task.write_user_slice(UserSlice::new(buf as usize, len), path.as_bytes())?;
+    Ok(buf as isize)
 }
 
 /// 一个系统调用，用于切换当前工作目录。`path` 指出要切换到的工作目录。
@@ -273,7 +299,7 @@ pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
 #[syscall_func(49)]
 pub fn sys_chdir(path: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let path = process.transfer_str(path);
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
     let dt = user_path_at(AT_FDCWD, &path)?.open(None)?;
 
     if dt.inode()?.inode_type() != VfsNodeType::Dir {
@@ -304,6 +330,84 @@ pub fn sys_fchdir(fd: usize) -> AlienResult<isize> {
     Ok(0)
 }
 
+/// 一个系统调用，将当前进程的根目录修改为 `path`，之后的绝对路径从该目录开始解析。
+///
+/// 不改变当前工作目录。需要拥有 CAP_SYS_CHROOT 能力，否则返回 `EPERM`；`path` 不是目录时返回 `ENOTDIR`，
+/// 没有搜索权限时返回 `EACCES`。
+///
+/// Reference: [chroot](https://man7.org/linux/man-pages/man2/chroot.2.html)
+#[syscall_func(51)]
+pub fn sys_chroot(path: *const u8) -> AlienResult<isize> {
+    let process = current_task().unwrap();
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
+    let dt = user_path_at(AT_FDCWD, &path)?.open(None)?;
+    let inode = dt.inode()?;
+    if inode.inode_type() != VfsNodeType::Dir {
+        return Err(LinuxErrno::ENOTDIR);
+    }
+    let cred = process.access_inner().cred.clone();
+    inode_permission(&cred, &inode, MAY_EXEC)?;
+    if !cred.has_cap(Capabilities::SYS_CHROOT) {
+        return Err(LinuxErrno::EPERM);
+    }
+    info!("chroot: {:?}", dt.path());
+    process.access_inner().fs_info.root = dt;
+    Ok(0)
+}
+
+/// 一个系统调用，将当前进程所在的 mount 命名空间的根目录修改为 `new_root`。
+///
+/// 只影响该命名空间中的进程：其中根目录或工作目录为当前进程的根目录的进程，其根目录或工作目录都被修改为 `new_root`。
+/// `put_old` 必须是 `new_root` 本身或位于其下的目录，否则返回 `EINVAL`。由于挂载记录在目录项上，
+/// 原根目录不会被移动到 `put_old` 处(这会使目录树中出现环)，之后需要通过原根目录访问的文件要在 pivot_root 之前打开。
+///
+/// 需要拥有 CAP_SYS_ADMIN 能力，否则返回 `EPERM`；`new_root` 或 `put_old` 不是目录时返回 `ENOTDIR`，
+/// `new_root` 就是当前的根目录时返回 `EBUSY`。
+///
+/// Reference: [pivot_root](https://man7.org/linux/man-pages/man2/pivot_root.2.html)
+#[syscall_func(41)]
+pub fn sys_pivot_root(new_root: *const u8, put_old: *const u8) -> AlienResult<isize> {
+    let process = current_task().unwrap();
+    if !process.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(LinuxErrno::EPERM);
+    }
+    let new_root = process.strncpy_from_user(new_root, PATH_MAX)?;
+    let put_old = process.strncpy_from_user(put_old, PATH_MAX)?;
+    let new_root = user_path_at(AT_FDCWD, &new_root)?.open(None)?;
+    let put_old = user_path_at(AT_FDCWD, &put_old)?.open(None)?;
+    if new_root.inode()?.inode_type() != VfsNodeType::Dir
+        || put_old.inode()?.inode_type() != VfsNodeType::Dir
+    {
+        return Err(LinuxErrno::ENOTDIR);
+    }
+    let (old_root, mnt) = {
+        let inner = process.access_inner();
+        (inner.fs_info.root.path(), inner.ns.mnt.clone())
+    };
+    let new_root_path = new_root.path();
+    if new_root_path == old_root {
+        return Err(LinuxErrno::EBUSY);
+    }
+    if !is_subpath(&put_old.path(), &new_root_path) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    info!("pivot_root: {} -> {}", old_root, new_root_path);
+    mnt.set_root(new_root.clone());
+    for task in all_tasks() {
+        let mut inner = task.access_inner();
+        if !Arc::ptr_eq(&inner.ns.mnt, &mnt) {
+            continue;
+        }
+        if inner.fs_info.root.path() == old_root {
+            inner.fs_info.root = new_root.clone();
+        }
+        if inner.fs_info.cwd.path() == old_root {
+            inner.fs_info.cwd = new_root.clone();
+        }
+    }
+    Ok(0)
+}
+
 /// 一个系统调用，用于在 相对于一个目录某位置处 路径下创建一个空的目录。功能与 [`sys_mkdir`] 相似。
 ///
 /// 有关对 `dirfd` 和 `mode` 的解析规则以及 flag 的相关设置可见 [`sys_openat`]。成功创建目录则返回 0；否则返回错误码。
@@ -312,7 +416,7 @@ pub fn sys_fchdir(fd: usize) -> AlienResult<isize> {
 #[syscall_func(34)]
 pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let path = process.transfer_str(path);
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
     let mut mode = InodeMode::from_bits_truncate(mode);
     warn!("mkdirat path: {}, mode: {:?}", path, mode);
     let path = user_path_at(dirfd, &path)?;
@@ -354,18 +458,19 @@ pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> AlienResult<isize>
 pub fn sys_writev(fd: usize, iovec: usize, iovcnt: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let mut iovs = process.read_user_slice(UserSlice::<IoVec>::new(iovec, iovcnt))?;
+    // busybox 可能会给stdout两个io_vec，第二个是空地址
+    iovs.retain(|iov| iov.base as usize != 0);
+    let total: usize = iovs.iter().map(|iov| iov.len).sum();
+    let mut remain = check_fsize_limit(&file, None, total)?;
     let mut count = 0;
-    for i in 0..iovcnt {
-        let mut iov = IoVec::empty();
-        let ptr = unsafe { (iovec as *mut IoVec).add(i) };
-        process.access_inner().copy_from_user(ptr, &mut iov);
-        let base = iov.base;
-        if base as usize == 0 {
-            // busybox 可能会给stdout两个io_vec，第二个是空地址
-            continue;
+    for iov in iovs {
+        if remain == 0 {
+            break;
         }
-        let len = iov.len;
-        let buf = process.transfer_buffer(base, len);
+        let len = min(iov.len, remain);
+        remain -= len;
+        let buf = process.readable_buffers(UserSlice::new(iov.base as usize, len))?;
         for b in buf.iter() {
             let r = file.write(b)?;
             count += r;
@@ -385,16 +490,15 @@ pub fn sys_writev(fd: usize, iovec: usize, iovcnt: usize) -> AlienResult<isize>
 pub fn sys_readv(fd: usize, iovec: usize, iovcnt: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let iovs = task.read_user_slice(UserSlice::<IoVec>::new(iovec, iovcnt))?;
     let mut count = 0;
-    for i in 0..iovcnt {
-        let ptr = unsafe { (iovec as *mut IoVec).add(i) };
-        let iov = task.transfer_raw_ptr(ptr);
+    for iov in iovs {
         let base = iov.base;
         if base as usize == 0 || iov.len == 0 {
             continue;
         }
         let len = iov.len;
-        let mut buf = task.transfer_buffer(base, len);
+        let mut buf = task.writable_buffers(UserSlice::new(base as usize, len))?;
         for b in buf.iter_mut() {
             info!("read file: {:?}, len:{:?}", fd, b.len());
             let r = file.read(b)?;
@@ -414,7 +518,7 @@ pub fn sys_readv(fd: usize, iovec: usize, iovcnt: usize) -> AlienResult<isize> {
 pub fn sys_pread(fd: usize, buf: usize, count: usize, offset: u64) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let mut buf = task.transfer_buffer(buf as *mut u8, count);
+    let mut buf = task.writable_buffers(UserSlice::new(buf, count))?;
     let mut offset = offset;
     let mut count = 0;
     for b in buf.iter_mut() {
@@ -435,7 +539,8 @@ pub fn sys_pread(fd: usize, buf: usize, count: usize, offset: u64) -> AlienResul
 pub fn sys_pwrite(fd: usize, buf: usize, count: usize, offset: u64) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let buf = task.transfer_buffer(buf as *mut u8, count);
+    let count = check_fsize_limit(&file, Some(offset), count)?;
+    let buf = task.readable_buffers(UserSlice::new(buf, count))?;
     let mut offset = offset;
     let mut count = 0;
     for b in buf.iter() {
@@ -453,7 +558,7 @@ pub fn sys_pwrite(fd: usize, buf: usize, count: usize, offset: u64) -> AlienResu
 /// 获取的信息会保存在 `stat` 指向的 [`FileStat`] 结构中，`flag` 是一组标志位，用于定义相关的操作类型，具体可见 [`StatFlags`]。
 ///
 /// 获取相关信息成功后，函数返回 0；否则函数会返回 -1 表示获取信息出错。
-/// 如果输入的 `stat` 为空指针，那么会导致函数 panic。
+/// 如果输入的 `stat` 不是有效的用户地址，函数返回 `EFAULT`。
 ///
 /// Reference: https://man7.org/linux/man-pages/man2/newfstatat.2.html
 #[syscall_func(79)]
@@ -464,7 +569,7 @@ pub fn sys_fstateat(
     flag: usize,
 ) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let path = process.transfer_str(path);
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
     let flag = StatFlags::from_bits_truncate(flag as u32);
     warn!("sys_fstateat: path: {:?}, flag: {:?}", path, flag);
     let path = user_path_at(dir_fd, &path)?;
@@ -477,9 +582,7 @@ pub fn sys_fstateat(
         (&mut file_stat as *mut FileStat as *mut usize as *mut VfsFileStat).write(attr);
     }
     warn!("sys_fstateat: res: {:?}", file_stat);
-    process
-        .access_inner()
-        .copy_to_user(&file_stat, stat as *mut FileStat);
+    process.write_user(UserPtr::<FileStat>::new(stat as usize), &file_stat)?;
     Ok(0)
 }
 
@@ -487,10 +590,9 @@ pub fn sys_fstateat(
 /// `fd` 用于指明要获取信息的文件的文件描述符。
 ///
 /// 获取相关信息成功后，函数返回 0；否则函数会返回 -1 表示获取信息出错。
-/// 如果输入的 `stat` 为空指针，那么会导致函数 panic。
+/// 如果输入的 `stat` 不是有效的用户地址，函数返回 `EFAULT`。
 #[syscall_func(80)]
 pub fn sys_fstat(fd: usize, stat: *mut u8) -> AlienResult<isize> {
-    assert!(!stat.is_null());
     let process = current_task().unwrap();
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     let attr = file.get_attr()?;
@@ -499,32 +601,28 @@ pub fn sys_fstat(fd: usize, stat: *mut u8) -> AlienResult<isize> {
         (&mut file_stat as *mut FileStat as *mut usize as *mut VfsFileStat).write(attr);
     }
     warn!("sys_fstat: {:?}, res: {:?}", fd, file_stat);
-    process
-        .access_inner()
-        .copy_to_user(&file_stat, stat as *mut FileStat);
+    process.write_user(UserPtr::<FileStat>::new(stat as usize), &file_stat)?;
     Ok(0)
 }
 
 /// 一个系统调用，用于获取一个已挂载的文件系统的使用情况。与 [`sys_statfs`] 的功能类似。
-/// 获取到的相关信息将会保存在 `statfs` 所指向的 [`FsStat`] 结构中，`fd` 可以是该已挂载的文件系统下的任意一个文件的文件描述符。
+/// 获取到的相关信息将会保存在 `statfs` 所指向的 [`FsStat`](constants::io::FsStat) 结构中，`fd` 可以是该已挂载的文件系统下的任意一个文件的文件描述符。
 ///
 /// 如果获取成功，函数会返回 0；否则返回 -1 表示获取信息异常。
 /// Reference: https://man7.org/linux/man-pages/man2/fstatfs64.2.html
 #[syscall_func(44)]
 pub fn sys_fstatfs(fd: isize, buf: *mut u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let buf = process.transfer_raw_ptr(buf as *mut FsStat);
     let file = process.get_file(fd as usize).ok_or(LinuxErrno::EBADF)?;
     let fs_stat = file.inode().get_super_block()?.stat_fs()?;
-    unsafe {
-        (&mut *buf as *mut FsStat as *mut usize as *mut VfsFsStat).write(fs_stat);
-    }
+    // FsStat 与 VfsFsStat 的布局相同
+    process.write_user(UserPtr::<VfsFsStat>::new(buf as usize), &fs_stat)?;
     warn!("sys_fstatfs: res: {:#x?}", fs_stat);
     Ok(0)
 }
 
 /// 一个系统调用，用于获取一个已挂载的文件系统的使用情况。
-/// 获取到的相关信息将会保存在 `statfs` 所指向的 [`FsStat`] 结构中，`path` 可以是该已挂载的文件系统下的任意一个文件的路径。
+/// 获取到的相关信息将会保存在 `statfs` 所指向的 [`FsStat`](constants::io::FsStat) 结构中，`path` 可以是该已挂载的文件系统下的任意一个文件的路径。
 ///
 /// 如果获取成功，函数会返回 0；否则返回 -1 表示获取信息异常。
 ///
@@ -532,16 +630,13 @@ pub fn sys_fstatfs(fd: isize, buf: *mut u8) -> AlienResult<isize> {
 #[syscall_func(43)]
 pub fn sys_statfs(path: *const u8, statfs: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let buf = process.transfer_raw_ptr(statfs as *mut FsStat);
-    let path = process.transfer_str(path);
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
 
     let path = user_path_at(AT_FDCWD, &path)?;
     let dt = path.open(None)?;
     let fs_stat = dt.inode()?.get_super_block()?.stat_fs()?;
-
-    unsafe {
-        (&mut *buf as *mut FsStat as *mut usize as *mut VfsFsStat).write(fs_stat);
-    }
+    // FsStat 与 VfsFsStat 的布局相同
+    process.write_user(UserPtr::<VfsFsStat>::new(statfs as usize), &fs_stat)?;
 
     warn!("sys_statfs: [{:?}] res: {:#x?}", path, fs_stat);
     Ok(0)
@@ -556,8 +651,8 @@ pub fn sys_renameat(
     new_path: *const u8,
 ) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let old_path = process.transfer_str(old_path);
-    let new_path = process.transfer_str(new_path);
+    let old_path = process.strncpy_from_user(old_path, PATH_MAX)?;
+    let new_path = process.strncpy_from_user(new_path, PATH_MAX)?;
 
     info!(
         "renameat2: {:?} {:?} {:?} {:?}",
@@ -587,8 +682,8 @@ pub fn sys_renameat2(
     flag: u32,
 ) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let old_path = process.transfer_str(old_path);
-    let new_path = process.transfer_str(new_path);
+    let old_path = process.strncpy_from_user(old_path, PATH_MAX)?;
+    let new_path = process.strncpy_from_user(new_path, PATH_MAX)?;
     let flag = Renameat2Flags::from_bits_truncate(flag);
     info!(
         "renameat2: {:?} {:?} {:?} {:?}, flag: {:?}",
@@ -645,13 +740,15 @@ pub fn send_file(
         0 => in_file.read(&mut buf)?,
         _ => {
             // offset 非零则要求不更新实际文件，更新这个用户给的值
-            let offset_ptr = task.transfer_raw_ptr(offset_ptr as *mut u64);
-            let nbytes = in_file.read_at(*offset_ptr, &mut buf)?;
-            *offset_ptr += nbytes as u64;
+            let offset_ptr = UserPtr::<u64>::new(offset_ptr);
+            let offset = task.read_user(offset_ptr)?;
+            let nbytes = in_file.read_at(offset, &mut buf)?;
+            task.write_user(offset_ptr, &(offset + nbytes as u64))?;
             nbytes
         }
     };
     info!("sys_sendfile: read {} bytes from in_file", nbytes);
+    let nbytes = check_fsize_limit(&out_file, None, nbytes)?;
     let w = out_file.write(&buf[0..nbytes as usize])?;
     Ok(w as _)
 }
@@ -696,18 +793,22 @@ pub fn copy_file_range(
         in_file.read(&mut buf)?
     } else {
         // offset 非零则要求不更新实际文件，更新这个用户给的值
-        let off_in_ptr = task.transfer_raw_ptr(off_in_ptr as *mut u64);
-        let nr = in_file.read_at(*off_in_ptr, &mut buf)?;
-        *off_in_ptr += nr as u64;
+        let off_in_ptr = UserPtr::<u64>::new(off_in_ptr);
+        let off_in = task.read_user(off_in_ptr)?;
+        let nr = in_file.read_at(off_in, &mut buf)?;
+        task.write_user(off_in_ptr, &(off_in + nr as u64))?;
         nr
     };
     info!("sys_copy_file_range: read {} bytes from in_file", r);
     let w = if off_out_ptr == 0 {
+        let r = check_fsize_limit(&out_file, None, r)?;
         out_file.write(&buf[..r])?
     } else {
-        let off_out_ptr = task.transfer_raw_ptr(off_out_ptr as *mut u64);
-        let wr = out_file.write_at(*off_out_ptr, &buf[..r])?;
-        *off_out_ptr += wr as u64;
+        let off_out_ptr = UserPtr::<u64>::new(off_out_ptr);
+        let off_out = task.read_user(off_out_ptr)?;
+        let r = check_fsize_limit(&out_file, Some(off_out), r)?;
+        let wr = out_file.write_at(off_out, &buf[..r])?;
+        task.write_user(off_out_ptr, &(off_out + wr as u64))?;
         wr
     };
     info!("sys_copy_file_range: write {} bytes to out_file", w);
@@ -1,3 +1,6 @@
+use alloc::sync::Arc;
+
+use config::PATH_MAX;
 use constants::{
     io::{FaccessatFlags, FaccessatMode, Fcntl64Cmd, OpenFlags, TeletypeCommand},
     AlienResult, LinuxErrno, AT_FDCWD,
@@ -5,11 +8,23 @@ use constants::{
 use log::{info, warn};
 use syscall_table::syscall_func;
 use timer::TimeSpec;
-use vfscore::utils::*;
+use vfscore::{inode::VfsInode, utils::*};
 
-use crate::{fs::user_path_at, task::current_task};
+use crate::{
+    fs::{inode_owner_and_perm, inode_permission, update_inode_attr, user_path_at},
+    mm::uaccess::UserSlice,
+    task::{current_task, optional_id, Capabilities, S_ISGID, S_ISUID, S_IXGRP},
+};
 
 const FD_CLOEXEC: usize = 1;
+/// faccessat 使用有效 id 而不是真实 id 进行检查
+const AT_EACCESS: usize = 0x200;
+/// 不解析路径最后的软链接
+const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+/// 路径为空时直接操作 dirfd 所指向的文件
+const AT_EMPTY_PATH: usize = 0x1000;
+/// 文件类型位的掩码
+const S_IFMT: u32 = 0o170000;
 
 /// 一个系统调用，用于对一个文件提供控制。
 ///
@@ -127,13 +142,12 @@ pub fn utimensat(
         let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
         file.dentry()
     } else {
-        let path = task.transfer_str(path);
+        let path = task.strncpy_from_user(path, PATH_MAX)?;
         let path = user_path_at(fd as isize, &path)?;
         let dt = path.open(None)?;
         dt
     };
 
-    let mut inner = task.access_inner();
     if times.is_null() {
         warn!(
             "utimensat: {:?} {:?} {:?} {:?}",
@@ -151,12 +165,8 @@ pub fn utimensat(
             TimeSpec::now().into(),
         )?;
     } else {
-        let mut atime = TimeSpec::new(0, 0);
-        let mut mtime = TimeSpec::new(0, 0);
-        inner.copy_from_user(times as *const TimeSpec, &mut atime);
-        unsafe {
-            inner.copy_from_user((times as *const TimeSpec).add(1), &mut mtime);
-        }
+        let times = task.read_user_slice(UserSlice::<TimeSpec>::new(times as usize, 2))?;
+        let (atime, mtime) = (times[0], times[1]);
         warn!(
             "utimensat: {:?} {:?} {:?} {:?}",
             fd as isize, path, atime, mtime
@@ -191,39 +201,106 @@ pub fn utimensat(
 /// 一个系统调用，用于检测当前进程是否有权限访问一个文件。
 ///
 /// 文件的路径由 `dirfd` 和 `path` 解析得到。解析相关信息可见 [`user_path_at`]。
-/// 目前 `mode` 和 `flag` 都未使用。仅检测当前进程是否有打开对应路径下文件的权限。
+/// `mode` 为 F_OK 时只检查文件是否存在，否则检查是否拥有 R_OK/W_OK/X_OK 所指明的权限。
+/// 默认使用进程的真实用户 id 与组 id 进行检查，`flag` 包含 AT_EACCESS 时使用有效 id。
 ///
-/// 如果有打开的权限，则返回 0；否则返回 -1。
+/// 如果有对应的权限，则返回 0；否则返回 EACCES。
+///
+/// Reference: [faccessat](https://man7.org/linux/man-pages/man2/faccessat.2.html)
 #[syscall_func(48)]
 pub fn faccessat(dirfd: isize, path: usize, mode: usize, flag: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let path = task.transfer_str(path as *const u8);
-    let mode = FaccessatMode::from_bits_truncate(mode as u32);
-    let flag = FaccessatFlags::from_bits_truncate(flag as u32);
+    let path = task.strncpy_from_user(path as *const u8, PATH_MAX)?;
+    let access_mode = FaccessatMode::from_bits_truncate(mode as u32);
+    let access_flag = FaccessatFlags::from_bits_truncate(flag as u32);
     info!(
         "faccessat file: {:?},flag:{:?}, mode:{:?}",
-        path, flag, mode
+        path, access_flag, access_mode
     );
     // todo! check the AT_SYMLINK_NOFOLLOW flag
-    let _path = user_path_at(dirfd, &path)?.open(None)?;
+    let dentry = user_path_at(dirfd, &path)?.open(None)?;
+    let mask = mode as u32 & 0o7;
+    if mask == 0 {
+        return Ok(0);
+    }
+    let cred = task.access_inner().cred.clone();
+    let cred = if flag & AT_EACCESS != 0 {
+        cred
+    } else {
+        cred.for_access()
+    };
+    inode_permission(&cred, &dentry.inode()?, mask)?;
+    Ok(0)
+}
+
+/// 修改 `inode` 的权限位。只有文件的所有者或拥有 CAP_FOWNER 能力的进程才能修改，否则返回 EPERM。
+fn do_chmod(inode: Arc<dyn VfsInode>, mode: usize) -> AlienResult<isize> {
+    let cred = current_task().unwrap().access_inner().cred.clone();
+    let attr = inode.get_attr()?;
+    let (uid, gid, _) = inode_owner_and_perm(&inode);
+    if cred.fsuid != uid && !cred.has_cap(Capabilities::FOWNER) {
+        return Err(LinuxErrno::EPERM);
+    }
+    let mut mode = mode as u32 & 0o7777;
+    // 不属于文件所属组的进程不能设置 setgid 位
+    if !cred.in_group(gid) && !cred.has_cap(Capabilities::FSETID) {
+        mode &= !S_ISGID;
+    }
+    update_inode_attr(&inode, Some((attr.st_mode & S_IFMT) | mode), None, None)?;
+    Ok(0)
+}
+
+/// 修改 `inode` 的所有者与所属组，`None` 表示保持不变。
+///
+/// 修改所有者需要 CAP_CHOWN 能力；文件的所有者可以将所属组修改为自己所在的组。
+/// 修改普通文件的所有者或所属组后，其 setuid/setgid 位将被清除。
+fn do_chown(inode: Arc<dyn VfsInode>, uid: Option<u32>, gid: Option<u32>) -> AlienResult<isize> {
+    let cred = current_task().unwrap().access_inner().cred.clone();
+    let attr = inode.get_attr()?;
+    let (owner, group, _) = inode_owner_and_perm(&inode);
+    let uid = uid.filter(|uid| *uid != owner);
+    let gid = gid.filter(|gid| *gid != group);
+    if !cred.has_cap(Capabilities::CHOWN) {
+        if uid.is_some() {
+            return Err(LinuxErrno::EPERM);
+        }
+        if gid.is_some_and(|gid| cred.fsuid != owner || !cred.in_group(gid)) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    if uid.is_none() && gid.is_none() {
+        return Ok(0);
+    }
+    let mut mode = attr.st_mode;
+    if inode.inode_type() == VfsNodeType::File {
+        mode &= !S_ISUID;
+        if mode & S_IXGRP != 0 {
+            mode &= !S_ISGID;
+        }
+    }
+    update_inode_attr(&inode, Some(mode), uid, gid)?;
     Ok(0)
 }
 
-/// 一个系统调用函数，用于修改文件或目录的权限。(待实现)
+/// 一个系统调用函数，用于修改文件或目录的权限。
 ///
 /// 在Alien系统中，每个文件或目录都有一个权限位，
-/// 用于控制该文件或目录的访问权限。sys_chmod函数可以用于修改这些权限位。
+/// 用于控制该文件或目录的访问权限。fchmod函数可以用于修改这些权限位。
 ///
-/// sys_chmod函数需要传入两个参数：第一个参数是需要要修改的文件的文件描述符，
-/// 第二个参数是新的权限值。
+/// fchmod函数需要传入两个参数：第一个参数是需要要修改的文件的文件描述符，
+/// 第二个参数是新的权限值。只有文件的所有者或拥有 CAP_FOWNER 能力的进程才能修改文件的权限。
 ///
 /// Reference: [chmod](https:///man7.org/linux/man-pages/man2/chmod.2.html)
 #[syscall_func(52)]
-pub fn chmod(_fd: usize, _mode: usize) -> isize {
-    0
+pub fn chmod(fd: usize, mode: usize) -> AlienResult<isize> {
+    let file = current_task()
+        .unwrap()
+        .get_file(fd)
+        .ok_or(LinuxErrno::EBADF)?;
+    do_chmod(file.dentry().inode()?, mode)
 }
 
-/// (待实现)一个系统调用函数，用于修改相对于某目录某位置处文件或目录的权限。
+/// 一个系统调用函数，用于修改相对于某目录某位置处文件或目录的权限。
 ///
 /// 当传入的`path`是一个相对地址时，那么`path`会被解析成基于文件描述符`dirfd`
 /// 所指向的目录地址的一个地址；当传入的`path`是一个相对地址并且
@@ -231,30 +308,63 @@ pub fn chmod(_fd: usize, _mode: usize) -> isize {
 /// 被解析成基于调用该系统调用的进程当前工作目录的一个地址；
 /// 当传入的`path`是一个绝对地址时，`dirfd`将被直接忽略。
 ///
-/// 当解析出的地址指向的文件是一个软链接时，将根据传入的`flag`的值进行以下的内容：
-/// + 若`flag`为0，则将对软链接进行解析，修改软链接的目标文件的权限
-/// + 若`flag`为AT_SYMLINK_NOFOLLOW，则将不对软链接进行解析，直接修改该文件的权限
-///
-/// `flag`处可以传入的值及其含义包括：
-/// + AT_SYMLINK_NOFOLLOW: 0x200，如果`path`解析之后指向的文件是一个软链接时，不对软链接进行解析，直接修改该文件的权限
-///
-/// `flag`可以置为AT_SYMLINK_NOFOLLOW或者为0。
+/// 与 Linux 相同，目前不支持修改软链接本身的权限，`flag` 包含 AT_SYMLINK_NOFOLLOW 时返回 EOPNOTSUPP。
 ///
 /// Reference: [chmod](https:///man7.org/linux/man-pages/man2/chmod.2.html)
 #[syscall_func(53)]
-pub fn chmodat(_dirfd: usize, _path: usize, _mode: usize, _flags: usize) -> isize {
-    0
+pub fn chmodat(dirfd: isize, path: usize, mode: usize, flags: usize) -> AlienResult<isize> {
+    if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        return Err(LinuxErrno::EOPNOTSUPP);
+    }
+    let task = current_task().unwrap();
+    let path = task.strncpy_from_user(path as *const u8, PATH_MAX)?;
+    let dentry = user_path_at(dirfd, &path)?.open(None)?;
+    do_chmod(dentry.inode()?, mode)
 }
 
-/// 一个系统调用，用于获取并设置当前进程的 `unmask`。在一个进程中，unmask 用于定义新建文件或目录的默认权限。
-/// 每次新建一个文件时，文件的默认权限是由 unmask 的值决定的。如果 unmask 值的某位被设置，在新建文件或目录时将禁用对应的权限。
+/// 一个系统调用函数，用于修改相对于某目录某位置处文件或目录的所有者与所属组。
 ///
-/// 函数执行成功后，将会把当前进程的 unmask 值置为传入的 `unmask`，同时返回原来的 unmask 值。
+/// `path` 的解析方式与 [`chmodat`] 相同；`flags` 包含 AT_EMPTY_PATH 且 `path` 为空时，直接修改 `dirfd` 所指向的文件。
+/// `uid` 或 `gid` 为 -1 时表示保持不变。
+///
+/// Reference: [chown](https://man7.org/linux/man-pages/man2/chown.2.html)
+#[syscall_func(54)]
+pub fn fchownat(
+    dirfd: isize,
+    path: usize,
+    uid: usize,
+    gid: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let path = task.strncpy_from_user(path as *const u8, PATH_MAX)?;
+    let inode = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        let file = task.get_file(dirfd as usize).ok_or(LinuxErrno::EBADF)?;
+        file.dentry().inode()?
+    } else {
+        user_path_at(dirfd, &path)?.open(None)?.inode()?
+    };
+    do_chown(inode, optional_id(uid), optional_id(gid))
+}
+
+/// 一个系统调用函数，用于修改文件描述符 `fd` 所指向的文件的所有者与所属组。
+///
+/// `uid` 或 `gid` 为 -1 时表示保持不变。
+///
+/// Reference: [chown](https://man7.org/linux/man-pages/man2/chown.2.html)
 #[syscall_func(55)]
-pub fn fchown() -> isize {
-    0
+pub fn fchown(fd: usize, uid: usize, gid: usize) -> AlienResult<isize> {
+    let file = current_task()
+        .unwrap()
+        .get_file(fd)
+        .ok_or(LinuxErrno::EBADF)?;
+    do_chown(file.dentry().inode()?, optional_id(uid), optional_id(gid))
 }
 
+/// 一个系统调用，用于获取并设置当前进程的 `unmask`。在一个进程中，unmask 用于定义新建文件或目录的默认权限。
+/// 每次新建一个文件时，文件的默认权限是由 unmask 的值决定的。如果 unmask 值的某位被设置，在新建文件或目录时将禁用对应的权限。
+///
+/// 函数执行成功后，将会把当前进程的 unmask 值置为传入的 `unmask`，同时返回原来的 unmask 值。
 #[syscall_func(166)]
 pub fn unmask(unmask: usize) -> isize {
     let task = current_task().unwrap();
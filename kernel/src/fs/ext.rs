@@ -1,11 +1,12 @@
 use core::cmp::min;
 
+use config::PATH_MAX;
 use constants::{AlienResult, LinuxErrno, AT_FDCWD};
 use syscall_table::syscall_func;
 use vfs::system_root_fs;
 use vfscore::path::VfsPath;
 
-use crate::{fs::user_path_at, task::current_task};
+use crate::{fs::user_path_at, mm::uaccess::UserSlice, task::current_task};
 
 /// 一个系统调用，用于设置文件的 扩展属性(xattrs, Extended Attributes)。
 ///
@@ -33,11 +34,11 @@ pub fn sys_setxattr(
     // we ignore flag
     assert_eq!(flag, 0);
     let process = current_task().unwrap();
-    let path = process.transfer_str(path);
-    let name = process.transfer_str(name);
-    let value = process.transfer_buffer(value, size);
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
+    let name = process.strncpy_from_user(name, PATH_MAX)?;
+    let value = process.read_user_slice(UserSlice::new(value as usize, size))?;
     let path = user_path_at(AT_FDCWD, &path)?;
-    path.set_xattr(&name, value[0])?;
+    path.set_xattr(&name, &value)?;
     Ok(0)
 }
 
@@ -71,11 +72,11 @@ pub fn sys_fsetxattr(
     // we ignore flag
     assert_eq!(flag, 0);
     let process = current_task().unwrap();
-    let name = process.transfer_str(name);
-    let value = process.transfer_buffer(value, size);
+    let name = process.strncpy_from_user(name, PATH_MAX)?;
+    let value = process.read_user_slice(UserSlice::new(value as usize, size))?;
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     let path = VfsPath::new(system_root_fs(), file.dentry());
-    path.set_xattr(&name, value[0])?;
+    path.set_xattr(&name, &value)?;
     Ok(0)
 }
 
@@ -98,18 +99,12 @@ pub fn sys_getxattr(
     size: usize,
 ) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let path = process.transfer_str(path);
-    let name = process.transfer_str(name);
-    let mut value = process.transfer_buffer(value, size);
+    let path = process.strncpy_from_user(path, PATH_MAX)?;
+    let name = process.strncpy_from_user(name, PATH_MAX)?;
     let path = user_path_at(AT_FDCWD, &path)?;
     let res = path.get_xattr(&name)?;
-    let mut copy = 0;
-    value.iter_mut().for_each(|x| {
-        let min_copy = min(x.len(), res.len() - copy);
-        x.copy_from_slice(&res[copy..copy + min_copy]);
-        copy += min_copy;
-    });
-    Ok(copy as _)
+    process.write_user_slice(UserSlice::new(value as usize, size), &res)?;
+    Ok(min(size, res.len()) as _)
 }
 
 /// 一个系统调用，用于获取文件的 扩展属性。在功能上与 [`sys_getxattr`] 相似。
@@ -138,18 +133,12 @@ pub fn sys_fgetxattr(
     size: usize,
 ) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let name = process.transfer_str(name);
-    let mut value = process.transfer_buffer(value, size);
+    let name = process.strncpy_from_user(name, PATH_MAX)?;
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     let path = VfsPath::new(system_root_fs(), file.dentry());
     let res = path.get_xattr(&name)?;
-    let mut copy = 0;
-    value.iter_mut().for_each(|x| {
-        let min_copy = min(x.len(), res.len() - copy);
-        x.copy_from_slice(&res[copy..copy + min_copy]);
-        copy += min_copy;
-    });
-    Ok(copy as _)
+    process.write_user_slice(UserSlice::new(value as usize, size), &res)?;
+    Ok(min(size, res.len()) as _)
 }
 
 /// 一个系统调用，用于获取一个文件的所有扩展属性类型 。有关 扩展属性 的相关信息可见 [`sys_setxattr`]。
@@ -167,8 +156,8 @@ pub fn sys_fgetxattr(
 #[syscall_func(11)]
 pub fn sys_listxattr(path: *const u8, list: *const u8, size: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let _path = process.transfer_str(path);
-    let _list = process.transfer_buffer(list, size);
+    let _path = process.strncpy_from_user(path, PATH_MAX)?;
+    let _list = process.writable_buffers(UserSlice::new(list as usize, size))?;
     unimplemented!();
 }
 
@@ -188,7 +177,7 @@ pub fn sys_llistxattr(path: *const u8, list: *const u8, size: usize) -> AlienRes
 #[syscall_func(13)]
 pub fn sys_flistxattr(fd: usize, list: *const u8, size: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let _list = process.transfer_buffer(list, size);
+    let _list = process.writable_buffers(UserSlice::new(list as usize, size))?;
     let _file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     unimplemented!();
 }
@@ -205,8 +194,8 @@ pub fn sys_flistxattr(fd: usize, list: *const u8, size: usize) -> AlienResult<is
 #[syscall_func(14)]
 pub fn sys_removexattr(path: *const u8, name: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let _path = process.transfer_str(path);
-    let _name = process.transfer_str(name);
+    let _path = process.strncpy_from_user(path, PATH_MAX)?;
+    let _name = process.strncpy_from_user(name, PATH_MAX)?;
     unimplemented!();
 }
 
@@ -226,7 +215,7 @@ pub fn sys_lremovexattr(path: *const u8, name: *const u8) -> AlienResult<isize>
 #[syscall_func(16)]
 pub fn sys_fremovexattr(fd: usize, name: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let _name = process.transfer_str(name);
+    let _name = process.strncpy_from_user(name, PATH_MAX)?;
     let _file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     unimplemented!();
 }
@@ -1,5 +1,6 @@
 use alloc::vec;
 
+use config::PATH_MAX;
 use constants::{
     io::{LinkFlags, OpenFlags, UnlinkatFlags},
     AlienResult,
@@ -7,7 +8,11 @@ use constants::{
 use log::{info, warn};
 use syscall_table::syscall_func;
 
-use crate::{fs::user_path_at, task::current_task};
+use crate::{
+    fs::{may_delete, user_path_at},
+    mm::uaccess::UserSlice,
+    task::current_task,
+};
 /// 一个系统调用，用于创建相对于一个目录某位置处的一个文件的(硬)链接。
 ///
 /// 当传入的 `old_name` 是一个相对地址时，那么 `old_name` 会被解析成基于文件描述符 `old_fd`
@@ -38,9 +43,9 @@ pub fn sys_linkat(
 ) -> AlienResult<isize> {
     let flag = LinkFlags::from_bits_truncate(flag as u32);
     let process = current_task().unwrap();
-    let old_name = process.transfer_str(old_name);
+    let old_name = process.strncpy_from_user(old_name, PATH_MAX)?;
     let old_path = user_path_at(old_fd, &old_name)?;
-    let new_name = process.transfer_str(new_name);
+    let new_name = process.strncpy_from_user(new_name, PATH_MAX)?;
     let new_path = user_path_at(new_fd, &new_name)?;
 
     warn!(
@@ -76,6 +81,9 @@ pub fn sys_linkat(
 ///
 /// `flag`可以置为AT_REMOVEDIR或者为0。
 ///
+/// 当前进程需要拥有对父目录的写与搜索权限，否则返回`EACCES`；父目录设置了 sticky 位时，
+/// 只有文件或父目录的所有者才能删除其中的文件，否则返回`EPERM`。
+///
 /// 如果成功删除文件链接，`sys_linkat`将返回0；否则返回-1或错误类型。
 ///
 /// Reference:
@@ -85,10 +93,14 @@ pub fn sys_linkat(
 #[syscall_func(35)]
 pub fn sys_unlinkat(fd: isize, path: *const u8, flag: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let path = task.transfer_str(path);
+    let path = task.strncpy_from_user(path, PATH_MAX)?;
     let flag = UnlinkatFlags::from_bits_truncate(flag as u32);
     info!("unlinkat path: {:?}, flag: {:?}", path, flag);
     let path = user_path_at(fd, &path)?;
+    if let Ok(dentry) = path.open(None) {
+        let cred = task.access_inner().cred.clone();
+        may_delete(&cred, &dentry)?;
+    }
     if flag.contains(UnlinkatFlags::AT_REMOVEDIR) {
         path.rmdir()?;
     } else {
@@ -109,8 +121,8 @@ pub fn sys_symlinkat(
     new_name: *const u8,
 ) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let old_name = process.transfer_str(old_name);
-    let new_name = process.transfer_str(new_name);
+    let old_name = process.strncpy_from_user(old_name, PATH_MAX)?;
+    let new_name = process.strncpy_from_user(new_name, PATH_MAX)?;
     let new_path = user_path_at(new_fd, &new_name)?;
     new_path.symlink(&old_name)?;
     Ok(0)
@@ -125,21 +137,12 @@ pub fn sys_symlinkat(
 #[syscall_func(78)]
 pub fn sys_readlinkat(fd: isize, path: *const u8, buf: *mut u8, size: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let path = task.transfer_str(path);
+    let path = task.strncpy_from_user(path, PATH_MAX)?;
     info!("readlink path: {}", path);
     let path = user_path_at(fd, &path)?;
     let dt = path.open2(None, OpenFlags::O_NOFOLLOW)?;
     let mut empty_buf = vec![0u8; size];
     let r = dt.inode()?.readlink(empty_buf.as_mut_slice())?;
-    let buf = task.transfer_buffer(buf, size);
-    let mut w = 0;
-    for buf in buf {
-        let len = buf.len();
-        buf.copy_from_slice(&empty_buf[w..w + len]);
-        w += len;
-        if w >= r {
-            break;
-        }
-    }
+    task.write_user_slice(UserSlice::new(buf as usize, r), &empty_buf[..r])?;
     Ok(r as isize)
 }
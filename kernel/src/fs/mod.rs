@@ -6,17 +6,40 @@ pub mod poll;
 pub mod select;
 pub mod stdio;
 
-use alloc::vec::Vec;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 
-use constants::{io::InodeMode, AlienResult, LinuxErrno, AT_FDCWD};
-use log::info;
-use vfs::system_root_fs;
+use constants::{
+    io::{InodeMode, OpenFlags, SeekFrom},
+    signal::SignalNumber,
+    AlienResult, LinuxErrno, AT_FDCWD,
+};
+use log::{info, warn};
+use vfs::{kfile::File, system_root_fs};
 use vfscore::{
+    dentry::VfsDentry,
+    inode::{InodeAttr, VfsInode},
     path::{SysContext, VfsPath},
     utils::{VfsInodeMode, VfsNodeType},
 };
 
-use crate::task::{current_task, FsContext};
+use crate::{
+    ipc::send_signal,
+    task::{current_task, Capabilities, Credentials, FsContext, RLimitRes, RLIM_INFINITY},
+};
+
+/// 读权限
+const MAY_READ: u32 = 4;
+/// 写权限
+const MAY_WRITE: u32 = 2;
+/// 执行(搜索)权限
+const MAY_EXEC: u32 = 1;
+/// 目录的 sticky 位，设置后只有文件或目录的所有者才能删除其中的文件
+const S_ISVTX: u32 = 0o1000;
 
 /// 地址解析函数，通过 `fd` 所指向的一个目录文件 和 相对于该目录文件的路径或绝对路径 `path` 解析出某目标文件的绝对路径。
 ///
@@ -26,22 +49,80 @@ use crate::task::{current_task, FsContext};
 /// 在`Alien`使用的`rvfs`中，对一个文件路径`path`是相对路径还是绝对路径的的判断条件如下：
 /// + 绝对路径：以`/`开头，如`/file1.txt`，表示根目录下的`file1.txt`文件；
 /// + 相对路径: 以`./`或者`../`或者其它开头，如`./file1.txt`，表示`dirfd`所指向的目录下的`file1.txt`文件。
-fn user_path_at(fd: isize, path: &str) -> AlienResult<VfsPath> {
+///
+/// 绝对路径从进程的根目录(可以通过 chroot 或 pivot_root 修改)开始解析。路径中最后一个分量之前的各级目录逐级解析，
+/// 每一级都查询当前进程所在的 mount 命名空间的挂载表，最后一个分量为挂载点时解析为挂载在其上的文件系统的根目录。
+/// 路径经过的每一级目录都需要拥有搜索(执行)权限，否则返回 `EACCES`。
+pub fn user_path_at(fd: isize, path: &str) -> AlienResult<VfsPath> {
     info!("user_path_at fd: {},path:{}", fd, path);
     let process = current_task().unwrap();
-    let res = if !path.starts_with("/") {
-        if fd == AT_FDCWD {
-            let fs_context = process.access_inner().fs_info.clone();
-            VfsPath::new(system_root_fs(), fs_context.cwd).join(path)
-        } else {
-            let fd = fd as usize;
-            let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-            VfsPath::new(system_root_fs(), file.dentry()).join(path)
-        }
+    let (fs_context, mnt, cred) = {
+        let inner = process.access_inner();
+        (
+            inner.fs_info.clone(),
+            inner.ns.mnt.clone(),
+            inner.cred.clone(),
+        )
+    };
+    let mut dir = if path.starts_with('/') {
+        fs_context.root.clone()
+    } else if fd == AT_FDCWD {
+        fs_context.cwd.clone()
     } else {
-        VfsPath::new(system_root_fs(), system_root_fs()).join(path)
+        let fd = fd as usize;
+        let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+        file.dentry()
     };
-    res.map_err(|e| e.into())
+    let mut components = path
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .peekable();
+    let mut last = ".";
+    while let Some(component) = components.next() {
+        if components.peek().is_none() && component != ".." {
+            last = component;
+            break;
+        }
+        inode_permission(&cred, &dir.inode()?, MAY_EXEC)?;
+        dir = mnt.lookup(&fs_context.root, dir, component)?;
+    }
+    let path = VfsPath::new(fs_context.root.clone(), dir.clone()).join(last)?;
+    if last != "." {
+        inode_permission(&cred, &dir.inode()?, MAY_EXEC)?;
+        if let Some(root) = path
+            .open(None)
+            .ok()
+            .and_then(|dentry| mnt.mounted_root(&dentry))
+        {
+            return Ok(VfsPath::new(fs_context.root, root).join(".")?);
+        }
+    }
+    Ok(path)
+}
+
+/// 从系统根目录开始的路径 `path` 是否是 `dir` 本身或者位于 `dir` 之下
+fn is_subpath(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path
+            .strip_prefix(dir)
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// 获取目录项 `dentry` 相对于根目录 `root` 的路径，用于 getcwd。
+///
+/// `dentry` 不在 `root` 之下时(例如 chroot 之后没有切换工作目录)，返回以 `(unreachable)` 开头的完整路径。
+fn path_in_root(dentry: &Arc<dyn VfsDentry>, root: &Arc<dyn VfsDentry>) -> String {
+    let path = dentry.path();
+    let root = root.path();
+    if root == "/" {
+        path
+    } else if !is_subpath(&path, &root) {
+        format!("(unreachable){}", path)
+    } else if path.len() == root.len() {
+        String::from("/")
+    } else {
+        path[root.len()..].to_string()
+    }
 }
 
 pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
@@ -76,6 +157,169 @@ pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
     true
 }
 
+/// 检查从 `offset` 处向文件 `file` 写入 `len` 个字节是否会超出当前进程的 `RLIMIT_FSIZE` 限制。
+///
+/// 只对普通文件进行检查，`offset` 为 `None` 时使用文件当前的读写位置(以 O_APPEND 打开时为文件末尾)。
+/// 函数返回在限制范围内允许写入的字节数；如果写入的起始位置已经达到限制，
+/// 则向当前进程发送 SIGXFSZ 并返回 EFBIG。
+fn check_fsize_limit(file: &Arc<dyn File>, offset: Option<u64>, len: usize) -> AlienResult<usize> {
+    let task = current_task().unwrap();
+    let limit = task.access_inner().resource_limits.cur(RLimitRes::Fsize);
+    if limit == RLIM_INFINITY || len == 0 {
+        return Ok(len);
+    }
+    // 管道、套接字等文件不受文件大小的限制
+    let attr = match file.get_attr() {
+        Ok(attr) => attr,
+        Err(_) => return Ok(len),
+    };
+    if file.inode().inode_type() != VfsNodeType::File {
+        return Ok(len);
+    }
+    let offset = match offset {
+        Some(offset) => offset,
+        None if file.get_open_flag().contains(OpenFlags::O_APPEND) => attr.st_size,
+        None => file.seek(SeekFrom::Current(0))?,
+    };
+    if offset >= limit {
+        warn!(
+            "write at {:#x} exceeds file size limit {:#x}",
+            offset, limit
+        );
+        send_signal(task.get_tid() as usize, SignalNumber::SIGXFSZ as usize);
+        return Err(LinuxErrno::EFBIG);
+    }
+    Ok(core::cmp::min(len as u64, limit - offset) as usize)
+}
+
+/// 获取 `inode` 的所有者、所属组与权限位(包括 setuid/setgid/sticky 位)。
+///
+/// 权限位优先使用 [`VfsInode::node_perm`]，其为空时使用 `st_mode` 中记录的权限位；
+/// 如果文件系统两者都没有记录，则认为该文件对所有用户开放。
+fn inode_owner_and_perm(inode: &Arc<dyn VfsInode>) -> (u32, u32, u32) {
+    let attr = inode.get_attr().ok();
+    let (uid, gid, mode) = attr
+        .map(|attr| (attr.st_uid, attr.st_gid, attr.st_mode))
+        .unwrap_or((0, 0, 0));
+    let mut perm = inode.node_perm().bits() as u32 & 0o777;
+    if perm == 0 {
+        perm = mode & 0o777;
+    }
+    if perm == 0 {
+        perm = 0o777;
+    }
+    (uid, gid, perm | (mode & 0o7000))
+}
+
+/// 检查凭证 `cred` 对 `inode` 是否拥有 `mask` 所指明的访问权限，没有权限时返回 EACCES
+fn inode_permission(cred: &Credentials, inode: &Arc<dyn VfsInode>, mask: u32) -> AlienResult<()> {
+    let (uid, gid, perm) = inode_owner_and_perm(inode);
+    let is_dir = inode.inode_type() == VfsNodeType::Dir;
+    if cred.permission(uid, gid, perm, mask, is_dir) {
+        Ok(())
+    } else {
+        Err(LinuxErrno::EACCES)
+    }
+}
+
+/// 检查当前进程是否可以删除 `dentry` 所指向的文件或目录。
+///
+/// 需要拥有对其父目录的写与搜索权限；如果父目录设置了 sticky 位，
+/// 则还需要是文件或父目录的所有者(或者拥有 CAP_FOWNER 能力)。
+fn may_delete(cred: &Credentials, dentry: &Arc<dyn VfsDentry>) -> AlienResult<()> {
+    let parent = match dentry.parent() {
+        Some(parent) => parent,
+        None => return Err(LinuxErrno::EBUSY),
+    };
+    let dir = parent.inode()?;
+    inode_permission(cred, &dir, MAY_WRITE | MAY_EXEC)?;
+    let (dir_uid, _, dir_perm) = inode_owner_and_perm(&dir);
+    if dir_perm & S_ISVTX != 0 {
+        let (uid, _, _) = inode_owner_and_perm(&dentry.inode()?);
+        if cred.fsuid != uid && cred.fsuid != dir_uid && !cred.has_cap(Capabilities::FOWNER) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    Ok(())
+}
+
+/// 获取路径 `path` 中最后一个分量之前的部分，即其父目录的路径
+fn parent_path(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(0) => "/",
+        Some(index) => &path[..index],
+        None => ".",
+    }
+}
+
+/// 修改 `inode` 的权限位与所有者，`None` 表示保持不变
+fn update_inode_attr(
+    inode: &Arc<dyn VfsInode>,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> AlienResult<()> {
+    let attr = inode.get_attr()?;
+    inode.set_attr(InodeAttr {
+        mode: mode.unwrap_or(attr.st_mode),
+        uid: uid.unwrap_or(attr.st_uid),
+        gid: gid.unwrap_or(attr.st_gid),
+        size: attr.st_size,
+        atime: attr.st_atime,
+        mtime: attr.st_mtime,
+        ctime: attr.st_ctime,
+    })?;
+    Ok(())
+}
+
+/// 检查当前进程是否可以执行 `path` 所指向的文件。
+///
+/// 文件必须是普通文件，且当前进程拥有对其的执行权限，否则返回 EACCES。
+/// 成功时返回文件的权限位与所有者 (mode, uid, gid)，供 exec 处理 setuid/setgid 位使用。
+pub fn check_exec_permission(path: &str) -> AlienResult<(u32, u32, u32)> {
+    let task = current_task().unwrap();
+    let cred = task.access_inner().cred.clone();
+    let dentry = user_path_at(AT_FDCWD, path)?.open(None)?;
+    let inode = dentry.inode()?;
+    if inode.inode_type() != VfsNodeType::File {
+        return Err(LinuxErrno::EACCES);
+    }
+    inode_permission(&cred, &inode, MAY_EXEC)?;
+    let (uid, gid, perm) = inode_owner_and_perm(&inode);
+    Ok((perm, uid, gid))
+}
+
+/// 打开或创建 `path` 处的文件用于写入 core 文件，文件被截断为空。
+///
+/// 新创建的文件属于当前进程的 fsuid 与 fsgid，权限为 0600；已经存在的文件必须是当前进程的 fsuid 所拥有的普通文件，
+/// 否则返回 EACCES。
+pub fn create_core_file(path: &str) -> AlienResult<Arc<dyn VfsInode>> {
+    let task = current_task().unwrap();
+    let cred = task.access_inner().cred.clone();
+    let core_path = user_path_at(AT_FDCWD, path)?;
+    let dentry = match core_path.open(None) {
+        Ok(dentry) => {
+            let inode = dentry.inode()?;
+            let (uid, _, _) = inode_owner_and_perm(&inode);
+            if inode.inode_type() != VfsNodeType::File || uid != cred.fsuid {
+                return Err(LinuxErrno::EACCES);
+            }
+            inode_permission(&cred, &inode, MAY_WRITE)?;
+            core_path.truncate(0)?;
+            dentry
+        }
+        Err(_) => {
+            let parent = user_path_at(AT_FDCWD, parent_path(path))?.open(None)?;
+            inode_permission(&cred, &parent.inode()?, MAY_WRITE | MAY_EXEC)?;
+            let dentry = core_path.open(Some(im2vim(InodeMode::from_bits_truncate(0o600))))?;
+            let _ = update_inode_attr(&dentry.inode()?, None, Some(cred.fsuid), Some(cred.fsgid));
+            dentry
+        }
+    };
+    Ok(dentry.inode()?)
+}
+
 /// [InodeMode](InodeMode)转换为[VfsInodeMode](VfsInodeMode)
 fn im2vim(mode: InodeMode) -> VfsInodeMode {
     VfsInodeMode::from_bits_truncate(mode.bits())
@@ -1,5 +1,3 @@
-use alloc::vec::Vec;
-
 use constants::{
     io::{PollEvents, PollFd},
     AlienResult, LinuxErrno,
@@ -8,7 +6,10 @@ use log::{info, warn};
 use syscall_table::syscall_func;
 use timer::TimeSpec;
 
-use crate::task::{current_task, do_suspend};
+use crate::{
+    mm::uaccess::{UserPtr, UserSlice},
+    task::{current_task, do_suspend},
+};
 
 /// 一个系统调用，用于在一些文件描述符上等待事件。作用与 [`pselect6`] 相似。
 ///
@@ -38,16 +39,11 @@ use crate::task::{current_task, do_suspend};
 #[syscall_func(73)]
 pub fn ppoll(fds_ptr: usize, nfds: usize, time: usize, _mask: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let mut fds = Vec::<PollFd>::with_capacity(nfds);
-    unsafe {
-        fds.set_len(nfds);
-    }
-    task.access_inner()
-        .copy_from_user_buffer(fds_ptr as *const PollFd, fds.as_mut_ptr(), nfds);
+    let mut fds = task.read_user_slice(UserSlice::<PollFd>::new(fds_ptr, nfds))?;
 
     info!("fds: {:?}", fds);
     let wait_time = if time != 0 {
-        let time_spec = task.transfer_raw_ptr(time as *mut TimeSpec);
+        let time_spec = task.read_user(UserPtr::<TimeSpec>::new(time))?;
         Some(time_spec.to_clock() + TimeSpec::now().to_clock())
     } else {
         None
@@ -71,8 +67,7 @@ pub fn ppoll(fds_ptr: usize, nfds: usize, time: usize, _mask: usize) -> AlienRes
 
         if res > 0 {
             // copy to user
-            task.access_inner()
-                .copy_to_user_buffer(fds.as_ptr(), fds_ptr as *mut PollFd, nfds);
+            task.write_user_slice(UserSlice::<PollFd>::new(fds_ptr, nfds), &fds)?;
             info!("ppoll return {:?}", fds);
             return Ok(res as isize);
         }
@@ -12,7 +12,10 @@ use log::{info, trace};
 use syscall_table::syscall_func;
 use timer::TimeSpec;
 
-use crate::task::{current_task, do_suspend};
+use crate::{
+    mm::uaccess::UserPtr,
+    task::{current_task, do_suspend},
+};
 
 /// 一个系统调用，实现 IO 端口的复用。一般用于用户程序的一段循环体中，
 /// 用于周期性检测一组关注的文件描述符集里是否有需要进行处理的IO事件发生。
@@ -64,17 +67,17 @@ pub fn pselect6(
     let task = current_task().unwrap();
 
     if sigmask != 0 {
-        let mask = task.access_inner().transfer_raw_ptr(sigmask as *mut usize);
-        let mask_num: Vec<SignalNumber> = SimpleBitSet(*mask).into();
-        info!("pselect6: sigmask = {} ---> {:?}, ", *mask, mask_num);
+        let mask = task.read_user(UserPtr::<usize>::new(sigmask))?;
+        let mask_num: Vec<SignalNumber> = SimpleBitSet(mask).into();
+        info!("pselect6: sigmask = {} ---> {:?}, ", mask, mask_num);
     }
 
     let (wait_time, time_spec) = if timeout != 0 {
-        let time_spec = task.transfer_raw_ptr(timeout as *mut TimeSpec);
+        let time_spec = task.read_user(UserPtr::<TimeSpec>::new(timeout))?;
         info!("pselect6: timeout = {:#x} ---> {:?}", timeout, time_spec);
         (
             Some(time_spec.to_clock() + TimeSpec::now().to_clock()),
-            Some(time_spec.clone()),
+            Some(time_spec),
         )
     } else {
         (Some(usize::MAX), None)
@@ -84,20 +87,17 @@ pub fn pselect6(
 
     // 这里暂时不考虑 sigmask 的问题
     let ori_readfds = if readfds != 0 {
-        let readfds = task.transfer_raw_ptr(readfds as *mut u64);
-        *readfds
+        task.read_user(UserPtr::<u64>::new(readfds))?
     } else {
         0
     };
     let ori_writefds = if writefds != 0 {
-        let writefds = task.transfer_raw_ptr(writefds as *mut u64);
-        *writefds
+        task.read_user(UserPtr::<u64>::new(writefds))?
     } else {
         0
     };
     let ori_exceptfds = if exceptfds != 0 {
-        let exceptfds = task.transfer_raw_ptr(exceptfds as *mut u64);
-        *exceptfds
+        task.read_user(UserPtr::<u64>::new(exceptfds))?
     } else {
         0
     };
@@ -111,7 +111,7 @@ pub fn pselect6(
         let mut set = 0;
         // 如果设置了监视是否可读的 fd
         if readfds != 0 {
-            let readfds = task.transfer_raw_ptr(readfds as *mut u64);
+            let mut ready = 0u64;
             trace!(
                 "[tid:{}]pselect6: readfds = {:#b}",
                 task.get_tid(),
@@ -123,20 +123,19 @@ pub fn pselect6(
                         let event = fd.poll(PollEvents::IN).expect("poll error");
                         if event.contains(PollEvents::IN) {
                             info!("pselect6: fd {} ready to read", i);
-                            readfds.set_bit(i, true);
+                            ready.set_bit(i, true);
                             set += 1;
-                        } else {
-                            readfds.set_bit(i, false);
                         }
                     } else {
                         return Err(LinuxErrno::EBADF.into());
                     }
                 }
             }
+            task.write_user(UserPtr::<u64>::new(readfds), &ready)?;
         }
         // 如果设置了监视是否可写的 fd
         if writefds != 0 {
-            let writefds = task.transfer_raw_ptr(writefds as *mut u64);
+            let mut ready = 0u64;
             trace!(
                 "[tid:{}]pselect6: writefds = {:#b}",
                 task.get_tid(),
@@ -148,20 +147,19 @@ pub fn pselect6(
                         let event = fd.poll(PollEvents::OUT).expect("poll error");
                         if event.contains(PollEvents::OUT) {
                             info!("pselect6: fd {} ready to write", i);
-                            writefds.set_bit(i, true);
+                            ready.set_bit(i, true);
                             set += 1;
-                        } else {
-                            writefds.set_bit(i, false);
                         }
                     } else {
                         return Err(LinuxErrno::EBADF.into());
                     }
                 }
             }
+            task.write_user(UserPtr::<u64>::new(writefds), &ready)?;
         }
         // 如果设置了监视是否异常的 fd
         if exceptfds != 0 {
-            let exceptfds = task.transfer_raw_ptr(exceptfds as *mut u64);
+            let mut ready = 0u64;
             trace!(
                 "[tid:{}]pselect6: exceptfds = {:#b}",
                 task.get_tid(),
@@ -173,21 +171,18 @@ pub fn pselect6(
                         let event = fd.poll(PollEvents::ERR).expect("poll error");
                         if event.contains(PollEvents::ERR) {
                             info!("pselect6: fd {} in exceptional conditions", i);
-                            exceptfds.set_bit(i, true);
+                            ready.set_bit(i, true);
                             set += 1;
-                        } else {
-                            exceptfds.set_bit(i, false);
                         }
                     } else {
                         return Err(LinuxErrno::EBADF.into());
                     }
                 }
             }
+            task.write_user(UserPtr::<u64>::new(exceptfds), &ready)?;
         }
 
         if set > 0 {
-            // let readfds = task.transfer_raw_ptr(readfds as *mut u64);
-            // error!("pselect6: readfds = {:#b}", *readfds);
             // 如果找到满足条件的 fd，则返回找到的 fd 数量
             return Ok(set as isize);
         }
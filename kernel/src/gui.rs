@@ -1,8 +1,10 @@
 //! GUI 相关的系统调用
+use alloc::vec::Vec;
+
 use devices::{GPU_DEVICE, KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE};
 use page_table::addr::{align_up_4k, PhysAddr, VirtAddr};
 
-use crate::task::current_task;
+use crate::{mm::uaccess::UserSlice, task::current_task};
 
 const FB_VADDR: usize = 0x1000_0000;
 
@@ -42,22 +44,18 @@ pub fn sys_framebuffer_flush() -> isize {
 #[syscall_func(2002)]
 pub fn sys_event_get(event_buf: *mut u64, len: usize) -> isize {
     let task = current_task().unwrap();
-    let user_buffer = task.transfer_buffer(event_buf, len);
-    let mut count = 0;
-    for buf in user_buffer {
-        let mut index = 0;
-        let len = buf.len();
-        while index < len {
-            let event = read_event();
-            if event == 0 {
-                break;
-            }
-            buf[index] = event;
-            index += 1;
-            count += 1;
+    let mut events = Vec::new();
+    while events.len() < len {
+        let event = read_event();
+        if event == 0 {
+            break;
         }
+        events.push(event);
+    }
+    if let Err(e) = task.write_user_slice(UserSlice::new(event_buf as usize, len), &events) {
+        return e.into();
     }
-    count
+    events.len() as isize
 }
 
 fn read_event() -> u64 {
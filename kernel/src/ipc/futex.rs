@@ -13,14 +13,80 @@
 //! Reference: https://cloud.tencent.com/developer/article/1176832
 //!
 use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
-use core::cmp::min;
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-use constants::{AlienError, AlienResult};
+use constants::{io::MapFlags, AlienError, AlienResult};
 use ksync::Mutex;
 use smpscheduler::FifoTask;
 use timer::read_timer;
 
-use crate::task::{Task, GLOBAL_TASK_MANAGER};
+use crate::{
+    ipc::FUTEX_WAITER,
+    mm::uaccess::UserPtr,
+    task::{Task, GLOBAL_TASK_MANAGER},
+};
+
+pub const FUTEX_WAIT: u32 = 0;
+pub const FUTEX_WAKE: u32 = 1;
+pub const FUTEX_REQUEUE: u32 = 3;
+pub const FUTEX_CMP_REQUEUE: u32 = 4;
+pub const FUTEX_WAKE_OP: u32 = 5;
+pub const FUTEX_LOCK_PI: u32 = 6;
+pub const FUTEX_UNLOCK_PI: u32 = 7;
+pub const FUTEX_TRYLOCK_PI: u32 = 8;
+pub const FUTEX_WAIT_BITSET: u32 = 9;
+pub const FUTEX_WAKE_BITSET: u32 = 10;
+pub const FUTEX_CMP_REQUEUE_PI: u32 = 12;
+
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+pub const FUTEX_CLOCK_REALTIME: u32 = 256;
+/// 去掉 `FUTEX_PRIVATE_FLAG` 和 `FUTEX_CLOCK_REALTIME` 后即为具体的操作类型
+pub const FUTEX_CMD_MASK: u32 = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+
+/// 可以匹配任意等待者的 bitset
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// futex 字中表示有进程在内核中等待的标志位
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// futex 字中表示持有者已经退出的标志位
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// futex 字中保存持有者 tid 的部分
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// 遍历 robust 列表时最多处理的节点数，防止用户态构造的环形链表使内核陷入死循环
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+/// FUTEX_WAKE_OP 中对 uaddr2 进行的原子操作
+const FUTEX_OP_SET: u32 = 0;
+const FUTEX_OP_ADD: u32 = 1;
+const FUTEX_OP_OR: u32 = 2;
+const FUTEX_OP_ANDN: u32 = 3;
+const FUTEX_OP_XOR: u32 = 4;
+/// 操作数 oparg 需要被解释为 `1 << oparg`
+const FUTEX_OP_OPARG_SHIFT: u32 = 8;
+
+/// FUTEX_WAKE_OP 中对 uaddr2 原值进行的比较
+const FUTEX_OP_CMP_EQ: u32 = 0;
+const FUTEX_OP_CMP_NE: u32 = 1;
+const FUTEX_OP_CMP_LT: u32 = 2;
+const FUTEX_OP_CMP_LE: u32 = 3;
+const FUTEX_OP_CMP_GT: u32 = 4;
+const FUTEX_OP_CMP_GE: u32 = 5;
+
+/// 用户态 robust 列表的列表头，与 Linux 中的 `struct robust_list_head` 布局一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct RobustListHead {
+    /// 链表中第一个节点的地址，链表为空时指向列表头自身
+    list: usize,
+    /// 锁中 futex 字相对于链表节点的偏移
+    futex_offset: isize,
+    /// 正在加锁或解锁过程中的节点
+    list_op_pending: usize,
+}
 
 /// 用于记录一个进程等待一个 futex 的相关信息
 pub struct FutexWaiter {
@@ -30,22 +96,82 @@ pub struct FutexWaiter {
     wait_time: Option<usize>,
     /// 超时事件的标志位，标识该进程对于 futex 等待是否超时
     timeout_flag: Arc<Mutex<bool>>,
+    /// 等待时指定的 bitset，只有唤醒时的 bitset 与其有交集才会被唤醒
+    bitset: u32,
+}
+
+/// futex 在等待队列中的键
+///
+/// 私有 futex 只在同一地址空间内可见，由地址空间和用户虚拟地址确定；
+/// 共享 futex 需要在映射了同一块内存的不同进程之间匹配，因此由物理地址或者文件 inode 与文件内偏移确定。
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FutexKey {
+    /// 地址空间(页表)的地址与用户虚拟地址
+    Private { mm: usize, uaddr: usize },
+    /// futex 所在的物理地址
+    Physical(usize),
+    /// 映射文件的 inode 的地址与 futex 在文件内的偏移
+    Inode { inode: usize, offset: usize },
 }
 
 /// 用于管理 futex 等待队列的数据结构
 ///
-/// 包含一个 futex id -> futexWait Vec 的 map
+/// 包含一个 futex key -> futexWait Vec 的 map
 pub struct FutexWaitManager {
-    map: BTreeMap<usize, Vec<FutexWaiter>>,
+    map: BTreeMap<FutexKey, Vec<FutexWaiter>>,
+}
+
+impl FutexKey {
+    /// 根据进程的页表解析 `uaddr` 对应的 futex 键
+    ///
+    /// `private` 为真(FUTEX_PRIVATE_FLAG)，或者 `uaddr` 位于私有映射中时，使用私有键；
+    /// 位于 System V 共享内存或匿名共享映射中时，使用其物理地址；位于 MAP_SHARED 文件映射中时，使用文件 inode 与文件内偏移。
+    /// 共享文件映射虽然直接映射 inode 的页缓存，各进程看到的是同一个物理页，但没有被映射的缓存页可能被回收后重新读入
+    /// 另一个物理页，因此不使用物理地址；页缓存同样以 inode 的地址区分文件，二者保持一致。
+    ///
+    /// 共享 futex 的 `uaddr` 无法访问时返回 `EFAULT`。
+    pub fn new(task: &Arc<Task>, uaddr: usize, private: bool) -> AlienResult<Self> {
+        let mut inner = task.access_inner();
+        let mm = Arc::as_ptr(&inner.address_space) as usize;
+        let private_key = FutexKey::Private { mm, uaddr };
+        if private {
+            return Ok(private_key);
+        }
+        let shared = match inner.mmap.get_region(uaddr) {
+            Some(region) if region.flags.contains(MapFlags::MAP_SHARED) => {
+                if let Some(file) = region.fd.as_ref() {
+                    let inode = Arc::as_ptr(&file.inode()) as *const () as usize;
+                    let offset = region.offset + (uaddr - region.start);
+                    return Ok(FutexKey::Inode { inode, offset });
+                }
+                true
+            }
+            Some(_) => false,
+            None => inner
+                .shm
+                .values()
+                .any(|info| info.start_va <= uaddr && uaddr < info.end_va),
+        };
+        if !shared {
+            return Ok(private_key);
+        }
+        Ok(FutexKey::Physical(inner.user_readable_addr(uaddr)?))
+    }
 }
 
 impl FutexWaiter {
     /// 创建一个新的 `FutexWaiter` 保存等待在某 futex 上的一个进程 有关等待的相关信息
-    pub fn new(task: Arc<Task>, wait_time: Option<usize>, timeout_flag: Arc<Mutex<bool>>) -> Self {
+    pub fn new(
+        task: Arc<Task>,
+        wait_time: Option<usize>,
+        timeout_flag: Arc<Mutex<bool>>,
+        bitset: u32,
+    ) -> Self {
         Self {
             task: Some(task),
             wait_time,
             timeout_flag,
+            bitset,
         }
     }
 
@@ -64,7 +190,7 @@ impl FutexWaitManager {
     }
 
     /// 在某等待队列中加入等待进程
-    pub fn add_waiter(&mut self, futex: usize, waiter: FutexWaiter) {
+    pub fn add_waiter(&mut self, futex: FutexKey, waiter: FutexWaiter) {
         self.map.entry(futex).or_insert(Vec::new()).push(waiter);
     }
 
@@ -127,49 +253,251 @@ impl FutexWaitManager {
         })
     }
 
-    /// 唤醒 futex 上的至多 num 个等待的进程
-    pub fn wake(&mut self, futex: usize, num: usize) -> AlienResult<usize> {
+    /// 唤醒 futex 上至多 num 个 bitset 与 `bitset` 有交集的进程，返回唤醒的进程数
+    pub fn wake_bitset(&mut self, futex: FutexKey, num: usize, bitset: u32) -> usize {
+        let mut count = 0;
         if let Some(waiters) = self.map.get_mut(&futex) {
-            error!("there are {} waiters, wake {}", waiters.len(), num);
-            let min_index = min(num, waiters.len());
-            for i in 0..min_index {
-                let task = waiters[i].wake();
+            waiters.retain_mut(|waiter| {
+                if count >= num || waiter.bitset & bitset == 0 {
+                    return true;
+                }
+                let task = waiter.wake();
                 GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+                count += 1;
+                false
+            });
+            if waiters.is_empty() {
+                self.map.remove(&futex);
             }
-            // delete waiters
-            waiters.drain(0..min_index);
-            warn!("wake {} tasks", min_index);
-            Ok(min_index)
-        } else {
-            error!("futex {} not found", futex);
-            Err(AlienError::EINVAL)
-        }
-    }
-
-    /// 将原来等待在 old_futex 上至多 num 个进程转移到 requeue_futex 上等待，返回转移的进程数
-    pub fn requeue(
-        &mut self,
-        requeue_futex: usize,
-        num: usize,
-        old_futex: usize,
-    ) -> AlienResult<usize> {
-        if num == 0 {
-            return Ok(0);
-        }
-        // move waiters
-        let mut waiters = self.map.remove(&old_futex).unwrap();
-        // create new waiters
-        let new_waiters = self.map.entry(requeue_futex).or_insert(Vec::new());
-        let min_index = min(num, waiters.len());
-        error!("requeue {} waiters", min_index);
-        for _ in 0..min_index {
-            let waiter = waiters.pop().unwrap();
-            new_waiters.push(waiter);
-        }
-        // insert old waiters
-        if !waiters.is_empty() {
-            self.map.insert(old_futex, waiters);
-        }
-        Ok(min_index)
+        }
+        count
+    }
+
+    /// 将原来等待在 old_futex 上至多 num 个进程按等待顺序转移到 requeue_futex 上等待，返回转移的进程数
+    ///
+    /// old_futex 上没有等待者时返回 0。
+    pub fn requeue(&mut self, requeue_futex: FutexKey, num: usize, old_futex: FutexKey) -> usize {
+        let waiters = match self.map.get_mut(&old_futex) {
+            Some(waiters) => waiters,
+            None => return 0,
+        };
+        let count = min(num, waiters.len());
+        // 转移到同一个 futex 上时等待队列不变
+        if count == 0 || requeue_futex == old_futex {
+            return count;
+        }
+        let moved = waiters.drain(0..count).collect::<Vec<_>>();
+        if waiters.is_empty() {
+            self.map.remove(&old_futex);
+        }
+        self.map
+            .entry(requeue_futex)
+            .or_insert(Vec::new())
+            .extend(moved);
+        count
+    }
+}
+
+/// 处理持有者退出时仍被持有的 robust futex
+///
+/// 若 futex 字中记录的持有者为 `tid`，则将其替换为 `FUTEX_OWNER_DIED`(保留 `FUTEX_WAITERS`)，
+/// 并在有等待者时唤醒其中一个，由被唤醒的进程接管该锁。
+fn handle_futex_death(task: &Arc<Task>, uaddr: usize, tid: u32) {
+    // robust 列表完全由用户态维护，地址无法访问时直接忽略
+    let word = match task
+        .access_inner()
+        .user_ref_mut(UserPtr::<AtomicU32>::new(uaddr))
+    {
+        Ok(word) => word,
+        Err(_) => return,
+    };
+    let mut val = word.load(Ordering::SeqCst);
+    loop {
+        if val & FUTEX_TID_MASK != tid {
+            return;
+        }
+        let new = (val & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        match word.compare_exchange(val, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => break,
+            Err(cur) => val = cur,
+        }
+    }
+    if val & FUTEX_WAITERS != 0 {
+        // 进程间共享的 robust 锁的等待者可能位于其它进程中，因此按共享 futex 解析
+        if let Ok(key) = FutexKey::new(task, uaddr, false) {
+            FUTEX_WAITER
+                .lock()
+                .wake_bitset(key, 1, FUTEX_BITSET_MATCH_ANY);
+        }
+    }
+}
+
+/// 线程退出时遍历其 robust 列表，释放其仍然持有的 robust 锁
+///
+/// 列表中节点地址的最低位用于标识 PI futex，计算 futex 地址时需要去掉。
+/// `list_op_pending` 指向的节点可能尚未加入链表，需要单独处理一次。
+///
+/// Reference: [robust-futex-ABI](https://www.kernel.org/doc/Documentation/robust-futex-ABI.txt)
+pub fn exit_robust_list(task: &Arc<Task>) {
+    let head_addr = task.access_inner().robust.head;
+    if head_addr == 0 {
+        return;
+    }
+    let head = match task.read_user(UserPtr::<RobustListHead>::new(head_addr)) {
+        Ok(head) => head,
+        Err(_) => return,
+    };
+    let tid = task.get_tid() as u32;
+    let futex_addr = |entry: usize| (entry & !1).wrapping_add_signed(head.futex_offset);
+    let mut entry = head.list;
+    let mut limit = ROBUST_LIST_LIMIT;
+    while entry != head_addr && limit > 0 {
+        // 在释放锁之前读出下一个节点，锁被其它线程接管后节点可能被修改
+        let next = match task.read_user(UserPtr::<usize>::new(entry & !1)) {
+            Ok(next) => next,
+            Err(_) => break,
+        };
+        if entry != head.list_op_pending {
+            handle_futex_death(task, futex_addr(entry), tid);
+        }
+        entry = next;
+        limit -= 1;
+    }
+    if head.list_op_pending != 0 {
+        handle_futex_death(task, futex_addr(head.list_op_pending), tid);
+    }
+}
+
+/// 执行 FUTEX_WAKE_OP 中编码在 `encoded_op` 里的原子操作，并返回对 `word` 原值的比较结果
+///
+/// `encoded_op` 的布局为 `op:4 | cmp:4 | oparg:12 | cmparg:12`，其中 oparg 与 cmparg 均为有符号数。
+/// 遇到无法识别的操作或比较类型时返回 `ENOSYS`。
+pub fn futex_atomic_op(encoded_op: u32, word: &AtomicU32) -> AlienResult<bool> {
+    let op = (encoded_op >> 28) & 0xf;
+    let cmp = (encoded_op >> 24) & 0xf;
+    // 对 12 位的参数进行符号扩展
+    let mut oparg = ((encoded_op << 8) as i32 >> 20) as u32;
+    let cmparg = ((encoded_op << 20) as i32) >> 20;
+    if op & FUTEX_OP_OPARG_SHIFT != 0 {
+        oparg = 1u32.checked_shl(oparg).unwrap_or(0);
+    }
+    let update = |old: u32| match op & !FUTEX_OP_OPARG_SHIFT {
+        FUTEX_OP_SET => Some(oparg),
+        FUTEX_OP_ADD => Some(old.wrapping_add(oparg)),
+        FUTEX_OP_OR => Some(old | oparg),
+        FUTEX_OP_ANDN => Some(old & !oparg),
+        FUTEX_OP_XOR => Some(old ^ oparg),
+        _ => None,
+    };
+    if update(0).is_none() || cmp > FUTEX_OP_CMP_GE {
+        return Err(AlienError::ENOSYS);
+    }
+    let old = word
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, update)
+        .map_err(|_| AlienError::ENOSYS)? as i32;
+    let res = match cmp {
+        FUTEX_OP_CMP_EQ => old == cmparg,
+        FUTEX_OP_CMP_NE => old != cmparg,
+        FUTEX_OP_CMP_LT => old < cmparg,
+        FUTEX_OP_CMP_LE => old <= cmparg,
+        FUTEX_OP_CMP_GT => old > cmparg,
+        _ => old >= cmparg,
+    };
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec, vec::Vec};
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use ksync::Mutex;
+
+    use crate::ipc::futex::{futex_atomic_op, FutexKey, FutexWaitManager, FutexWaiter};
+
+    fn waiter(bitset: u32) -> FutexWaiter {
+        FutexWaiter {
+            task: None,
+            wait_time: None,
+            timeout_flag: Arc::new(Mutex::new(false)),
+            bitset,
+        }
+    }
+
+    fn bitsets(manager: &FutexWaitManager, key: FutexKey) -> Option<Vec<u32>> {
+        manager
+            .map
+            .get(&key)
+            .map(|waiters| waiters.iter().map(|waiter| waiter.bitset).collect())
+    }
+
+    #[test]
+    pub fn test_futex_key() {
+        let private = FutexKey::Private {
+            mm: 1,
+            uaddr: 0x1000,
+        };
+        assert_ne!(
+            private,
+            FutexKey::Private {
+                mm: 2,
+                uaddr: 0x1000
+            }
+        );
+        assert_ne!(private, FutexKey::Physical(0x1000));
+        assert_ne!(
+            FutexKey::Inode {
+                inode: 1,
+                offset: 0
+            },
+            FutexKey::Inode {
+                inode: 1,
+                offset: 4
+            }
+        );
+        let mut manager = FutexWaitManager::new();
+        manager.add_waiter(private, waiter(1));
+        manager.add_waiter(FutexKey::Physical(0x1000), waiter(2));
+        manager.add_waiter(private, waiter(3));
+        assert_eq!(bitsets(&manager, private), Some(vec![1, 3]));
+        assert_eq!(bitsets(&manager, FutexKey::Physical(0x1000)), Some(vec![2]));
+    }
+
+    #[test]
+    pub fn test_futex_requeue() {
+        let old = FutexKey::Physical(0x1000);
+        let new = FutexKey::Physical(0x2000);
+        let mut manager = FutexWaitManager::new();
+        assert_eq!(manager.requeue(new, 1, old), 0);
+        assert!(manager.map.is_empty());
+        for bitset in 1..=3 {
+            manager.add_waiter(old, waiter(bitset));
+        }
+        assert_eq!(manager.requeue(old, 2, old), 2);
+        assert_eq!(bitsets(&manager, old), Some(vec![1, 2, 3]));
+        manager.add_waiter(new, waiter(4));
+        assert_eq!(manager.requeue(new, 2, old), 2);
+        assert_eq!(bitsets(&manager, old), Some(vec![3]));
+        assert_eq!(bitsets(&manager, new), Some(vec![4, 1, 2]));
+        assert_eq!(manager.requeue(new, 5, old), 1);
+        assert_eq!(bitsets(&manager, old), None);
+        assert_eq!(bitsets(&manager, new), Some(vec![4, 1, 2, 3]));
+    }
+
+    #[test]
+    pub fn test_futex_atomic_op() {
+        let word = AtomicU32::new(1);
+        // op = ADD, cmp = EQ, oparg = 2, cmparg = 1
+        assert_eq!(futex_atomic_op(0x1000_2001, &word), Ok(true));
+        assert_eq!(word.load(Ordering::SeqCst), 3);
+        // op = OR | OPARG_SHIFT, cmp = GT, oparg = 4, cmparg = -1
+        assert_eq!(futex_atomic_op(0xa400_4fff, &word), Ok(true));
+        assert_eq!(word.load(Ordering::SeqCst), 0x13);
+        // op = SET, cmp = LT, oparg = 0, cmparg = 0
+        assert_eq!(futex_atomic_op(0x0200_0000, &word), Ok(false));
+        assert_eq!(word.load(Ordering::SeqCst), 0);
+        assert!(futex_atomic_op(0x5000_0000, &word).is_err());
+        assert!(futex_atomic_op(0x0600_0000, &word).is_err());
+        assert_eq!(word.load(Ordering::SeqCst), 0);
     }
 }
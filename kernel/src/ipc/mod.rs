@@ -4,14 +4,15 @@
 //! [`pipe`] 子模块指明了 Alien 中管道结构。
 //! [`shm`] 子模块指明了 Alien 中的共享内存结构。
 //! [`signal`] 子模块指明了 Alien 中使用的信号机制。
+//! [`sigqueue`] 子模块指明了 Alien 中待处理信号的排队机制。
 
 use alloc::sync::Arc;
-use core::sync::atomic::{AtomicI32, Ordering};
-
-use constants::{
-    ipc::{FutexOp, RobustList},
-    AlienResult, LinuxErrno,
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicI32, AtomicU32, Ordering},
 };
+
+use constants::{ipc::RobustList, AlienResult, LinuxErrno};
 use ksync::Mutex;
 pub use pipe::*;
 pub use shm::*;
@@ -21,14 +22,18 @@ use timer::TimeSpec;
 
 use crate::{
     fs::basic::sys_close,
-    ipc::futex::{FutexWaitManager, FutexWaiter},
-    task::{current_task, schedule::schedule, TaskState},
+    ipc::futex::*,
+    mm::uaccess::UserPtr,
+    task::{
+        current_task, find_task, may_trace, pid_from_user, schedule::schedule, Task, TaskState,
+    },
 };
 
 pub mod futex;
 mod pipe;
 pub mod shm;
 pub mod signal;
+pub mod sigqueue;
 
 /// 一个全局变量，用于记录和管理 futex 的等待队列
 pub static FUTEX_WAITER: Lazy<Mutex<FutexWaitManager>> =
@@ -55,12 +60,17 @@ pub fn sys_pipe(pipe: *mut u32, _flag: u32) -> AlienResult<isize> {
         return Err(LinuxErrno::EINVAL);
     }
     let process = current_task().unwrap();
-    let fd_pair = process.transfer_raw_ptr(pipe as *mut FdPair);
     let (read, write) = make_pipe_file()?;
     let read_fd = process.add_file(read).map_err(|_| LinuxErrno::EMFILE)?;
     let write_fd = process.add_file(write).map_err(|_| LinuxErrno::EMFILE)?;
-    fd_pair.fd[0] = read_fd as u32;
-    fd_pair.fd[1] = write_fd as u32;
+    let fd_pair = FdPair {
+        fd: [read_fd as u32, write_fd as u32],
+    };
+    if let Err(e) = process.write_user(UserPtr::new(pipe as usize), &fd_pair) {
+        let _ = process.remove_file(read_fd);
+        let _ = process.remove_file(write_fd);
+        return Err(e);
+    }
     Ok(0)
 }
 
@@ -112,20 +122,26 @@ static FCOUNT: Mutex<usize> = Mutex::new(0);
 ///
 /// 参数：
 /// + `uaddr`: 用户态下共享内存的地址，里面存放的是一个对齐的整型计数器，指向一个 futex。
-/// + `futex_op`: 指明操作的类型。具体操作类型可见 [`FutexOp`]。目前 Alien 识别的 futex_op 包括：
-///     + FutexOp::FutexWaitPrivate | FutexOp::FutexWait: 先比较 uaddr 上计数器的值和 val 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
-/// 该进程将等待在 uaddr 上，并根据 val2 的值确定等待的逻辑。若 val2 值为0，则表示进程一直等待；若 val2 是一个正数，则表示进程将在等待 val2 时间后因超时被唤醒
-///     + FutexOp::FutexCmpRequeuePiPrivate: 先比较 uaddr 上计数器的值和 val3 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
+/// 不带 `FUTEX_PRIVATE_FLAG` 时，位于共享内存或共享文件映射中的 futex 会按照 [`FutexKey`] 在不同进程之间匹配。
+/// + `futex_op`: 指明操作的类型。去掉 `FUTEX_PRIVATE_FLAG` 和 `FUTEX_CLOCK_REALTIME` 后，目前 Alien 识别的操作包括：
+///     + FUTEX_WAIT | FUTEX_WAIT_BITSET: 先比较 uaddr 上计数器的值和 val 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
+/// 该进程将等待在 uaddr 上，并根据 val2 的值确定等待的逻辑。若 val2 值为0，则表示进程一直等待；否则 val2 指向等待的超时时间，
+/// FUTEX_WAIT 的超时时间为相对时间，FUTEX_WAIT_BITSET 的超时时间为绝对时间。FUTEX_WAIT_BITSET 会将 val3 作为等待的 bitset。
+///     + FUTEX_WAKE | FUTEX_WAKE_BITSET: 唤醒至多 val 个在 uaddr 上等待的进程，FUTEX_WAKE_BITSET 只唤醒 bitset 与 val3 有交集的进程。最后返回 唤醒的进程数。
+///     + FUTEX_CMP_REQUEUE: 先比较 uaddr 上计数器的值和 val3 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
 /// 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待，最后返回 唤醒的进程数 + 转移的进程数
-///     + FutexOp::FutexRequeuePrivate: 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待
+///     + FUTEX_REQUEUE: 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待
 /// 最后返回 唤醒的进程数 + 转移的进程数
-///     + FutexOp::FutexWakePrivate | FutexOp::FutexWake: 唤醒至多 val 个在 uaddr 上等待的进程。最后返回 唤醒的进程数。
+///     + FUTEX_WAKE_OP: 对 uaddr2 执行 val3 中编码的原子操作，唤醒至多 val 个在 uaddr 上等待的进程，若 uaddr2 原值满足 val3 中编码的比较条件，
+/// 再唤醒至多 val2 个在 uaddr2 上等待的进程。最后返回 唤醒的进程总数。
+///     + FUTEX_LOCK_PI | FUTEX_TRYLOCK_PI | FUTEX_UNLOCK_PI | FUTEX_CMP_REQUEUE_PI: 优先级继承(PI) futex 需要在等待时提升锁持有者的优先级，
+/// 而 Alien 的调度器为 FIFO 调度，没有进程优先级的概念，无法实现真正的优先级继承，因此这些操作均返回 `ENOSYS`。
 /// + `val`: 传入的参数1，将根据 futex_op 发挥不同的作用。
 /// + `val2`: 传入的参数2，将根据 futex_op 发挥不同的作用。
 /// + `uaddr2`: 传入的地址2，将根据 futex_op 发挥不同的作用。
 /// + `val3`: 传入的参数3，将根据 futex_op 发挥不同的作用。
 ///
-/// 在此过程中，如果出现异常，会返回异常类型。
+/// 在此过程中，如果出现异常，会返回异常类型；对于无法识别的操作，将返回 `ENOSYS`。
 ///
 /// Reference: [futex](https://man7.org/linux/man-pages/man2/futex.2.html)
 #[syscall_func(98)]
@@ -136,92 +152,149 @@ pub fn futex(
     val2: usize,
     uaddr2: usize,
     val3: u32,
-) -> isize {
+) -> AlienResult<isize> {
     *FCOUNT.lock() += 1;
-    let futex_op = FutexOp::try_from(futex_op).unwrap();
+    let cmd = futex_op & FUTEX_CMD_MASK;
     let task = current_task().unwrap();
-    let task_inner = task.access_inner();
     warn!(
         "futex: {:?} {:?} {:?} {:?} {:?} {:?}",
         uaddr, futex_op, val, val2, uaddr2, val3
     );
-    match futex_op {
-        FutexOp::FutexWaitPrivate | FutexOp::FutexWait => {
-            let uaddr_ref = task_inner.transfer_raw_ptr_mut(uaddr as *mut i32);
-            let uaddr_atomic = AtomicI32::from_mut(uaddr_ref);
-
-            if uaddr_atomic.load(Ordering::SeqCst) != val as i32 {
-                error!("FutexWait: uaddr_ref != val");
-                return LinuxErrno::EAGAIN as isize;
+    if uaddr % size_of::<u32>() != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    // 不带 FUTEX_PRIVATE_FLAG 的 futex 可能在进程间共享，需要通过页表解析其键值
+    let private = futex_op & FUTEX_PRIVATE_FLAG != 0;
+    let key = FutexKey::new(task, uaddr, private)?;
+    match cmd {
+        FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+            let bitset = if cmd == FUTEX_WAIT {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            if bitset == 0 {
+                return Err(LinuxErrno::EINVAL);
             }
             // we checkout the timeout
             let wait_time = if val2 != 0 {
-                let time_spec = task_inner.transfer_raw_ptr(val2 as *const TimeSpec);
-                Some(time_spec.to_clock() + TimeSpec::now().to_clock())
+                let time_spec = task.read_user(UserPtr::<TimeSpec>::new(val2))?;
+                if cmd == FUTEX_WAIT {
+                    Some(time_spec.to_clock() + TimeSpec::now().to_clock())
+                } else {
+                    Some(time_spec.to_clock())
+                }
             } else {
                 // wait forever
                 None
             };
-            // add to wait queue
-            drop(task_inner);
             warn!("Futex wait time: {:?}", wait_time);
-            let timeout_flag = Arc::new(Mutex::new(false));
-            let waiter = FutexWaiter::new(task.clone(), wait_time, timeout_flag.clone());
-            FUTEX_WAITER.lock().add_waiter(uaddr, waiter);
-            // switch to other task
-            task.update_state(TaskState::Waiting);
-            warn!("Because of futex, we switch to other task");
-            schedule();
-            // checkout the timeout flag
-            let timeout_flag = timeout_flag.lock();
-            if *timeout_flag {
-                return 0;
-            }
+            futex_wait(task, uaddr, key, val, wait_time, bitset)
         }
-        FutexOp::FutexCmpRequeuePiPrivate => {
-            let uaddr_ref = task_inner.transfer_raw_ptr(uaddr as *const u32);
-            if *uaddr_ref != val3 {
+        FUTEX_CMP_REQUEUE => {
+            if task.read_user(UserPtr::<u32>::new(uaddr))? != val3 {
                 error!("FutexRequeuePrivate: uaddr_ref != val");
-                return LinuxErrno::EAGAIN as isize;
+                return Err(LinuxErrno::EAGAIN);
             }
-            // wake val tasks
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            // requeue val2 tasks to uaddr2
-            let res2 = FUTEX_WAITER.lock().requeue(uaddr2, val2, uaddr);
-            if res2.is_err() {
-                return LinuxErrno::EINVAL as isize;
+            let key2 = FutexKey::new(task, uaddr2, private)?;
+            futex_requeue(key, val, val2, key2)
+        }
+        FUTEX_REQUEUE => {
+            let key2 = FutexKey::new(task, uaddr2, private)?;
+            futex_requeue(key, val, val2, key2)
+        }
+        FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+            let bitset = if cmd == FUTEX_WAKE {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            if bitset == 0 {
+                return Err(LinuxErrno::EINVAL);
             }
-            return res2.unwrap() as isize + res.unwrap() as isize;
+            let res = FUTEX_WAITER.lock().wake_bitset(key, val as usize, bitset);
+            Ok(res as isize)
         }
-        FutexOp::FutexRequeuePrivate => {
-            // wake val tasks
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
+        FUTEX_WAKE_OP => {
+            if uaddr2 % size_of::<u32>() != 0 {
+                return Err(LinuxErrno::EINVAL);
             }
-            // requeue val2 tasks to uaddr2
-            let res2 = FUTEX_WAITER.lock().requeue(uaddr2, val2, uaddr);
-            if res2.is_err() {
-                return LinuxErrno::EINVAL as isize;
+            let key2 = FutexKey::new(task, uaddr2, private)?;
+            let word = task
+                .access_inner()
+                .user_ref_mut(UserPtr::<AtomicU32>::new(uaddr2))?;
+            // 持有等待队列的锁，保证原子操作与唤醒之间不会有新的等待者加入
+            let mut futex_waiter = FUTEX_WAITER.lock();
+            let cond = futex_atomic_op(val3, &word)?;
+            let mut res = futex_waiter.wake_bitset(key, val as usize, FUTEX_BITSET_MATCH_ANY);
+            if cond {
+                res += futex_waiter.wake_bitset(key2, val2, FUTEX_BITSET_MATCH_ANY);
             }
-            return res2.unwrap() as isize + res.unwrap() as isize;
+            Ok(res as isize)
         }
-        FutexOp::FutexWakePrivate | FutexOp::FutexWake => {
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res.unwrap() as isize;
+        FUTEX_LOCK_PI | FUTEX_TRYLOCK_PI | FUTEX_UNLOCK_PI | FUTEX_CMP_REQUEUE_PI => {
+            // 调度器没有优先级，无法提升锁持有者的优先级，不提供优先级继承 futex
+            Err(LinuxErrno::ENOSYS)
         }
         _ => {
-            panic!("futex: unimplemented futex_op: {:?}", futex_op);
-            // return LinuxErrno::EINVAL as isize;
+            warn!("futex: unimplemented futex_op: {:?}", futex_op);
+            Err(LinuxErrno::ENOSYS)
         }
     }
-    0
+}
+
+/// 使当前进程以 `bitset` 等待在 `uaddr` 上，直到被唤醒或超时。`key` 为 `uaddr` 对应的 futex 键。
+/// 等待超时返回 `ETIMEDOUT`；等待被信号打断返回 `EINTR`。
+///
+/// 比较 `uaddr` 上的值与加入等待队列在持有等待队列锁的情况下进行，避免丢失在两者之间发生的唤醒。
+fn futex_wait(
+    task: &Arc<Task>,
+    uaddr: usize,
+    key: FutexKey,
+    val: u32,
+    wait_time: Option<usize>,
+    bitset: u32,
+) -> AlienResult<isize> {
+    // 等待只需要读取 futex 字，只读的映射中的 futex 同样可以等待
+    let uaddr_atomic = task
+        .access_inner()
+        .user_ref(UserPtr::<AtomicI32>::new(uaddr))?;
+    let timeout_flag = Arc::new(Mutex::new(false));
+    {
+        let mut futex_waiter = FUTEX_WAITER.lock();
+        if uaddr_atomic.load(Ordering::SeqCst) != val as i32 {
+            error!("FutexWait: uaddr_ref != val");
+            return Err(LinuxErrno::EAGAIN);
+        }
+        // add to wait queue
+        let waiter = FutexWaiter::new(task.clone(), wait_time, timeout_flag.clone(), bitset);
+        futex_waiter.add_waiter(key, waiter);
+    }
+    // 等待期间不需要访问 futex 字，解除对其所在页的固定
+    drop(uaddr_atomic);
+    // switch to other task
+    task.update_state(TaskState::Waiting);
+    warn!("Because of futex, we switch to other task");
+    schedule();
+    if *timeout_flag.lock() {
+        return Err(LinuxErrno::ETIMEDOUT);
+    }
+    if task.access_inner().signal_receivers.lock().have_signal() {
+        return Err(LinuxErrno::EINTR);
+    }
+    Ok(0)
+}
+
+/// 唤醒至多 `val` 个在 key 上等待的进程后，将原来等待在 key 上至多 `num` 个进程转移到 key2 上等待
+///
+/// 唤醒与转移在同一次持有等待队列锁的过程中完成；key 上没有等待者时两者均为 0。
+fn futex_requeue(key: FutexKey, val: u32, num: usize, key2: FutexKey) -> AlienResult<isize> {
+    let mut futex_waiter = FUTEX_WAITER.lock();
+    // wake val tasks
+    let res = futex_waiter.wake_bitset(key, val as usize, FUTEX_BITSET_MATCH_ANY);
+    // requeue val2 tasks to uaddr2
+    let res2 = futex_waiter.requeue(key2, num, key);
+    Ok(res2 as isize + res as isize)
 }
 
 /// 一个系统调用，用于设置当前进程的 robust 锁的列表头。robust 锁主要是解决当一个持有互斥锁的线程退出之后这个锁成为不可用状态的问题。
@@ -240,21 +313,32 @@ pub fn set_robust_list(head: usize, len: usize) -> isize {
 
 /// 一个系统调用，用于获取某进程的 robust 锁的列表头。robust 锁主要是解决当一个持有互斥锁的线程退出之后这个锁成为不可用状态的问题。
 ///
-/// `pid` 指明了要获取相关信息的进程号；`head_ptr` 指明了获取信息后保存的位置；`len_ptr` 指明了获取列表长度信息后保存的位置。
+/// `pid` 指明了要获取相关信息的线程号，为 0 时表示当前线程；`head_ptr` 指明了获取信息后保存的位置；`len_ptr` 指明了获取列表长度信息后保存的位置。
 ///
-/// 当 `pid` 的值为 0 时，将会导致函数 panic；当函数正确执行时，返回 0。
+/// 找不到 `pid` 对应的线程时返回 `ESRCH`；没有权限访问目标线程(与 ptrace 的权限检查相同)时返回 `EPERM`；
+/// `head_ptr` 或 `len_ptr` 无效时返回 `EFAULT`；当函数正确执行时，返回 0。
+///
+/// Reference: [get_robust_list](https://man7.org/linux/man-pages/man2/get_robust_list.2.html)
 #[syscall_func(100)]
-pub fn get_robust_list(pid: usize, head_ptr: usize, len_ptr: usize) -> isize {
-    assert_eq!(pid, 0);
+pub fn get_robust_list(pid: usize, head_ptr: usize, len_ptr: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let task_inner = task.access_inner();
-    let head = task_inner.robust.head;
+    let head = if pid == 0 {
+        task.access_inner().robust.head
+    } else {
+        let target = pid_from_user(pid)
+            .and_then(find_task)
+            .ok_or(LinuxErrno::ESRCH)?;
+        let cred = task.access_inner().cred.clone();
+        let target_inner = target.access_inner();
+        if !may_trace(&cred, &target_inner.cred) {
+            return Err(LinuxErrno::EPERM);
+        }
+        target_inner.robust.head
+    };
     let len = RobustList::HEAD_SIZE;
-    let head_ref = task_inner.transfer_raw_ptr_mut(head_ptr as *mut usize);
-    let len_ref = task_inner.transfer_raw_ptr_mut(len_ptr as *mut usize);
-    *head_ref = head;
-    *len_ref = len;
-    0
+    task.write_user(UserPtr::<usize>::new(head_ptr), &head)?;
+    task.write_user(UserPtr::<usize>::new(len_ptr), &len)?;
+    Ok(0)
 }
 
 /// 唤醒所有当前正在等待 futex 但因为超时或者信号而需要被唤醒的进程
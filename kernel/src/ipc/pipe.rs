@@ -19,12 +19,13 @@ use core::{
     sync::atomic::AtomicUsize,
 };
 
-use config::PIPE_BUF;
+use config::{FRAME_SIZE, PIPE_BUF};
 use constants::{
     io::{OpenFlags, PollEvents, SeekFrom},
     AlienResult, LinuxErrno,
 };
 use ksync::Mutex;
+use mem::{alloc_frame_trackers, FrameTracker};
 use vfs::{
     kfile::File,
     pipefs::{PipeFsDirInodeImpl, PIPE_FS_ROOT},
@@ -75,14 +76,14 @@ impl PipeFile {
 }
 
 /// create a pipe file
-pub fn make_pipe_file() -> VfsResult<(Arc<PipeFile>, Arc<PipeFile>)> {
+pub fn make_pipe_file() -> AlienResult<(Arc<PipeFile>, Arc<PipeFile>)> {
     let root = PIPE_FS_ROOT.get().unwrap();
     let root_inode = root
         .inode()?
         .downcast_arc::<PipeFsDirInodeImpl>()
         .map_err(|_| VfsError::Invalid)
         .unwrap();
-    let inode = Arc::new(PipeInode::new());
+    let inode = Arc::new(PipeInode::new().ok_or(LinuxErrno::ENOMEM)?);
     let num_str = PIPE.fetch_add(1, core::sync::atomic::Ordering::AcqRel);
     let same_inode =
         root_inode.add_file_manually(&num_str.to_string(), inode.clone(), "rw-rw-rw-".into())?;
@@ -150,8 +151,8 @@ pub struct PipeInode {
 }
 
 struct PipeInodeData {
-    /// 缓冲区的数据部分
-    pub buf: [u8; PIPE_BUF],
+    /// 缓冲区的数据部分，直接使用物理页，避免占用内核堆
+    pub buf: FrameTracker,
     /// 缓冲区头部，用于指明当前的读位置
     pub head: usize,
     /// 缓冲区尾部，用于指明当前的写位置
@@ -224,17 +225,18 @@ impl PipeInodeData {
 }
 
 impl PipeInode {
-    /// 创建一片新的管道缓冲区，在 `Pipe::new` 中被调用
-    pub fn new() -> PipeInode {
-        PipeInode {
+    /// 创建一片新的管道缓冲区，在 `Pipe::new` 中被调用。物理页不足时返回 `None`
+    pub fn new() -> Option<PipeInode> {
+        let buf = alloc_frame_trackers(PIPE_BUF / FRAME_SIZE)?;
+        Some(PipeInode {
             data: Mutex::new(PipeInodeData {
-                buf: [0; PIPE_BUF],
+                buf,
                 head: 0,
                 tail: 0,
                 read_wait: None,
                 write_wait: None,
             }),
-        }
+        })
     }
 
     pub fn set_reader(&self, reader: &Arc<PipeFile>) {
@@ -144,10 +144,10 @@ pub fn shmget(key: usize, size: usize, shmflg: u32) -> isize {
         info!("create new share memory {}", key);
         // alloc frames
         let frames = alloc_frame_trackers(align_down_4k(size) / FRAME_SIZE);
-        // if frames.is_none() {
-        //     return LinuxErrno::ENOMEM as isize;
-        // }
-        // let frames = frames.unwrap();
+        if frames.is_none() {
+            return LinuxErrno::ENOMEM as isize;
+        }
+        let frames = frames.unwrap();
         let share_mem = ShmMemory::new(frames);
         shm_memory.insert(key, share_mem);
         return key as isize;
@@ -187,11 +187,16 @@ pub fn shmat(shmid: usize, shmaddr: usize, shmflg: u32) -> AlienResult<isize> {
     assert_eq!(shmaddr, 0);
     // we must find a place to map
     let task = current_task().unwrap();
-    let free_map = task.access_inner().mmap.alloc(shm.len());
+    let free_map = task
+        .access_inner()
+        .mmap
+        .alloc(shm.len())
+        .ok_or(LinuxErrno::ENOMEM)?;
     // map to va
     error!("shm map range:{:#x?}", free_map);
     shm.access_inner().state = ShmMemoryState::Used;
     let mut task_inner = task.access_inner();
+    task_inner.mmap.reserve_range(free_map.clone());
     let mut address_space = task_inner.address_space.lock();
     let size = shm.len();
     let start_phy = shm.access_inner().frames.start();
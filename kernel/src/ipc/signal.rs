@@ -7,43 +7,281 @@ use core::mem::size_of;
 
 use constants::{
     signal::{
-        SigAction, SigActionDefault, SigActionFlags, SigInfo, SigProcMaskHow, SignalNumber,
-        SignalReceivers, SignalUserContext, SimpleBitSet,
+        SigAction, SigActionDefault, SigActionFlags, SigProcMaskHow, SignalNumber, SignalReceivers,
+        SignalUserContext, SimpleBitSet,
     },
-    LinuxErrno,
+    AlienResult, LinuxErrno,
 };
 use ksync::Mutex;
 use syscall_table::syscall_func;
 use timer::{read_timer, TimeSpec};
 
-use crate::task::{current_task, do_exit, do_suspend};
+use crate::{
+    ipc::sigqueue::*,
+    mm::uaccess::UserPtr,
+    task::{
+        current_task, do_coredump, do_exit_by_signal, do_suspend, find_task, pid_for_receiver,
+        pid_from_user, pidfd_task, ptrace_kill_wakeup, ptrace_signal_stop, RLimitRes,
+    },
+};
 
-/// 记录每个线程的信号量，从 tid 获取信号相关信息
-static TID2SIGNALS: Mutex<BTreeMap<usize, Arc<Mutex<SignalReceivers>>>> =
+/// 记录每个线程所属的进程以及信号量，从 tid 获取信号相关信息
+static TID2SIGNALS: Mutex<BTreeMap<usize, (usize, Arc<Mutex<SignalReceivers>>)>> =
     Mutex::new(BTreeMap::new());
 
+/// 线程和进程的待处理信号队列
+struct PendingTable {
+    /// 发送给某个线程的信号，以 tid 为键
+    threads: BTreeMap<usize, SigPending>,
+    /// 发送给整个进程的信号，以 pid 为键，可以由进程中任一线程处理
+    processes: BTreeMap<usize, SigPending>,
+}
+
+/// 当前正在备用栈上执行信号处理函数
+pub const SS_ONSTACK: i32 = 1;
+/// 禁用备用栈
+pub const SS_DISABLE: i32 = 2;
+/// 备用栈的最小大小
+pub const MINSIGSTKSZ: usize = 2048;
+/// sigaction 的标志位，信号处理函数在通过 sigaltstack 设置的备用栈上执行
+const SA_ONSTACK: usize = 0x0800_0000;
+
+/// 信号处理的备用栈，与 Linux 中的 `stack_t` 结构相同
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SignalStack {
+    /// 备用栈的起始地址
+    pub ss_sp: usize,
+    /// 备用栈的状态，为 0 或 [`SS_DISABLE`]，读取时可能包含 [`SS_ONSTACK`]
+    pub ss_flags: i32,
+    /// 备用栈的大小
+    pub ss_size: usize,
+}
+
+impl Default for SignalStack {
+    fn default() -> Self {
+        Self {
+            ss_sp: 0,
+            ss_flags: SS_DISABLE,
+            ss_size: 0,
+        }
+    }
+}
+
+impl SignalStack {
+    /// 判断 `sp` 是否位于备用栈中
+    pub fn on_stack(&self, sp: usize) -> bool {
+        self.ss_flags & SS_DISABLE == 0 && sp > self.ss_sp && sp <= self.ss_sp + self.ss_size
+    }
+}
+
+static SIG_PENDING: Mutex<PendingTable> = Mutex::new(PendingTable {
+    threads: BTreeMap::new(),
+    processes: BTreeMap::new(),
+});
+
 /// 所有线程初始化时均需要加入表
-pub fn global_register_signals(tid: usize, signals: Arc<Mutex<SignalReceivers>>) {
-    TID2SIGNALS.lock().insert(tid, signals).take();
+pub fn global_register_signals(tid: usize, pid: usize, signals: Arc<Mutex<SignalReceivers>>) {
+    TID2SIGNALS.lock().insert(tid, (pid, signals)).take();
 }
 
-/// 所有线程退出时均需要从表中删除
+/// 所有线程退出时均需要从表中删除，同时丢弃其待处理的信号。进程中最后一个线程退出时，还会丢弃进程的待处理信号。
 pub fn global_logoff_signals(tid: usize) {
-    TID2SIGNALS.lock().remove(&tid).take();
+    let mut tid2signals = TID2SIGNALS.lock();
+    let pid = tid2signals.remove(&tid).map(|(pid, _)| pid);
+    let last_thread = pid.is_some_and(|pid| tid2signals.values().all(|(p, _)| *p != pid));
+    drop(tid2signals);
+    let mut pending = SIG_PENDING.lock();
+    pending.threads.remove(&tid);
+    if last_thread {
+        pending.processes.remove(&pid.unwrap());
+    }
 }
 
 /// 获取信号量。这个函数会复制一个 Arc，不会影响表中的信号本身
 pub fn get_signals_from_tid(tid: usize) -> Option<Arc<Mutex<SignalReceivers>>> {
-    TID2SIGNALS.lock().get(&tid).map(|s| s.clone())
+    TID2SIGNALS.lock().get(&tid).map(|(_, s)| s.clone())
+}
+
+/// 判断进程 pid 是否还有线程存在
+pub fn process_exists(pid: usize) -> bool {
+    TID2SIGNALS.lock().values().any(|(p, _)| *p == pid)
 }
 
-/// 发送一个信号给进程 tid
+/// 由内核发送一个信号给线程 tid
 pub fn send_signal(tid: usize, signum: usize) {
-    if let Some(signals) = get_signals_from_tid(tid) {
-        // 获取目标线程(可以是自己)的 signals 数组
-        warn!("send signal {:?} to {}", SignalNumber::from(signum), tid);
-        signals.lock().try_add_bit(signum);
+    let _ = send_signal_info(tid, SignalInfo::kernel(signum));
+}
+
+/// 由内核发送一个带有完整信息的信号给线程 tid
+pub fn send_signal_info(tid: usize, info: SignalInfo) -> AlienResult<()> {
+    queue_thread_signal(tid, info, usize::MAX)
+}
+
+/// 向当前线程发送因执行指令出错而产生的同步信号
+///
+/// 重新执行出错的指令只会再次出错，因此与 Linux 的 `force_sig_fault` 相同，信号被阻塞或者被忽略而无法递送时，
+/// 直接以该信号终止当前进程并生成 core 文件。
+pub fn force_sig_fault(info: SignalInfo) {
+    let task = current_task().unwrap();
+    let signum = info.signum();
+    let undeliverable = {
+        let inner = task.access_inner();
+        let blocked = inner.signal_receivers.lock().mask.bits() & (1 << signum) != 0;
+        let ignored = inner
+            .signal_handlers
+            .lock()
+            .get_action_ref(signum)
+            .map_or(false, |action| action.is_ignore());
+        blocked || ignored
+    };
+    if undeliverable {
+        terminate_by_signal(signum);
+        return;
     }
+    let _ = send_signal_info(task.get_tid() as usize, info);
+}
+
+/// 判断信号是否是因执行指令出错而产生的同步信号，由 kill 等发送的同名信号不属于同步信号
+fn is_sync_fault(info: &SignalInfo) -> bool {
+    let sig = SignalNumber::from(info.signum());
+    matches!(
+        sig,
+        SignalNumber::SIGSEGV | SignalNumber::SIGBUS | SignalNumber::SIGILL | SignalNumber::SIGFPE
+    ) && info.si_code > 0
+        && info.si_code < SI_KERNEL
+}
+
+/// 由内核发送一个带有完整信息的信号给进程 pid
+pub fn send_process_signal_info(pid: usize, info: SignalInfo) -> AlienResult<()> {
+    queue_process_signal(pid, info, usize::MAX)
+}
+
+/// 将信号加入线程 tid 的待处理队列，并设置其信号位
+///
+/// `limit` 为目标队列中允许排队的实时信号数量。线程不存在时返回 `ESRCH`。
+fn queue_thread_signal(tid: usize, info: SignalInfo, limit: usize) -> AlienResult<()> {
+    let signals = get_signals_from_tid(tid).ok_or(LinuxErrno::ESRCH)?;
+    SIG_PENDING
+        .lock()
+        .threads
+        .entry(tid)
+        .or_default()
+        .push(info, limit)?;
+    // 获取目标线程(可以是自己)的 signals 数组
+    warn!(
+        "send signal {:?} to {}",
+        SignalNumber::from(info.signum()),
+        tid
+    );
+    signals.lock().try_add_bit(info.signum());
+    if info.signum() == SignalNumber::SIGKILL as usize {
+        ptrace_kill_wakeup(tid);
+    }
+    Ok(())
+}
+
+/// 将信号加入进程 pid 的待处理队列，并交给进程中的一个线程处理
+///
+/// 优先选择没有屏蔽该信号的线程，其次选择主线程。进程不存在时返回 `ESRCH`。
+fn queue_process_signal(pid: usize, info: SignalInfo, limit: usize) -> AlienResult<()> {
+    let threads: Vec<(usize, Arc<Mutex<SignalReceivers>>)> = TID2SIGNALS
+        .lock()
+        .iter()
+        .filter(|(_, (p, _))| *p == pid)
+        .map(|(tid, (_, signals))| (*tid, signals.clone()))
+        .collect();
+    if threads.is_empty() {
+        return Err(LinuxErrno::ESRCH);
+    }
+    SIG_PENDING
+        .lock()
+        .processes
+        .entry(pid)
+        .or_default()
+        .push(info, limit)?;
+    let signum = info.signum();
+    let (tid, signals) = threads
+        .iter()
+        .find(|(_, signals)| signals.lock().mask.bits() & (1 << signum) == 0)
+        .or_else(|| threads.iter().find(|(tid, _)| *tid == pid))
+        .unwrap_or(&threads[0]);
+    warn!(
+        "send signal {:?} to process {} (thread {})",
+        SignalNumber::from(signum),
+        pid,
+        tid
+    );
+    signals.lock().try_add_bit(signum);
+    if signum == SignalNumber::SIGKILL as usize {
+        // SIGKILL 终止整个进程，处于 ptrace 停止状态的线程都需要被唤醒
+        threads.iter().for_each(|(tid, _)| ptrace_kill_wakeup(*tid));
+    }
+    Ok(())
+}
+
+/// 取出线程 tid(属于进程 pid) 的一个编号为 `signum` 的待处理信号的信息
+///
+/// 先从线程的队列中查找，再从进程的队列中查找；队列中没有记录时返回一个由内核发送的信号的信息。
+/// 若取出后仍有同一信号的实例在排队，会重新设置 `receivers` 中的信号位，使其之后被继续处理。
+pub fn dequeue_signal(
+    tid: usize,
+    pid: usize,
+    signum: usize,
+    receivers: &mut SignalReceivers,
+) -> SignalInfo {
+    let mut pending = SIG_PENDING.lock();
+    let info = pending
+        .threads
+        .get_mut(&tid)
+        .and_then(|queue| queue.take(signum))
+        .or_else(|| {
+            pending
+                .processes
+                .get_mut(&pid)
+                .and_then(|queue| queue.take(signum))
+        });
+    let remaining = pending
+        .threads
+        .get(&tid)
+        .is_some_and(|queue| queue.contains(signum))
+        || pending
+            .processes
+            .get(&pid)
+            .is_some_and(|queue| queue.contains(signum));
+    drop(pending);
+    if remaining {
+        receivers.try_add_bit(signum);
+    }
+    info.unwrap_or_else(|| SignalInfo::kernel(signum))
+}
+
+/// 线程 tid(属于进程 pid) 是否有待处理的 SIGKILL，用于在内核中等待时及时退出
+pub fn sigkill_pending(tid: usize, pid: usize) -> bool {
+    let signum = SignalNumber::SIGKILL as usize;
+    let pending = SIG_PENDING.lock();
+    pending
+        .threads
+        .get(&tid)
+        .is_some_and(|queue| queue.contains(signum))
+        || pending
+            .processes
+            .get(&pid)
+            .is_some_and(|queue| queue.contains(signum))
+}
+
+/// 以当前进程作为发送者构造一个发送给全局 tid 为 `receiver` 的任务的信号信息，同时返回当前进程允许排队的实时信号数量
+///
+/// 信号中的 pid 为当前进程在接收者所在的 pid 命名空间中的 pid。
+fn current_sender_info(receiver: usize, signum: usize, code: i32) -> (SignalInfo, usize) {
+    let task = current_task().unwrap();
+    let pid = find_task(receiver).map_or(0, |receiver| {
+        pid_for_receiver(&receiver, task.get_pid() as usize)
+    });
+    let inner = task.access_inner();
+    let info = SignalInfo::user(signum, code, pid, inner.cred.ruid);
+    let limit = inner.get_prlimit(RLimitRes::Sigpending).rlim_cur as usize;
+    (info, limit)
 }
 
 /// 一个系统调用，用于获取或修改与指定信号相关联的处理动作。
@@ -76,23 +314,74 @@ pub fn sigaction(sig: usize, action: usize, old_action: usize) -> isize {
     if !old_action.is_null() {
         let mut tmp = SigAction::empty();
         signal_handler.get_action(sig, &mut tmp);
-        task_inner.copy_to_user(&tmp, old_action);
+        if let Err(e) = task_inner.write_user(UserPtr::from(old_action), &tmp) {
+            return e.into();
+        }
     }
     if !action.is_null() {
-        let mut tmp_action = SigAction::empty();
-        task_inner.copy_from_user(action, &mut tmp_action);
+        let tmp_action = match task_inner.read_user(UserPtr::from(action)) {
+            Ok(action) => action,
+            Err(e) => return e.into(),
+        };
         warn!("sig {:?} action is {:?}", signum, tmp_action);
         signal_handler.set_action(sig, &tmp_action);
     }
     0
 }
 
+/// 一个系统调用，用于获取或设置当前线程执行信号处理函数时使用的备用栈。
+///
+/// 对于设置了 `SA_ONSTACK` 标志的信号，其处理函数将在备用栈上执行。这使得栈溢出导致的 `SIGSEGV` 也能被用户处理。
+///
+/// 参数：
+/// + `ss`: 新的备用栈，详情可见 [`SignalStack`]。当该值为空指针时，不修改备用栈。
+/// + `old_ss`: 指出原备用栈要保存到的位置。当该值为空指针时，不保存原备用栈。
+///
+/// 函数执行成功后返回 0；若当前正在备用栈上执行信号处理函数时试图修改备用栈，返回 `EPERM`；
+/// 若 `ss_flags` 不合法，返回 `EINVAL`；若备用栈的大小小于 [`MINSIGSTKSZ`]，返回 `ENOMEM`。
+///
+/// Reference: [sigaltstack](https://man7.org/linux/man-pages/man2/sigaltstack.2.html)
+#[syscall_func(132)]
+pub fn sigaltstack(ss: usize, old_ss: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let mut task_inner = task.access_inner();
+    let sp = task_inner.trap_frame().regs()[2];
+    let current = task_inner.sig_alt_stack;
+    let on_stack = current.on_stack(sp);
+    if old_ss != 0 {
+        let mut old = current;
+        if on_stack {
+            old.ss_flags = SS_ONSTACK;
+        }
+        task_inner.write_user(UserPtr::<SignalStack>::new(old_ss), &old)?;
+    }
+    if ss != 0 {
+        let mut new = task_inner.read_user(UserPtr::<SignalStack>::new(ss))?;
+        if on_stack {
+            return Err(LinuxErrno::EPERM);
+        }
+        match new.ss_flags {
+            SS_DISABLE => new = SignalStack::default(),
+            // SS_ONSTACK 被视为 0
+            0 | SS_ONSTACK => {
+                if new.ss_size < MINSIGSTKSZ {
+                    return Err(LinuxErrno::ENOMEM);
+                }
+                new.ss_flags = 0;
+            }
+            _ => return Err(LinuxErrno::EINVAL),
+        }
+        task_inner.sig_alt_stack = new;
+    }
+    Ok(0)
+}
+
 /// 一个系统调用，用于使得一个进程在一段时间限制内等待一个信号，并保存信号的相关信息。
 ///
 /// 参数：
 /// + `set`: 用于指明等待的信号集，当进程接收到 `set` 中的任一一种信号时，都会返回。
-/// + `info`: 用于指明保存信号相关信息的位置。 当该值为空时，将不执行保存信号信息的操作。具体可见 [`SigInfo`] 结构。
-/// + `time`: 指明等待的时间。具体可见 [`TimeSpec`] 结构。
+/// + `info`: 用于指明保存信号相关信息的位置。 当该值为空时，将不执行保存信号信息的操作。具体可见 [`SignalInfo`] 结构。
+/// + `time`: 指明等待的时间。具体可见 [`TimeSpec`] 结构。当该值为空指针时，将一直等待。
 ///
 /// 当函数在规定的时间内成功接收到 `set` 中包含的某个信号时，将会返回该信号的序号；
 /// 当函数在规定的时间内未接收到 `set` 中包含的某个信号时，将返回 `EAGAIN` 表示超时；
@@ -110,21 +399,34 @@ pub fn sigtimewait(set: usize, info: usize, time: usize) -> isize {
     let mut target_time = 0;
 
     let task = current_task().unwrap().clone();
-    let mut time_spec = TimeSpec::new(0, 0);
-    task.access_inner()
-        .copy_from_user(time as *const TimeSpec, &mut time_spec);
+    // time 为空指针时一直等待
+    let time_spec = if time == 0 {
+        None
+    } else {
+        match task.read_user(UserPtr::<TimeSpec>::new(time)) {
+            Ok(time_spec) => Some(time_spec),
+            Err(e) => return e.into(),
+        }
+    };
     loop {
         let mut task_inner = task.access_inner();
         let mut signal_receivers = task_inner.signal_receivers.lock();
         for i in 1..64 {
             if set & (1 << i) != 0 {
                 if signal_receivers.check_signal(i) {
+                    let tmp_info = dequeue_signal(
+                        task.get_tid() as usize,
+                        task.get_pid() as usize,
+                        i,
+                        &mut signal_receivers,
+                    );
                     if info != 0 {
-                        let mut tmp_info = SigInfo::default();
-                        tmp_info.si_signo = i as i32;
-                        tmp_info.si_code = 0;
                         drop(signal_receivers);
-                        task_inner.copy_to_user(&tmp_info, info as *mut SigInfo);
+                        if let Err(e) =
+                            task_inner.write_user(UserPtr::<SignalInfo>::new(info), &tmp_info)
+                        {
+                            return e.into();
+                        }
                     }
                     return i as isize;
                 }
@@ -132,22 +434,23 @@ pub fn sigtimewait(set: usize, info: usize, time: usize) -> isize {
         }
 
         // wait time
-        if time_spec.tv_sec == 0 && time_spec.tv_nsec == 0 {
-            return -1;
+        if let Some(time_spec) = time_spec {
+            if time_spec.tv_sec == 0 && time_spec.tv_nsec == 0 {
+                return -1;
+            }
+            if !flag {
+                warn!("sigtimewait: sleep for {:?}", time_spec);
+                let t_time = read_timer() + time_spec.to_clock();
+                target_time = t_time;
+                flag = true;
+            }
+            if read_timer() >= target_time {
+                warn!("sigtimewait: timeout");
+                break;
+            }
         }
         drop(signal_receivers);
         drop(task_inner);
-        if !flag {
-            warn!("sigtimewait: sleep for {:?}", time_spec);
-            let t_time = read_timer() + time_spec.to_clock();
-            target_time = t_time;
-            flag = true;
-        }
-        let now = read_timer();
-        if now >= target_time {
-            warn!("sigtimewait: timeout");
-            break;
-        }
         do_suspend();
         let task = current_task().unwrap();
 
@@ -176,25 +479,31 @@ pub fn sigtimewait(set: usize, info: usize, time: usize) -> isize {
 #[syscall_func(135)]
 pub fn sigprocmask(how: usize, set: usize, oldset: usize, _sig_set_size: usize) -> isize {
     let task = current_task().unwrap();
-    let task_inner = task.access_inner();
-    let mut signal_receivers = task_inner.signal_receivers.lock();
+    let mut task_inner = task.access_inner();
+    let signal_receivers = task_inner.signal_receivers.clone();
+    let mut signal_receivers = signal_receivers.lock();
     if oldset != 0 {
-        let set_mut = task_inner.transfer_raw_ptr_mut(oldset as *mut usize);
-        *set_mut = signal_receivers.mask.bits();
+        let old = signal_receivers.mask.bits();
+        if let Err(e) = task_inner.write_user(UserPtr::<usize>::new(oldset), &old) {
+            return e.into();
+        }
     }
     let how = SigProcMaskHow::from(how);
     warn!("sigprocmask: how: {:?}, set: {:x}", how, set);
     if set != 0 {
-        let set = task_inner.transfer_raw_ptr(set as *const usize);
+        let set = match task_inner.read_user(UserPtr::<usize>::new(set)) {
+            Ok(set) => set,
+            Err(e) => return e.into(),
+        };
         match how {
             SigProcMaskHow::SigBlock => {
-                signal_receivers.mask += SimpleBitSet::from(*set);
+                signal_receivers.mask += SimpleBitSet::from(set);
             }
             SigProcMaskHow::SigUnblock => {
-                signal_receivers.mask -= SimpleBitSet::from(*set);
+                signal_receivers.mask -= SimpleBitSet::from(set);
             }
             SigProcMaskHow::SigSetMask => {
-                signal_receivers.mask = SimpleBitSet::from(*set);
+                signal_receivers.mask = SimpleBitSet::from(set);
             }
             SigProcMaskHow::Unknown => {
                 return LinuxErrno::EINVAL as isize;
@@ -207,29 +516,46 @@ pub fn sigprocmask(how: usize, set: usize, oldset: usize, _sig_set_size: usize)
 }
 
 /// 一个系统调用函数，向 `pid` 指定的进程发送信号。
-/// 如果进程中有多个线程，则会发送给任意一个未阻塞的线程。
+/// 如果进程中有多个线程，则会发送给任意一个未阻塞的线程。信号的 `si_pid` 与 `si_uid` 为当前进程在接收者的 pid 命名空间中的 pid 与真实用户 id。
 ///
 /// pid 有如下情况
 /// 1. pid > 0，则发送给指定进程
 /// 2. pid = 0，则发送给所有同组进程
 /// 3. pid = -1，则发送给除了初始进程(pid=1)外的所有当前进程有权限的进程
-/// 4. pid < -2，则发送给组内 pid 为参数相反数的进程
+/// 4. pid < -1，则发送给进程组 id 为参数相反数的进程组中的所有进程
 ///
-/// 目前 2/3/4 未实现。对于 1，仿照 zCore 的设置，认为**当前进程自己或其直接子进程** 是"有权限"或者"同组"的进程。
+/// 目前 2/3/4 未实现。Alien 中还没有进程组，pid = 0 或 pid < -1 时找不到目标进程组，返回 `ESRCH`；pid = -1 时返回 `EINVAL`。对于 1，仿照 zCore 的设置，认为**当前进程自己或其直接子进程** 是"有权限"或者"同组"的进程。
 ///  
+/// 当 `sig` 为 0 时只检查进程是否存在。
+///
 /// 目前如果函数成功执行后会返回0；否则返回错误类型。
 ///
 /// Reference: [kill](https://man7.org/linux/man-pages/man2/kill.2.html)
 #[syscall_func(129)]
 pub fn kill(pid: usize, sig: usize) -> isize {
     warn!("kill pid {}, signal id {:?}", pid, SignalNumber::from(sig));
-    if pid > 0 {
+    if sig >= SIGNAL_MAX {
+        return LinuxErrno::EINVAL as isize;
+    }
+    if (pid as isize) > 0 {
         //println!("kill pid {}, signal id {}", pid, signal_id);
-        if sig > 0 {
-            send_signal(pid, sig);
+        let pid = match pid_from_user(pid) {
+            Some(pid) => pid,
+            None => return LinuxErrno::ESRCH as isize,
+        };
+        if sig == 0 {
+            return if process_exists(pid) {
+                0
+            } else {
+                LinuxErrno::ESRCH as isize
+            };
         }
-        0
-    } else if pid == 0 {
+        let (info, limit) = current_sender_info(pid, sig, SI_USER);
+        match queue_process_signal(pid, info, limit) {
+            Ok(()) => 0,
+            Err(e) => e as isize,
+        }
+    } else if pid == 0 || (pid as isize) < -1 {
         LinuxErrno::ESRCH as isize
     } else {
         // 如果 signal_id == 0，则仅为了检查是否存在对应进程，此时应该返回参数错误。是的，用户库是会刻意触发这个错误的
@@ -245,16 +571,151 @@ pub fn kill(pid: usize, sig: usize) -> isize {
 #[syscall_func(130)]
 pub fn tkill(tid: usize, sig: usize) -> isize {
     warn!("tkill tid {}, signal id {:?}", tid, SignalNumber::from(sig));
-    if tid > 0 && sig > 0 {
+    if tid > 0 && sig > 0 && sig < SIGNAL_MAX {
         //println!("kill pid {}, signal id {}", pid, signal_id);
-        send_signal(tid, sig);
-        0
+        let tid = match pid_from_user(tid) {
+            Some(tid) => tid,
+            None => return LinuxErrno::ESRCH as isize,
+        };
+        let (info, limit) = current_sender_info(tid, sig, SI_TKILL);
+        match queue_thread_signal(tid, info, limit) {
+            Ok(()) => 0,
+            Err(e) => e as isize,
+        }
     } else {
         // 如果 signal_id == 0，则仅为了检查是否存在对应进程，此时应该返回参数错误。是的，用户库是会刻意触发这个错误的
         LinuxErrno::EINVAL as isize
     }
 }
 
+/// 一个系统调用函数，向进程 `tgid` 中的线程 `tid` 发送信号。
+///
+/// 线程不存在或不属于进程 `tgid` 时返回 `ESRCH`；`sig` 为 0 时只检查线程是否存在。
+///
+/// Reference: [tgkill](https://man7.org/linux/man-pages/man2/tgkill.2.html)
+#[syscall_func(131)]
+pub fn tgkill(tgid: usize, tid: usize, sig: usize) -> AlienResult<isize> {
+    warn!(
+        "tgkill tgid {}, tid {}, signal id {:?}",
+        tgid,
+        tid,
+        SignalNumber::from(sig)
+    );
+    if (tgid as isize) <= 0 || (tid as isize) <= 0 || sig >= SIGNAL_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let (tgid, tid) = thread_from_user(tgid, tid)?;
+    check_thread_group(tgid, tid)?;
+    if sig == 0 {
+        return Ok(0);
+    }
+    let (info, limit) = current_sender_info(tid, sig, SI_TKILL);
+    queue_thread_signal(tid, info, limit)?;
+    Ok(0)
+}
+
+/// 将当前进程所在的 pid 命名空间中的 `tgid` 与 `tid` 转换为全局的 tid，在其中不可见时返回 `ESRCH`
+fn thread_from_user(tgid: usize, tid: usize) -> AlienResult<(usize, usize)> {
+    let tgid = pid_from_user(tgid).ok_or(LinuxErrno::ESRCH)?;
+    let tid = pid_from_user(tid).ok_or(LinuxErrno::ESRCH)?;
+    Ok((tgid, tid))
+}
+
+/// 检查线程 tid 是否属于进程 tgid，不属于或线程不存在时返回 `ESRCH`
+fn check_thread_group(tgid: usize, tid: usize) -> AlienResult<()> {
+    match TID2SIGNALS.lock().get(&tid) {
+        Some((pid, _)) if *pid == tgid => Ok(()),
+        _ => Err(LinuxErrno::ESRCH),
+    }
+}
+
+/// 从用户空间读取 rt_sigqueueinfo 与 rt_tgsigqueueinfo 传入的信号信息
+///
+/// 向其它进程发送信号时，`si_code` 必须为负数(即伪装成由 sigqueue 等用户接口发送)，否则返回 `EPERM`。
+fn user_sigqueue_info(target_pid: usize, sig: usize, uinfo: usize) -> AlienResult<SignalInfo> {
+    if sig == 0 || sig >= SIGNAL_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut info = task.read_user(UserPtr::<SignalInfo>::new(uinfo))?;
+    if (info.si_code >= 0 || info.si_code == SI_TKILL) && target_pid != task.get_pid() as usize {
+        return Err(LinuxErrno::EPERM);
+    }
+    info.si_signo = sig as i32;
+    Ok(info)
+}
+
+/// 一个系统调用函数，向进程 `tgid` 发送信号 `sig`，并携带由 `uinfo` 指向的信号信息。
+///
+/// 与 [`kill`] 不同，实时信号的每次发送都会单独排队，其附带的 `si_value` 可以由接收者通过 `SA_SIGINFO` 处理函数或 [`sigtimewait`] 获取。
+/// 排队的实时信号数量超过 `RLIMIT_SIGPENDING` 时返回 `EAGAIN`。
+///
+/// Reference: [rt_sigqueueinfo](https://man7.org/linux/man-pages/man2/rt_sigqueueinfo.2.html)
+#[syscall_func(138)]
+pub fn rt_sigqueueinfo(tgid: usize, sig: usize, uinfo: usize) -> AlienResult<isize> {
+    let tgid = pid_from_user(tgid).ok_or(LinuxErrno::ESRCH)?;
+    let info = user_sigqueue_info(tgid, sig, uinfo)?;
+    let task = current_task().unwrap();
+    let limit = task
+        .access_inner()
+        .get_prlimit(RLimitRes::Sigpending)
+        .rlim_cur as usize;
+    queue_process_signal(tgid, info, limit)?;
+    Ok(0)
+}
+
+/// 一个系统调用函数，向进程 `tgid` 中的线程 `tid` 发送信号 `sig`，并携带由 `uinfo` 指向的信号信息。
+///
+/// 参数的限制与 [`rt_sigqueueinfo`] 相同；线程不存在或不属于进程 `tgid` 时返回 `ESRCH`。
+///
+/// Reference: [rt_tgsigqueueinfo](https://man7.org/linux/man-pages/man2/rt_tgsigqueueinfo.2.html)
+#[syscall_func(240)]
+pub fn rt_tgsigqueueinfo(tgid: usize, tid: usize, sig: usize, uinfo: usize) -> AlienResult<isize> {
+    let (tgid, tid) = thread_from_user(tgid, tid)?;
+    let info = user_sigqueue_info(tgid, sig, uinfo)?;
+    check_thread_group(tgid, tid)?;
+    let task = current_task().unwrap();
+    let limit = task
+        .access_inner()
+        .get_prlimit(RLimitRes::Sigpending)
+        .rlim_cur as usize;
+    queue_thread_signal(tid, info, limit)?;
+    Ok(0)
+}
+
+/// 一个系统调用函数，向 `pidfd` 所指向的进程发送信号 `sig`。
+///
+/// 与 [`kill`] 不同，pidfd 始终指向创建它时的进程，不会因为 pid 被重新分配而将信号发送给其它进程。
+/// `uinfo` 不为空时携带其指向的信号信息，限制与 [`rt_sigqueueinfo`] 相同；`flags` 目前必须为 0。
+/// `pidfd` 不是 pidfd 时返回 `EBADF`，进程已经退出时返回 `ESRCH`；`sig` 为 0 时只检查进程是否存在。
+///
+/// Reference: [pidfd_send_signal](https://man7.org/linux/man-pages/man2/pidfd_send_signal.2.html)
+#[syscall_func(424)]
+pub fn pidfd_send_signal(
+    pidfd: usize,
+    sig: usize,
+    uinfo: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    if flags != 0 || sig >= SIGNAL_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let pid = pidfd_task(pidfd)?.get_pid() as usize;
+    if sig == 0 {
+        return if process_exists(pid) {
+            Ok(0)
+        } else {
+            Err(LinuxErrno::ESRCH)
+        };
+    }
+    let (mut info, limit) = current_sender_info(pid, sig, SI_USER);
+    if uinfo != 0 {
+        info = user_sigqueue_info(pid, sig, uinfo)?;
+    }
+    queue_process_signal(pid, info, limit)?;
+    Ok(0)
+}
+
 /// 一个系统调用函数，用于在用户态执行完信号处理函数后重新装回原 trap 上下文，一般不会被用户态程序调用。函数返回原 trap 上下文的 a0。
 #[syscall_func(139)]
 pub fn signal_return() -> isize {
@@ -274,9 +735,10 @@ pub fn signal_return() -> isize {
 /// 当进入 `signal_handler` 后，对于该进程 `signal_receivers` 下所有信号种类开始遍历：
 /// 先检查此种信号是否满足上面所有的前提，如果有一项以上不满足，直接continue;
 /// 否则需要根据该信号是否已经设置非默认的处理函数进行接下来的操作。
+/// 被跟踪的进程在处理信号之前先进入信号递送停止，由跟踪者决定实际递送的信号，详见 [`ptrace_signal_stop`]。
 ///
 /// + 对于一些固定采用采用默认信号处理方式的信号，或由于未设置其它信号处理函数的信号，仍然使用默认信号处理方式，Alien 中采用 [`SigActionDefault`] 对该信号进行判定：
-///     + 如果属于 `Terminate` 类型，将导致进程终止。
+///     + 如果属于 `Terminate` 类型，将导致进程终止。SIGSEGV、SIGABRT 等信号终止进程前还会生成 core 文件，详见 [`do_coredump`]。
 ///     + 如果属于 `Ignore` 类型，进程将直接忽略该信号。
 /// + 如果进程已经设置过信号处理函数，由于信号处理函数的位置位于用户虚拟内存空间，需要回到用户态下进行信号处理函数的执行，
 /// 但由于原来在用户态下我们还保存有一个 trap 上下文，因此我们需要记录这个 trap 上下文，同时将设计好的新的执行信号处理函数的上下文转移至原trap上下文的位置，
@@ -287,21 +749,50 @@ pub fn signal_return() -> isize {
 pub fn signal_handler() {
     let task = current_task().unwrap();
     let mut task_inner = task.access_inner();
-    let receiver = task_inner.signal_receivers.clone();
-    let mut receiver = receiver.lock();
-    let handler = task_inner.signal_handlers.clone();
-    let handler = handler.lock();
-    if let Some(signum) = receiver.get_one_signal() {
+    let receivers = task_inner.signal_receivers.clone();
+    let mut receiver = receivers.lock();
+    let handlers = task_inner.signal_handlers.clone();
+    let mut handler = handlers.lock();
+    if let Some(mut signum) = receiver.get_one_signal() {
+        let mut siginfo = dequeue_signal(
+            task.get_tid() as usize,
+            task.get_pid() as usize,
+            signum,
+            &mut receiver,
+        );
+        if task_inner.ptrace.is_traced() {
+            // 被跟踪的线程进入信号递送停止，由跟踪者决定实际递送的信号
+            drop(task_inner);
+            drop(handler);
+            drop(receiver);
+            let new_signum = ptrace_signal_stop(task, signum);
+            if new_signum == 0 {
+                return;
+            }
+            if new_signum != signum {
+                signum = new_signum;
+                siginfo = SignalInfo::kernel(signum);
+            }
+            task_inner = task.access_inner();
+            receiver = receivers.lock();
+            handler = handlers.lock();
+        }
         let sig = SignalNumber::from(signum);
         error!("task {:?} receive signal {:?}", task.tid, sig);
         match sig {
-            SignalNumber::SIGSEGV | SignalNumber::SIGBUS => {
-                // we need exit the process
+            SignalNumber::SIGSEGV
+            | SignalNumber::SIGBUS
+            | SignalNumber::SIGILL
+            | SignalNumber::SIGFPE
+                if handler
+                    .get_action_ref(signum)
+                    .map_or(true, |action| action.is_ignore()) =>
+            {
+                // 没有用户处理函数的 SIGSEGV、SIGBUS、SIGILL 与 SIGFPE 无法被忽略，we need exit the process
                 drop(task_inner);
                 drop(handler);
                 drop(receiver);
-                warn!("task {:?} exit by signal {:?}", task.tid, sig);
-                do_exit(-1);
+                terminate_by_signal(signum);
             }
             _ => {
                 if let Some(action) = handler.get_action_ref(signum) {
@@ -310,8 +801,39 @@ pub fn signal_handler() {
                         return;
                     }
                     warn!("find handler for signal {:?}", sig);
+                    // 设置了 SA_ONSTACK 且备用栈可用时，在备用栈上执行处理函数，否则在当前的用户栈上执行
+                    let old_sp = task_inner.trap_frame().regs()[2];
+                    let alt_stack = task_inner.sig_alt_stack;
+                    let stack_top = if action.flags.bits() as usize & SA_ONSTACK != 0
+                        && alt_stack.ss_flags & SS_DISABLE == 0
+                        && !alt_stack.on_stack(old_sp)
+                    {
+                        alt_stack.ss_sp + alt_stack.ss_size
+                    } else {
+                        old_sp
+                    };
+                    let frame_size =
+                        0x200 + size_of::<SignalInfo>() + size_of::<SignalUserContext>() + 0x20;
+                    // 栈溢出时无法在用户栈上放置信号处理的上下文，此时与 Linux 相同，进程被强制终止
+                    if task_inner
+                        .prepare_signal_frame(stack_top - frame_size..stack_top)
+                        .is_err()
+                    {
+                        drop(task_inner);
+                        drop(handler);
+                        drop(receiver);
+                        terminate_by_signal(SignalNumber::SIGSEGV as usize);
+                        return;
+                    }
                     if !task_inner.save_trap_frame() {
                         // we are in signal handler,don't nest
+                        // 处理函数中再次发生的同步异常无法递送，继续执行只会不断出错，此时终止进程
+                        if is_sync_fault(&siginfo) {
+                            drop(task_inner);
+                            drop(handler);
+                            drop(receiver);
+                            terminate_by_signal(signum);
+                        }
                         return;
                     }
                     // save the trap context
@@ -334,27 +856,20 @@ pub fn signal_handler() {
                         old_pc,
                         trap_contex.regs()[2]
                     );
-                    let mut sp = trap_contex.regs()[2] - 0x200; // 128
+                    let mut sp = stack_top - 0x200; // 128
                     if action.flags.contains(SigActionFlags::SA_SIGINFO) {
                         task_inner.signal_set_siginfo = true;
                         // 如果带 SIGINFO，则需要在用户栈上放额外的信息
-                        sp = (sp - size_of::<SigInfo>()) & !0xf;
+                        sp = (sp - size_of::<SignalInfo>()) & !0xf;
                         info!("add siginfo at {:x}", sp);
-                        let mut info = SigInfo::default();
-                        info.si_signo = signum as i32;
-                        unsafe {
-                            let phy_sp = task_inner.transfer_raw(sp);
-                            *(phy_sp as *mut SigInfo) = info;
-                        }
+                        // 信号处理的上下文所在的页已经由 prepare_signal_frame 检查过
+                        let _ = task_inner.write_user(UserPtr::new(sp), &siginfo);
                         // a1 = &siginfo
                         trap_contex.regs()[11] = sp;
                         sp = (sp - size_of::<SignalUserContext>()) & !0xf;
                         info!("add ucontext at {:x}", sp);
-                        unsafe {
-                            let phy_sp = task_inner.transfer_raw(sp);
-                            *(phy_sp as *mut SignalUserContext) =
-                                SignalUserContext::init(receiver.mask.bits() as u64, old_pc);
-                        }
+                        let context = SignalUserContext::init(receiver.mask.bits() as u64, old_pc);
+                        let _ = task_inner.write_user(UserPtr::new(sp), &context);
                         // a2 = &ucontext
                         trap_contex.regs()[12] = sp;
                     }
@@ -376,7 +891,7 @@ pub fn signal_handler() {
                             drop(task_inner);
                             drop(handler);
                             drop(receiver);
-                            do_exit(-1);
+                            terminate_by_signal(signum);
                         }
                         SigActionDefault::Ignore => {
                             // 忽略信号时，要将已保存的上下文删除
@@ -389,6 +904,35 @@ pub fn signal_handler() {
     }
 }
 
+/// 信号的默认处理方式为终止进程并生成 core 文件时返回 true
+fn sig_dumps_core(sig: SignalNumber) -> bool {
+    matches!(
+        sig,
+        SignalNumber::SIGQUIT
+            | SignalNumber::SIGILL
+            | SignalNumber::SIGTRAP
+            | SignalNumber::SIGABRT
+            | SignalNumber::SIGBUS
+            | SignalNumber::SIGFPE
+            | SignalNumber::SIGSEGV
+            | SignalNumber::SIGXCPU
+            | SignalNumber::SIGXFSZ
+            | SignalNumber::SIGSYS
+    )
+}
+
+/// 因信号 `signum` 终止当前线程，信号的默认处理方式包含转储时先生成 core 文件
+fn terminate_by_signal(signum: usize) {
+    let core_dumped = sig_dumps_core(SignalNumber::from(signum)) && do_coredump(signum);
+    warn!(
+        "task {} exit by signal {:?}, core dumped: {}",
+        current_task().unwrap().get_tid(),
+        SignalNumber::from(signum),
+        core_dumped
+    );
+    do_exit_by_signal(signum, core_dumped);
+}
+
 /// 一个系统调用函数，用于阻塞当前进程，等待其他进程传入信号打断阻塞。当进程接收到某种信号时，终止阻塞，函数返回 `EINTR`。
 #[syscall_func(133)]
 pub fn sigsuspend() -> isize {
@@ -0,0 +1,152 @@
+//! 待处理信号队列。
+//!
+//! [`SignalReceivers`](constants::signal::SignalReceivers) 只记录了某种信号是否待处理，无法区分同一信号的多个实例，
+//! 也无法保存发送者的信息。这里为每个线程和每个进程分别维护一个待处理信号队列，队列中的每一项都是一个完整的 [`SignalInfo`]。
+//! 标准信号(小于 [`SIGRTMIN`])在队列中至多存在一个实例，实时信号则按发送顺序排队。
+use alloc::collections::VecDeque;
+
+use constants::{AlienResult, LinuxErrno};
+
+/// 第一个实时信号
+pub const SIGRTMIN: usize = 32;
+/// 信号的上界，合法的信号为 1..SIGNAL_MAX，即 1 到 SIGRTMAX(64)
+pub const SIGNAL_MAX: usize = 65;
+
+/// 由 kill 发送的信号
+pub const SI_USER: i32 = 0;
+/// 由内核发送的信号
+pub const SI_KERNEL: i32 = 0x80;
+/// 由 sigqueue 发送的信号
+pub const SI_QUEUE: i32 = -1;
+/// 由 tkill 或 tgkill 发送的信号
+pub const SI_TKILL: i32 = -6;
+
+/// SIGCHLD: 子进程正常退出
+pub const CLD_EXITED: i32 = 1;
+/// SIGCHLD: 子进程被信号终止
+pub const CLD_KILLED: i32 = 2;
+/// SIGCHLD: 子进程被信号终止并生成了 core 文件
+pub const CLD_DUMPED: i32 = 3;
+/// SIGCHLD: 被跟踪的子进程进入 ptrace 停止状态
+pub const CLD_TRAPPED: i32 = 4;
+
+/// SIGSEGV: 访问的地址没有被映射
+pub const SEGV_MAPERR: i32 = 1;
+/// SIGSEGV: 访问的地址没有对应的访问权限
+pub const SEGV_ACCERR: i32 = 2;
+
+/// SIGBUS: 访问的地址无法被映射到物理内存
+pub const BUS_ADRERR: i32 = 2;
+
+/// SIGTRAP: 执行了断点指令
+pub const TRAP_BRKPT: i32 = 1;
+/// SIGTRAP: 单步执行完成
+pub const TRAP_TRACE: i32 = 2;
+
+/// 一个信号的完整信息，布局与 Linux 中 64 位的 `siginfo_t` 一致，共 128 字节
+///
+/// `fields` 对应 `siginfo_t` 中的联合体部分，不同来源的信号使用不同的解释方式：
+/// + kill/tkill: `si_pid`(低 32 位) 与 `si_uid`(高 32 位) 位于 `fields[0]`
+/// + sigqueue: 在 kill 的基础上，`si_value` 位于 `fields[1]`
+/// + SIGCHLD: 在 kill 的基础上，`si_status` 位于 `fields[1]` 的低 32 位
+/// + 访存异常: `si_addr` 位于 `fields[0]`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SignalInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    pub fields: [usize; 14],
+}
+
+impl SignalInfo {
+    /// 创建一个只包含信号编号和来源的信号信息
+    pub fn new(signum: usize, code: i32) -> Self {
+        Self {
+            si_signo: signum as i32,
+            si_errno: 0,
+            si_code: code,
+            _pad: 0,
+            fields: [0; 14],
+        }
+    }
+
+    /// 创建一个由内核发送的信号的信息
+    pub fn kernel(signum: usize) -> Self {
+        Self::new(signum, SI_KERNEL)
+    }
+
+    /// 创建一个由进程 `pid`(其真实用户为 `uid`) 发送的信号的信息
+    pub fn user(signum: usize, code: i32, pid: usize, uid: u32) -> Self {
+        let mut info = Self::new(signum, code);
+        info.fields[0] = (pid as u32 as usize) | ((uid as usize) << 32);
+        info
+    }
+
+    /// 创建子进程 `pid` 状态改变时发送给父进程的 SIGCHLD 信息
+    pub fn child(signum: usize, code: i32, pid: usize, uid: u32, status: i32) -> Self {
+        let mut info = Self::user(signum, code, pid, uid);
+        info.fields[1] = status as u32 as usize;
+        info
+    }
+
+    /// 创建访问地址 `addr` 时发生异常而产生的信号的信息
+    pub fn fault(signum: usize, code: i32, addr: usize) -> Self {
+        let mut info = Self::new(signum, code);
+        info.fields[0] = addr;
+        info
+    }
+
+    /// 信号的编号
+    pub fn signum(&self) -> usize {
+        self.si_signo as usize
+    }
+}
+
+/// 判断一个信号是否是实时信号
+pub fn is_rt_signal(signum: usize) -> bool {
+    signum >= SIGRTMIN
+}
+
+/// 一个线程或进程的待处理信号队列
+#[derive(Debug, Default)]
+pub struct SigPending {
+    queue: VecDeque<SignalInfo>,
+}
+
+impl SigPending {
+    /// 将一个信号加入队列
+    ///
+    /// 标准信号已经在队列中时不会重复加入；实时信号的数量达到 `limit` 时返回 `EAGAIN`。
+    pub fn push(&mut self, info: SignalInfo, limit: usize) -> AlienResult<()> {
+        let signum = info.signum();
+        if !is_rt_signal(signum) {
+            if !self.contains(signum) {
+                self.queue.push_back(info);
+            }
+            return Ok(());
+        }
+        let rt_count = self
+            .queue
+            .iter()
+            .filter(|info| is_rt_signal(info.signum()))
+            .count();
+        if rt_count >= limit {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        self.queue.push_back(info);
+        Ok(())
+    }
+
+    /// 取出队列中最早的一个编号为 `signum` 的信号
+    pub fn take(&mut self, signum: usize) -> Option<SignalInfo> {
+        let index = self.queue.iter().position(|info| info.signum() == signum)?;
+        self.queue.remove(index)
+    }
+
+    /// 判断队列中是否存在编号为 `signum` 的信号
+    pub fn contains(&self, signum: usize) -> bool {
+        self.queue.iter().any(|info| info.signum() == signum)
+    }
+}
@@ -19,6 +19,7 @@ mod gui;
 mod ipc;
 mod mm;
 mod net;
+mod random;
 mod system;
 mod task;
 mod time;
@@ -30,6 +31,7 @@ use core::{
 };
 
 use platform::platform_machine_info;
+use vfs::proc::ProcessFileKind;
 
 use crate::task::DriverTaskImpl;
 
@@ -52,7 +54,13 @@ fn main(hart_id: usize) {
         vfs::init_filesystem().expect("init filesystem failed");
         trap::init_trap_subsystem();
         arch::allow_access_user_memory();
+        random::init_entropy_pool();
+        mm::aslr::init_aslr();
         task::init_task();
+        mm::swap::init_reclaim();
+        vfs::proc::register_process_file_provider(ProcessFileKind::Maps, mm::map::proc_maps);
+        vfs::proc::register_process_file_provider(ProcessFileKind::Status, mm::stat::proc_status);
+        vfs::proc::register_process_file_provider(ProcessFileKind::Statm, mm::stat::proc_statm);
         // register all syscall
         syscall_table::init_init_array!();
         STARTED.store(false, Ordering::Relaxed);
@@ -0,0 +1,103 @@
+//! 地址空间布局随机化(ASLR)。
+//!
+//! execve 构建新的地址空间时，用户栈的栈顶、内存映射区域的起始地址(动态链接器也加载在这里)、
+//! 位置无关程序的加载基址与堆的起始地址分别加上一个从熵池中取得的按页对齐的随机偏移量。
+//!
+//! 随机化的程度由 `/proc/sys/kernel/randomize_va_space` 控制：`0` 关闭随机化，`1` 随机化除堆以外的区域，
+//! `2`(默认)同时随机化堆的起始地址。设置了 `personality(ADDR_NO_RANDOMIZE)` 的进程不进行随机化。
+//! Alien 没有向用户程序映射 vDSO，因此没有需要随机化的 vDSO 地址。
+//!
+//! Reference: [randomize_va_space](https://docs.kernel.org/admin-guide/sysctl/kernel.html#randomize-va-space)
+use alloc::{format, string::String};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use config::{ELF_BASE_RELOCATE, FRAME_BITS, PROCESS_HEAP_MAX, USER_STACK_TOP};
+use vfs::proc::{register_sysctl, SysctlOps};
+use vfscore::{error::VfsError, VfsResult};
+
+use crate::{
+    random::get_random_u64,
+    task::{current_task, Capabilities},
+};
+
+/// personality: 关闭地址空间布局随机化
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+
+/// 用户栈栈顶随机偏移的页数的位数，偏移量最大为 1GB
+const STACK_RND_BITS: usize = 18;
+/// 内存映射区域起始地址随机偏移的页数的位数，偏移量最大为 1GB
+const MMAP_RND_BITS: usize = 18;
+/// 位置无关程序加载基址随机偏移的页数的位数，偏移量最大为 256MB
+const PIE_RND_BITS: usize = 16;
+/// 堆起始地址随机偏移的页数的位数，偏移量最大为 32MB
+const BRK_RND_BITS: usize = 13;
+
+/// `/proc/sys/kernel/randomize_va_space` 的值
+static RANDOMIZE_VA_SPACE: AtomicUsize = AtomicUsize::new(2);
+
+/// 新程序地址空间中各区域的位置
+pub struct VaLayout {
+    /// 用户栈的栈顶
+    pub stack_top: usize,
+    /// 内存映射区域的起始地址
+    pub mmap_base: usize,
+    /// 位置无关程序的加载基址
+    pub pie_base: usize,
+    /// 堆的起始地址相对于程序数据段末尾的偏移
+    pub brk_offset: usize,
+}
+
+impl VaLayout {
+    /// 根据 `randomize_va_space` 与进程的 `personality` 决定新程序的地址空间布局
+    pub fn new(personality: u32) -> Self {
+        let level = if personality & ADDR_NO_RANDOMIZE != 0 {
+            0
+        } else {
+            RANDOMIZE_VA_SPACE.load(Ordering::Relaxed)
+        };
+        let offset = |bits: usize, enabled: bool| {
+            if enabled {
+                (get_random_u64() as usize & ((1 << bits) - 1)) << FRAME_BITS
+            } else {
+                0
+            }
+        };
+        Self {
+            stack_top: USER_STACK_TOP - offset(STACK_RND_BITS, level >= 1),
+            mmap_base: PROCESS_HEAP_MAX + offset(MMAP_RND_BITS, level >= 1),
+            pie_base: ELF_BASE_RELOCATE + offset(PIE_RND_BITS, level >= 1),
+            brk_offset: offset(BRK_RND_BITS, level >= 2),
+        }
+    }
+}
+
+fn randomize_va_space_read() -> String {
+    format!("{}\n", RANDOMIZE_VA_SPACE.load(Ordering::Relaxed))
+}
+
+fn randomize_va_space_write(data: &[u8]) -> VfsResult<()> {
+    let task = current_task().ok_or(VfsError::PermissionDenied)?;
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(VfsError::PermissionDenied);
+    }
+    let level = match data.strip_suffix(b"\n").unwrap_or(data) {
+        b"0" => 0,
+        b"1" => 1,
+        b"2" => 2,
+        _ => return Err(VfsError::Invalid),
+    };
+    RANDOMIZE_VA_SPACE.store(level, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 创建 `/proc/sys/kernel/randomize_va_space` 文件
+pub fn init_aslr() {
+    register_sysctl(
+        "kernel",
+        "randomize_va_space",
+        SysctlOps {
+            read: randomize_va_space_read,
+            write: randomize_va_space_write,
+        },
+    );
+}
@@ -1,5 +1,8 @@
 use alloc::{string::String, vec, vec::Vec};
-use core::fmt::{Debug, Formatter};
+use core::{
+    fmt::{Debug, Formatter},
+    ops::Range,
+};
 
 use mem::VmmPageAllocator;
 use page_table::table::Sv39PageTable;
@@ -16,6 +19,8 @@ pub enum ELFError {
     NoEntrySegment,
     RelocationError,
     DynsymNotFind,
+    InterpreterNotFound,
+    NoMemory,
 }
 
 impl Debug for ELFInfo {
@@ -31,14 +36,22 @@ impl Debug for ELFInfo {
 
 pub struct ELFInfo {
     pub address_space: Sv39PageTable<VmmPageAllocator>,
+    /// 开始执行的地址，动态链接的程序为动态链接器的入口
     pub entry: usize,
+    /// 程序本身的入口(`AT_ENTRY`)
+    pub program_entry: usize,
     pub stack_top: usize,
     pub heap_bottom: usize,
+    /// 内存映射区域的起始地址
+    pub mmap_base: usize,
     pub ph_num: usize,
     pub ph_entry_size: usize,
     pub ph_drift: usize,
     pub tls: usize,
-    pub bias: usize,
+    /// 动态链接器的加载基址(`AT_BASE`)，静态链接的程序为 0
+    pub interp_base: usize,
+    /// 动态链接器占用的地址范围，需要在内存映射区域中预留
+    pub interp_range: Option<Range<usize>>,
     pub name: String,
 }
 
@@ -1,93 +1,93 @@
-use alloc::{
-    collections::BTreeMap,
-    string::{String, ToString},
-    vec,
-    vec::Vec,
-};
-use core::{cmp::min, fmt::Debug};
+use alloc::{collections::BTreeMap, string::ToString, vec, vec::Vec};
+use core::{cmp::min, ops::Range};
 
 use config::*;
+use constants::{AlienResult, LinuxErrno};
 use mem::{VmmPageAllocator, FRAME_REF_MANAGER};
 use page_table::{
     addr::{align_up_4k, PhysAddr, VirtAddr},
     pte::MappingFlags,
     table::Sv39PageTable,
 };
-use spin::Lazy;
-use xmas_elf::program::{SegmentData, Type};
+use xmas_elf::{
+    program::{SegmentData, Type},
+    ElfFile,
+};
 
 use crate::{
     fs,
     ipc::ShmInfo,
-    mm::elf::{ELFError, ELFInfo, ELFReader},
+    mm::{
+        aslr::VaLayout,
+        elf::{ELFError, ELFInfo, ELFReader},
+    },
     trap::TrapFrame,
 };
 
 extern "C" {
     fn strampoline();
 }
-#[derive(Debug)]
-pub struct UserStack {
-    pub virt_stack_top: usize,
-    pub stack_top: usize,
-    pub stack_bottom: usize,
+/// 用于构建新程序初始用户栈的辅助结构
+///
+/// 数据从栈顶开始按照虚拟地址向下写入，可以跨越多页。写入每一页前通过 `translate` 获得其物理地址，
+/// `translate` 负责为该页分配物理页，必要时拓展用户栈；失败时返回 `ENOMEM`。参数与环境变量的总大小由调用者事先检查。
+pub struct UserStack<F: FnMut(usize) -> AlienResult<usize>> {
+    /// 当前栈顶的虚拟地址
+    sp: usize,
+    translate: F,
 }
 
-impl UserStack {
-    pub fn new(phy_stack_top: usize, virt_stack_top: usize) -> Self {
+impl<F: FnMut(usize) -> AlienResult<usize>> UserStack<F> {
+    pub fn new(virt_stack_top: usize, translate: F) -> Self {
         Self {
-            virt_stack_top,
-            stack_top: phy_stack_top,
-            stack_bottom: phy_stack_top - FRAME_SIZE,
+            sp: virt_stack_top,
+            translate,
         }
     }
-    pub fn push(&mut self, data: usize) -> Result<usize, &'static str> {
-        if self.stack_top - 8 < self.stack_bottom {
-            return Err("Stack Overflow");
-        }
-        unsafe {
-            self.stack_top -= 8;
-            *(self.stack_top as *mut usize) = data;
+
+    /// 将 `data` 写入虚拟地址 `addr` 处，逐页转换为物理地址
+    fn write(&mut self, addr: usize, data: &[u8]) -> AlienResult<()> {
+        let mut written = 0;
+        while written < data.len() {
+            let virt = addr + written;
+            let len = min(FRAME_SIZE - virt % FRAME_SIZE, data.len() - written);
+            let phy = (self.translate)(virt).map_err(|_| LinuxErrno::ENOMEM)?;
+            unsafe {
+                (phy as *mut u8).copy_from_nonoverlapping(data[written..].as_ptr(), len);
+            }
+            written += len;
         }
-        trace!(
-            "stack top: {:#x}, data:{:#x?}",
-            self.virt_stack_top - (FRAME_SIZE - (self.stack_top - self.stack_bottom)),
-            data
-        );
-        Ok(self.virt_stack_top - (FRAME_SIZE - (self.stack_top - self.stack_bottom)))
+        Ok(())
+    }
+
+    /// 将栈顶向下移动 `len` 字节并按 `align` 对齐，返回新的栈顶
+    fn reserve(&mut self, len: usize, align: usize) -> AlienResult<usize> {
+        let start = self.sp.checked_sub(len).ok_or(LinuxErrno::E2BIG)?;
+        self.sp = start & !(align - 1);
+        Ok(self.sp)
+    }
+
+    pub fn push(&mut self, data: usize) -> AlienResult<usize> {
+        let sp = self.reserve(8, 1)?;
+        self.write(sp, &data.to_ne_bytes())?;
+        trace!("stack top: {:#x}, data:{:#x?}", sp, data);
+        Ok(sp)
     }
 
-    pub fn push_str(&mut self, data: &str) -> Result<usize, &'static str> {
+    pub fn push_str(&mut self, data: &str) -> AlienResult<usize> {
         self.push_bytes(data.as_bytes())
     }
 
-    pub fn push_bytes(&mut self, data: &[u8]) -> Result<usize, &'static str> {
-        let len = data.len();
+    pub fn push_bytes(&mut self, data: &[u8]) -> AlienResult<usize> {
         // align 8
-        let start = self.stack_top - len;
-        let start = start & !7;
-        if start < self.stack_bottom {
-            return Err("Stack Overflow");
-        }
-        unsafe {
-            self.stack_top = start;
-            let ptr = self.stack_top as *mut u8;
-            ptr.copy_from_nonoverlapping(data.as_ptr(), len);
-        }
-        trace!(
-            "stack top: {:#x}",
-            self.virt_stack_top - (FRAME_SIZE - (self.stack_top - self.stack_bottom))
-        );
-        Ok(self.virt_stack_top - (FRAME_SIZE - (self.stack_top - self.stack_bottom)))
+        let sp = self.reserve(data.len(), 8)?;
+        self.write(sp, data)?;
+        trace!("stack top: {:#x}", sp);
+        Ok(sp)
     }
 
-    pub fn align_to(&mut self, align: usize) -> Result<usize, &'static str> {
-        let start = self.stack_top & !(align - 1);
-        if start < self.stack_bottom {
-            return Err("Stack Overflow");
-        }
-        self.stack_top = start;
-        Ok(self.virt_stack_top - (FRAME_SIZE - (self.stack_top - self.stack_bottom)))
+    pub fn align_to(&mut self, align: usize) -> AlienResult<usize> {
+        self.reserve(0, align)
     }
 }
 
@@ -122,10 +122,17 @@ pub fn build_thread_address_space(
 pub fn build_cow_address_space(
     p_table: &mut Sv39PageTable<VmmPageAllocator>,
     shm: BTreeMap<usize, ShmInfo>,
-) -> Sv39PageTable<VmmPageAllocator> {
-    let mut address_space = Sv39PageTable::<VmmPageAllocator>::try_new().unwrap();
+    skip: &[Range<usize>],
+) -> AlienResult<Sv39PageTable<VmmPageAllocator>> {
+    let mut address_space =
+        Sv39PageTable::<VmmPageAllocator>::try_new().map_err(|_| LinuxErrno::ENOMEM)?;
     for (v_addr, target) in p_table.get_record().into_iter() {
         trace!("v_addr: {:?}, target: {}", v_addr, target);
+        // the pages of shared file mappings belong to the page cache, the child maps them on page fault;
+        // the regions marked with MADV_DONTFORK are not inherited by the child
+        if skip.iter().any(|range| range.contains(&v_addr.as_usize())) {
+            continue;
+        }
         let (phy, flag, page_size) = p_table.query(v_addr).unwrap();
 
         // shm should remap, we can't use cow for it
@@ -143,7 +150,7 @@ pub fn build_cow_address_space(
             assert_eq!(usize::from(page_size), TRAMPOLINE - TRAP_CONTEXT_BASE);
             let dst = address_space
                 .map_no_target(v_addr, page_size, flag, false)
-                .unwrap();
+                .map_err(|_| LinuxErrno::ENOMEM)?;
             // copy data
             let src_ptr = phy.as_usize() as *const u8;
             let dst_ptr = dst.as_usize() as *mut u8;
@@ -152,14 +159,18 @@ pub fn build_cow_address_space(
             }
         } else if is_in_segs(v_addr.as_usize()) {
             // for shm, we now skip it
-            address_space.map(v_addr, phy, page_size, flag).unwrap();
+            address_space
+                .map(v_addr, phy, page_size, flag)
+                .map_err(|_| LinuxErrno::ENOMEM)?;
         } else {
             // cow
             // checkout whether pte flags has `W` flag
             let mut flags = flag.clone();
             if !flag.contains(MappingFlags::V) {
                 // if flags is not valid, we just map it
-                address_space.map(v_addr, phy, page_size, flags).unwrap();
+                address_space
+                    .map(v_addr, phy, page_size, flags)
+                    .map_err(|_| LinuxErrno::ENOMEM)?;
                 if target {
                     address_space.get_record_mut().insert(v_addr, true);
                 }
@@ -171,7 +182,9 @@ pub fn build_cow_address_space(
                                             // update parent's flag and clear dirty
                 p_table.modify_pte_flags(v_addr, flags, false).unwrap();
             }
-            address_space.map(v_addr, phy, page_size, flags).unwrap();
+            address_space
+                .map(v_addr, phy, page_size, flags)
+                .map_err(|_| LinuxErrno::ENOMEM)?;
             // add ref for alloc page
             if target {
                 for i in 0..usize::from(page_size) / FRAME_SIZE {
@@ -183,157 +196,198 @@ pub fn build_cow_address_space(
             }
         }
     }
-    address_space
+    Ok(address_space)
+}
+
+/// 将 `elf` 的所有可加载段映射到地址空间中，每个段的地址加上 `bias`，返回这些段占用的地址范围
+///
+/// 段在文件中的数据超出文件末尾，或者比段在内存中的大小还要大时返回 `FileBreak`。
+fn load_segments(
+    address_space: &mut Sv39PageTable<VmmPageAllocator>,
+    elf: &ElfFile,
+    bias: usize,
+) -> Result<Range<usize>, ELFError> {
+    let mut start = usize::MAX;
+    let mut break_addr = 0usize;
+    for ph in elf
+        .program_iter()
+        .filter(|ph| ph.get_type() == Ok(Type::Load))
+    {
+        let start_addr = ph.virtual_addr() as usize + bias;
+        let end_addr = start_addr + ph.mem_size() as usize;
+        let mut permission: MappingFlags = "UVAD".into();
+        let ph_flags = ph.flags();
+        if ph_flags.is_read() {
+            permission |= MappingFlags::R;
+        }
+        if ph_flags.is_write() {
+            permission |= MappingFlags::W;
+        }
+        if ph_flags.is_execute() {
+            permission |= MappingFlags::X;
+        }
+        let vaddr = VirtAddr::from(start_addr).align_down_4k();
+        let end_vaddr = VirtAddr::from(end_addr).align_up_4k();
+        start = min(start, vaddr.as_usize());
+        // 记录程序地址空间的最大地址
+        break_addr = end_addr;
+        let len = end_vaddr.as_usize() - vaddr.as_usize();
+        warn!(
+            "load segment: {:#x} - {:#x} -> {:#x}-{:#x}, permission: {:?}",
+            start_addr,
+            end_addr,
+            vaddr.as_usize(),
+            end_vaddr.as_usize(),
+            permission
+        );
+        let mut data = (ph.offset() as usize)
+            .checked_add(ph.file_size() as usize)
+            .and_then(|end| elf.input.get(ph.offset() as usize..end))
+            .ok_or(ELFError::FileBreak)?;
+        let map_info = address_space
+            .map_region_no_target(vaddr, len, permission, false, false)
+            .map_err(|_| ELFError::NoMemory)?;
+        // copy data
+        let mut page_offset = start_addr & (FRAME_SIZE - 1);
+        let mut count = 0;
+        map_info
+            .into_iter()
+            .for_each(|(_vir, phy, page_size)| unsafe {
+                let size: usize = page_size.into();
+                let min = min(size - page_offset, data.len());
+                let dst = (phy.as_usize() + page_offset) as *mut u8;
+                core::ptr::copy(data.as_ptr(), dst, min);
+                data = &data[min..];
+                count += min;
+                page_offset = 0;
+            });
+        if count != ph.file_size() as usize {
+            return Err(ELFError::FileBreak);
+        }
+    }
+    Ok(start..break_addr)
 }
 
-static LD_MUSL_RV64_CACHE: Lazy<Vec<u8>> = Lazy::new(|| {
+/// 通过 VFS 读取 `path` 处的动态链接器并将其加载到地址空间中，返回其入口地址、加载基址与占用的地址范围
+///
+/// 位置无关的动态链接器被加载到 `base`，即内存映射区域的起始处，动态链接器自身不能再指定动态链接器。
+fn load_interpreter(
+    address_space: &mut Sv39PageTable<VmmPageAllocator>,
+    path: &str,
+    base: usize,
+) -> Result<(usize, usize, Range<usize>), ELFError> {
     let mut data = vec![];
-    fs::read_all("/tests/libc.so", &mut data);
-    data
-});
+    if !fs::read_all(path, &mut data) {
+        warn!("interpreter {} not found", path);
+        return Err(ELFError::InterpreterNotFound);
+    }
+    let interp = ElfFile::new(&data).map_err(|_| ELFError::NotELF)?;
+    if interp
+        .program_iter()
+        .any(|ph| ph.get_type() == Ok(Type::Interp))
+    {
+        return Err(ELFError::NotSupported);
+    }
+    let bias = match interp.header.pt2.type_().as_type() {
+        xmas_elf::header::Type::Executable => 0,
+        xmas_elf::header::Type::SharedObject => base,
+        _ => return Err(ELFError::NotSupported),
+    };
+    let range = load_segments(address_space, &interp, bias)?;
+    let entry = interp.header.pt2.entry_point() as usize + bias;
+    warn!(
+        "load interpreter: {} at {:#x}, entry: {:#x}",
+        path, bias, entry
+    );
+    Ok((entry, bias, range))
+}
 
+/// 根据 ELF 文件构建新的地址空间
+///
+/// 对于动态链接的程序，程序本身与 PT_INTERP 指定的动态链接器都会被加载，程序从动态链接器的入口开始执行，
+/// 动态链接器通过辅助向量中的 `AT_BASE`、`AT_ENTRY` 与 `AT_PHDR` 找到自己与程序。
+///
+/// 用户栈、动态链接器、位置无关程序与堆的位置由 `layout` 决定。
+/// 分配页表或物理页失败时返回 `NoMemory`，由调用者转换为 `ENOMEM`。
 pub fn build_elf_address_space(
     elf: &[u8],
-    args: &mut Vec<String>,
     name: &str,
+    layout: VaLayout,
 ) -> Result<ELFInfo, ELFError> {
-    let mut address_space = Sv39PageTable::<VmmPageAllocator>::try_new().unwrap();
+    let mut address_space =
+        Sv39PageTable::<VmmPageAllocator>::try_new().map_err(|_| ELFError::NoMemory)?;
     const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
-    if elf[0..4] != ELF_MAGIC {
+    if elf.len() < ELF_MAGIC.len() || elf[0..4] != ELF_MAGIC {
         return Err(ELFError::NotELF);
     }
-    let elf = xmas_elf::ElfFile::new(elf).map_err(|_| ELFError::NotELF)?;
-    // check whether it's a dynamic linked elf
-    if let Some(inter) = elf
+    let elf = ElfFile::new(elf).map_err(|_| ELFError::NotELF)?;
+    let mut interps = elf
         .program_iter()
-        .find(|ph| ph.get_type().unwrap() == Type::Interp)
-    {
-        let data = match inter.get_data(&elf).unwrap() {
-            SegmentData::Undefined(data) => data,
-            _ => return Err(ELFError::NoEntrySegment),
-        };
-        let path = core::str::from_utf8(data).unwrap();
-        assert!(
-            path.starts_with("/lib/ld-musl-riscv64"),
-            "interpreter: {:?}",
-            path
-        );
-        let mut new_args = vec!["/tests/libc.so\0".to_string()];
-        // args.remove(0); // remove the first arg
-        new_args.extend(args.clone());
-        *args = new_args;
-        // load interpreter
-        warn!("load interpreter: {}, new_args:{:?}", path, args);
-        return build_elf_address_space(&LD_MUSL_RV64_CACHE, args, "/tests/libc.so");
-    }
+        .filter(|ph| ph.get_type() == Ok(Type::Interp));
+    let interp_path = match (interps.next(), interps.next()) {
+        (None, _) => None,
+        (Some(inter), None) => {
+            let data = match inter.get_data(&elf) {
+                Ok(SegmentData::Undefined(data)) => data,
+                _ => return Err(ELFError::NoEntrySegment),
+            };
+            let path = core::str::from_utf8(data).map_err(|_| ELFError::NotELF)?;
+            Some(path.trim_end_matches('\0'))
+        }
+        // Emmm, It has multiple interpreters.
+        _ => return Err(ELFError::NotSupported),
+    };
 
     // calculate bias for dynamic linked elf
     // if elf is static linked, bias is 0
     let bias = match elf.header.pt2.type_().as_type() {
         // static
         xmas_elf::header::Type::Executable => 0,
-        xmas_elf::header::Type::SharedObject => {
-            match elf
-                .program_iter()
-                .filter(|ph| ph.get_type().unwrap() == Type::Interp)
-                .count()
-            {
-                // It's a loader!
-                0 => ELF_BASE_RELOCATE,
-                // It's a dynamically linked ELF.
-                1 => 0,
-                // Emmm, It has multiple interpreters.
-                _ => return Err(ELFError::NotSupported),
-            }
-        }
+        // position independent executable, or a loader which is executed directly
+        xmas_elf::header::Type::SharedObject => layout.pie_base,
         _ => return Err(ELFError::NotSupported),
     };
     trace!("bias: {:#x}", bias);
 
     let tls = elf
         .program_iter()
-        .find(|x| x.get_type().unwrap() == Type::Tls)
+        .find(|x| x.get_type() == Ok(Type::Tls))
         .map(|ph| ph.virtual_addr())
         .unwrap_or(0);
 
     warn!("ELF tls: {:#x}", tls);
 
-    let mut break_addr = 0usize;
-    elf.program_iter()
-        .filter(|ph| ph.get_type() == Ok(Type::Load))
-        .for_each(|ph| {
-            let start_addr = ph.virtual_addr() as usize + bias;
-            let end_addr = start_addr + ph.mem_size() as usize;
-            let mut permission: MappingFlags = "UVAD".into();
-            let ph_flags = ph.flags();
-            if ph_flags.is_read() {
-                permission |= MappingFlags::R;
-            }
-            if ph_flags.is_write() {
-                permission |= MappingFlags::W;
-            }
-            if ph_flags.is_execute() {
-                permission |= MappingFlags::X;
-            }
-            let vaddr = VirtAddr::from(start_addr).align_down_4k();
-            let end_vaddr = VirtAddr::from(end_addr).align_up_4k();
-            // 记录程序地址空间的最大地址
-            break_addr = end_addr;
-            let len = end_vaddr.as_usize() - vaddr.as_usize();
-            warn!(
-                "load segment: {:#x} - {:#x} -> {:#x}-{:#x}, permission: {:?}",
-                start_addr,
-                end_addr,
-                vaddr.as_usize(),
-                end_vaddr.as_usize(),
-                permission
-            );
-            let mut data =
-                &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
-            let map_info = address_space
-                .map_region_no_target(vaddr, len, permission, false, false)
-                .unwrap();
-            // copy data
-            let mut page_offset = start_addr & (FRAME_SIZE - 1);
-            let mut count = 0;
-            map_info
-                .into_iter()
-                .for_each(|(_vir, phy, page_size)| unsafe {
-                    let size: usize = page_size.into();
-                    let min = min(size - page_offset, data.len());
-                    let dst = (phy.as_usize() + page_offset) as *mut u8;
-                    core::ptr::copy(data.as_ptr(), dst, min);
-                    data = &data[min..];
-                    count += min;
-                    page_offset = 0;
-                });
-            assert_eq!(count, ph.file_size() as usize);
-        });
+    let break_addr = load_segments(&mut address_space, &elf, bias)?.end;
+    let interp = match interp_path {
+        Some(path) => Some(load_interpreter(
+            &mut address_space,
+            path,
+            layout.mmap_base,
+        )?),
+        None => None,
+    };
 
     // 地址向上取整对齐4
     let ceil_addr = align_up_4k(break_addr + FRAME_SIZE);
-    // 留出一个用户栈的位置+隔离页
-    let top = ceil_addr + USER_STACK_SIZE + FRAME_SIZE;
-    warn!(
-        "user stack: {:#x} - {:#x}",
-        top - USER_STACK_SIZE - FRAME_SIZE,
-        top - FRAME_SIZE
-    );
+    // 用户栈位于地址空间的顶端，向下增长
+    let top = layout.stack_top;
+    warn!("user stack: {:#x} - {:#x}", top - USER_STACK_SIZE, top);
     // map user stack
     address_space
         .map_region_no_target(
-            VirtAddr::from(top - USER_STACK_SIZE - FRAME_SIZE),
+            VirtAddr::from(top - USER_STACK_SIZE),
             USER_STACK_SIZE,
             "RWUAD".into(),
             false,
             true,
         )
-        .unwrap();
+        .map_err(|_| ELFError::NoMemory)?;
     // 初始化一个有效页
     address_space
-        .validate(VirtAddr::from(top - FRAME_SIZE * 2), "RWUVAD".into())
-        .unwrap();
-    let heap_bottom = top;
+        .validate(VirtAddr::from(top - FRAME_SIZE), "RWUVAD".into())
+        .map_err(|_| ELFError::NoMemory)?;
+    // 堆空间位于 elf 的数据段之后
+    let heap_bottom = ceil_addr + layout.brk_offset;
     // align to 4k
     warn!("trap context: {:#x} - {:#x}", TRAP_CONTEXT_BASE, TRAMPOLINE);
     address_space
@@ -344,7 +398,7 @@ pub fn build_elf_address_space(
             true,
             false,
         )
-        .unwrap();
+        .map_err(|_| ELFError::NoMemory)?;
     warn!(
         "TRAMPOLINE: {:#x} - {:#x}",
         TRAMPOLINE,
@@ -358,7 +412,7 @@ pub fn build_elf_address_space(
             "RXVAD".into(),
             true,
         )
-        .unwrap();
+        .map_err(|_| ELFError::NoMemory)?;
 
     let res = if let Some(phdr) = elf
         .program_iter()
@@ -383,8 +437,8 @@ pub fn build_elf_address_space(
         elf.header.pt2.entry_point() + bias as u64,
         res + bias as u64
     );
-    // relocate if elf is dynamically linked
-    if bias != 0 {
+    // relocate if elf is a loader or a static-pie, otherwise the interpreter does it
+    if bias != 0 && interp.is_none() {
         if let Ok(kvs) = elf.relocate_plt(bias) {
             kvs.into_iter().for_each(|kv| {
                 trace!("relocate: {:#x} -> {:#x}", kv.0, kv.1);
@@ -402,16 +456,24 @@ pub fn build_elf_address_space(
             info!("relocate dyn done")
         }
     }
+    let program_entry = elf.header.pt2.entry_point() as usize + bias;
+    let (entry, interp_base, interp_range) = match interp {
+        Some((entry, base, range)) => (entry, base, Some(range)),
+        None => (program_entry, 0, None),
+    };
     Ok(ELFInfo {
         address_space,
-        entry: elf.header.pt2.entry_point() as usize + bias,
-        stack_top: top - FRAME_SIZE,
+        entry,
+        program_entry,
+        stack_top: top,
         heap_bottom,
+        mmap_base: layout.mmap_base,
         ph_num: elf.header.pt2.ph_count() as usize,
         ph_entry_size: elf.header.pt2.ph_entry_size() as usize,
         ph_drift: res as usize + bias,
         tls: tls as usize + bias,
-        bias,
+        interp_base,
+        interp_range,
         name: name.to_string(),
     })
 }
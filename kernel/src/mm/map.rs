@@ -1,17 +1,21 @@
-use alloc::{sync::Arc, vec::Vec};
-use core::ops::Range;
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc};
+use core::{cmp::min, ops::Range};
 
 use bitflags::bitflags;
-use config::{FRAME_SIZE, PROCESS_HEAP_MAX};
+use config::FRAME_SIZE;
 use constants::{io::MapFlags, AlienResult, LinuxErrno};
+use ksync::Mutex;
 use page_table::{
     addr::{align_up_4k, VirtAddr},
     pte::MappingFlags,
 };
 use syscall_table::syscall_func;
-use vfs::kfile::File;
+use vfs::{kfile::File, page_cache::PageCache};
 
-use crate::task::current_task;
+use crate::{
+    mm::uaccess::UserSlice,
+    task::{current_task, find_process_from_user},
+};
 
 bitflags! {
     pub struct ProtFlags: u32 {
@@ -22,6 +26,21 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct MsyncFlags: u32 {
+        const MS_ASYNC = 0x1;
+        const MS_INVALIDATE = 0x2;
+        const MS_SYNC = 0x4;
+    }
+}
+
+bitflags! {
+    pub struct MremapFlags: u32 {
+        const MREMAP_MAYMOVE = 0x1;
+        const MREMAP_FIXED = 0x2;
+    }
+}
+
 impl Into<MappingFlags> for ProtFlags {
     fn into(self) -> MappingFlags {
         let mut perm = MappingFlags::empty();
@@ -39,13 +58,84 @@ impl Into<MappingFlags> for ProtFlags {
     }
 }
 
+/// mmap: 与 `MAP_FIXED` 相同，但映射范围与已有的映射重叠时返回 `EEXIST` 而不是覆盖原有的映射
+pub const MAP_FIXED_NOREPLACE: u32 = 0x100000;
+/// mmap: 映射区域在访问其下方的页时向低地址方向增长，用于栈
+pub const MAP_GROWSDOWN: u32 = 0x100;
+
+/// 向下增长的映射区域与其下方的映射之间至少保留的间隔，访问该间隔中的页时区域会向下增长
+pub const GROWSDOWN_GAP: usize = 256 * FRAME_SIZE;
+
+/// madvise 的建议类型
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MadviseAdvice {
+    /// 没有特殊的访问模式，缺页时少量预读
+    Normal = 0,
+    /// 随机访问，缺页时不预读
+    Random = 1,
+    /// 顺序访问，缺页时大量预读
+    Sequential = 2,
+    /// 即将访问，预先读入文件页与被换出的页
+    WillNeed = 3,
+    /// 不再需要，释放物理页，之后访问时重新缺页
+    DontNeed = 4,
+    /// 与 `DontNeed` 相同，但只能用于匿名映射
+    Free = 8,
+    /// 子进程不继承该区域
+    DontFork = 10,
+    /// 撤销 `DontFork`
+    DoFork = 11,
+    /// 为区域启用透明大页
+    HugePage = 14,
+    /// 为区域禁用透明大页
+    NoHugePage = 15,
+}
+
+impl TryFrom<usize> for MadviseAdvice {
+    type Error = LinuxErrno;
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        let advice = match value {
+            0 => MadviseAdvice::Normal,
+            1 => MadviseAdvice::Random,
+            2 => MadviseAdvice::Sequential,
+            3 => MadviseAdvice::WillNeed,
+            4 => MadviseAdvice::DontNeed,
+            8 => MadviseAdvice::Free,
+            10 => MadviseAdvice::DontFork,
+            11 => MadviseAdvice::DoFork,
+            14 => MadviseAdvice::HugePage,
+            15 => MadviseAdvice::NoHugePage,
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        Ok(advice)
+    }
+}
+
+impl MadviseAdvice {
+    /// 文件映射缺页时，在缺页的页之后预读的页数
+    pub fn readahead_pages(&self) -> usize {
+        match self {
+            MadviseAdvice::Random => 0,
+            MadviseAdvice::Sequential => 32,
+            _ => 4,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-/// The Process should manage the mmap info
+/// 进程的虚拟内存区域(VMA)树
+///
+/// 所有内存映射区域按照起始地址有序保存，区域之间互不重叠。分配新的映射时从 `map_start` 开始查找第一个足够大的空隙，
+/// 因此被解除映射的地址空间可以被重新使用。
 pub struct MMapInfo {
-    /// The start address of the mmap, it is a constant
+    /// 自动分配映射地址时的最低地址
     map_start: usize,
-    /// The regions of the mmap
-    regions: Vec<MMapRegion>,
+    /// 自动分配映射地址时的上界，之上为用户栈可以增长到的范围
+    map_top: usize,
+    /// 以起始地址为键的内存映射区域
+    regions: BTreeMap<usize, MMapRegion>,
+    /// 不属于任何映射区域但已经被占用的地址范围(如共享内存段)，以起始地址为键，值为结束地址
+    reserved: BTreeMap<usize, usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -63,55 +153,150 @@ pub struct MMapRegion {
     pub fd: Option<Arc<dyn File>>,
     /// The offset in the file to start from
     pub offset: usize,
+    /// 访问区域下方的页时区域向下增长(`MAP_GROWSDOWN`)
+    pub grows_down: bool,
+    /// 通过 madvise 设置的访问模式，决定缺页时的预读页数
+    pub access: MadviseAdvice,
+    /// 子进程不继承该区域(`MADV_DONTFORK`)
+    pub dont_fork: bool,
+    /// 通过 madvise 设置的透明大页策略，`None` 表示遵循全局策略
+    pub huge: Option<bool>,
 }
 
 impl MMapInfo {
-    pub fn new() -> Self {
+    pub fn new(map_start: usize, map_top: usize) -> Self {
         Self {
-            map_start: PROCESS_HEAP_MAX,
-            regions: Vec::new(),
+            map_start,
+            map_top,
+            regions: BTreeMap::new(),
+            reserved: BTreeMap::new(),
+        }
+    }
+
+    /// 查找一段长度为 `len` 的空闲地址范围，没有足够大的空隙时返回 `None`
+    ///
+    /// 向下增长的区域下方会保留 [`GROWSDOWN_GAP`] 大小的间隔。
+    pub fn alloc(&self, len: usize) -> Option<Range<usize>> {
+        let len = align_up_4k(len);
+        let mut start = self.map_start;
+        loop {
+            let end = start.checked_add(len)?;
+            if end > self.map_top {
+                return None;
+            }
+            match self.used_end(start..end) {
+                Some(used_end) => start = used_end,
+                None => return Some(start..end),
+            }
         }
     }
 
-    pub fn alloc(&mut self, len: usize) -> Range<usize> {
-        let addr = self.map_start;
-        self.map_start += len;
-        // align to Frame size
-        self.map_start = (self.map_start + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
-        addr..self.map_start
+    /// 返回与 `range` 重叠的已被占用的地址范围中最大的结束地址，没有重叠时返回 `None`
+    ///
+    /// 向下增长的区域下方的 [`GROWSDOWN_GAP`] 同样视为被占用。区域之间互不重叠，因此从高地址向低地址查找时，
+    /// 遇到结束地址不超过 `range.start` 的区域即可停止。
+    fn used_end(&self, range: Range<usize>) -> Option<usize> {
+        let region = self
+            .regions
+            .range(..range.end.saturating_add(GROWSDOWN_GAP))
+            .rev()
+            .map(|(_, region)| region)
+            .take_while(|region| region.start + region.map_len > range.start)
+            .find(|region| {
+                let gap = if region.grows_down { GROWSDOWN_GAP } else { 0 };
+                region.start.saturating_sub(gap) < range.end
+            })
+            .map(|region| region.start + region.map_len);
+        let reserved = self
+            .reserved
+            .range(..range.end)
+            .next_back()
+            .map(|(_, end)| *end)
+            .filter(|end| *end > range.start);
+        region.max(reserved)
     }
 
+    /// 自动分配映射地址时的上界，映射不能超过该地址
+    pub fn top(&self) -> usize {
+        self.map_top
+    }
+
+    /// 将 `range` 标记为已被占用，之后分配映射地址时会跳过该范围
+    pub fn reserve_range(&mut self, range: Range<usize>) {
+        self.reserved.insert(range.start, range.end);
+    }
+
+    /// 加入一个映射区域，已经存在起始地址相同的区域时将其替换
     pub fn add_region(&mut self, region: MMapRegion) {
-        self.regions.push(region);
+        self.regions.insert(region.start, region);
     }
 
     pub fn get_region(&self, addr: usize) -> Option<&MMapRegion> {
-        for region in self.regions.iter() {
-            if region.start <= addr && addr < region.start + region.len {
-                return Some(region);
-            }
-        }
-        None
+        self.regions
+            .range(..=addr)
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| addr < region.start + region.map_len)
     }
 
-    pub fn get_region_mut(&mut self, addr: usize) -> Option<&mut MMapRegion> {
-        for region in self.regions.iter_mut() {
-            if region.start <= addr && addr < region.start + region.len {
-                return Some(region);
-            }
+    /// 创建子进程时调用，返回子进程继承的映射区域，设置了 `MADV_DONTFORK` 的区域不会被继承
+    pub fn fork(&self) -> Self {
+        let mut info = self.clone();
+        info.regions.retain(|_, region| !region.dont_fork);
+        info
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &MMapRegion> {
+        self.regions.values()
+    }
+
+    /// 返回与 `range` 重叠的第一个映射区域，`range` 为空时返回 `None`
+    pub fn first_overlap(&self, range: Range<usize>) -> Option<&MMapRegion> {
+        if range.is_empty() {
+            return None;
         }
-        None
+        self.regions
+            .range(..range.end)
+            .map(|(_, region)| region)
+            .find(|region| range.start < region.start + region.map_len)
+    }
+
+    /// 返回位于 `addr` 之上的第一个映射区域
+    pub fn next_region(&self, addr: usize) -> Option<&MMapRegion> {
+        self.regions
+            .range(addr + 1..)
+            .next()
+            .map(|(_, region)| region)
     }
 
     pub fn remove_region(&mut self, addr: usize) {
-        let mut index = 0;
-        for region in self.regions.iter() {
-            if region.start <= addr && addr < region.start + region.len {
-                break;
+        let start = self.get_region(addr).unwrap().start;
+        self.regions.remove(&start);
+    }
+
+    /// 将包含 `addr` 的区域与其前后相邻且属性相同的区域合并
+    pub fn merge(&mut self, addr: usize) {
+        let mut start = match self.get_region(addr) {
+            Some(region) => region.start,
+            None => return,
+        };
+        if let Some((&prev, region)) = self.regions.range(..start).next_back() {
+            if region.can_merge(&self.regions[&start]) {
+                let next = self.regions.remove(&start).unwrap();
+                self.regions.get_mut(&prev).unwrap().absorb(next);
+                start = prev;
+            }
+        }
+        let next_start = {
+            let region = &self.regions[&start];
+            region.start + region.map_len
+        };
+        if let Some(next) = self.regions.get(&next_start) {
+            if self.regions[&start].can_merge(next) {
+                let next = self.regions.remove(&next_start).unwrap();
+                self.regions.get_mut(&start).unwrap().absorb(next);
             }
-            index += 1;
         }
-        self.regions.remove(index);
     }
 }
 
@@ -133,6 +318,10 @@ impl MMapRegion {
             flags,
             fd,
             offset,
+            grows_down: false,
+            access: MadviseAdvice::Normal,
+            dont_fork: false,
+            huge: None,
         }
     }
     // [a-b]
@@ -155,10 +344,71 @@ impl MMapRegion {
     pub fn set_flags(&mut self, flags: MapFlags) {
         self.flags = flags;
     }
+
+    /// 共享文件映射直接映射文件的缓存页，返回该文件的页缓存
+    pub fn page_cache(&self) -> Option<Arc<Mutex<PageCache>>> {
+        if !self.flags.contains(MapFlags::MAP_SHARED) {
+            return None;
+        }
+        self.fd.as_ref().and_then(|file| file.page_cache())
+    }
+
+    /// 判断 `next` 是否紧接在该区域之后，并且二者的属性相同，可以合并为一个区域
+    fn can_merge(&self, next: &MMapRegion) -> bool {
+        let same_file = match (&self.fd, &next.fd) {
+            (None, None) => true,
+            (Some(file), Some(next_file)) => {
+                Arc::as_ptr(file) as *const () == Arc::as_ptr(next_file) as *const ()
+                    && self.offset + self.map_len == next.offset
+            }
+            _ => false,
+        };
+        self.start + self.map_len == next.start
+            && self.len == self.map_len
+            && self.prot == next.prot
+            && self.flags == next.flags
+            && self.grows_down == next.grows_down
+            && self.access == next.access
+            && self.dont_fork == next.dont_fork
+            && self.huge == next.huge
+            && same_file
+    }
+
+    /// 将紧接在该区域之后的区域 `next` 合并到该区域中
+    fn absorb(&mut self, next: MMapRegion) {
+        self.len = self.map_len + next.len;
+        self.map_len += next.map_len;
+    }
+
+    /// 区域在 `/proc/<pid>/maps` 中的权限表示，如 `rw-p`
+    pub fn perms(&self) -> String {
+        let mut perms = String::new();
+        perms.push(if self.prot.contains(ProtFlags::PROT_READ) {
+            'r'
+        } else {
+            '-'
+        });
+        perms.push(if self.prot.contains(ProtFlags::PROT_WRITE) {
+            'w'
+        } else {
+            '-'
+        });
+        perms.push(if self.prot.contains(ProtFlags::PROT_EXEC) {
+            'x'
+        } else {
+            '-'
+        });
+        perms.push(if self.flags.contains(MapFlags::MAP_SHARED) {
+            's'
+        } else {
+            'p'
+        });
+        perms
+    }
 }
 
-/// 一个函数调用，用于消除内存映射。
-/// 注意：传入的`start`必须是某段内存映射的首地址，`len`必须是该段内存映射的长度，否则将导致函数返回`EINVAL`。函数正常执行将返回0。
+/// 一个函数调用，用于消除 `[start, start + len)` 范围内的内存映射。
+/// 注意：传入的`start`必须与页对齐，`len`不能为0，否则将导致函数返回`EINVAL`。该范围可以只覆盖某段内存映射的一部分，函数正常执行将返回0。
 #[syscall_func(215)]
 pub fn do_munmap(start: usize, len: usize) -> isize {
     let task = current_task().unwrap();
@@ -174,7 +424,7 @@ pub fn do_munmap(start: usize, len: usize) -> isize {
 /// + `start`: 所要创建的映射区的起始地址。当该值为0时，内核将自动为其分配一段内存空间创建内存映射。该值在函数运行过程中将被调整为与4K对齐。
 /// + `len`: 指明所要创建的映射区的长度。该值在函数运行过程中将被调整为与4K对齐。
 /// + `prot`: 指明创建内存映射区的初始保护位。具体可见[`ProtFlags`]。
-/// + `flags`: 指明mmap操作的相关设置。具体可见[`MapFlags`]。
+/// + `flags`: 指明mmap操作的相关设置。具体可见[`MapFlags`]，此外还支持 [`MAP_FIXED_NOREPLACE`] 与 [`MAP_GROWSDOWN`]。
 /// + `fd`: 指明要创建内存映射的文件的文件描述符。
 /// + `offset`: 将从文件中偏移量为`offset`处开始映射。该值需要和4K对齐。
 ///
@@ -192,20 +442,57 @@ pub fn do_mmap(
     let process = current_task().unwrap();
     let mut process_inner = process.access_inner();
     let prot = ProtFlags::from_bits_truncate(prot);
-    let flags = MapFlags::from_bits_truncate(flags);
     warn!(
         "mmap: start: {:#x}, len: {:#x}, prot: {:?}, flags: {:?}, fd: {}, offset: {:#x}",
-        start, len, prot, flags, fd, offset
+        start,
+        len,
+        prot,
+        MapFlags::from_bits_truncate(flags),
+        fd,
+        offset
     );
     process_inner
         .add_mmap(start, len, prot, flags, fd, offset)
         .map(|addr| addr as isize)
 }
 
+/// 一个系统调用，用于扩大或缩小一段已经存在的内存映射，必要时将其移动到新的位置。
+///
+/// + `old_addr`: 原映射的起始地址，需要与页对齐。
+/// + `old_size`: 原映射的长度。目前不支持为 0 时复制共享映射的用法。
+/// + `new_size`: 调整后映射的长度。
+/// + `flags`: 可以包含 `MREMAP_MAYMOVE` 和 `MREMAP_FIXED`，具体可见[`MremapFlags`]。
+/// + `new_addr`: 指定了 `MREMAP_FIXED` 时映射被移动到的地址，该地址上原有的映射会被解除。
+///
+/// 函数成功执行后将返回调整后映射的起始地址。参数不合法时返回 `EINVAL`；原映射不存在时返回 `EFAULT`；
+/// 映射无法原地扩展且不允许移动时返回 `ENOMEM`。
+///
+/// Reference: [mremap](https://man7.org/linux/man-pages/man2/mremap.2.html)
+#[syscall_func(216)]
+pub fn do_mremap(
+    old_addr: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: u32,
+    new_addr: usize,
+) -> AlienResult<isize> {
+    let flags = MremapFlags::from_bits(flags).ok_or(LinuxErrno::EINVAL)?;
+    warn!(
+        "mremap: old_addr: {:#x}, old_size: {:#x}, new_size: {:#x}, flags: {:?}, new_addr: {:#x}",
+        old_addr, old_size, new_size, flags, new_addr
+    );
+    let process = current_task().unwrap();
+    let mut process_inner = process.access_inner();
+    process_inner
+        .remap(old_addr, old_size, new_size, flags, new_addr)
+        .map(|addr| addr as isize)
+}
+
 /// 一个系统调用，用于修改内存映射的保护位，从而修改对内存映射的访问权限。
-/// 函数会检查传入的`start`和`len`所指示的内存映射区是否已经处于被映射状态，如果是，则将对应内存映射区的保护位与`prot`做或运算。
+/// 函数会将`start`和`len`所指示范围内的内存映射区的保护位设置为`prot`，只被部分覆盖的内存映射区会被拆分。
 ///
-/// 如果函数正常执行，则返回0；如果`start`和`len`所指示的内存映射区未已经处于被映射状态，函数将返回-1。
+/// 如果函数正常执行，则返回0；如果`start`未与页对齐，返回`EINVAL`；如果该范围内没有任何映射，返回`ENOMEM`。
+/// Reference: [mprotect](https://man7.org/linux/man-pages/man2/mprotect.2.html)
 #[syscall_func(226)]
 pub fn map_protect(start: usize, len: usize, prot: u32) -> AlienResult<isize> {
     let process = current_task().unwrap();
@@ -219,34 +506,176 @@ pub fn map_protect(start: usize, len: usize, prot: u32) -> AlienResult<isize> {
     Ok(0)
 }
 
-/// (待实现)一个系统调用，用于同步文件在内存映射中的修改。一个文件通过[`do_mmap`]映射到内存中，可以在内存中对其进行快速的读写。
-/// 当我们对文件的映射进行修改后，如果不调用`msync`系统调用，那么在调用[`do_munmap`]之前内存中的相应内容都不会写回磁盘文件，有可能导致不一致性问题。
-/// 目前函数仅会检查所传入的`addr`是否已经被映射，如果没有被映射，则会返回`EFAULT`；否则直接返回0。
+/// 一个系统调用，用于同步文件在内存映射中的修改。一个文件通过[`do_mmap`]以 `MAP_SHARED` 方式映射到内存中后，
+/// 对映射的修改只会保存在文件的页缓存中，调用`msync`会将 `[addr, addr + len)` 范围内被修改过的页写回文件。
 ///
-/// Reference: [madvise](https://man7.org/linux/man-pages/man2/madvise.2.html)
+/// + `flags`: 可以为 `MS_ASYNC` 或 `MS_SYNC`，二者不能同时指定。目前两者都会在返回前完成写回。`MS_INVALIDATE` 无需处理，
+///   因为所有映射共享同一份缓存页。
+///
+/// `addr` 没有与页对齐或 `flags` 不合法时返回 `EINVAL`；范围中存在未被映射的地址时返回 `ENOMEM`。
+///
+/// Reference: [msync](https://man7.org/linux/man-pages/man2/msync.2.html)
 #[syscall_func(227)]
-pub fn msync(addr: usize, len: usize, flags: usize) -> isize {
+pub fn msync(addr: usize, len: usize, flags: usize) -> AlienResult<isize> {
     warn!(
         "msync: addr: {:#x}, len: {:#x}, flags: {:#x}",
         addr, len, flags
     );
+    let flags = MsyncFlags::from_bits(flags as u32).ok_or(LinuxErrno::EINVAL)?;
+    if flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC) || addr % FRAME_SIZE != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
     let task = current_task().unwrap();
-    let address_space = &task.access_inner().address_space;
-    let res = address_space.lock().query(VirtAddr::from(addr));
-    if res.is_err() {
-        return LinuxErrno::EFAULT as isize;
+    let inner = task.access_inner();
+    let end = addr + align_up_4k(len);
+    let mut start = addr;
+    while start < end {
+        let region = inner.mmap.get_region(start).ok_or(LinuxErrno::ENOMEM)?;
+        let region_end = min(end, region.start + region.map_len);
+        if let Some(cache) = region.page_cache() {
+            let offset = region.offset + (start - region.start);
+            cache.lock().sync_range(offset as u64, region_end - start)?;
+        }
+        start = region_end;
     }
-    0
+    Ok(0)
 }
 
-/// (待实现)一个系统调用，用于向内核提供使用内存的建议。目前直接返回0。
+/// 一个系统调用，用于向内核提供使用 `[addr, addr + len)` 范围内内存的建议。
+///
+/// + `MADV_DONTNEED`/`MADV_FREE`: 释放范围内的物理页，之后访问匿名页将读到全零的数据，访问文件映射将重新读入文件内容。`MADV_FREE` 只能用于匿名映射。
+/// + `MADV_WILLNEED`: 预先将文件页读入页缓存，并换入被换出的页。
+/// + `MADV_NORMAL`/`MADV_RANDOM`/`MADV_SEQUENTIAL`: 设置区域的访问模式，决定文件映射缺页时的预读页数。
+/// + `MADV_DONTFORK`/`MADV_DOFORK`: 设置子进程是否继承该区域。
+/// + `MADV_HUGEPAGE`/`MADV_NOHUGEPAGE`: 为匿名映射区域启用或禁用透明大页。
 ///
+/// `addr` 未与页对齐或者建议类型不受支持时返回 `EINVAL`，范围内没有任何映射时返回 `ENOMEM`。
 /// Reference: [madvise](https://man7.org/linux/man-pages/man2/madvise.2.html)
 #[syscall_func(233)]
-pub fn madvise(addr: usize, len: usize, advice: usize) -> isize {
+pub fn madvise(addr: usize, len: usize, advice: usize) -> AlienResult<isize> {
     warn!(
         "madvise: addr: {:#x}, len: {:#x}, advice: {:#x}",
         addr, len, advice
     );
-    0
+    let advice = MadviseAdvice::try_from(advice)?;
+    let task = current_task().unwrap();
+    task.access_inner().advise(addr, len, advice)?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于查询 `[addr, addr + len)` 范围内的页是否驻留在内存中。
+///
+/// 结果写入 `vec` 所指的数组，每一页对应一个字节，最低位为 1 表示该页驻留在内存中，文件映射的页只要在页缓存中即视为驻留。
+/// `addr` 未与页对齐时返回 `EINVAL`，范围内有未被映射的页时返回 `ENOMEM`。
+/// Reference: [mincore](https://man7.org/linux/man-pages/man2/mincore.2.html)
+#[syscall_func(232)]
+pub fn mincore(addr: usize, len: usize, vec: *mut u8) -> AlienResult<isize> {
+    if addr % FRAME_SIZE != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let residency = inner.mincore(addr..addr + align_up_4k(len))?;
+    if !residency.is_empty() {
+        inner.write_user_slice(UserSlice::new(vec as usize, residency.len()), &residency)?;
+    }
+    Ok(0)
+}
+
+/// 生成进程 `pid` 的 `/proc/<pid>/maps` 文件内容，`pid` 为读取者所在的 pid 命名空间中的 pid，进程不存在时返回 `None`
+///
+/// 每一行依次为区域的地址范围、权限、文件偏移、设备号、inode 号与路径，堆和栈分别以 `[heap]` 与 `[stack]` 表示。
+pub fn proc_maps(pid: usize) -> Option<String> {
+    let task = find_process_from_user(pid)?;
+    let inner = task.access_inner();
+    let mut maps = String::new();
+    let heap = inner.heap.lock();
+    if heap.end > heap.start {
+        maps += &format!(
+            "{:08x}-{:08x} rw-p 00000000 00:00 0 [heap]\n",
+            heap.start, heap.end
+        );
+    }
+    drop(heap);
+    for region in inner.mmap.regions() {
+        let path = region
+            .fd
+            .as_ref()
+            .map(|file| file.dentry().path())
+            .unwrap_or_default();
+        maps += &format!(
+            "{:08x}-{:08x} {} {:08x} 00:00 0 {}\n",
+            region.start,
+            region.start + region.map_len,
+            region.perms(),
+            region.offset,
+            path
+        );
+    }
+    maps += &format!(
+        "{:08x}-{:08x} rw-p 00000000 00:00 0 [stack]\n",
+        inner.stack.start, inner.stack.end
+    );
+    Some(maps)
+}
+
+#[cfg(test)]
+mod tests {
+    use config::FRAME_SIZE;
+    use constants::io::MapFlags;
+
+    use crate::mm::map::{MMapInfo, MMapRegion, ProtFlags, GROWSDOWN_GAP};
+
+    const START: usize = 0x1000_0000;
+
+    fn region(start: usize, len: usize) -> MMapRegion {
+        MMapRegion::new(
+            start,
+            len,
+            len,
+            ProtFlags::PROT_READ,
+            MapFlags::MAP_ANONYMOUS,
+            None,
+            0,
+        )
+    }
+
+    #[test]
+    pub fn test_mmap_alloc() {
+        let mut info = MMapInfo::new(START, START + 0x10_0000);
+        assert_eq!(info.alloc(1), Some(START..START + FRAME_SIZE));
+        info.add_region(region(START, FRAME_SIZE));
+        info.add_region(region(START + 3 * FRAME_SIZE, FRAME_SIZE));
+        // 两个区域之间的空隙可以被重新使用
+        assert_eq!(
+            info.alloc(2 * FRAME_SIZE),
+            Some(START + FRAME_SIZE..START + 3 * FRAME_SIZE)
+        );
+        assert_eq!(
+            info.alloc(3 * FRAME_SIZE),
+            Some(START + 4 * FRAME_SIZE..START + 7 * FRAME_SIZE)
+        );
+        info.reserve_range(START + FRAME_SIZE..START + 2 * FRAME_SIZE);
+        assert_eq!(
+            info.alloc(FRAME_SIZE),
+            Some(START + 2 * FRAME_SIZE..START + 3 * FRAME_SIZE)
+        );
+        assert_eq!(info.alloc(0x10_0000), None);
+        assert_eq!(info.alloc(usize::MAX - FRAME_SIZE), None);
+    }
+
+    #[test]
+    pub fn test_mmap_alloc_growsdown_gap() {
+        let top = START + 2 * GROWSDOWN_GAP;
+        let mut info = MMapInfo::new(START, top);
+        let mut stack = region(START + GROWSDOWN_GAP, FRAME_SIZE);
+        stack.grows_down = true;
+        info.add_region(stack);
+        // 向下增长的区域下方的间隔不能被分配
+        assert_eq!(
+            info.alloc(FRAME_SIZE),
+            Some(START + GROWSDOWN_GAP + FRAME_SIZE..START + GROWSDOWN_GAP + 2 * FRAME_SIZE)
+        );
+        assert_eq!(info.alloc(GROWSDOWN_GAP), None);
+    }
 }
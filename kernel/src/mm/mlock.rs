@@ -0,0 +1,243 @@
+//! 内存锁定(mlock)。
+//!
+//! 被锁定的页常驻内存，页面回收时不会被换出。每个地址空间中被锁定的范围记录在这里，
+//! 锁定时内核会预先为范围内的每一页分配物理页(`MLOCK_ONFAULT`/`MCL_ONFAULT` 除外)，
+//! 之后在被锁定的范围中缺页分配的页同样不会被换出。
+//!
+//! 一个进程锁定的总大小受 `RLIMIT_MEMLOCK` 的限制，拥有 `CAP_IPC_LOCK` 的进程不受限制。
+//! 解除映射的范围自动解除锁定，锁定不会被 fork 产生的子进程继承，进程退出或执行新程序时其地址空间的锁定全部解除。
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    cmp::{max, min},
+    ops::Range,
+};
+
+use bitflags::bitflags;
+use config::FRAME_SIZE;
+use constants::{AlienResult, LinuxErrno};
+use ksync::Mutex;
+use mem::VmmPageAllocator;
+use page_table::table::Sv39PageTable;
+use syscall_table::syscall_func;
+
+use crate::task::current_task;
+
+type AddressSpace = Mutex<Sv39PageTable<VmmPageAllocator>>;
+
+bitflags! {
+    pub struct MlockallFlags: u32 {
+        /// 锁定当前所有的映射
+        const MCL_CURRENT = 1;
+        /// 锁定之后创建的映射
+        const MCL_FUTURE = 2;
+        /// 只锁定而不预先分配物理页
+        const MCL_ONFAULT = 4;
+    }
+}
+
+/// mlock2: 只锁定而不预先分配物理页
+const MLOCK_ONFAULT: usize = 1;
+
+/// 一个地址空间中被锁定的范围
+struct LockedSpace {
+    space: Weak<AddressSpace>,
+    /// 以起始地址为键，值为结束地址，范围之间互不重叠也不相邻
+    ranges: BTreeMap<usize, usize>,
+}
+
+impl LockedSpace {
+    /// 从被锁定的范围中去除 `range`
+    fn remove(&mut self, range: Range<usize>) {
+        let overlaps = self
+            .ranges
+            .range(..range.end)
+            .filter(|(_, end)| **end > range.start)
+            .map(|(start, end)| (*start, *end))
+            .collect::<Vec<_>>();
+        for (start, end) in overlaps {
+            self.ranges.remove(&start);
+            if start < range.start {
+                self.ranges.insert(start, range.start);
+            }
+            if range.end < end {
+                self.ranges.insert(range.end, end);
+            }
+        }
+    }
+
+    /// 将 `range` 加入被锁定的范围，与其重叠或相邻的范围被合并
+    fn insert(&mut self, range: Range<usize>) {
+        let mut start = range.start;
+        let mut end = range.end;
+        let adjacent = self
+            .ranges
+            .range(..=range.end)
+            .filter(|(_, e)| **e >= range.start)
+            .map(|(s, e)| (*s, *e))
+            .collect::<Vec<_>>();
+        for (s, e) in adjacent {
+            self.ranges.remove(&s);
+            start = min(start, s);
+            end = max(end, e);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    /// `range` 范围内被锁定的字节数
+    fn locked_in(&self, range: Range<usize>) -> usize {
+        self.ranges
+            .range(..range.end)
+            .map(|(start, end)| min(*end, range.end).saturating_sub(max(*start, range.start)))
+            .sum()
+    }
+}
+
+/// 所有地址空间中被锁定的范围，以地址空间的地址为键
+static LOCKED: Mutex<BTreeMap<usize, LockedSpace>> = Mutex::new(BTreeMap::new());
+
+/// 查找地址 `key` 处的地址空间的锁定记录，该地址空间已经被释放时其记录已经失效，返回 `None`
+fn live_entry(locked: &BTreeMap<usize, LockedSpace>, key: usize) -> Option<&LockedSpace> {
+    locked
+        .get(&key)
+        .filter(|entry| entry.space.strong_count() > 0)
+}
+
+/// 计算 `[addr, addr + len)` 所覆盖的页的范围，范围超出地址空间时返回 `EINVAL`
+fn page_range(addr: usize, len: usize) -> AlienResult<Range<usize>> {
+    let end = addr
+        .checked_add(len)
+        .and_then(|end| end.checked_add(FRAME_SIZE - 1))
+        .ok_or(LinuxErrno::EINVAL)?;
+    Ok(addr & !(FRAME_SIZE - 1)..end & !(FRAME_SIZE - 1))
+}
+
+/// 锁定地址空间 `space` 中 `range` 范围内的页
+pub fn lock_range(space: &Arc<AddressSpace>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    let mut locked = LOCKED.lock();
+    locked.retain(|_, entry| entry.space.strong_count() > 0);
+    locked
+        .entry(Arc::as_ptr(space) as usize)
+        .or_insert_with(|| LockedSpace {
+            space: Arc::downgrade(space),
+            ranges: BTreeMap::new(),
+        })
+        .insert(range);
+}
+
+/// 解除地址空间 `space` 中 `range` 范围内的页的锁定
+pub fn unlock_range(space: &Arc<AddressSpace>, range: Range<usize>) {
+    let mut locked = LOCKED.lock();
+    let key = Arc::as_ptr(space) as usize;
+    if let Some(entry) = locked.get_mut(&key) {
+        entry.remove(range);
+        if entry.ranges.is_empty() {
+            locked.remove(&key);
+        }
+    }
+}
+
+/// 移动映射时调用，将地址空间 `space` 中 `from` 范围内的锁定移动到以 `to` 开始的位置
+pub fn move_locked_range(space: &Arc<AddressSpace>, from: Range<usize>, to: usize) {
+    let mut locked = LOCKED.lock();
+    let entry = match locked.get_mut(&(Arc::as_ptr(space) as usize)) {
+        Some(entry) => entry,
+        None => return,
+    };
+    let moved = entry
+        .ranges
+        .range(..from.end)
+        .filter(|(_, end)| **end > from.start)
+        .map(|(start, end)| max(*start, from.start)..min(*end, from.end))
+        .collect::<Vec<_>>();
+    entry.remove(from.clone());
+    for range in moved {
+        entry.insert(range.start - from.start + to..range.end - from.start + to);
+    }
+}
+
+/// 地址空间 `space` 中 `range` 范围内被锁定的字节数
+pub fn locked_bytes(space: &Arc<AddressSpace>, range: Range<usize>) -> usize {
+    live_entry(&LOCKED.lock(), Arc::as_ptr(space) as usize)
+        .map_or(0, |entry| entry.locked_in(range))
+}
+
+/// 页面回收时调用，判断地址空间 `space` 中的页 `addr` 是否被锁定。
+///
+/// 调用者可能持有锁定记录的锁，此时无法判断，返回 `None`。
+pub fn page_locked(space: usize, addr: usize) -> Option<bool> {
+    let locked = LOCKED.try_lock()?;
+    Some(
+        live_entry(&locked, space)
+            .map_or(false, |entry| entry.locked_in(addr..addr + FRAME_SIZE) > 0),
+    )
+}
+
+/// 一个系统调用，用于锁定 `[addr, addr + len)` 范围内的内存，使其常驻内存而不会被换出。
+///
+/// 范围内的每一页都必须已经被映射，否则返回 `ENOMEM`；`addr + len` 溢出时返回 `EINVAL`；锁定的总大小超过 `RLIMIT_MEMLOCK` 的限制时返回 `ENOMEM`，
+/// 该限制为 0 且进程没有 `CAP_IPC_LOCK` 时返回 `EPERM`。
+///
+/// Reference: [mlock](https://man7.org/linux/man-pages/man2/mlock.2.html)
+#[syscall_func(228)]
+pub fn mlock(addr: usize, len: usize) -> AlienResult<isize> {
+    mlock2(addr, len, 0)
+}
+
+/// 一个系统调用，与 [`mlock`] 相同，`flags` 中包含 `MLOCK_ONFAULT` 时不会预先分配物理页，页在缺页时才被分配并锁定。
+///
+/// Reference: [mlock2](https://man7.org/linux/man-pages/man2/mlock2.2.html)
+#[syscall_func(284)]
+pub fn mlock2(addr: usize, len: usize, flags: usize) -> AlienResult<isize> {
+    if flags & !MLOCK_ONFAULT != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let range = page_range(addr, len)?;
+    let task = current_task().unwrap();
+    task.access_inner()
+        .mlock(range, flags & MLOCK_ONFAULT == 0)?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于解除 `[addr, addr + len)` 范围内内存的锁定。
+///
+/// Reference: [munlock](https://man7.org/linux/man-pages/man2/munlock.2.html)
+#[syscall_func(229)]
+pub fn munlock(addr: usize, len: usize) -> AlienResult<isize> {
+    let range = page_range(addr, len)?;
+    let task = current_task().unwrap();
+    unlock_range(&task.access_inner().address_space, range);
+    Ok(0)
+}
+
+/// 一个系统调用，用于锁定进程当前的所有映射(`MCL_CURRENT`)以及之后创建的映射(`MCL_FUTURE`)。
+///
+/// Reference: [mlockall](https://man7.org/linux/man-pages/man2/mlockall.2.html)
+#[syscall_func(230)]
+pub fn mlockall(flags: u32) -> AlienResult<isize> {
+    let flags = MlockallFlags::from_bits(flags).ok_or(LinuxErrno::EINVAL)?;
+    if !flags.intersects(MlockallFlags::MCL_CURRENT | MlockallFlags::MCL_FUTURE) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    task.access_inner().mlockall(flags)?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于解除进程所有映射的锁定，之后创建的映射也不再被锁定。
+///
+/// Reference: [munlockall](https://man7.org/linux/man-pages/man2/munlockall.2.html)
+#[syscall_func(231)]
+pub fn munlockall() -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    inner.mlockall = MlockallFlags::empty();
+    unlock_range(&inner.address_space, 0..usize::MAX);
+    0
+}
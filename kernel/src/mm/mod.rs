@@ -1,8 +1,15 @@
 use arch::hart_id;
 
+pub mod aslr;
 pub mod elf;
 pub mod loader;
 pub mod map;
+pub mod mlock;
+pub mod oom;
+pub mod stat;
+pub mod swap;
+pub mod thp;
+pub mod uaccess;
 
 /// This function will be call in slab allocator
 #[no_mangle]
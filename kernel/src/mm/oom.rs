@@ -0,0 +1,133 @@
+//! 内存耗尽处理(OOM killer)。
+//!
+//! 物理页分配失败且 [`reclaim`](super::swap) 无法回收任何页面时，内核会调用 [`out_of_memory`] 记录内存已经耗尽。
+//! 此时仍处于物理页分配器中，调用者可能持有任意的锁，因此选择进程并发送 `SIGKILL` 被推迟到返回用户态之前的
+//! [`oom_kill`] 中完成，被选中的进程退出后释放的物理页可以满足后续的分配。
+//! 分配失败的系统调用返回 `ENOMEM`，分配失败的缺页异常则向出错的进程发送 `SIGBUS`。
+//!
+//! 每个进程在创建地址空间时通过 [`register_address_space`] 登记，OOM killer 根据登记的地址空间统计各个进程占用的物理页数。
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use config::FRAME_SIZE;
+use constants::signal::SignalNumber;
+use ksync::Mutex;
+use mem::VmmPageAllocator;
+use page_table::{pte::MappingFlags, table::Sv39PageTable};
+
+use crate::{
+    ipc::{process_exists, send_process_signal_info, sigqueue::SignalInfo},
+    system::kernel_log,
+    task::current_task,
+};
+
+type AddressSpace = Mutex<Sv39PageTable<VmmPageAllocator>>;
+
+/// init 进程的 pid，它永远不会被选中
+const INIT_PID: usize = 1;
+
+/// 一个进程登记的地址空间
+struct OomEntry {
+    name: String,
+    space: Weak<AddressSpace>,
+}
+
+/// 所有进程的地址空间，以 pid 为键
+static ADDRESS_SPACES: Mutex<BTreeMap<usize, OomEntry>> = Mutex::new(BTreeMap::new());
+
+/// 上一次被选中的进程，该进程退出之前不会再选择新的进程
+static OOM_VICTIM: AtomicUsize = AtomicUsize::new(0);
+
+/// 内存已经耗尽，需要在返回用户态之前结束一个进程
+static OOM_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 登记进程 `pid` 的地址空间，进程创建或执行新程序时调用
+pub fn register_address_space(pid: usize, name: &str, space: &Arc<AddressSpace>) {
+    let mut spaces = ADDRESS_SPACES.lock();
+    spaces.retain(|_, entry| entry.space.strong_count() > 0);
+    spaces.insert(
+        pid,
+        OomEntry {
+            name: String::from(name),
+            space: Arc::downgrade(space),
+        },
+    );
+}
+
+/// 统计地址空间中已经分配了物理页的页数
+fn resident_pages(space: &mut Sv39PageTable<VmmPageAllocator>) -> usize {
+    let addrs = space
+        .get_record_mut()
+        .iter()
+        .filter(|(_, target)| **target)
+        .map(|(addr, _)| *addr)
+        .collect::<Vec<_>>();
+    addrs
+        .into_iter()
+        .filter_map(|addr| space.query(addr).ok())
+        .filter(|(_, flags, _)| flags.contains(MappingFlags::V))
+        .map(|(_, _, size)| usize::from(size) / FRAME_SIZE)
+        .sum()
+}
+
+/// 物理内存耗尽时由页面回收函数调用，只记录需要结束一个进程
+///
+/// 该函数在分配物理页失败时被调用，调用者可能持有任意的锁。向进程发送信号需要获取进程的锁并分配内存，
+/// 在这里进行可能造成死锁，因此只设置标志位，由 [`oom_kill`] 在调用者释放所有的锁之后完成。
+pub fn out_of_memory() {
+    OOM_PENDING.store(true, Ordering::Release);
+}
+
+/// 在返回用户态之前调用，内存耗尽时选择占用物理页最多的进程并结束它
+///
+/// 此时当前核没有持有任何锁，可以等待地址空间的锁以统计各个进程占用的物理页数。找不到其它进程时选择当前进程。
+pub fn oom_kill() {
+    if !OOM_PENDING.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    let victim = OOM_VICTIM.load(Ordering::Acquire);
+    if victim != 0 && process_exists(victim) {
+        // 上一次选中的进程还没有退出，它占用的内存将很快被释放
+        return;
+    }
+    let current = current_task().map(|task| task.pid);
+    let mut candidate = None;
+    for (pid, entry) in ADDRESS_SPACES.lock().iter() {
+        if *pid == INIT_PID {
+            continue;
+        }
+        let space = match entry.space.upgrade() {
+            Some(space) => space,
+            None => continue,
+        };
+        let pages = resident_pages(&mut space.lock());
+        if candidate.as_ref().map_or(true, |(_, _, max)| pages > *max) {
+            candidate = Some((*pid, entry.name.clone(), pages));
+        }
+    }
+    let (pid, name, pages) = match (candidate, current) {
+        (Some(candidate), _) => candidate,
+        (None, Some(pid)) if pid != INIT_PID => (pid, String::new(), 0),
+        _ => {
+            error!("out of memory: no process to kill");
+            return;
+        }
+    };
+    OOM_VICTIM.store(pid, Ordering::Release);
+    error!(
+        "out of memory: kill process {} ({}), {} pages",
+        pid, name, pages
+    );
+    kernel_log(format_args!(
+        "Out of memory: Killed process {} ({}) rss:{}kB",
+        pid,
+        name,
+        pages * FRAME_SIZE / 1024
+    ));
+    let _ = send_process_signal_info(pid, SignalInfo::kernel(SignalNumber::SIGKILL as usize));
+}
@@ -0,0 +1,107 @@
+//! 进程的内存使用统计。
+//!
+//! 统计结果在读取 `/proc/<pid>/status` 与 `/proc/<pid>/statm` 时根据进程的页表即时计算，因此总是与页表保持一致。
+//! 驻留集(RSS)分为匿名页、文件页与共享内存页三类，fork 后父子进程共享的写时复制页同时计入两者。
+//! 将所有进程的驻留集与系统中已分配的物理页数对比，可以发现解除映射后没有被释放的页。
+use alloc::{format, string::String};
+
+use config::FRAME_SIZE;
+
+use crate::task::{find_process_from_user, pid_to_user, TaskState};
+
+/// 一个进程的内存使用情况，大小以字节为单位，页数以页为单位
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MemUsage {
+    /// 虚拟地址空间的大小
+    pub size: usize,
+    /// 匿名页的页数
+    pub anon: usize,
+    /// 文件页的页数，包括页缓存中的页与只读的 ELF 段
+    pub file: usize,
+    /// 共享内存与共享匿名映射的页数
+    pub shmem: usize,
+    /// 被 mlock 锁定的大小
+    pub locked: usize,
+    /// 堆、私有可写映射与可写 ELF 段的大小
+    pub data: usize,
+    /// 用户栈的大小
+    pub stack: usize,
+    /// 代码段的大小
+    pub text: usize,
+    /// 被换出的页数
+    pub swap: usize,
+}
+
+impl MemUsage {
+    /// 驻留集的页数
+    pub fn rss(&self) -> usize {
+        self.anon + self.file + self.shmem
+    }
+}
+
+/// 以 kB 为单位输出 `/proc/<pid>/status` 中的一项
+fn kb(name: &str, bytes: usize) -> String {
+    format!("{}:\t{:>8} kB\n", name, bytes / 1024)
+}
+
+/// 生成 `/proc/<pid>/status` 的内容，`pid` 为读取者所在的 pid 命名空间中的 pid
+pub fn proc_status(pid: usize) -> Option<String> {
+    let task = find_process_from_user(pid)?;
+    let state = match task.state() {
+        TaskState::Running => "R (running)",
+        TaskState::Ready => "R (running)",
+        TaskState::Waiting => "S (sleeping)",
+        TaskState::Traced => "t (tracing stop)",
+        TaskState::Zombie | TaskState::Terminated => "Z (zombie)",
+    };
+    let inner = task.access_inner();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| pid_to_user(parent.pid));
+    let cred = inner.cred.clone();
+    let usage = inner.mem_usage();
+    drop(inner);
+    let mut status = format!(
+        "Name:\t{}\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\nUid:\t{}\t{}\t{}\t{}\nGid:\t{}\t{}\t{}\t{}\n",
+        task.get_name(),
+        state,
+        pid,
+        pid,
+        ppid,
+        cred.ruid,
+        cred.euid,
+        cred.suid,
+        cred.fsuid,
+        cred.rgid,
+        cred.egid,
+        cred.sgid,
+        cred.fsgid
+    );
+    status += &kb("VmSize", usage.size);
+    status += &kb("VmLck", usage.locked);
+    status += &kb("VmRSS", usage.rss() * FRAME_SIZE);
+    status += &kb("RssAnon", usage.anon * FRAME_SIZE);
+    status += &kb("RssFile", usage.file * FRAME_SIZE);
+    status += &kb("RssShmem", usage.shmem * FRAME_SIZE);
+    status += &kb("VmData", usage.data);
+    status += &kb("VmStk", usage.stack);
+    status += &kb("VmExe", usage.text);
+    status += &kb("VmSwap", usage.swap * FRAME_SIZE);
+    Some(status)
+}
+
+/// 生成 `/proc/<pid>/statm` 的内容，`pid` 为读取者所在的 pid 命名空间中的 pid，依次为虚拟地址空间大小、驻留集、共享页、代码段、库(总为 0)、数据段与脏页(总为 0)的页数
+pub fn proc_statm(pid: usize) -> Option<String> {
+    let task = find_process_from_user(pid)?;
+    let usage = task.access_inner().mem_usage();
+    Some(format!(
+        "{} {} {} {} 0 {} 0\n",
+        usage.size / FRAME_SIZE,
+        usage.rss(),
+        usage.file + usage.shmem,
+        usage.text / FRAME_SIZE,
+        (usage.data + usage.stack) / FRAME_SIZE
+    ))
+}
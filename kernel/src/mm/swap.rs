@@ -0,0 +1,603 @@
+//! 页面回收与交换。
+//!
+//! 物理页不足时，物理页分配器会调用 [`reclaim`] 回收页面：首先丢弃页缓存中最久未被访问的干净页，
+//! 然后将匿名页(堆、栈与匿名映射中的页)写入交换区，释放其占用的物理页。
+//!
+//! 启用交换区后，匿名页在缺页时被加入 LRU 链表。回收时从链表头部开始扫描，并使用页表项的访问位(A)
+//! 实现二次机会算法：访问位被设置的页清除访问位后移动到链表尾部，访问位没有被设置的页才会被换出。
+//! 被换出的页在页表中保留一个无效的页表项，再次访问时在缺页处理中从交换区读回，见 [`swap_in`]。
+//! 被 [`mlock`](super::mlock) 锁定的页不会被换出。
+//!
+//! 交换区可以是块设备或普通文件，需要事先使用 `mkswap` 格式化，并通过 [`swapon`] 启用。
+//!
+//! 内核通过物理地址直接访问用户缓冲区，这种访问不会设置访问位。系统调用在使用期间会固定缓冲区所在的页
+//! (见 [`UserBuffers`](super::uaccess::UserBuffers))，使其引用计数大于一，回收时跳过这样的页；
+//! 此外回收时也会跳过当前进程的地址空间。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::{
+    cmp::max,
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use arch::hart_id;
+use config::{FRAME_BITS, FRAME_SIZE, PATH_MAX};
+use constants::{AlienResult, LinuxErrno, AT_FDCWD};
+use ksync::Mutex;
+use mem::{alloc_frame_trackers, VmmPageAllocator, FRAME_REF_MANAGER};
+use page_table::{addr::VirtAddr, pte::MappingFlags, table::Sv39PageTable};
+use spin::Lazy;
+use syscall_table::syscall_func;
+use vfs::page_cache::reclaim_clean_pages;
+use vfscore::{inode::VfsInode, utils::VfsNodeType};
+
+use crate::{
+    fs::user_path_at,
+    mm::{mlock::page_locked, oom::out_of_memory},
+    task::{current_task, do_suspend, Capabilities},
+};
+
+type AddressSpace = Mutex<Sv39PageTable<VmmPageAllocator>>;
+
+/// 交换区头部的签名，位于交换区第一页的末尾
+const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+/// 交换区头部中 `last_page` 字段的偏移
+const SWAP_LAST_PAGE_OFFSET: usize = 1028;
+/// 交换区头部中 `nr_badpages` 字段的偏移
+const SWAP_NR_BADPAGES_OFFSET: usize = 1032;
+/// 交换区头部中坏页列表的偏移
+const SWAP_BADPAGES_OFFSET: usize = 1536;
+
+/// swapon: 使用 `flags` 中指定的优先级
+const SWAP_FLAG_PREFER: usize = 0x8000;
+/// swapon: 优先级所在的位
+const SWAP_FLAG_PRIO_MASK: usize = 0x7fff;
+/// swapon: 所有合法的标志位，丢弃(discard)相关的标志位被接受但不起作用
+const SWAP_FLAGS_VALID: usize = SWAP_FLAG_PRIO_MASK | SWAP_FLAG_PREFER | 0x70000;
+
+/// 每次回收的最少页数，回收后留有余量，避免紧接着的分配再次触发回收
+const RECLAIM_BATCH: usize = 32;
+/// 坏页或交换区头部对应的交换槽，永远不会被分配
+const SLOT_RESERVED: u16 = u16::MAX;
+
+/// 交换区中的一个交换槽
+#[derive(Debug, Copy, Clone)]
+struct SwapSlot {
+    /// 交换区的编号
+    area: usize,
+    /// 交换槽在交换区中的页号
+    index: usize,
+}
+
+/// 一个被换出的页
+struct SwappedPage {
+    /// 页所在的地址空间。持有弱引用保证地址空间的地址在该记录存在期间不会被复用
+    space: Weak<AddressSpace>,
+    slot: SwapSlot,
+}
+
+/// 一个已启用的交换区
+struct SwapArea {
+    id: usize,
+    inode: Arc<dyn VfsInode>,
+    priority: isize,
+    /// 每个交换槽被引用的次数，fork 后父子进程共享被换出的页
+    slots: Vec<u16>,
+    /// 空闲的交换槽数量
+    free: usize,
+    /// 正在被停用的交换区不再分配交换槽
+    active: bool,
+}
+
+impl SwapArea {
+    fn alloc_slot(&mut self) -> Option<usize> {
+        if !self.active || self.free == 0 {
+            return None;
+        }
+        let index = self.slots.iter().position(|refs| *refs == 0)?;
+        self.slots[index] = 1;
+        self.free -= 1;
+        Some(index)
+    }
+}
+
+struct SwapState {
+    /// 已启用的交换区，按照优先级从高到低排列
+    areas: Vec<SwapArea>,
+    next_id: usize,
+    /// 下一个没有指定优先级的交换区使用的优先级
+    next_priority: isize,
+    /// 被换出的页，以 (地址空间, 虚拟页地址) 为键
+    swapped: BTreeMap<(usize, usize), SwappedPage>,
+}
+
+impl SwapState {
+    fn area_mut(&mut self, id: usize) -> &mut SwapArea {
+        self.areas.iter_mut().find(|area| area.id == id).unwrap()
+    }
+
+    /// 从优先级最高的交换区中分配一个交换槽
+    fn alloc_slot(&mut self) -> Option<SwapSlot> {
+        self.areas.iter_mut().find_map(|area| {
+            area.alloc_slot().map(|index| SwapSlot {
+                area: area.id,
+                index,
+            })
+        })
+    }
+
+    fn dup_slot(&mut self, slot: SwapSlot) {
+        self.area_mut(slot.area).slots[slot.index] += 1;
+    }
+
+    fn free_slot(&mut self, slot: SwapSlot) {
+        let area = self.area_mut(slot.area);
+        area.slots[slot.index] -= 1;
+        if area.slots[slot.index] == 0 {
+            area.free += 1;
+        }
+    }
+
+    /// 释放地址空间已经被销毁的页占用的交换槽
+    fn collect_dead(&mut self) {
+        let dead = self
+            .swapped
+            .iter()
+            .filter(|(_, page)| page.space.strong_count() == 0)
+            .map(|(key, page)| (*key, page.slot))
+            .collect::<Vec<_>>();
+        for (key, slot) in dead {
+            self.swapped.remove(&key);
+            self.free_slot(slot);
+        }
+    }
+}
+
+static SWAP: Lazy<Mutex<SwapState>> = Lazy::new(|| {
+    Mutex::new(SwapState {
+        areas: Vec::new(),
+        next_id: 0,
+        next_priority: -1,
+        swapped: BTreeMap::new(),
+    })
+});
+
+/// 匿名页的 LRU 链表，每一项为 (所在的地址空间, 虚拟页地址, 加入链表时所属的进程)
+static ANON_LRU: Lazy<Mutex<VecDeque<(Weak<AddressSpace>, usize, usize)>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// 交换区是否已经启用，没有启用交换区时不需要记录匿名页
+static SWAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 正在回收页面的核，第 i 位对应第 i 个核。回收过程中的 I/O 也可能分配物理页，此时不能再次进入回收
+static RECLAIMING: AtomicUsize = AtomicUsize::new(0);
+
+/// 向物理页分配器注册页面回收函数
+pub fn init_reclaim() {
+    mem::register_reclaimer(reclaim);
+}
+
+/// 物理页不足时由物理页分配器调用，至少回收 `count` 个页，返回实际回收的页数
+///
+/// 没有回收到任何页面时通知 OOM killer 在返回用户态之前结束一个进程。
+fn reclaim(count: usize) -> usize {
+    let hart = 1 << hart_id();
+    if RECLAIMING.fetch_or(hart, Ordering::Acquire) & hart != 0 {
+        return 0;
+    }
+    let target = max(count, RECLAIM_BATCH);
+    let mut reclaimed = reclaim_clean_pages(target);
+    if reclaimed < target {
+        reclaimed += swap_out(target - reclaimed);
+    }
+    if reclaimed == 0 {
+        out_of_memory();
+    }
+    RECLAIMING.fetch_and(!hart, Ordering::Release);
+    warn!("reclaim {} pages", reclaimed);
+    reclaimed
+}
+
+/// 将至多 `count` 个匿名页换出到交换区，返回换出的页数
+///
+/// 调用者可能持有任意地址空间的锁或者物理页引用计数的锁，因此这里不会等待被占用的锁。
+fn swap_out(count: usize) -> usize {
+    if !SWAP_ENABLED.load(Ordering::Acquire) {
+        return 0;
+    }
+    let (mut lru, mut swap) = match (ANON_LRU.try_lock(), SWAP.try_lock()) {
+        (Some(lru), Some(swap)) => (lru, swap),
+        _ => return 0,
+    };
+    swap.collect_dead();
+    let current_pid = current_task().map(|task| task.pid);
+    let mut reclaimed = 0;
+    // 每一项至多被扫描两次：第一次清除访问位，第二次换出
+    let mut budget = lru.len() * 2;
+    while reclaimed < count && budget > 0 {
+        budget -= 1;
+        let (weak, addr, pid) = match lru.pop_front() {
+            Some(entry) => entry,
+            None => break,
+        };
+        let space = match weak.upgrade() {
+            Some(space) => space,
+            None => continue,
+        };
+        if Some(pid) == current_pid {
+            lru.push_back((weak, addr, pid));
+            continue;
+        }
+        // 被锁定的页常驻内存
+        if page_locked(Arc::as_ptr(&space) as usize, addr) != Some(false) {
+            lru.push_back((weak, addr, pid));
+            continue;
+        }
+        let mut table = match space.try_lock() {
+            Some(table) => table,
+            None => {
+                lru.push_back((weak, addr, pid));
+                continue;
+            }
+        };
+        let v_addr = VirtAddr::from(addr);
+        let (phy, flags, size) = match table.query(v_addr) {
+            Ok(res) => res,
+            Err(_) => continue,
+        };
+        // 页已经被解除映射或者不再属于该地址空间
+        if !flags.contains(MappingFlags::V)
+            || usize::from(size) != FRAME_SIZE
+            || table.get_record_mut().get(&v_addr) != Some(&true)
+        {
+            continue;
+        }
+        // 写时复制共享的页暂时不能换出；调用者可能正持有引用计数的锁，此时跳过该页，也不会释放任何物理页
+        let refs = FRAME_REF_MANAGER
+            .try_lock()
+            .map(|manager| manager.get_ref(phy.as_usize() >> FRAME_BITS));
+        if refs != Some(1) {
+            lru.push_back((weak, addr, pid));
+            continue;
+        }
+        if flags.contains(MappingFlags::A) {
+            table
+                .modify_pte_flags(v_addr, flags - MappingFlags::A, false)
+                .unwrap();
+            lru.push_back((weak, addr, pid));
+            continue;
+        }
+        let slot = match swap.alloc_slot() {
+            Some(slot) => slot,
+            None => {
+                lru.push_front((weak, addr, pid));
+                break;
+            }
+        };
+        let buf = unsafe { core::slice::from_raw_parts(phy.as_usize() as *const u8, FRAME_SIZE) };
+        let inode = swap.area_mut(slot.area).inode.clone();
+        if inode
+            .write_at((slot.index * FRAME_SIZE) as u64, buf)
+            .is_err()
+        {
+            swap.free_slot(slot);
+            lru.push_back((weak, addr, pid));
+            continue;
+        }
+        // 释放物理页，保留一个无效的页表项，再次访问时触发缺页
+        table.unmap_region(v_addr, FRAME_SIZE).unwrap();
+        table
+            .map_region_no_target(v_addr, FRAME_SIZE, flags - MappingFlags::V, false, true)
+            .unwrap();
+        swap.swapped.insert(
+            (Arc::as_ptr(&space) as usize, addr),
+            SwappedPage { space: weak, slot },
+        );
+        reclaimed += 1;
+    }
+    reclaimed
+}
+
+/// 将地址空间 `space` 中的匿名页 `addr` 加入 LRU 链表，使其可以被换出
+pub fn track_anon_page(space: &Arc<AddressSpace>, addr: usize) {
+    if !SWAP_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    let pid = current_task().map_or(0, |task| task.pid);
+    ANON_LRU
+        .lock()
+        .push_back((Arc::downgrade(space), addr & !(FRAME_SIZE - 1), pid));
+}
+
+/// 将地址空间 `space` 中被换出的页 `addr` 读回内存，页不在交换区中时返回 `false`
+///
+/// 读取交换区时不持有 `SWAP` 的锁：先增加交换槽的引用计数，保证读取期间交换槽不会被释放或者重新分配，
+/// 将数据读到一个临时的物理页中，再重新获取锁，只有该页仍然位于该交换槽中时才将数据复制到新分配的页中。
+/// 读取期间该页已经被其它线程读回或者被解除映射时，丢弃读到的数据。
+fn load_page(space: &Arc<AddressSpace>, addr: usize) -> AlienResult<bool> {
+    let key = (Arc::as_ptr(space) as usize, addr);
+    let (slot, inode) = {
+        let mut swap = SWAP.lock();
+        let slot = match swap.swapped.get(&key) {
+            Some(page) => page.slot,
+            None => return Ok(false),
+        };
+        swap.dup_slot(slot);
+        (slot, swap.area_mut(slot.area).inode.clone())
+    };
+    let frame = alloc_frame_trackers(1)
+        .ok_or(LinuxErrno::ENOMEM)
+        .and_then(|mut frame| {
+            inode.read_at((slot.index * FRAME_SIZE) as u64, &mut frame[..])?;
+            Ok(frame)
+        });
+    let mut swap = SWAP.lock();
+    swap.free_slot(slot);
+    let frame = frame?;
+    if swap.swapped.get(&key).map(|page| page.slot) != Some(slot) {
+        return Ok(true);
+    }
+    let mut table = space.lock();
+    let (_, flags, _) = table
+        .query(VirtAddr::from(addr))
+        .map_err(|_| LinuxErrno::EFAULT)?;
+    table
+        .validate(VirtAddr::from(addr), flags | "VAD".into())
+        .map_err(|_| LinuxErrno::ENOMEM)?;
+    let (phy, _, _) = table.query(VirtAddr::from(addr)).unwrap();
+    unsafe {
+        core::ptr::copy_nonoverlapping(frame.as_ptr(), phy.as_usize() as *mut u8, FRAME_SIZE);
+    }
+    drop(table);
+    swap.swapped.remove(&key);
+    swap.free_slot(slot);
+    Ok(true)
+}
+
+/// 在缺页处理中调用，如果地址空间 `space` 中的页 `addr` 已经被换出，则将其读回内存并返回 `true`
+pub fn swap_in(space: &Arc<AddressSpace>, addr: usize) -> AlienResult<bool> {
+    // 停用最后一个交换区前所有被换出的页都已经被读回
+    if !SWAP_ENABLED.load(Ordering::Acquire) {
+        return Ok(false);
+    }
+    let addr = addr & !(FRAME_SIZE - 1);
+    if !load_page(space, addr)? {
+        return Ok(false);
+    }
+    track_anon_page(space, addr);
+    Ok(true)
+}
+
+/// 创建子进程时调用，子进程与父进程共享父进程中被换出的页
+pub fn fork_swapped_pages(parent: &Arc<AddressSpace>, child: &Arc<AddressSpace>) {
+    let mut swap = SWAP.lock();
+    let parent_key = Arc::as_ptr(parent) as usize;
+    let child_key = Arc::as_ptr(child) as usize;
+    let pages = swap
+        .swapped
+        .range((parent_key, 0)..=(parent_key, usize::MAX))
+        .map(|((_, addr), page)| (*addr, page.slot))
+        .collect::<Vec<_>>();
+    for (addr, slot) in pages {
+        swap.dup_slot(slot);
+        swap.swapped.insert(
+            (child_key, addr),
+            SwappedPage {
+                space: Arc::downgrade(child),
+                slot,
+            },
+        );
+    }
+}
+
+/// 解除映射时调用，丢弃地址空间 `space` 中 `range` 范围内被换出的页
+pub fn release_swapped_pages(space: &Arc<AddressSpace>, range: Range<usize>) {
+    let mut swap = SWAP.lock();
+    let key = Arc::as_ptr(space) as usize;
+    let pages = swap
+        .swapped
+        .range((key, range.start)..(key, range.end))
+        .map(|(key, page)| (*key, page.slot))
+        .collect::<Vec<_>>();
+    for (key, slot) in pages {
+        swap.swapped.remove(&key);
+        swap.free_slot(slot);
+    }
+}
+
+/// 判断地址空间 `space` 中 `range` 范围内是否有被换出的页
+pub fn has_swapped_pages(space: &Arc<AddressSpace>, range: Range<usize>) -> bool {
+    let key = Arc::as_ptr(space) as usize;
+    SWAP.lock()
+        .swapped
+        .range((key, range.start)..(key, range.end))
+        .next()
+        .is_some()
+}
+
+/// 地址空间 `space` 中被换出的页数
+pub fn swapped_pages(space: &Arc<AddressSpace>) -> usize {
+    let key = Arc::as_ptr(space) as usize;
+    SWAP.lock()
+        .swapped
+        .range((key, 0)..(key, usize::MAX))
+        .count()
+}
+
+/// 移动映射时调用，将地址空间 `space` 中 `from` 范围内被换出的页移动到以 `to` 开始的位置
+pub fn move_swapped_pages(space: &Arc<AddressSpace>, from: Range<usize>, to: usize) {
+    let mut swap = SWAP.lock();
+    let key = Arc::as_ptr(space) as usize;
+    let pages = swap
+        .swapped
+        .range((key, from.start)..(key, from.end))
+        .map(|(key, _)| *key)
+        .collect::<Vec<_>>();
+    for (_, addr) in pages {
+        let page = swap.swapped.remove(&(key, addr)).unwrap();
+        swap.swapped.insert((key, to + addr - from.start), page);
+    }
+}
+
+/// 读取 `inode` 中的交换区头部，返回每个交换槽的初始引用计数
+fn read_swap_header(inode: &Arc<dyn VfsInode>) -> AlienResult<Vec<u16>> {
+    let mut header = vec![0u8; FRAME_SIZE];
+    if inode.read_at(0, &mut header)? != FRAME_SIZE
+        || &header[FRAME_SIZE - SWAP_MAGIC.len()..] != SWAP_MAGIC
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap()) as usize;
+    let mut pages = read_u32(SWAP_LAST_PAGE_OFFSET) + 1;
+    let size = inode.get_attr()?.st_size as usize / FRAME_SIZE;
+    if inode.inode_type() == VfsNodeType::File {
+        pages = pages.min(size);
+    }
+    if pages < 2 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut slots = vec![0; pages];
+    slots[0] = SLOT_RESERVED;
+    let bad_pages = read_u32(SWAP_NR_BADPAGES_OFFSET);
+    for i in 0..bad_pages {
+        let offset = SWAP_BADPAGES_OFFSET + i * 4;
+        if offset + 4 > FRAME_SIZE - SWAP_MAGIC.len() {
+            break;
+        }
+        if let Some(slot) = slots.get_mut(read_u32(offset)) {
+            *slot = SLOT_RESERVED;
+        }
+    }
+    Ok(slots)
+}
+
+/// 查找路径 `path` 对应的交换区文件或块设备
+fn swap_inode(path: *const u8) -> AlienResult<Arc<dyn VfsInode>> {
+    let task = current_task().unwrap();
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(LinuxErrno::EPERM);
+    }
+    let path = task.strncpy_from_user(path, PATH_MAX)?;
+    let inode = user_path_at(AT_FDCWD, &path)?.open(None)?.inode()?;
+    match inode.inode_type() {
+        VfsNodeType::File | VfsNodeType::BlockDevice => Ok(inode),
+        _ => Err(LinuxErrno::EINVAL),
+    }
+}
+
+/// 一个系统调用，用于启用 `path` 指向的交换区。交换区可以是一个块设备或普通文件，需要事先使用 `mkswap` 格式化。
+///
+/// `flags` 中设置了 `SWAP_FLAG_PREFER` 时，交换区的优先级为 `flags & SWAP_FLAG_PRIO_MASK`，否则优先级低于所有已启用的交换区。
+/// 换出页面时优先使用优先级高的交换区。
+///
+/// 调用者没有 CAP_SYS_ADMIN 能力时返回 `EPERM`；交换区已经被启用时返回 `EBUSY`；交换区头部不合法时返回 `EINVAL`。
+///
+/// Reference: [swapon](https://man7.org/linux/man-pages/man2/swapon.2.html)
+#[syscall_func(224)]
+pub fn swapon(path: *const u8, flags: usize) -> AlienResult<isize> {
+    if flags & !SWAP_FLAGS_VALID != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let inode = swap_inode(path)?;
+    let mut swap = SWAP.lock();
+    if swap
+        .areas
+        .iter()
+        .any(|area| Arc::ptr_eq(&area.inode, &inode))
+    {
+        return Err(LinuxErrno::EBUSY);
+    }
+    let slots = read_swap_header(&inode)?;
+    let priority = if flags & SWAP_FLAG_PREFER != 0 {
+        (flags & SWAP_FLAG_PRIO_MASK) as isize
+    } else {
+        swap.next_priority -= 1;
+        swap.next_priority + 1
+    };
+    let id = swap.next_id;
+    swap.next_id += 1;
+    let free = slots.iter().filter(|refs| **refs == 0).count();
+    info!("swapon: area {} with {} free pages", id, free);
+    let area = SwapArea {
+        id,
+        inode,
+        priority,
+        slots,
+        free,
+        active: true,
+    };
+    let index = swap
+        .areas
+        .iter()
+        .position(|area| area.priority < priority)
+        .unwrap_or(swap.areas.len());
+    swap.areas.insert(index, area);
+    SWAP_ENABLED.store(true, Ordering::Release);
+    Ok(0)
+}
+
+/// 一个系统调用，用于停用 `path` 指向的交换区。交换区中所有被换出的页都会先被读回内存。
+///
+/// 调用者没有 CAP_SYS_ADMIN 能力时返回 `EPERM`；交换区没有被启用时返回 `EINVAL`；
+/// 没有足够的内存读回被换出的页时返回 `ENOMEM`。
+///
+/// Reference: [swapoff](https://man7.org/linux/man-pages/man2/swapoff.2.html)
+#[syscall_func(225)]
+pub fn swapoff(path: *const u8) -> AlienResult<isize> {
+    let inode = swap_inode(path)?;
+    let mut swap = SWAP.lock();
+    let id = swap
+        .areas
+        .iter()
+        .find(|area| Arc::ptr_eq(&area.inode, &inode))
+        .map(|area| area.id)
+        .ok_or(LinuxErrno::EINVAL)?;
+    swap.area_mut(id).active = false;
+    // 读回页面时会释放锁，期间 fork 可能使更多的页引用该交换区，因此重复检查直到没有页位于该交换区中
+    loop {
+        swap.collect_dead();
+        let pages = swap
+            .swapped
+            .iter()
+            .filter(|(_, page)| page.slot.area == id)
+            .map(|((_, addr), page)| (*addr, page.space.clone()))
+            .collect::<Vec<_>>();
+        if pages.is_empty() {
+            break;
+        }
+        drop(swap);
+        for (addr, space) in pages {
+            if let Some(space) = space.upgrade() {
+                if load_page(&space, addr).is_err() {
+                    SWAP.lock().area_mut(id).active = true;
+                    return Err(LinuxErrno::ENOMEM);
+                }
+                track_anon_page(&space, addr);
+            }
+        }
+        swap = SWAP.lock();
+    }
+    // 其它线程可能仍在读取该交换区中的页，等待它们释放交换槽
+    while swap
+        .area_mut(id)
+        .slots
+        .iter()
+        .any(|refs| *refs != 0 && *refs != SLOT_RESERVED)
+    {
+        drop(swap);
+        do_suspend();
+        swap = SWAP.lock();
+    }
+    swap.areas.retain(|area| area.id != id);
+    if swap.areas.is_empty() {
+        SWAP_ENABLED.store(false, Ordering::Release);
+        ANON_LRU.lock().clear();
+    }
+    Ok(0)
+}
@@ -0,0 +1,186 @@
+//! 透明大页(THP)。
+//!
+//! 匿名映射区域(以及堆)中按 [`HUGE_PAGE_SIZE`] 对齐且完整位于区域之中的范围，在第一次缺页时直接映射一个大页，
+//! 以减少大数组等工作负载的 TLB 缺失。区域可以通过 `madvise(MADV_HUGEPAGE/MADV_NOHUGEPAGE)` 启用或禁用大页，
+//! 没有设置时遵循 [`THP_ALWAYS`]。大页的物理页由伙伴系统分配，分配失败时退回到普通的页。
+//! 只有使用伙伴系统(`pager_buddy`)作为物理页分配器时才会启用大页，默认的位图分配器(`pager_bitmap`)下
+//! 所有的区域都使用普通的页，`madvise(MADV_HUGEPAGE)` 只记录建议而不起作用。
+//!
+//! 大页不属于页表(页表不会释放它)，而是记录在这里。对大页的一部分解除映射、修改保护位、移动映射，
+//! 或者创建子进程(写时复制)之前，大页会被拆分为普通的页，拆分后这些页交给页表管理，也可以被换出。
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{mem::forget, ops::Range};
+
+use config::{FRAME_SIZE, HUGE_PAGE_SIZE};
+use ksync::Mutex;
+use mem::{alloc_huge_frame_trackers, FrameTracker, VmmPageAllocator, HUGE_FRAME_SUPPORTED};
+use page_table::{
+    addr::{PhysAddr, VirtAddr},
+    pte::MappingFlags,
+    table::Sv39PageTable,
+};
+
+use crate::mm::swap::{has_swapped_pages, track_anon_page};
+
+type AddressSpace = Mutex<Sv39PageTable<VmmPageAllocator>>;
+
+/// 没有通过 madvise 设置时，是否为足够大的匿名映射启用大页
+pub const THP_ALWAYS: bool = true;
+
+/// 一个已经映射的大页
+struct HugePage {
+    space: Weak<AddressSpace>,
+    frame: FrameTracker,
+}
+
+/// 所有已经映射的大页，以 (地址空间, 虚拟地址) 为键
+static HUGE_PAGES: Mutex<BTreeMap<(usize, usize), HugePage>> = Mutex::new(BTreeMap::new());
+
+/// 根据区域通过 madvise 设置的大页策略判断是否启用大页，物理页分配器不支持大页时总是返回 `false`
+pub fn huge_page_enabled(advice: Option<bool>) -> bool {
+    HUGE_FRAME_SUPPORTED && advice.unwrap_or(THP_ALWAYS)
+}
+
+/// 在缺页处理中调用，尝试为 `addr` 所在的大页范围映射一个大页，成功时返回 `true`
+///
+/// 该范围需要完整位于 `bounds` 之中，且其中的页都还没有被分配或者被换出。
+pub fn map_huge_page(
+    space: &Arc<AddressSpace>,
+    addr: usize,
+    bounds: Range<usize>,
+    flags: MappingFlags,
+) -> bool {
+    let start = addr & !(HUGE_PAGE_SIZE - 1);
+    let range = start..start + HUGE_PAGE_SIZE;
+    if range.start < bounds.start || range.end > bounds.end {
+        return false;
+    }
+    if has_swapped_pages(space, range.clone()) {
+        return false;
+    }
+    let mut table = space.lock();
+    let untouched = range.clone().step_by(FRAME_SIZE).all(|addr| {
+        table
+            .query(VirtAddr::from(addr))
+            .map_or(false, |(_, flags, _)| !flags.contains(MappingFlags::V))
+    });
+    if !untouched {
+        return false;
+    }
+    let mut frame = match alloc_huge_frame_trackers() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    frame.fill(0);
+    table
+        .unmap_region(VirtAddr::from(start), HUGE_PAGE_SIZE)
+        .unwrap();
+    let res = table.map_region(
+        VirtAddr::from(start),
+        PhysAddr::from(frame.start()),
+        HUGE_PAGE_SIZE,
+        flags | "VAD".into(),
+        true,
+    );
+    if res.is_err() {
+        // 恢复延迟分配的普通页
+        table
+            .map_region_no_target(VirtAddr::from(start), HUGE_PAGE_SIZE, flags, false, true)
+            .unwrap();
+        return false;
+    }
+    drop(table);
+    let mut huge_pages = HUGE_PAGES.lock();
+    huge_pages.retain(|_, page| page.space.strong_count() > 0);
+    huge_pages.insert(
+        (Arc::as_ptr(space) as usize, start),
+        HugePage {
+            space: Arc::downgrade(space),
+            frame,
+        },
+    );
+    true
+}
+
+/// 将跨越 `addr` 的大页拆分为普通的页，`addr` 与大页对齐时什么也不做
+pub fn split_huge_page(space: &Arc<AddressSpace>, addr: usize) {
+    if addr % HUGE_PAGE_SIZE == 0 {
+        return;
+    }
+    split_huge_pages(space, addr..addr + 1);
+}
+
+/// 将与 `range` 重叠的大页全部拆分为普通的页
+pub fn split_huge_pages(space: &Arc<AddressSpace>, range: Range<usize>) {
+    let key = Arc::as_ptr(space) as usize;
+    let first = range.start & !(HUGE_PAGE_SIZE - 1);
+    let pages = {
+        let mut huge_pages = HUGE_PAGES.lock();
+        let starts = huge_pages
+            .range((key, first)..(key, range.end))
+            .map(|((_, start), _)| *start)
+            .collect::<Vec<_>>();
+        starts
+            .into_iter()
+            .map(|start| (start, huge_pages.remove(&(key, start)).unwrap().frame))
+            .collect::<Vec<_>>()
+    };
+    for (start, frame) in pages {
+        let mut table = space.lock();
+        let (phy, flags, _) = table.query(VirtAddr::from(start)).unwrap();
+        table
+            .unmap_region(VirtAddr::from(start), HUGE_PAGE_SIZE)
+            .unwrap();
+        table
+            .map_region(VirtAddr::from(start), phy, HUGE_PAGE_SIZE, flags, false)
+            .unwrap();
+        // 拆分后每个页由页表负责释放
+        for addr in (start..start + HUGE_PAGE_SIZE).step_by(FRAME_SIZE) {
+            table.get_record_mut().insert(VirtAddr::from(addr), true);
+        }
+        drop(table);
+        forget(frame);
+        for addr in (start..start + HUGE_PAGE_SIZE).step_by(FRAME_SIZE) {
+            track_anon_page(space, addr);
+        }
+    }
+}
+
+/// 解除映射后调用，释放完整位于 `range` 之中的大页
+pub fn release_huge_pages(space: &Arc<AddressSpace>, range: Range<usize>) {
+    let key = Arc::as_ptr(space) as usize;
+    let mut huge_pages = HUGE_PAGES.lock();
+    let starts = huge_pages
+        .range((key, range.start)..(key, range.end))
+        .map(|((_, start), _)| *start)
+        .filter(|start| start + HUGE_PAGE_SIZE <= range.end)
+        .collect::<Vec<_>>();
+    for start in starts {
+        huge_pages.remove(&(key, start));
+    }
+}
+
+/// 进程退出或执行新程序时调用，解除地址空间 `space` 中所有大页的映射并释放它们
+pub fn release_all_huge_pages(space: &Arc<AddressSpace>) {
+    let key = Arc::as_ptr(space) as usize;
+    let pages = {
+        let mut huge_pages = HUGE_PAGES.lock();
+        let starts = huge_pages
+            .range((key, 0)..=(key, usize::MAX))
+            .map(|((_, start), _)| *start)
+            .collect::<Vec<_>>();
+        starts
+            .into_iter()
+            .map(|start| (start, huge_pages.remove(&(key, start)).unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let mut table = space.lock();
+    for (start, _page) in pages {
+        // 先解除映射再释放物理页
+        let _ = table.unmap_region(VirtAddr::from(start), HUGE_PAGE_SIZE);
+    }
+}
@@ -0,0 +1,194 @@
+//! 对用户地址空间的访问。
+//!
+//! 系统调用收到的用户指针使用 [`UserPtr`] 与 [`UserSlice`] 表示，通过 `TaskInner` 的 `read_user`、`write_user`
+//! 等方法访问。访问前内核会逐页检查地址是否位于用户可以访问的映射中，必要时像用户态的访问一样处理缺页，
+//! 地址无效时返回 `EFAULT`，而不是让内核 panic。
+//!
+//! 地址的合法性完全由页表转换保证：复制在持有地址空间的锁时进行，先确认用户地址仍然以所需的权限映射到同一物理页，
+//! 再通过内核对物理内存的直接映射访问该物理页。复制过程中不会访问用户虚拟地址，也就不会在内核中触发缺页异常。
+//!
+//! 需要在释放地址空间的锁之后继续访问用户内存的场合(如读写文件时的缓冲区、futex 字)使用 [`UserBuffers`] 与 [`UserRef`]，
+//! 它们所在的物理页在使用期间被固定，不会被换出，其它线程解除映射时也不会被释放。
+use alloc::vec::Vec;
+use core::{fmt::Debug, marker::PhantomData, ops::Deref};
+
+use config::FRAME_BITS;
+use mem::FRAME_REF_MANAGER;
+
+/// 用户地址空间中指向 T 类型数据的指针
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> Debug for UserPtr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "UserPtr({:#x})", self.addr)
+    }
+}
+
+impl<T> UserPtr<T> {
+    pub const fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 指针在用户地址空间中的地址
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+impl<T> From<*const T> for UserPtr<T> {
+    fn from(ptr: *const T) -> Self {
+        Self::new(ptr as usize)
+    }
+}
+
+impl<T> From<*mut T> for UserPtr<T> {
+    fn from(ptr: *mut T) -> Self {
+        Self::new(ptr as usize)
+    }
+}
+
+/// 用户地址空间中由 `len` 个 T 类型数据组成的缓冲区
+pub struct UserSlice<T> {
+    addr: usize,
+    len: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserSlice<T> {}
+
+impl<T> Debug for UserSlice<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "UserSlice({:#x}, {})", self.addr, self.len)
+    }
+}
+
+impl<T> UserSlice<T> {
+    pub const fn new(addr: usize, len: usize) -> Self {
+        Self {
+            addr,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 缓冲区在用户地址空间中的起始地址
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// 缓冲区中 T 类型数据的个数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// 一个被固定的物理页
+///
+/// 固定时物理页的引用计数加一：页面回收不会换出引用计数大于一的页，解除映射时也只是减少引用计数，
+/// 因此该页在被解除固定之前一直有效。
+struct PinnedFrame(usize);
+
+impl PinnedFrame {
+    /// 固定物理地址 `physical` 所在的页。不由物理页分配器管理的页(如设备内存)不会被回收，不需要固定，返回 `None`
+    fn new(physical: usize) -> Option<Self> {
+        let frame = physical >> FRAME_BITS;
+        FRAME_REF_MANAGER
+            .lock()
+            .try_add_ref(frame)
+            .then_some(Self(frame))
+    }
+}
+
+impl Drop for PinnedFrame {
+    fn drop(&mut self) {
+        FRAME_REF_MANAGER.lock().dec_ref(self.0);
+    }
+}
+
+/// 用户地址空间中的缓冲区对应的一组物理片段，每一段都不跨页
+///
+/// 片段所在的物理页在该结构存在期间一直被固定，系统调用可以在读写文件或者等待时直接访问这些片段。
+/// `WRITABLE` 为 false 时片段来自只读检查，只能读取。
+pub struct UserBuffers<const WRITABLE: bool> {
+    /// 各个片段的物理地址与长度
+    bufs: Vec<(usize, usize)>,
+    /// 片段所在的被固定的物理页
+    pins: Vec<PinnedFrame>,
+}
+
+impl<const WRITABLE: bool> UserBuffers<WRITABLE> {
+    pub(crate) fn new() -> Self {
+        Self {
+            bufs: Vec::new(),
+            pins: Vec::new(),
+        }
+    }
+
+    /// 加入一个以物理地址 `physical` 开始、长度为 `len` 的片段并固定其所在的页，调用者需要持有地址空间的锁
+    pub(crate) fn push(&mut self, physical: usize, len: usize) {
+        self.bufs.push((physical, len));
+        self.pins.extend(PinnedFrame::new(physical));
+    }
+
+    /// 按顺序遍历各个片段
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.bufs.iter().map(|&(physical, len)| unsafe {
+            core::slice::from_raw_parts(physical as *const u8, len)
+        })
+    }
+}
+
+impl UserBuffers<true> {
+    /// 按顺序遍历各个片段，片段可以写入
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut [u8]> + '_ {
+        self.bufs.iter().map(|&(physical, len)| unsafe {
+            core::slice::from_raw_parts_mut(physical as *mut u8, len)
+        })
+    }
+}
+
+/// 用户地址空间中一个 T 类型数据的引用，用于需要原子地访问用户内存的场合(如 futex)
+///
+/// 数据所在的物理页在引用存在期间一直被固定。
+pub struct UserRef<T> {
+    ptr: *const T,
+    _pin: Option<PinnedFrame>,
+}
+
+impl<T> UserRef<T> {
+    /// 创建物理地址 `physical` 处的引用并固定其所在的页，调用者需要持有地址空间的锁
+    pub(crate) fn new(physical: usize) -> Self {
+        Self {
+            ptr: physical as *const T,
+            _pin: PinnedFrame::new(physical),
+        }
+    }
+}
+
+impl<T> Deref for UserRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
@@ -5,7 +5,7 @@
 //! [`socket`] 子模块指明了Alien 内核中使用的套接字。
 //! [`unix`] 子模块指明了有关 Unix 协议族下的套接字结构。(目前有关的功能有待支持)
 //!
-use alloc::{sync::Arc, vec, vec::Vec};
+use alloc::{sync::Arc, vec::Vec};
 
 use constants::{io::OpenFlags, net::*, AlienResult, LinuxErrno};
 use knet::{
@@ -15,6 +15,7 @@ use knet::{
 use vfs::kfile::File;
 
 use crate::{
+    mm::uaccess::{UserPtr, UserSlice},
     net::addr::socket_addr_resolution,
     task::{current_task, do_suspend},
 };
@@ -115,12 +116,11 @@ pub fn accept(socketfd: usize, socket_addr: usize, addr_len: usize) -> AlienResu
                 let peer_addr = socket.peer_addr().unwrap();
                 info!("accept peer addr: {:?}", peer_addr);
                 let raw_ip_addr = RawIpV4Addr::from(peer_addr);
-                let addr_len_ref = task
-                    .access_inner()
-                    .transfer_raw_ptr_mut(addr_len as *mut u32);
-                *addr_len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
-                task.access_inner()
-                    .copy_to_user(&raw_ip_addr, socket_addr as *mut RawIpV4Addr);
+                task.write_user(
+                    UserPtr::<u32>::new(addr_len),
+                    &(core::mem::size_of::<RawIpV4Addr>() as u32),
+                )?;
+                task.write_user(UserPtr::<RawIpV4Addr>::new(socket_addr), &raw_ip_addr)?;
             }
             let fd = task.add_file(file).map_err(|_| LinuxErrno::EMFILE)?;
             Ok(fd as isize)
@@ -185,10 +185,11 @@ pub fn getsockname(socketfd: usize, socket_addr: usize, len: usize) -> AlienResu
     info!("getsockname: {:?}", local_addr);
     let raw_ip_addr = RawIpV4Addr::from(local_addr);
     let task = current_task().unwrap();
-    task.access_inner()
-        .copy_to_user(&raw_ip_addr, socket_addr as *mut RawIpV4Addr);
-    let len_ref = task.access_inner().transfer_raw_ptr_mut(len as *mut u32);
-    *len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
+    task.write_user(UserPtr::<RawIpV4Addr>::new(socket_addr), &raw_ip_addr)?;
+    task.write_user(
+        UserPtr::<u32>::new(len),
+        &(core::mem::size_of::<RawIpV4Addr>() as u32),
+    )?;
     Ok(0)
 }
 
@@ -207,10 +208,11 @@ pub fn get_peer_name(socketfd: usize, sockaddr: usize, len: usize) -> AlienResul
     info!("get_peer_name: {:?}", socket_addr);
     let raw_ip_addr = RawIpV4Addr::from(socket_addr);
     let task = current_task().unwrap();
-    task.access_inner()
-        .copy_to_user(&raw_ip_addr, sockaddr as *mut RawIpV4Addr);
-    let len_ref = task.access_inner().transfer_raw_ptr_mut(len as *mut u32);
-    *len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
+    task.write_user(UserPtr::<RawIpV4Addr>::new(sockaddr), &raw_ip_addr)?;
+    task.write_user(
+        UserPtr::<u32>::new(len),
+        &(core::mem::size_of::<RawIpV4Addr>() as u32),
+    )?;
     Ok(0)
 }
 
@@ -236,14 +238,7 @@ pub fn sendto(
     assert_eq!(flags, 0);
     let socket_fd = common_socket_syscall(socketfd)?;
     let task = current_task().unwrap();
-    let message = task.transfer_buffer(message, length);
-    // to vec<u8>
-    // todo!(don't need)
-    let message = message
-        .iter()
-        .map(|buf| buf.to_vec())
-        .flatten()
-        .collect::<Vec<u8>>();
+    let message = task.read_user_slice(UserSlice::<u8>::new(message as usize, length))?;
     let socket = socket_fd.get_socketdata()?;
     match socket.socket_type() {
         SocketType::SOCK_STREAM | SocketType::SOCK_SEQPACKET => {
@@ -297,19 +292,25 @@ pub fn recvfrom(
         socket.peer_addr(),
         socket.local_addr()
     );
-    let mut tmp_buffer = vec![0u8; length];
+    // the length comes from the user, the buffer may be larger than the free memory
+    let mut tmp_buffer = Vec::new();
+    tmp_buffer
+        .try_reserve_exact(length)
+        .map_err(|_| LinuxErrno::ENOMEM)?;
+    tmp_buffer.resize(length, 0u8);
     let recv_info = socket.recvfrom(tmp_buffer.as_mut_slice(), flags)?;
     let task = current_task().unwrap();
-    task.access_inner()
-        .copy_to_user_buffer(tmp_buffer.as_ptr(), buffer, recv_info.0);
+    task.write_user_slice(
+        UserSlice::<u8>::new(buffer as usize, recv_info.0),
+        &tmp_buffer[..recv_info.0],
+    )?;
     if src_addr != 0 {
         let raw_ip_addr = RawIpV4Addr::from(recv_info.1);
-        task.access_inner()
-            .copy_to_user(&raw_ip_addr, src_addr as *mut RawIpV4Addr);
-        let addr_len_ref = task
-            .access_inner()
-            .transfer_raw_ptr_mut(addr_len as *mut u32);
-        *addr_len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
+        task.write_user(UserPtr::<RawIpV4Addr>::new(src_addr), &raw_ip_addr)?;
+        task.write_user(
+            UserPtr::<u32>::new(addr_len),
+            &(core::mem::size_of::<RawIpV4Addr>() as u32),
+        )?;
     }
     Ok(recv_info.0 as isize)
 }
@@ -368,6 +369,7 @@ pub fn getsockopt(
     let socket_fd = common_socket_syscall(socketfd)?;
     let _socket = socket_fd.get_socketdata()?;
     let level = SocketLevel::try_from(level).map_err(|_| LinuxErrno::EINVAL)?;
+    let task = current_task().unwrap();
     match level {
         SocketLevel::Ip => {}
         SocketLevel::Socket => {
@@ -375,45 +377,39 @@ pub fn getsockopt(
             info!("[getsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
             match opt_name {
                 SocketOption::SO_RCVBUF => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = netcore::common::SOCKET_RECV_BUFFER_SIZE as u32;
+                    task.write_user(
+                        UserPtr::<u32>::new(opt_value),
+                        &(netcore::common::SOCKET_RECV_BUFFER_SIZE as u32),
+                    )?;
                 }
                 SocketOption::SO_SNDBUF => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = netcore::common::SOCKET_SEND_BUFFER_SIZE as u32;
+                    task.write_user(
+                        UserPtr::<u32>::new(opt_value),
+                        &(netcore::common::SOCKET_SEND_BUFFER_SIZE as u32),
+                    )?;
                 }
                 SocketOption::SO_ERROR => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = 0;
+                    task.write_user(UserPtr::<u32>::new(opt_value), &0)?;
                 }
                 _ => {}
             }
-            let opt_len_ref = current_task()
-                .unwrap()
-                .transfer_raw_ptr(opt_len as *mut u32);
-            *opt_len_ref = core::mem::size_of::<u32>() as u32;
+            task.write_user(
+                UserPtr::<u32>::new(opt_len),
+                &(core::mem::size_of::<u32>() as u32),
+            )?;
         }
         SocketLevel::Tcp => {
             let opt_name = TcpSocketOption::try_from(opt_name).map_err(|_| LinuxErrno::EINVAL)?;
             info!("[getsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
             match opt_name {
                 TcpSocketOption::TCP_MAXSEG => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = netcore::common::MAX_SEGMENT_SIZE as u32;
+                    task.write_user(
+                        UserPtr::<u32>::new(opt_value),
+                        &(netcore::common::MAX_SEGMENT_SIZE as u32),
+                    )?;
                 }
                 TcpSocketOption::TCP_NODELAY => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = 0;
+                    task.write_user(UserPtr::<u32>::new(opt_value), &0)?;
                 }
                 _ => {}
             }
@@ -463,13 +459,12 @@ pub fn socket_pair(domain: usize, c_type: usize, proto: usize, sv: usize) -> Ali
     Err(LinuxErrno::EAFNOSUPPORT.into())
 }
 
-/// 通过socket文件描述符fd获取对应的文件
+/// 通过socket文件描述符fd获取对应的文件，文件描述符不是 socket 时返回 `ENOTSOCK`
 fn common_socket_syscall(socket_fd: usize) -> AlienResult<Arc<SocketFile>> {
     let task = current_task().unwrap();
     let socket_fd = task.get_file(socket_fd).ok_or(LinuxErrno::EBADF)?;
     let socket_file = socket_fd
         .downcast_arc::<SocketFile>()
-        .map_err(|_| LinuxErrno::EINVAL)
-        .expect("socket_fd is not a socket file");
+        .map_err(|_| LinuxErrno::ENOTSOCK)?;
     Ok(socket_file)
 }
@@ -0,0 +1,84 @@
+//! 内核熵池。
+//!
+//! 熵池保存 256 位的状态。启动时混入多次读取时钟的抖动，之后每次中断到来时混入当前的时钟周期数与被中断的指令地址，
+//! 这些值的低位随中断到来的时刻变化，是内核中主要的熵来源。取随机数时先混入当前的时钟周期数，
+//! 再由 xoshiro256** 生成器根据状态产生输出。
+use arch::hart_id;
+use ksync::Mutex;
+use timer::read_timer;
+
+/// 熵池的状态
+struct EntropyPool {
+    state: [u64; 4],
+}
+
+impl EntropyPool {
+    /// 状态的初始值取 SHA-512 的初始哈希值，保证状态不全为 0
+    const fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667f3bcc908,
+                0xbb67ae8584caa73b,
+                0x3c6ef372fe94f82b,
+                0xa54ff53a5f1d36f1,
+            ],
+        }
+    }
+
+    /// 将 `value` 混入状态
+    fn mix(&mut self, value: u64) {
+        self.state[0] ^= value;
+        self.state[2] ^= value.rotate_left(29);
+        self.next();
+    }
+
+    /// xoshiro256**
+    fn next(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+}
+
+static ENTROPY_POOL: Mutex<EntropyPool> = Mutex::new(EntropyPool::new());
+
+/// 启动时初始化熵池，混入启动核的编号与连续读取时钟得到的时间差
+pub fn init_entropy_pool() {
+    let mut pool = ENTROPY_POOL.lock();
+    pool.mix(hart_id() as u64);
+    let mut last = read_timer();
+    for _ in 0..64 {
+        let now = read_timer();
+        pool.mix((now - last) as u64 ^ (now as u64).rotate_left(32));
+        last = now;
+    }
+}
+
+/// 中断到来时调用，将当前的时钟周期数与被中断的指令地址 `pc` 混入熵池
+pub fn add_interrupt_randomness(pc: usize) {
+    ENTROPY_POOL
+        .lock()
+        .mix(read_timer() as u64 ^ (pc as u64).rotate_left(17));
+}
+
+/// 从熵池中取得一个随机数
+pub fn get_random_u64() -> u64 {
+    let mut pool = ENTROPY_POOL.lock();
+    pool.mix(read_timer() as u64);
+    pool.next()
+}
+
+/// 用随机字节填充 `buf`
+pub fn get_random_bytes(buf: &mut [u8]) {
+    buf.chunks_mut(8).for_each(|chunk| {
+        let value = get_random_u64().to_ne_bytes();
+        chunk.copy_from_slice(&value[..chunk.len()]);
+    });
+}
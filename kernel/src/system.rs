@@ -1,14 +1,22 @@
 //! uname系统调用实现
-use core::cmp::min;
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    cmp::min,
+    fmt::{Arguments, Write},
+};
 
 use constants::{
     sys::{Rusage, RusageFlag, Sysinfo, SyslogAction, TimeVal},
     AlienResult, LinuxErrno,
 };
+use ksync::Mutex;
 use syscall_table::syscall_func;
 use timer::{get_time_ms, TimeFromFreq};
 
-use crate::task::current_task;
+use crate::{
+    mm::uaccess::{UserPtr, UserSlice},
+    task::{current_task, find_task, pid_from_user, Capabilities, Task, HOST_NAME_MAX},
+};
 
 /// 记录系统信息的结构，包括操作系统名、在网络中的用户名、操作系统release和version版本、硬件类型、域名等信息。
 #[repr(C)]
@@ -28,14 +36,15 @@ pub struct Utsname {
     domainname: [u8; 65],
 }
 
-/// 返回系统信息，信息保存在[`Utsname`]结构中。
+/// 返回系统信息，信息保存在[`Utsname`]结构中。主机名与域名来自当前任务所在的 UTS 命名空间。
 fn system_info() -> Utsname {
     const SYSNAME: &str = "Linux";
-    const NODENAME: &str = "Alien";
     const RELEASE: &str = "5.1";
     const VERSION: &str = "5.1";
     const MACHINE: &str = "riscv64";
-    const DOMAINNAME: &str = "RustOS";
+    let uts = current_task().unwrap().access_inner().ns.uts.clone();
+    let nodename = uts.hostname.lock();
+    let domainname = uts.domainname.lock();
     let mut name = Utsname {
         sysname: [0; 65],
         nodename: [0; 65],
@@ -45,11 +54,11 @@ fn system_info() -> Utsname {
         domainname: [0; 65],
     };
     name.sysname[..SYSNAME.len()].copy_from_slice(SYSNAME.as_bytes());
-    name.nodename[..NODENAME.len()].copy_from_slice(NODENAME.as_bytes());
+    name.nodename[..nodename.len()].copy_from_slice(&nodename);
     name.release[..RELEASE.len()].copy_from_slice(RELEASE.as_bytes());
     name.version[..VERSION.len()].copy_from_slice(VERSION.as_bytes());
     name.machine[..MACHINE.len()].copy_from_slice(MACHINE.as_bytes());
-    name.domainname[..DOMAINNAME.len()].copy_from_slice(DOMAINNAME.as_bytes());
+    name.domainname[..domainname.len()].copy_from_slice(&domainname);
     name
 }
 
@@ -57,11 +66,48 @@ fn system_info() -> Utsname {
 ///
 /// 函数成功执行后返回0。
 #[syscall_func(160)]
-pub fn uname(utsname: *const u8) -> isize {
+pub fn uname(utsname: *const u8) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    task.access_inner()
-        .copy_to_user(&system_info(), utsname as *mut Utsname);
-    0
+    task.write_user(UserPtr::<Utsname>::new(utsname as usize), &system_info())?;
+    Ok(0)
+}
+
+/// 从用户空间读取长度为 `len` 的 UTS 名称，`len` 超过 [`HOST_NAME_MAX`] 时返回 `EINVAL`，没有 CAP_SYS_ADMIN 能力时返回 `EPERM`
+fn read_uts_name(name: usize, len: usize) -> AlienResult<Vec<u8>> {
+    if len > HOST_NAME_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(LinuxErrno::EPERM);
+    }
+    task.read_user_slice(UserSlice::<u8>::new(name, len))
+}
+
+/// 一个系统调用，将当前任务所在的 UTS 命名空间的主机名设置为 `name` 处长度为 `len` 的字符串。
+///
+/// `len` 超过 [`HOST_NAME_MAX`] 时返回 `EINVAL`，没有 CAP_SYS_ADMIN 能力时返回 `EPERM`。
+///
+/// Reference: [sethostname](https://man7.org/linux/man-pages/man2/sethostname.2.html)
+#[syscall_func(161)]
+pub fn sethostname(name: usize, len: usize) -> AlienResult<isize> {
+    let hostname = read_uts_name(name, len)?;
+    let uts = current_task().unwrap().access_inner().ns.uts.clone();
+    *uts.hostname.lock() = hostname;
+    Ok(0)
+}
+
+/// 一个系统调用，将当前任务所在的 UTS 命名空间的域名设置为 `name` 处长度为 `len` 的字符串。
+///
+/// 参数的限制与 [`sethostname`] 相同。
+///
+/// Reference: [setdomainname](https://man7.org/linux/man-pages/man2/setdomainname.2.html)
+#[syscall_func(162)]
+pub fn setdomainname(name: usize, len: usize) -> AlienResult<isize> {
+    let domainname = read_uts_name(name, len)?;
+    let uts = current_task().unwrap().access_inner().ns.uts.clone();
+    *uts.domainname.lock() = domainname;
+    Ok(0)
 }
 
 const LOG_BUF_LEN: usize = 4096;
@@ -69,6 +115,62 @@ const LOG: &str = r"
 [    0.000000] Linux version 5.10.0-7-riscv64 (debian-kernel@lists.debian.org) (gcc-10 (Debian 10.2.1-6) 10.2.1 20210110, GNU ld (GNU Binutils for Debian) 2.35.2) #1 SMP Debian 5.10.40-1 (2021-05-28)
 ";
 
+/// 内核消息环状缓冲区，缓冲区满时覆盖最早的消息
+static KERNEL_LOG: Mutex<LogRing> = Mutex::new(LogRing {
+    buf: [0; LOG_BUF_LEN],
+    start: 0,
+    len: 0,
+});
+
+/// 固定大小的环状缓冲区，写入消息时不需要分配内存
+struct LogRing {
+    buf: [u8; LOG_BUF_LEN],
+    /// 最早的字节所在的位置
+    start: usize,
+    /// 缓冲区中的字节数
+    len: usize,
+}
+
+impl LogRing {
+    fn push(&mut self, byte: u8) {
+        let end = (self.start + self.len) % LOG_BUF_LEN;
+        self.buf[end] = byte;
+        if self.len == LOG_BUF_LEN {
+            self.start = (self.start + 1) % LOG_BUF_LEN;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        self.buf[(self.start + index) % LOG_BUF_LEN]
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+impl Write for LogRing {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        s.bytes().for_each(|byte| self.push(byte));
+        Ok(())
+    }
+}
+
+/// 向内核消息环状缓冲区中写入一条消息，消息可以通过 [`syslog`] 读取
+///
+/// 该函数可能在分配物理页失败时被调用，因此不会分配内存，也不会等待缓冲区的锁，缓冲区被占用时消息会被丢弃。
+pub fn kernel_log(args: Arguments) {
+    if let Some(mut log) = KERNEL_LOG.try_lock() {
+        let time = get_time_ms();
+        let _ = write!(log, "[{:5}.{:06}] ", time / 1000, time % 1000 * 1000);
+        let _ = log.write_fmt(args);
+        log.push(b'\n');
+    }
+}
+
 /// (待完善)一个系统调用函数，用于对内核消息环状缓冲区进行操作。
 ///
 /// + `log_type`: 指明操作的类型，具体值可见[`SyslogAction`]；
@@ -86,21 +188,35 @@ pub fn syslog(log_type: u32, buf: usize, len: usize) -> isize {
     if log_type.is_err() {
         return LinuxErrno::EINVAL as isize;
     }
-    match log_type.unwrap() {
+    let log_type = log_type.unwrap();
+    match log_type {
         SyslogAction::OPEN | SyslogAction::CLOSE => 0,
         SyslogAction::READ | SyslogAction::ReadAll | SyslogAction::ReadClear => {
             let min_len = min(len, LOG_BUF_LEN);
             let task = current_task().unwrap();
-            // the buf may be not valid, so we need to check it -- > sbrk heap
-            let mut buf = task.transfer_buffer(buf as *mut u8, min_len);
             let log = LOG.as_bytes();
-            let mut offset = 0;
-            buf.iter_mut().for_each(|buf| {
-                let copy_len = min(log.len() - offset, buf.len());
-                buf[..copy_len].copy_from_slice(&log[offset..offset + copy_len]);
-                offset += copy_len;
-            });
-            offset as isize
+            // 先输出固定的启动信息，再输出内核运行时记录的消息
+            let content = {
+                let kernel_log = KERNEL_LOG.lock();
+                let total = min(log.len() + kernel_log.len, min_len);
+                (0..total)
+                    .map(|index| {
+                        if index < log.len() {
+                            log[index]
+                        } else {
+                            kernel_log.get(index - log.len())
+                        }
+                    })
+                    .collect::<Vec<u8>>()
+            };
+            // 写入用户缓冲区时可能处理缺页，不能持有内核消息缓冲区的锁
+            if let Err(e) = task.write_user_slice(UserSlice::new(buf, content.len()), &content) {
+                return e.into();
+            }
+            if matches!(log_type, SyslogAction::ReadClear) {
+                KERNEL_LOG.lock().clear();
+            }
+            content.len() as isize
         }
         SyslogAction::Unknown => LinuxErrno::EINVAL as isize,
         _ => 0,
@@ -116,7 +232,7 @@ extern "C" {
 ///
 /// 目前功能还有待完善。正确执行后返回0。
 #[syscall_func(179)]
-pub fn sys_info(dst_info: usize) -> isize {
+pub fn sys_info(dst_info: usize) -> AlienResult<isize> {
     const LINUX_SYSINFO_LOADS_SCALE: usize = 65536;
     let task = current_task().unwrap();
     // calculate the task number
@@ -141,9 +257,8 @@ pub fn sys_info(dst_info: usize) -> isize {
         freehigh: 0,
         mem_unit: 1,
     };
-    task.access_inner()
-        .copy_to_user(&info, dst_info as *mut Sysinfo);
-    0
+    task.write_user(UserPtr::<Sysinfo>::new(dst_info), &info)?;
+    Ok(0)
 }
 
 /// (待实现)一个系统调用，设置进程调度的参数。目前直接返回0。
@@ -164,27 +279,34 @@ pub fn sched_setaffinity() -> isize {
     0
 }
 
-/// (待完善)一个系统调用，获取某进程对CPU的亲和力(位掩码)。当前进程的cpu亲和力将保存到`mask`所指向的位置。函数执行成功后返回8。
+/// 获取 sched 系列系统调用所指向的线程，`pid` 为 0 时为当前线程，否则为当前 pid 命名空间中的线程 `pid`，不存在时返回 `ESRCH`
+fn sched_target(pid: usize) -> AlienResult<Arc<Task>> {
+    if pid == 0 {
+        return Ok(current_task().unwrap().clone());
+    }
+    pid_from_user(pid)
+        .and_then(find_task)
+        .ok_or(LinuxErrno::ESRCH)
+}
+
+/// (待完善)一个系统调用，获取某线程对CPU的亲和力(位掩码)。线程 `pid` 的cpu亲和力将保存到`mask`所指向的位置。函数执行成功后返回8。
 #[syscall_func(123)]
-pub fn sched_getaffinity(pid: usize, size: usize, mask: usize) -> isize {
+pub fn sched_getaffinity(pid: usize, size: usize, mask: usize) -> AlienResult<isize> {
     warn!(
         "sched_getaffinity: pid: {}, size: {}, mask: {}",
         pid, size, mask
     );
-    assert_eq!(pid, 0);
+    let res = sched_target(pid)?.access_inner().cpu_affinity;
     let task = current_task().unwrap();
-    let res = task.access_inner().cpu_affinity;
-    let mask = task.access_inner().transfer_raw_ptr_mut(mask as *mut usize);
-    *mask = res;
-    8
+    task.write_user(UserPtr::<usize>::new(mask), &res)?;
+    Ok(8)
 }
 
-/// (待实现)一个系统调用，用于获取当前CPU的调度策略。目前直接返回0。
+/// (待实现)一个系统调用，用于获取线程 `pid` 的调度策略。目前总是返回0(SCHED_OTHER)，线程不存在时返回 `ESRCH`。
 #[syscall_func(120)]
-pub fn sched_getscheduler(pid: usize) -> isize {
-    assert_eq!(pid, 0);
-    // let task = current_task().unwrap();
-    0
+pub fn sched_getscheduler(pid: usize) -> AlienResult<isize> {
+    sched_target(pid)?;
+    Ok(0)
 }
 
 /// (待实现)一个系统调用，用于设置当前CPU的调度策略。目前直接返回0。
@@ -212,8 +334,7 @@ pub fn getrusage(who: isize, usage: usize) -> AlienResult<isize> {
     let mut task_usage = Rusage::new();
     task_usage.ru_utime = TimeVal::from_freq(static_info.tms_utime);
     task_usage.ru_stime = TimeVal::from_freq(static_info.tms_stime);
-    task.access_inner()
-        .copy_to_user(&task_usage, usage as *mut Rusage);
+    task.write_user(UserPtr::<Rusage>::new(usage), &task_usage)?;
     Ok(0)
 }
 
@@ -0,0 +1,310 @@
+//! binfmt_misc：用户注册的可执行文件格式。
+//!
+//! 拥有 `CAP_SYS_ADMIN` 的进程向 `/proc/sys/fs/binfmt_misc/register` 写入 `:name:type:offset:magic:mask:interpreter:flags`
+//! 注册一条规则，文件开头 `offset` 处的内容与 `magic` 匹配(类型 `M`，比较前与 `mask` 按位与)或文件扩展名为 `magic`(类型 `E`)时，
+//! 文件交给 `interpreter` 执行，参数被改写为 `[interpreter, 文件路径, 原参数[1..]]`；
+//! 带有 `P` 标志时保留原参数的第一个参数，即 `[interpreter, 文件路径, 原参数...]`。
+//!
+//! 每条规则对应 `/proc/sys/fs/binfmt_misc/<name>` 文件，读取该文件得到规则的内容，写入 `0`、`1`、`-1` 分别禁用、启用、删除该规则。
+//! 对 `status` 文件的写入作用于所有规则。
+//!
+//! Reference: [binfmt_misc](https://docs.kernel.org/admin-guide/binfmt-misc.html)
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use constants::AlienResult;
+use ksync::Mutex;
+use vfs::proc::{
+    add_binfmt_misc_entry, register_binfmt_misc_ops, remove_binfmt_misc_entry, BinfmtMiscOps,
+};
+use vfscore::{error::VfsError, VfsResult};
+
+use super::{Binary, BINPRM_BUF_SIZE};
+use crate::task::{current_task, Capabilities};
+
+/// 规则匹配的方式
+enum MiscMatch {
+    /// 文件开头 `offset` 处的内容与 `magic & mask` 相同
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    /// 文件的扩展名，不包含 `.`
+    Extension(String),
+}
+
+/// 一条 binfmt_misc 规则
+struct MiscRule {
+    name: String,
+    matcher: MiscMatch,
+    interpreter: String,
+    /// 保留原参数的第一个参数(`P`)
+    preserve_argv0: bool,
+    /// 注册时给出的标志
+    flags: String,
+    enabled: bool,
+}
+
+impl MiscRule {
+    fn matches(&self, path: &str, data: &[u8]) -> bool {
+        match &self.matcher {
+            MiscMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let content = match data.get(*offset..*offset + magic.len()) {
+                    Some(content) => content,
+                    None => return false,
+                };
+                content.iter().enumerate().all(|(i, byte)| {
+                    let mask = mask.as_ref().map_or(0xff, |mask| mask[i]);
+                    byte & mask == magic[i]
+                })
+            }
+            MiscMatch::Extension(extension) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                name.rsplit_once('.')
+                    .map_or(false, |(_, ext)| ext == extension)
+            }
+        }
+    }
+
+    /// 规则文件的内容，与 Linux 的格式相同
+    fn describe(&self) -> String {
+        let mut content = format!(
+            "{}\ninterpreter {}\nflags: {}\n",
+            if self.enabled { "enabled" } else { "disabled" },
+            self.interpreter,
+            self.flags
+        );
+        match &self.matcher {
+            MiscMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                content += &format!("offset {}\nmagic {}\n", offset, hex(magic));
+                if let Some(mask) = mask {
+                    content += &format!("mask {}\n", hex(mask));
+                }
+            }
+            MiscMatch::Extension(extension) => content += &format!("extension .{}\n", extension),
+        }
+        content
+    }
+}
+
+/// 所有已注册的规则，按照注册的先后顺序排列，先注册的规则优先
+static MISC_RULES: Mutex<Vec<MiscRule>> = Mutex::new(Vec::new());
+/// binfmt_misc 是否启用
+static MISC_ENABLED: AtomicBool = AtomicBool::new(true);
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 解析规则中的魔数或掩码，支持 `\xHH` 与 `\\` 两种转义
+fn unescape(field: &str) -> Option<Vec<u8>> {
+    let bytes = field.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            res.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1) {
+            Some(b'\\') => {
+                res.push(b'\\');
+                i += 2;
+            }
+            Some(b'x') => {
+                let digits = core::str::from_utf8(bytes.get(i + 2..i + 4)?).ok()?;
+                res.push(u8::from_str_radix(digits, 16).ok()?);
+                i += 4;
+            }
+            _ => return None,
+        }
+    }
+    Some(res)
+}
+
+/// 解析写入 `register` 的规则
+fn parse_rule(input: &str) -> Option<MiscRule> {
+    let input = input.trim_end_matches('\n');
+    let delimiter = input.chars().next()?;
+    let fields = input[delimiter.len_utf8()..]
+        .split(delimiter)
+        .collect::<Vec<_>>();
+    if fields.len() != 6 && fields.len() != 7 {
+        return None;
+    }
+    let (name, kind, offset, magic, mask, interpreter) = (
+        fields[0], fields[1], fields[2], fields[3], fields[4], fields[5],
+    );
+    let flags = fields.get(6).copied().unwrap_or("");
+    if name.is_empty()
+        || name.contains('/')
+        || [".", "..", "register", "status"].contains(&name)
+        || interpreter.is_empty()
+    {
+        return None;
+    }
+    // 不支持需要传递已打开文件的 `O` 与 `C` 标志，`F` 标志没有影响
+    if flags.chars().any(|flag| !matches!(flag, 'P' | 'F')) {
+        return None;
+    }
+    let matcher = match kind {
+        "M" => {
+            let offset = if offset.is_empty() {
+                0
+            } else {
+                offset.parse::<usize>().ok()?
+            };
+            let magic = unescape(magic)?;
+            let mask = if mask.is_empty() {
+                None
+            } else {
+                Some(unescape(mask)?)
+            };
+            if magic.is_empty()
+                || offset + magic.len() > BINPRM_BUF_SIZE
+                || mask
+                    .as_ref()
+                    .map_or(false, |mask| mask.len() != magic.len())
+            {
+                return None;
+            }
+            MiscMatch::Magic {
+                offset,
+                magic,
+                mask,
+            }
+        }
+        "E" => {
+            if magic.is_empty() || magic.contains('/') || !offset.is_empty() || !mask.is_empty() {
+                return None;
+            }
+            MiscMatch::Extension(magic.to_string())
+        }
+        _ => return None,
+    };
+    Some(MiscRule {
+        name: name.to_string(),
+        matcher,
+        interpreter: interpreter.to_string(),
+        preserve_argv0: flags.contains('P'),
+        flags: flags.to_string(),
+        enabled: true,
+    })
+}
+
+/// 写入 `status` 或规则文件的命令
+enum MiscCommand {
+    Disable,
+    Enable,
+    Remove,
+}
+
+fn parse_command(data: &[u8]) -> VfsResult<MiscCommand> {
+    match data.strip_suffix(b"\n").unwrap_or(data) {
+        b"0" => Ok(MiscCommand::Disable),
+        b"1" => Ok(MiscCommand::Enable),
+        b"-1" => Ok(MiscCommand::Remove),
+        _ => Err(VfsError::Invalid),
+    }
+}
+
+fn misc_read(name: &str) -> Option<String> {
+    if name == "status" {
+        let enabled = MISC_ENABLED.load(Ordering::Relaxed);
+        return Some(String::from(if enabled {
+            "enabled\n"
+        } else {
+            "disabled\n"
+        }));
+    }
+    MISC_RULES
+        .lock()
+        .iter()
+        .find(|rule| rule.name == name)
+        .map(|rule| rule.describe())
+}
+
+fn misc_write(name: &str, data: &[u8]) -> VfsResult<()> {
+    let task = current_task().ok_or(VfsError::PermissionDenied)?;
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(VfsError::PermissionDenied);
+    }
+    let mut rules = MISC_RULES.lock();
+    match name {
+        "register" => {
+            let input = core::str::from_utf8(data).map_err(|_| VfsError::Invalid)?;
+            let rule = parse_rule(input).ok_or(VfsError::Invalid)?;
+            if rules.iter().any(|r| r.name == rule.name) {
+                return Err(VfsError::Invalid);
+            }
+            add_binfmt_misc_entry(&rule.name);
+            rules.push(rule);
+        }
+        "status" => match parse_command(data)? {
+            MiscCommand::Disable => MISC_ENABLED.store(false, Ordering::Relaxed),
+            MiscCommand::Enable => MISC_ENABLED.store(true, Ordering::Relaxed),
+            MiscCommand::Remove => {
+                rules
+                    .drain(..)
+                    .for_each(|rule| remove_binfmt_misc_entry(&rule.name));
+            }
+        },
+        _ => {
+            let index = rules
+                .iter()
+                .position(|rule| rule.name == name)
+                .ok_or(VfsError::NoEntry)?;
+            match parse_command(data)? {
+                MiscCommand::Disable => rules[index].enabled = false,
+                MiscCommand::Enable => rules[index].enabled = true,
+                MiscCommand::Remove => {
+                    let rule = rules.remove(index);
+                    remove_binfmt_misc_entry(&rule.name);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 注册 `/proc/sys/fs/binfmt_misc` 目录下文件的读写操作
+pub fn init_binfmt_misc() {
+    register_binfmt_misc_ops(BinfmtMiscOps {
+        read: misc_read,
+        write: misc_write,
+    });
+}
+
+pub fn load_misc_binary(
+    path: &str,
+    data: &[u8],
+    args: &mut Vec<String>,
+) -> Option<AlienResult<Binary>> {
+    if !MISC_ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let rules = MISC_RULES.lock();
+    let rule = rules
+        .iter()
+        .find(|rule| rule.enabled && rule.matches(path, data))?;
+    if !rule.preserve_argv0 && !args.is_empty() {
+        args.remove(0);
+    }
+    args.insert(0, format!("{}\0", path));
+    args.insert(0, format!("{}\0", rule.interpreter));
+    Some(Ok(Binary::Interpreter(rule.interpreter.clone())))
+}
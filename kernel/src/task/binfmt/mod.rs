@@ -0,0 +1,74 @@
+//! 可执行文件格式的注册表。
+//!
+//! `execve` 读入文件后依次询问 [`FORMATS`] 中的每种格式，由第一个识别该文件的格式决定如何执行它：
+//! 用户通过 `/proc/sys/fs/binfmt_misc/register` 注册的规则([`misc`])最先被检查，然后是 `#!` 脚本([`script`])，最后是 ELF 文件。
+//!
+//! 交给解释器执行的文件会改写参数，并以解释器代替原文件重新查找格式，嵌套的层数不能超过 [`MAX_INTERP_DEPTH`]。
+//! 没有任何格式能识别的文件返回 `ENOEXEC`。
+use alloc::{string::String, vec::Vec};
+
+use constants::{AlienResult, LinuxErrno};
+pub use misc::init_binfmt_misc;
+
+mod misc;
+mod script;
+
+/// 脚本第一行以及 binfmt_misc 规则中魔数所在范围的最大长度，与 Linux 的 `BINPRM_BUF_SIZE` 相同
+const BINPRM_BUF_SIZE: usize = 256;
+/// 解释器嵌套的最大层数
+pub const MAX_INTERP_DEPTH: usize = 4;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// 识别出的可执行文件
+pub enum Binary {
+    /// 直接加载的 ELF 文件
+    Elf,
+    /// 交给解释器执行的文件，包含解释器的路径，参数已经被改写为解释器的参数
+    Interpreter(String),
+}
+
+/// 一种可执行文件格式
+pub struct BinaryFormat {
+    pub name: &'static str,
+    /// 识别路径为 `path`、内容为 `data` 的文件，文件不属于该格式时返回 `None`。
+    ///
+    /// 文件需要交给解释器执行时，参数 `args` 会被改写。参数中的每个字符串都以 `'\0'` 结尾。
+    pub load: fn(path: &str, data: &[u8], args: &mut Vec<String>) -> Option<AlienResult<Binary>>,
+}
+
+/// 所有的可执行文件格式，按照检查的先后顺序排列
+static FORMATS: [BinaryFormat; 3] = [
+    BinaryFormat {
+        name: "misc",
+        load: misc::load_misc_binary,
+    },
+    BinaryFormat {
+        name: "script",
+        load: script::load_script,
+    },
+    BinaryFormat {
+        name: "elf",
+        load: load_elf_binary,
+    },
+];
+
+/// 查找能够识别文件的格式，返回识别的结果
+pub fn load_binary(path: &str, data: &[u8], args: &mut Vec<String>) -> AlienResult<Binary> {
+    FORMATS
+        .iter()
+        .find_map(|format| {
+            let res = (format.load)(path, data, args)?;
+            trace!("{} is recognized as {} binary", path, format.name);
+            Some(res)
+        })
+        .unwrap_or(Err(LinuxErrno::ENOEXEC))
+}
+
+fn load_elf_binary(
+    _path: &str,
+    data: &[u8],
+    _args: &mut Vec<String>,
+) -> Option<AlienResult<Binary>> {
+    data.starts_with(ELF_MAGIC).then_some(Ok(Binary::Elf))
+}
@@ -0,0 +1,59 @@
+//! `#!` 脚本。
+//!
+//! 脚本的第一行形如 `#!interpreter [arg]`，脚本由 `interpreter` 执行，参数按照 Linux 的规则改写为
+//! `[interpreter, arg, 脚本路径, 原参数[1..]]`，其中 `arg` 可以省略。
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use constants::{AlienResult, LinuxErrno};
+
+use super::{Binary, BINPRM_BUF_SIZE};
+
+pub fn load_script(path: &str, data: &[u8], args: &mut Vec<String>) -> Option<AlienResult<Binary>> {
+    if !data.starts_with(b"#!") {
+        return None;
+    }
+    Some(parse_script(data).map(|(interpreter, arg)| {
+        let mut new_args = Vec::with_capacity(args.len() + 3);
+        new_args.push(format!("{}\0", interpreter));
+        if let Some(arg) = arg {
+            new_args.push(format!("{}\0", arg));
+        }
+        new_args.push(format!("{}\0", path));
+        new_args.extend(args.drain(..).skip(1));
+        *args = new_args;
+        Binary::Interpreter(interpreter)
+    }))
+}
+
+/// 解析脚本的第一行 `#!interpreter [arg]`，返回解释器与可选的参数
+///
+/// 解释器与参数之间以空格或制表符分隔，解释器之后的内容去除首尾空白后作为一个整体成为唯一的参数。
+/// 第一行超过 [`BINPRM_BUF_SIZE`] 且解释器路径被截断时返回 `ENOEXEC`。
+fn parse_script(data: &[u8]) -> AlienResult<(String, Option<String>)> {
+    let buf = &data[2..data.len().min(BINPRM_BUF_SIZE)];
+    let (line, complete) = match buf.iter().position(|c| *c == b'\n') {
+        Some(end) => (&buf[..end], true),
+        None => (buf, data.len() <= BINPRM_BUF_SIZE),
+    };
+    let line = core::str::from_utf8(line).map_err(|_| LinuxErrno::ENOEXEC)?;
+    let line = line.trim_matches(|c| c == ' ' || c == '\t' || c == '\r' || c == '\0');
+    let (interpreter, arg) = match line.find(|c| c == ' ' || c == '\t') {
+        Some(pos) => (
+            &line[..pos],
+            Some(line[pos..].trim_matches(|c| c == ' ' || c == '\t')),
+        ),
+        None => (line, None),
+    };
+    // 第一行被截断时，只有解释器路径完整才能执行
+    if interpreter.is_empty() || (!complete && arg.is_none()) {
+        return Err(LinuxErrno::ENOEXEC);
+    }
+    Ok((
+        interpreter.to_string(),
+        arg.filter(|arg| !arg.is_empty()).map(|arg| arg.to_string()),
+    ))
+}
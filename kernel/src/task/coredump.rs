@@ -0,0 +1,571 @@
+//! 进程因信号终止时生成 ELF 格式的 core 文件。
+//!
+//! 默认处理方式为终止并转储的信号(如 SIGSEGV、SIGABRT)终止进程时，内核向 `/proc/sys/kernel/core_pattern`
+//! 所指明的路径写入 core 文件，以便使用 riscv64 的 gdb 离线调试。文件中包含：
+//! + 一个 PT_NOTE 段，依次为 NT_PRPSINFO、每个线程的 NT_PRSTATUS(第一个为收到信号的线程)、NT_AUXV 与 NT_FILE；
+//! + 每个内存区域对应的 PT_LOAD 段，包括程序与动态链接器的段、堆、内存映射区域与用户栈。
+//!   没有物理页的页不会被分配物理页，在文件中留空；没有访问权限的区域只记录地址范围。
+//!
+//! core 文件的大小不能超过 `RLIMIT_CORE`，超出的部分被丢弃，该限制小于一页(默认为 0)时不生成 core 文件。
+//! 以 setuid/setgid 身份运行的进程不生成 core 文件。
+//!
+//! `core_pattern` 中可以使用 `%p`(pid)、`%i`(tid)、`%u`(uid)、`%g`(gid)、`%s`(信号)、`%t`(时间)、`%e`(进程名)与 `%%`，
+//! 不支持以 `|` 开头的管道。相对路径相对于进程的当前工作目录。
+//!
+//! Reference: [core](https://man7.org/linux/man-pages/man5/core.5.html)
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{cmp::min, mem::size_of};
+
+use config::FRAME_SIZE;
+use ksync::Mutex;
+use page_table::{addr::VirtAddr, pte::MappingFlags};
+use timer::TimeSpec;
+use vfs::{
+    kfile::KernelFile,
+    proc::{register_sysctl, SysctlOps},
+};
+use vfscore::{error::VfsError, inode::VfsInode, VfsResult};
+
+use crate::{
+    fs::create_core_file,
+    mm::map::ProtFlags,
+    task::{current_task, process_threads, Capabilities, RLimitRes, Task},
+};
+
+/// core_pattern 的最大长度，与 Linux 的 `CORENAME_MAX_SIZE` 相同
+const CORENAME_MAX_SIZE: usize = 128;
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+/// RVC 与双精度浮点 ABI(lp64d)
+const EF_RISCV: u32 = 0x5;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x46494c45;
+
+/// `/proc/sys/kernel/core_pattern` 的值
+static CORE_PATTERN: Mutex<String> = Mutex::new(String::new());
+
+#[repr(C)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+/// NT_PRSTATUS 的内容，与 riscv64 Linux 的 `struct elf_prstatus` 相同
+#[repr(C)]
+#[derive(Default)]
+struct ElfPrstatus {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+    pr_cursig: i16,
+    _pad0: i16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_utime: [i64; 2],
+    pr_stime: [i64; 2],
+    pr_cutime: [i64; 2],
+    pr_cstime: [i64; 2],
+    /// pc 与 x1-x31
+    pr_reg: [u64; 32],
+    pr_fpvalid: i32,
+    _pad1: i32,
+}
+
+/// NT_PRPSINFO 的内容，与 riscv64 Linux 的 `struct elf_prpsinfo` 相同
+#[repr(C)]
+struct ElfPrpsinfo {
+    pr_state: u8,
+    pr_sname: u8,
+    pr_zomb: u8,
+    pr_nice: i8,
+    _pad0: u32,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 向 `buf` 中加入一个名称为 `CORE` 的 note
+fn push_note(buf: &mut Vec<u8>, n_type: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0\0\0\0";
+    buf.extend_from_slice(&5u32.to_ne_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_ne_bytes());
+    buf.extend_from_slice(&n_type.to_ne_bytes());
+    buf.extend_from_slice(NAME);
+    buf.extend_from_slice(desc);
+    buf.resize((buf.len() + 3) & !3, 0);
+}
+
+/// core 文件中的一个 PT_LOAD 段
+struct CoreSegment {
+    start: usize,
+    end: usize,
+    flags: u32,
+    /// 是否写入段的内容
+    dump: bool,
+}
+
+/// 收集当前进程的内存区域，按照起始地址排序
+fn collect_segments(task: &Arc<Task>) -> Vec<CoreSegment> {
+    let inner = task.access_inner();
+    let mut segments = Vec::new();
+    let heap = inner.heap.lock();
+    if heap.end > heap.start {
+        segments.push(CoreSegment {
+            start: heap.start,
+            end: heap.end,
+            flags: PF_R | PF_W,
+            dump: true,
+        });
+    }
+    drop(heap);
+    for region in inner.mmap.regions() {
+        let mut flags = 0;
+        if region.prot.contains(ProtFlags::PROT_READ) {
+            flags |= PF_R;
+        }
+        if region.prot.contains(ProtFlags::PROT_WRITE) {
+            flags |= PF_W;
+        }
+        if region.prot.contains(ProtFlags::PROT_EXEC) {
+            flags |= PF_X;
+        }
+        segments.push(CoreSegment {
+            start: region.start,
+            end: region.start + region.map_len,
+            flags,
+            dump: flags & PF_R != 0,
+        });
+    }
+    segments.push(CoreSegment {
+        start: inner.stack.start,
+        end: inner.stack.end,
+        flags: PF_R | PF_W,
+        dump: true,
+    });
+    // 程序与动态链接器的段不属于上面的区域，从页表中找出其余的用户页并合并相邻的页
+    let known = segments
+        .iter()
+        .map(|segment| segment.start..segment.end)
+        .collect::<Vec<_>>();
+    let address_space = inner.address_space.lock();
+    let mut others: Vec<CoreSegment> = Vec::new();
+    for (v_addr, _) in address_space.get_record().into_iter() {
+        let addr = v_addr.as_usize();
+        if known.iter().any(|range| range.contains(&addr)) {
+            continue;
+        }
+        let (_, flag, page_size) = match address_space.query(VirtAddr::from(addr)) {
+            Ok(res) => res,
+            Err(_) => continue,
+        };
+        if !flag.contains(MappingFlags::V | MappingFlags::U) {
+            continue;
+        }
+        let mut flags = 0;
+        if flag.contains(MappingFlags::R) {
+            flags |= PF_R;
+        }
+        if flag.contains(MappingFlags::W) || flag.contains(MappingFlags::RSD) {
+            flags |= PF_W;
+        }
+        if flag.contains(MappingFlags::X) {
+            flags |= PF_X;
+        }
+        let end = addr + usize::from(page_size);
+        match others.last_mut() {
+            Some(last) if last.end == addr && last.flags == flags => last.end = end,
+            _ => others.push(CoreSegment {
+                start: addr,
+                end,
+                flags,
+                dump: true,
+            }),
+        }
+    }
+    drop(address_space);
+    segments.extend(others);
+    segments.sort_unstable_by_key(|segment| segment.start);
+    segments
+}
+
+/// 生成 NT_FILE 的内容，记录所有文件映射所在的范围与对应的文件
+fn file_note(task: &Arc<Task>) -> Vec<u8> {
+    let inner = task.access_inner();
+    let files = inner
+        .mmap
+        .regions()
+        .filter_map(|region| {
+            // 只有路径上的文件才有文件名，socket 等文件没有对应的目录项
+            let file = region.fd.clone()?.downcast_arc::<KernelFile>().ok()?;
+            Some((
+                region.start,
+                region.start + region.map_len,
+                region.offset / FRAME_SIZE,
+                file.dentry().path(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&(files.len() as u64).to_ne_bytes());
+    desc.extend_from_slice(&(FRAME_SIZE as u64).to_ne_bytes());
+    for (start, end, offset, _) in files.iter() {
+        desc.extend_from_slice(&(*start as u64).to_ne_bytes());
+        desc.extend_from_slice(&(*end as u64).to_ne_bytes());
+        desc.extend_from_slice(&(*offset as u64).to_ne_bytes());
+    }
+    for (.., path) in files.iter() {
+        desc.extend_from_slice(path.as_bytes());
+        desc.push(0);
+    }
+    desc
+}
+
+/// 生成 NT_PRSTATUS，`signum` 为 0 表示该线程不是收到信号的线程
+fn prstatus(thread: &Arc<Task>, signum: usize) -> ElfPrstatus {
+    let regs = thread.trap_frame().user_regs();
+    ElfPrstatus {
+        si_signo: signum as i32,
+        pr_cursig: signum as i16,
+        pr_pid: thread.get_tid() as i32,
+        pr_ppid: thread
+            .access_inner()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.get_pid() as i32),
+        pr_pgrp: thread.get_pid() as i32,
+        pr_sid: thread.get_pid() as i32,
+        pr_reg: regs.map(|reg| reg as u64),
+        ..Default::default()
+    }
+}
+
+/// 生成 NT_PRPSINFO
+fn prpsinfo(task: &Arc<Task>) -> ElfPrpsinfo {
+    let inner = task.access_inner();
+    let name = inner.name.trim_end_matches('\0');
+    let mut info = ElfPrpsinfo {
+        pr_state: 0,
+        pr_sname: b'R',
+        pr_zomb: 0,
+        pr_nice: 0,
+        _pad0: 0,
+        pr_flag: 0,
+        pr_uid: inner.cred.ruid,
+        pr_gid: inner.cred.rgid,
+        pr_pid: task.get_pid() as i32,
+        pr_ppid: inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.get_pid() as i32),
+        pr_pgrp: task.get_pid() as i32,
+        pr_sid: task.get_pid() as i32,
+        pr_fname: [0; 16],
+        pr_psargs: [0; 80],
+    };
+    let fname = name.rsplit('/').next().unwrap_or(name).as_bytes();
+    let len = min(fname.len(), info.pr_fname.len() - 1);
+    info.pr_fname[..len].copy_from_slice(&fname[..len]);
+    let len = min(name.len(), info.pr_psargs.len() - 1);
+    info.pr_psargs[..len].copy_from_slice(&name.as_bytes()[..len]);
+    info
+}
+
+/// 按照 core_pattern 生成 core 文件的路径
+fn core_file_name(task: &Arc<Task>, signum: usize) -> String {
+    let pattern = CORE_PATTERN.lock().clone();
+    let pattern = if pattern.is_empty() {
+        "core"
+    } else {
+        pattern.as_str()
+    };
+    let inner = task.access_inner();
+    let mut name = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            name.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => name.push('%'),
+            Some('p') | Some('P') => name += &task.get_pid().to_string(),
+            Some('i') | Some('I') => name += &task.get_tid().to_string(),
+            Some('u') => name += &inner.cred.ruid.to_string(),
+            Some('g') => name += &inner.cred.rgid.to_string(),
+            Some('s') => name += &signum.to_string(),
+            Some('t') => name += &TimeSpec::now().tv_sec.to_string(),
+            Some('e') => {
+                let comm = inner.name.trim_end_matches('\0');
+                let comm = comm.rsplit('/').next().unwrap_or(comm);
+                name += &comm.replace('/', "!");
+            }
+            // 与 Linux 相同，不认识的格式被忽略
+            _ => {}
+        }
+    }
+    name
+}
+
+/// 向 core 文件写入数据，超出 `RLIMIT_CORE` 的部分被丢弃
+struct CoreWriter {
+    inode: Arc<dyn VfsInode>,
+    /// 下一次写入的位置
+    offset: usize,
+    /// 已经写入文件的末尾
+    written: usize,
+    limit: usize,
+}
+
+impl CoreWriter {
+    /// 写入 `data`，写入失败或已经达到限制时返回 false
+    fn write(&mut self, data: &[u8]) -> bool {
+        let len = min(data.len(), self.limit.saturating_sub(self.offset));
+        let mut done = 0;
+        while done < len {
+            match self
+                .inode
+                .write_at((self.offset + done) as u64, &data[done..len])
+            {
+                Ok(0) | Err(_) => return false,
+                Ok(n) => done += n,
+            }
+        }
+        self.offset += len;
+        self.written = self.offset;
+        len == data.len()
+    }
+
+    /// 跳过 `len` 个字节，在文件中留下空洞
+    fn skip(&mut self, len: usize) {
+        self.offset += len;
+    }
+
+    /// 写入空洞之后的最后一个字节，使文件的大小包含末尾的空洞。写入失败或者空洞超出限制时返回 false
+    fn finish(&mut self) -> bool {
+        let complete = self.offset <= self.limit;
+        let end = min(self.offset, self.limit);
+        if end > self.written {
+            self.offset = end - 1;
+            return self.write(&[0]) && complete;
+        }
+        complete
+    }
+}
+
+/// 当前进程因信号 `signum` 终止时调用，生成 core 文件，成功时返回 true
+///
+/// 写入失败或者 core 文件超出 `RLIMIT_CORE` 的限制时返回 false，文件中保留已经写入的部分。
+pub fn do_coredump(signum: usize) -> bool {
+    let task = current_task().unwrap().clone();
+    let inner = task.access_inner();
+    let limit = inner.resource_limits.cur(RLimitRes::Core);
+    let limit = min(limit, usize::MAX as u64) as usize;
+    let cred = &inner.cred;
+    let dumpable = cred.euid == cred.ruid && cred.egid == cred.rgid;
+    let auxv = inner.auxv.clone();
+    drop(inner);
+    if limit < FRAME_SIZE || !dumpable {
+        return false;
+    }
+    let name = core_file_name(&task, signum);
+    let inode = match create_core_file(&name) {
+        Ok(inode) => inode,
+        Err(e) => {
+            warn!("create core file {} failed: {:?}", name, e);
+            return false;
+        }
+    };
+
+    // notes
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRPSINFO, as_bytes(&prpsinfo(&task)));
+    push_note(&mut notes, NT_PRSTATUS, as_bytes(&prstatus(&task, signum)));
+    process_threads(task.pid)
+        .iter()
+        .filter(|thread| thread.get_tid() != task.get_tid())
+        .for_each(|thread| push_note(&mut notes, NT_PRSTATUS, as_bytes(&prstatus(thread, 0))));
+    let auxv = auxv
+        .iter()
+        .chain([(0, 0)].iter())
+        .flat_map(|(key, value)| [*key as u64, *value as u64])
+        .flat_map(|value| value.to_ne_bytes())
+        .collect::<Vec<u8>>();
+    push_note(&mut notes, NT_AUXV, &auxv);
+    push_note(&mut notes, NT_FILE, &file_note(&task));
+
+    // headers
+    let segments = collect_segments(&task);
+    let ph_num = segments.len() + 1;
+    let note_offset = size_of::<Elf64Ehdr>() + ph_num * size_of::<Elf64Phdr>();
+    let data_offset = (note_offset + notes.len() + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+    let mut e_ident = [0u8; 16];
+    e_ident[..4].copy_from_slice(b"\x7fELF");
+    // ELFCLASS64, ELFDATA2LSB, EV_CURRENT, ELFOSABI_SYSV
+    e_ident[4..7].copy_from_slice(&[2, 1, 1]);
+    let ehdr = Elf64Ehdr {
+        e_ident,
+        e_type: ET_CORE,
+        e_machine: EM_RISCV,
+        e_version: 1,
+        e_entry: 0,
+        e_phoff: size_of::<Elf64Ehdr>() as u64,
+        e_shoff: 0,
+        e_flags: EF_RISCV,
+        e_ehsize: size_of::<Elf64Ehdr>() as u16,
+        e_phentsize: size_of::<Elf64Phdr>() as u16,
+        e_phnum: ph_num as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+    let mut headers = Vec::new();
+    headers.extend_from_slice(as_bytes(&ehdr));
+    let note = Elf64Phdr {
+        p_type: PT_NOTE,
+        p_flags: 0,
+        p_offset: note_offset as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes.len() as u64,
+        p_memsz: 0,
+        p_align: 0,
+    };
+    headers.extend_from_slice(as_bytes(&note));
+    let mut offset = data_offset;
+    for segment in segments.iter() {
+        let len = segment.end - segment.start;
+        let file_len = if segment.dump { len } else { 0 };
+        let phdr = Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags: segment.flags,
+            p_offset: offset as u64,
+            p_vaddr: segment.start as u64,
+            p_paddr: 0,
+            p_filesz: file_len as u64,
+            p_memsz: len as u64,
+            p_align: FRAME_SIZE as u64,
+        };
+        headers.extend_from_slice(as_bytes(&phdr));
+        offset += file_len;
+    }
+
+    let mut writer = CoreWriter {
+        inode,
+        offset: 0,
+        written: 0,
+        limit,
+    };
+    let mut ok = writer.write(&headers) && writer.write(&notes);
+    writer.skip(data_offset - writer.offset);
+    // 逐页读取内存，读取时不持有锁，避免写入文件时关闭中断
+    let mut page = vec![0u8; FRAME_SIZE];
+    'dump: for segment in segments.iter().filter(|segment| segment.dump) {
+        for addr in (segment.start..segment.end).step_by(FRAME_SIZE) {
+            if !ok {
+                break 'dump;
+            }
+            if task.access_inner().read_page_for_dump(addr, &mut page) {
+                ok = writer.write(&page);
+            } else {
+                writer.skip(FRAME_SIZE);
+            }
+        }
+    }
+    let ok = ok && writer.finish();
+    warn!(
+        "task {} dump core to {}, complete: {}",
+        task.get_tid(),
+        name,
+        ok
+    );
+    ok
+}
+
+fn core_pattern_read() -> String {
+    let pattern = CORE_PATTERN.lock();
+    if pattern.is_empty() {
+        String::from("core\n")
+    } else {
+        format!("{}\n", pattern)
+    }
+}
+
+fn core_pattern_write(data: &[u8]) -> VfsResult<()> {
+    let task = current_task().ok_or(VfsError::PermissionDenied)?;
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(VfsError::PermissionDenied);
+    }
+    let pattern = core::str::from_utf8(data).map_err(|_| VfsError::Invalid)?;
+    let pattern = pattern.strip_suffix('\n').unwrap_or(pattern);
+    if pattern.is_empty() || pattern.len() >= CORENAME_MAX_SIZE || pattern.starts_with('|') {
+        return Err(VfsError::Invalid);
+    }
+    *CORE_PATTERN.lock() = pattern.to_string();
+    Ok(())
+}
+
+/// 创建 `/proc/sys/kernel/core_pattern` 文件
+pub fn init_coredump() {
+    register_sysctl(
+        "kernel",
+        "core_pattern",
+        SysctlOps {
+            read: core_pattern_read,
+            write: core_pattern_write,
+        },
+    );
+}
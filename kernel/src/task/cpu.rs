@@ -1,17 +1,18 @@
 //! Alien 中有关进程的系统调用 和 多核的相关支持。
-use alloc::{
-    string::{String, ToString},
-    sync::Arc,
-    vec::Vec,
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    cmp::min,
+    mem::size_of,
+    sync::atomic::{AtomicBool, Ordering},
 };
-use core::cell::UnsafeCell;
 
-use config::CPU_NUM;
+use config::{CPU_NUM, FRAME_SIZE, MAX_ARG_STRLEN, PATH_MAX, USER_STACK_MAX_SIZE};
 use constants::{
-    ipc::FutexOp,
+    io::OpenFlags,
     signal::SignalNumber,
     task::{CloneFlags, WaitOptions},
-    AlienError, AlienResult, PrLimit, PrLimitRes,
+    AlienError, AlienResult, LinuxErrno, PrLimit,
 };
 use ksync::Mutex;
 use log::{info, warn};
@@ -22,12 +23,23 @@ use syscall_table::syscall_func;
 
 use crate::{
     fs,
-    ipc::{futex, global_logoff_signals},
+    ipc::{
+        futex,
+        futex::{exit_robust_list, FUTEX_WAKE},
+        global_logoff_signals, send_signal, sigkill_pending,
+        sigqueue::SIGNAL_MAX,
+    },
+    mm::uaccess::UserPtr,
     task::{
+        binfmt::{load_binary, Binary, MAX_INTERP_DEPTH},
         context::Context,
+        namespace::{check_new_namespaces, child_reaper, exit_pid_namespace},
+        pid_from_user, pid_to_user,
+        pidfd::PidFd,
+        ptrace::{is_tracing, ptrace_exec, ptrace_exit, ptrace_wait},
+        resource::RLimitRes,
         schedule::schedule,
         task::{Task, TaskState},
-        INIT_PROCESS,
     },
     trap::{check_task_timer_expired, TrapFrame},
 };
@@ -121,39 +133,57 @@ pub fn current_trap_frame() -> &'static mut TrapFrame {
 /// `exit_code`中的值，将会在其父进程调用[`wait4`]时，作为信息传递给父进程。
 /// 当一个具有子进程的进程终止时，其所有子进程将转交至init进程，由init进程完成其子进程相关资源的回收。
 /// 当`clear_child_tid`不为0时，会将`clear_child_tid`该处的值置为0，同时内核唤醒当前正在等待的futex。
+/// 在此之前会遍历进程的 robust 列表，释放其仍然持有的 robust 锁，详见[`exit_robust_list`]。
 ///
 /// 当调用该函数的进程为`pid==0`的init进程时，将直接调用`system_shutdown`使得内核终止。
 #[syscall_func(93)]
 pub fn do_exit(exit_code: i32) -> isize {
+    exit_with_status((exit_code & 0xff) << 8)
+}
+
+/// 因信号 `signum` 终止当前线程，`core_dumped` 表示是否生成了 core 文件。
+///
+/// 父进程通过 [`wait4`] 得到的状态中记录了终止进程的信号，生成了 core 文件时还设置了 `WCOREDUMP` 标志(0x80)。
+pub fn do_exit_by_signal(signum: usize, core_dumped: bool) -> isize {
+    let core_flag = if core_dumped { 0x80 } else { 0 };
+    exit_with_status(signum as i32 & 0x7f | core_flag)
+}
+
+/// 终止当前线程，`exit_code` 为父进程通过 [`wait4`] 得到的状态
+fn exit_with_status(exit_code: i32) -> isize {
     let task = current_task().unwrap();
-    let exit_code = (exit_code & 0xff) << 8;
     if task.get_pid() == 1 {
         println!("Init process exit with code {}", exit_code);
         system_shutdown();
     }
+    ptrace_exit(task);
+    exit_pid_namespace(task);
     {
-        let init = INIT_PROCESS.clone();
+        let reaper = child_reaper(task);
         task.take_children().into_iter().for_each(|child| {
-            child.update_parent(init.clone());
-            init.insert_child(child);
+            child.update_parent(reaper.clone());
+            reaper.insert_child(child);
         });
     }
     task.update_state(TaskState::Zombie);
     task.update_exit_code(exit_code);
     global_logoff_signals(task.get_tid() as usize);
+    // robust 锁可能位于共享的文件映射中，需要在释放映射之前处理
+    exit_robust_list(task);
     // 回收一些物理页，不然等到wait系统调用真正进行回收时，可能会出现OOM
     // 在这里还不能回收内核栈页，因为还需要用到内核栈页来执行下面的代码
     task.pre_recycle();
     info!("pre recycle done");
     let clear_child_tid = task.clear_child_tid();
     if clear_child_tid != 0 {
-        let phy_addr = task.transfer_raw_ptr(clear_child_tid as *mut usize);
-        *phy_addr = 0;
+        // 地址无效时不再清零，但仍然唤醒等待者
+        let _ = task.write_user(UserPtr::<usize>::new(clear_child_tid), &0);
         info!("exit wake futex on {:#x}", clear_child_tid);
-        futex(clear_child_tid, FutexOp::FutexWake as u32, 1, 0, 0, 0);
+        let _ = futex(clear_child_tid, FUTEX_WAKE, 1, 0, 0, 0);
     } else {
         info!("exit clear_child_tid is 0");
     }
+    complete_vfork(task);
     schedule();
     0
 }
@@ -196,10 +226,12 @@ pub fn set_sid() -> isize {
 }
 
 /// 获取当前正在运行task的pid号。在Alien中pid作为线程组的标识符，位于同一线程组中的线程的pid相同。
+///
+/// 返回的是当前task所在的 pid 命名空间中的 pid 号，下同。
 #[syscall_func(172)]
 pub fn get_pid() -> isize {
     let process = current_task().unwrap();
-    process.get_pid()
+    pid_to_user(process.get_pid() as usize)
 }
 
 /// 获取当前正在运行task的ppid号，即父task的pid号。
@@ -210,39 +242,54 @@ pub fn get_ppid() -> isize {
     if parent.is_none() {
         return 0;
     } else {
-        parent.unwrap().upgrade().unwrap().get_pid()
+        pid_to_user(parent.unwrap().upgrade().unwrap().get_pid() as usize)
     }
 }
 
-/// (待实现)获取用户 id。在实现多用户权限前默认为最高权限。目前直接返回0。
-#[syscall_func(174)]
-pub fn getuid() -> isize {
-    0
-}
-
-/// (待实现)获取有效用户 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限。目前直接返回0。
-#[syscall_func(175)]
-pub fn geteuid() -> isize {
-    0
-}
-
-/// (待实现)获取用户组 id。在实现多用户权限前默认为最高权限。目前直接返回0。
-#[syscall_func(176)]
-pub fn getgid() -> isize {
-    0
-}
-
-/// (待实现)获取有效用户组 id，即相当于哪个用户组的权限。在实现多用户组权限前默认为最高权限。目前直接返回0。
-#[syscall_func(177)]
-pub fn getegid() -> isize {
-    0
-}
-
 /// 获取当前正在运行task的tid号。在Alien中tid作为task的唯一标识符。
 #[syscall_func(178)]
 pub fn get_tid() -> isize {
     let process = current_task().unwrap();
-    process.get_tid()
+    pid_to_user(process.get_tid() as usize)
+}
+
+/// clone 的标志：在 `pidfd` 处返回指向子进程的 pidfd，见 [`PidFd`]
+const CLONE_PIDFD: u64 = 0x1000;
+/// clone3 的标志：将子进程中除了被忽略的信号以外的信号处理函数重置为默认
+const CLONE_CLEAR_SIGHAND: u64 = 0x1_0000_0000;
+/// clone3 的标志：在 `cgroup` 所指的 cgroup 中创建子进程，Alien 没有 cgroup，该标志被忽略
+const CLONE_INTO_CGROUP: u64 = 0x2_0000_0000;
+/// clone 的 `flag` 中低 8 位为子进程退出时发送给父进程的信号
+const CSIGNAL: u64 = 0xff;
+/// 最早版本的 `struct clone_args` 的大小，不包含 `set_tid` 与 `cgroup` 字段
+const CLONE_ARGS_SIZE_VER0: usize = 64;
+
+/// clone3 的参数，与 Linux 中的 `struct clone_args` 相同
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CloneArgs {
+    /// 控制父子进程之间资源共享程度的标志
+    pub flags: u64,
+    /// 包含 `CLONE_PIDFD` 时，写入 pidfd 的地址
+    pub pidfd: u64,
+    /// 包含 `CLONE_CHILD_SETTID` 或 `CLONE_CHILD_CLEARTID` 时使用的子进程中的地址
+    pub child_tid: u64,
+    /// 包含 `CLONE_PARENT_SETTID` 时，写入子进程 tid 的地址
+    pub parent_tid: u64,
+    /// 子进程退出时发送给父进程的信号
+    pub exit_signal: u64,
+    /// 子进程用户栈的最低地址
+    pub stack: u64,
+    /// 子进程用户栈的大小
+    pub stack_size: u64,
+    /// 包含 `CLONE_SETTLS` 时子进程的 TLS
+    pub tls: u64,
+    /// 指定子进程的 tid，目前被忽略
+    pub set_tid: u64,
+    /// `set_tid` 中 tid 的个数，目前被忽略
+    pub set_tid_size: u64,
+    /// 包含 `CLONE_INTO_CGROUP` 时子进程所在的 cgroup，目前被忽略
+    pub cgroup: u64,
 }
 
 /// 一个系统调用，用于创建一个子进程。
@@ -258,33 +305,176 @@ pub fn get_tid() -> isize {
 /// `tls`用于为子进程创建新的TLS(thread-local storage)值，在flag包含`CLONE_SETTLS`时才会实际产生效果。
 /// `ctid`用于给子进程中的[`set_child_tid`]和[`clear_child_tid`]赋值(分别在flag中包含`CLONE_CHILD_SETTID`和`CLONE_CHILD_CLEARTID`时产生效果)。
 ///
-/// 成功创建子进程后父进程会返回子进程的tid号，子进程的返回值将被设置为0；否则返回-1。
+/// flag 包含 `CLONE_PIDFD` 时，指向子进程的 pidfd 被写入 `ptid` 处，此时不能同时包含 `CLONE_PARENT_SETTID`。
+/// flag 包含 `CLONE_VFORK` 时，父进程阻塞直到子进程执行新程序或者退出。
+///
+/// 成功创建子进程后父进程会返回子进程的tid号，子进程的返回值将被设置为0；否则返回错误类型。
 ///
 /// Reference: [clone](https://www.man7.org/linux/man-pages/man2/clone.2.html)
 #[syscall_func(220)]
-pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) -> isize {
-    let clone_flag = CloneFlags::from_bits_truncate(flag as u32);
-    // check whether flag include signal
-    let sig = flag & 0xff;
-    let sig = SignalNumber::from(sig);
+pub fn clone(
+    flag: usize,
+    stack: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> AlienResult<isize> {
+    // 旧的 clone 只使用 flag 的低 32 位
+    let flags = flag as u32 as u64;
+    if flags & CLONE_PIDFD != 0
+        && CloneFlags::from_bits_truncate(flags as u32).contains(CloneFlags::CLONE_PARENT_SETTID)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    do_clone(CloneArgs {
+        flags: flags & !CSIGNAL,
+        pidfd: ptid as u64,
+        child_tid: ctid as u64,
+        parent_tid: ptid as u64,
+        exit_signal: flags & CSIGNAL,
+        stack: stack as u64,
+        tls: tls as u64,
+        ..CloneArgs::default()
+    })
+}
+
+/// 一个系统调用，以 `struct clone_args` 作为参数创建一个子进程。
+///
+/// `uargs` 指向 [`CloneArgs`]，`size` 为其大小，用于兼容不同版本的结构：比内核更早的版本中缺少的字段视为 0，
+/// 比内核更新的版本中多出的部分必须为 0，否则返回 `E2BIG`。与 [`clone`] 不同，子进程的用户栈由最低地址 `stack`
+/// 与大小 `stack_size` 给出，pidfd 写入单独的 `pidfd` 字段。另外支持 `CLONE_CLEAR_SIGHAND`，
+/// 它不能与 `CLONE_SIGHAND` 同时使用。`set_tid` 与 `cgroup` 字段目前被忽略。
+///
+/// 成功创建子进程后父进程会返回子进程的tid号，子进程的返回值将被设置为0；否则返回错误类型。
+///
+/// Reference: [clone3](https://www.man7.org/linux/man-pages/man2/clone3.2.html)
+#[syscall_func(435)]
+pub fn clone3(uargs: usize, size: usize) -> AlienResult<isize> {
+    if size < CLONE_ARGS_SIZE_VER0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if size > FRAME_SIZE {
+        return Err(LinuxErrno::E2BIG);
+    }
+    let task = current_task().unwrap();
+    let mut data = vec![0u8; size];
+    task.access_inner().copy_from_user_bytes(uargs, &mut data)?;
+    let known = min(size, size_of::<CloneArgs>());
+    if data[known..].iter().any(|byte| *byte != 0) {
+        return Err(LinuxErrno::E2BIG);
+    }
+    let mut args = CloneArgs::default();
+    unsafe {
+        core::slice::from_raw_parts_mut(&mut args as *mut CloneArgs as *mut u8, known)
+            .copy_from_slice(&data[..known]);
+    }
+    if args.flags & CSIGNAL != 0
+        || args.flags & !(u32::MAX as u64 | CLONE_CLEAR_SIGHAND | CLONE_INTO_CGROUP) != 0
+        || args.exit_signal >= SIGNAL_MAX as u64
+        || (args.stack == 0) != (args.stack_size == 0)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    do_clone(args)
+}
+
+/// [`clone`] 与 [`clone3`] 的共同实现
+fn do_clone(args: CloneArgs) -> AlienResult<isize> {
+    let clone_flag = CloneFlags::from_bits_truncate(args.flags as u32);
+    check_new_namespaces(clone_flag)?;
+    if args.flags & CLONE_PIDFD != 0 && clone_flag.contains(CloneFlags::CLONE_THREAD) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if args.flags & CLONE_CLEAR_SIGHAND != 0 && clone_flag.contains(CloneFlags::CLONE_SIGHAND) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let sig = SignalNumber::from(args.exit_signal as usize);
     let mut task = current_task().unwrap();
+    if args.flags & CLONE_PIDFD != 0 {
+        // 预先检查是否可以写入 pidfd，子进程创建后就无法撤销了
+        task.write_user(UserPtr::<i32>::new(args.pidfd as usize), &-1)?;
+    }
 
     let child_num = task.access_inner().children.len();
     if child_num >= 10 {
         do_suspend();
         task = current_task().unwrap();
     }
-    let new_task = task.t_clone(clone_flag, stack, sig, ptid, tls, ctid);
-    if new_task.is_none() {
-        return -1;
+    let new_task = task.t_clone(
+        clone_flag,
+        args.stack.wrapping_add(args.stack_size) as usize,
+        sig,
+        args.parent_tid as usize,
+        args.tls as usize,
+        args.child_tid as usize,
+    )?;
+    if args.flags & CLONE_CLEAR_SIGHAND != 0 {
+        clear_signal_handlers(&new_task);
     }
-    let new_task = new_task.unwrap();
     // update return value
     let trap_frame = new_task.trap_frame();
     trap_frame.update_res(0);
     let tid = new_task.get_tid();
+    let pidfd = if args.flags & CLONE_PIDFD != 0 {
+        let pidfd = PidFd::new(&new_task, OpenFlags::empty())
+            .and_then(|pidfd| {
+                task.add_file(Arc::new(pidfd))
+                    .map_err(|_| LinuxErrno::EMFILE)
+            })
+            .and_then(|fd| task.write_user(UserPtr::<i32>::new(args.pidfd as usize), &(fd as i32)));
+        if pidfd.is_err() {
+            // 子进程已经创建，无法撤销，让它在返回用户态时被终止
+            send_signal(tid as usize, SignalNumber::SIGKILL as usize);
+        }
+        pidfd
+    } else {
+        Ok(())
+    };
+    let vfork_done = if clone_flag.contains(CloneFlags::CLONE_VFORK) && pidfd.is_ok() {
+        let done = Arc::new(AtomicBool::new(false));
+        new_task.access_inner().vfork_done = Some(done.clone());
+        Some(done)
+    } else {
+        None
+    };
     GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(new_task)));
-    tid
+    pidfd?;
+    // 子进程执行新程序或者退出之前使用父进程的地址空间与用户栈，父进程需要等待
+    // 父进程收到 SIGKILL 时不再等待，返回后由信号处理终止
+    if let Some(done) = vfork_done {
+        let task = current_task().unwrap();
+        while !done.load(Ordering::Acquire)
+            && !sigkill_pending(task.get_tid() as usize, task.get_pid() as usize)
+        {
+            do_suspend();
+        }
+    }
+    Ok(pid_to_user(tid as usize))
+}
+
+/// 将 `task` 中除了被忽略的信号以外的信号处理函数重置为默认，用于 `CLONE_CLEAR_SIGHAND`
+fn clear_signal_handlers(task: &Arc<Task>) {
+    let handlers = task.access_inner().signal_handlers.clone();
+    let mut handlers = handlers.lock();
+    let ignored = (1..SIGNAL_MAX)
+        .filter_map(|signum| {
+            handlers
+                .get_action_ref(signum)
+                .filter(|action| action.is_ignore())
+                .map(|action| (signum, *action))
+        })
+        .collect::<Vec<_>>();
+    handlers.clear();
+    ignored
+        .iter()
+        .for_each(|(signum, action)| handlers.set_action(*signum, action));
+}
+
+/// 由 vfork 创建的子进程执行新程序或者退出时，唤醒等待它的父进程
+fn complete_vfork(task: &Task) {
+    if let Some(done) = task.access_inner().vfork_done.take() {
+        done.store(true, Ordering::Release);
+    }
 }
 
 /// 一个系统调用，用于执行一个文件。
@@ -293,38 +483,55 @@ pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) ->
 /// `args_ptr`用于指明保存启动可执行文件时要传入的参数的地址。
 /// `env`用于指明保存相关环境变量的地址。
 ///
+/// 当前进程需要拥有对该文件的执行权限，否则返回`EACCES`。如果文件设置了 setuid/setgid 位，
+/// 进程的有效用户 id/组 id 将被设置为文件的所有者/所属组。
+///
+/// 以 `#!` 开头的脚本以及匹配 binfmt_misc 规则的文件交给解释器执行，见 [`load_binary`]；
+/// 解释器嵌套过深时返回 `ELOOP`，无法识别的文件格式返回 `ENOEXEC`。
+///
+/// 路径的长度不能超过 [`PATH_MAX`]；单个参数或环境变量的长度不能超过 [`MAX_ARG_STRLEN`]，
+/// 且所有参数与环境变量(包括指针数组)的总大小不能超过 `RLIMIT_STACK` 的 1/4，否则返回 `E2BIG`。
+///
 /// 成功执行文件后会返回0；否则会返回-1或错误类型。
 #[syscall_func(221)]
 pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let mut path_str = task.transfer_str(path);
+    let path_str = task.strncpy_from_user(path, PATH_MAX)?;
     // get the args and push them into the new process stack
-    let (mut args, envs) = parse_user_arg_env(args_ptr, env);
+    let (mut args, envs) = parse_user_arg_env(args_ptr, env)?;
     warn!("exec path: {}", path_str);
     warn!("exec args: {:?} ,env: {:?}", args, envs);
-    if path_str.ends_with(".sh") {
-        if args.is_empty() {
-            let mut new_path = path_str.clone();
-            new_path.push('\0');
-            args.insert(0, new_path);
+    let mut file = path_str.clone();
+    for _ in 0..=MAX_INTERP_DEPTH {
+        let (mode, uid, gid) = fs::check_exec_permission(&file)?;
+        let mut data = Vec::new();
+        if !fs::read_all(&file, &mut data) {
+            info!("exec {} failed", file);
+            return Err(AlienError::ENOENT);
         }
-        path_str = "./busybox".to_string();
-        args.insert(0, "sh\0".to_string());
-    }
-    let mut data = Vec::new();
-    if path_str.contains("libc-bench") {
-        path_str = "libc-bench2".to_string();
-    }
-    if fs::read_all(&path_str, &mut data) {
-        let res = task.exec(&path_str, data.as_slice(), args, envs);
-        if res.is_err() {
-            return Err(AlienError::ENOEXEC);
+        match load_binary(&file, &data, &mut args)? {
+            Binary::Elf => {
+                // 与 Linux 相同，脚本的 setuid/setgid 位被忽略，只有解释器的生效
+                let mut cred = task.access_inner().cred.clone();
+                cred.apply_exec(mode, uid, gid);
+                if let Err(e) = task.exec(&path_str, data.as_slice(), args, envs, cred) {
+                    if e == LinuxErrno::ENOMEM {
+                        // 原来的地址空间已经被替换，进程无法再返回用户态，与 Linux 相同以 SIGSEGV 终止
+                        do_exit_by_signal(SignalNumber::SIGSEGV as usize, false);
+                    }
+                    return Err(e);
+                }
+                complete_vfork(task);
+                ptrace_exec(task);
+                return Ok(0);
+            }
+            Binary::Interpreter(interpreter) => {
+                warn!("exec {} with interpreter {}", file, interpreter);
+                file = interpreter;
+            }
         }
-        Ok(0)
-    } else {
-        info!("exec {} failed", path_str);
-        Err(AlienError::ENOENT)
     }
+    Err(AlienError::ELOOP)
 }
 
 /// 一个系统调用，用于父进程等待某子进程退出。
@@ -336,9 +543,22 @@ pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isiz
 /// 一般`wait4`会使得父进程阻塞，直到子进程退出，返回退出的子进程pid。但当`wait_options`包含`WNOHANG`时，即使未发现子程序返回，函数也将直接返回0。
 /// 当父进程的所有子进程中不包含进程号为pid的子进程，将返回-1。
 ///
+/// 跟踪者同样可以等待其跟踪的线程，此时`pid`为线程号。被跟踪的线程进入 ptrace 停止状态时返回其线程号，
+/// `exit_code`中为其停止的状态。
+///
+/// `pid`与返回值均为当前进程所在的 pid 命名空间中的 pid 号。
+///
 /// Reference:[wait](https://man7.org/linux/man-pages/man2/wait.2.html)
 #[syscall_func(260)]
 pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8) -> isize {
+    let pid = if pid > 0 {
+        match pid_from_user(pid as usize) {
+            Some(tid) => tid as isize,
+            None => return -1,
+        }
+    } else {
+        pid
+    };
     loop {
         let task = current_task().unwrap();
         if task
@@ -346,12 +566,23 @@ pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8)
             .iter()
             .find(|child| child.get_pid() == pid || pid == -1)
             .is_none()
+            && !is_tracing(task, pid)
         {
             return -1;
         }
+        // 先报告被跟踪的线程的停止状态
+        if let Some((tid, status)) = ptrace_wait(task, pid) {
+            if !exit_code.is_null() {
+                if let Err(e) = task.write_user(UserPtr::from(exit_code), &status) {
+                    return e.into();
+                }
+            }
+            return pid_to_user(tid as usize);
+        }
         let res = task.check_child(pid);
         if let Some(index) = res {
             let child = task.remove_child(index);
+            child.access_inner().cred.dec_tasks();
             assert_eq!(
                 Arc::strong_count(&child),
                 1,
@@ -362,12 +593,13 @@ pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8)
                 child.get_tid()
             );
             if !exit_code.is_null() {
-                let exit_code_ref = task.transfer_raw_ptr(exit_code);
-                *exit_code_ref = child.exit_code();
+                if let Err(e) = task.write_user(UserPtr::from(exit_code), &child.exit_code()) {
+                    return e.into();
+                }
             }
-            return child.get_tid();
+            return pid_to_user(child.get_tid() as usize);
         } else {
-            let wait_options = WaitOptions::from_bits(options).unwrap();
+            let wait_options = WaitOptions::from_bits_truncate(options);
             if wait_options.contains(WaitOptions::WNOHANG) {
                 return 0;
             } else {
@@ -377,12 +609,12 @@ pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8)
     }
 }
 
-/// 一个系统调用，用于改变堆区的大小(目前仅可以增加堆区大小)
+/// 一个系统调用，用于改变堆区的大小
 ///
-/// `addr`用于指明扩充堆区后，堆区的末尾位置。
-/// 当`addr`所标识的位置在当前堆起始位置的前方，或者堆当前已使用的末尾位置的前方时，将会导致增加堆区大小失败。
+/// `addr`用于指明调整堆区后，堆区的末尾位置。`addr`在堆当前已使用的末尾位置的前方时将缩减堆区，释放不再使用的整页。
+/// 当`addr`所标识的位置在堆起始位置的前方时，将会导致调整堆区大小失败。
 ///
-/// 成功增加堆区大小时，函数返回堆当前已使用的末尾位置；否则返回-1。
+/// 成功调整堆区大小时，函数返回堆当前已使用的末尾位置；否则返回-1。
 #[syscall_func(214)]
 pub fn do_brk(addr: usize) -> isize {
     let process = current_task().unwrap();
@@ -391,11 +623,14 @@ pub fn do_brk(addr: usize) -> isize {
     if addr == 0 {
         return heap_info.current as isize;
     }
-    if addr < heap_info.start || addr < heap_info.current {
-        // panic!("heap can't be shrinked");
+    if addr < heap_info.start {
         return -1;
     }
-    let res = inner.extend_heap(addr);
+    let res = if addr < heap_info.current {
+        inner.shrink_heap(addr)
+    } else {
+        inner.extend_heap(addr)
+    };
     if res.is_err() {
         return -1;
     }
@@ -407,90 +642,128 @@ pub fn do_brk(addr: usize) -> isize {
 pub fn set_tid_address(tidptr: usize) -> isize {
     let task = current_task().unwrap();
     task.set_tid_address(tidptr);
-    task.get_tid()
+    pid_to_user(task.get_tid() as usize)
 }
 
-/// 一个系统调用，用于修改进程的资源限制。
+/// 一个系统调用，用于获取或修改进程的资源限制。
 ///
-/// 进程对其拥有的资源，包括用户栈大小、可以打开的文件描述符数、用户地址空间大小等都有所上限。
+/// 进程对其拥有的资源，包括 CPU 时间、文件大小、用户栈大小、可以打开的文件描述符数、用户地址空间大小等都有所上限。
 ///
 /// `prlimit64`则可以根据资源的种类对不同的资源进行大小的限制。针对每一具体限制都包括软上限和硬上限，具体可见[`PrLimit`]。
-/// `pid`用于指明需要修改资源限制的进程的pid号。
-/// `resource`用于指明需要修改的资源类型，可选的值包括`RLIMIT_STACK`、`RLIMIT_NOFILE`、`RLIMIT_AS`等，详情可见[`PrLimitRes`]。
+/// `pid`用于指明需要修改资源限制的进程的pid号，目前只支持当前进程。
+/// `resource`用于指明需要修改的资源类型，可选的值包括`RLIMIT_CPU`、`RLIMIT_STACK`、`RLIMIT_NOFILE`、`RLIMIT_AS`等，详情可见[`RLimitRes`]。
 /// `new_limit`用于指明新限制的指针，如果为空指针则不进行新限制的赋值。
 /// `old_limit`用于指明存放旧限制的指针，如果为空则不进行旧限制的保存。
 ///
-/// 正确执行后会返回0；如果输入的pid为0或者为当前正在运行的进程号，则会直接终止。
+/// 资源限制在 fork 时会被子进程继承，在 exec 后保持不变。
+///
+/// 正确执行后会返回0；如果`pid`不为0且不是当前进程，则返回`ESRCH`；
+/// 如果`resource`无效，或者新限制的软上限大于硬上限，则返回`EINVAL`。
+///
+/// Reference: [prlimit64](https://man7.org/linux/man-pages/man2/prlimit.2.html)
 #[syscall_func(261)]
-pub fn prlimit64(pid: usize, resource: usize, new_limit: *const u8, old_limit: *mut u8) -> isize {
-    assert!(pid == 0 || pid == current_task().unwrap().get_pid() as usize);
+pub fn prlimit64(
+    pid: usize,
+    resource: usize,
+    new_limit: *const u8,
+    old_limit: *mut u8,
+) -> AlienResult<isize> {
     let task = current_task().unwrap();
+    if pid != 0 && pid_from_user(pid) != Some(task.pid) {
+        return Err(LinuxErrno::ESRCH);
+    }
+    let resource = RLimitRes::try_from(resource)?;
     let mut inner = task.access_inner();
-    if let Ok(resource) = PrLimitRes::try_from(resource) {
-        if !old_limit.is_null() {
-            let limit = inner.get_prlimit(resource);
-            warn!("get rlimit nofile to {:?}", limit);
-            inner.copy_to_user(&limit, old_limit as *mut PrLimit);
-        }
-        match resource {
-            PrLimitRes::RlimitStack => {}
-            PrLimitRes::RlimitNofile => {
-                if !new_limit.is_null() {
-                    let mut limit = PrLimit::new(0, 0);
-                    inner.copy_from_user(new_limit as *const PrLimit, &mut limit);
-                    warn!("set rlimit nofile to {:?}", limit);
-                    inner.set_prlimit(resource, limit);
-                }
-            }
-            PrLimitRes::RlimitAs => {}
-        }
+    if !old_limit.is_null() {
+        let limit = inner.get_prlimit(resource);
+        inner.write_user(UserPtr::<PrLimit>::new(old_limit as usize), &limit)?;
     }
-    0
+    if !new_limit.is_null() {
+        let limit = inner.read_user(UserPtr::<PrLimit>::new(new_limit as usize))?;
+        warn!("set rlimit {:?} to {:?}", resource, limit);
+        inner.set_prlimit(resource, limit)?;
+    }
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取当前进程的资源限制，等价于`prlimit64(0, resource, NULL, rlim)`。
+///
+/// Reference: [getrlimit](https://man7.org/linux/man-pages/man2/getrlimit.2.html)
+#[syscall_func(163)]
+pub fn getrlimit(resource: usize, rlim: *mut u8) -> AlienResult<isize> {
+    prlimit64(0, resource, core::ptr::null(), rlim)
+}
+
+/// 一个系统调用，用于设置当前进程的资源限制，等价于`prlimit64(0, resource, rlim, NULL)`。
+///
+/// Reference: [setrlimit](https://man7.org/linux/man-pages/man2/getrlimit.2.html)
+#[syscall_func(164)]
+pub fn setrlimit(resource: usize, rlim: *const u8) -> AlienResult<isize> {
+    prlimit64(0, resource, rlim, core::ptr::null_mut())
+}
+
+/// 一个系统调用，用于设置当前进程的执行域，返回原来的执行域。`persona` 为 `0xffffffff` 时只查询而不修改。
+///
+/// 目前只有 [`ADDR_NO_RANDOMIZE`] 标志有实际作用，设置该标志后之后 execve 的程序不进行地址空间布局随机化。
+/// 执行域在 fork 时会被子进程继承，在 exec 后保持不变。
+///
+/// Reference: [personality](https://man7.org/linux/man-pages/man2/personality.2.html)
+///
+/// [`ADDR_NO_RANDOMIZE`]: crate::mm::aslr::ADDR_NO_RANDOMIZE
+#[syscall_func(92)]
+pub fn personality(persona: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let old = inner.personality;
+    if persona != u32::MAX {
+        inner.personality = persona;
+    }
+    old as isize
 }
 
 /// 用于exec可执行文件时，分别在args_ptr和env_ptr所指向的地址处取出参数和环境变量
-fn parse_user_arg_env(args_ptr: usize, env_ptr: usize) -> (Vec<String>, Vec<String>) {
+///
+/// 参数与环境变量的总大小不能超过 `RLIMIT_STACK` 的 1/4，以保证新程序的用户栈在放入它们后仍有空间可用。
+fn parse_user_arg_env(args_ptr: usize, env_ptr: usize) -> AlienResult<(Vec<String>, Vec<String>)> {
     let task = current_task().unwrap();
-    let mut args = Vec::new();
-
-    if args_ptr != 0 {
-        let mut start = args_ptr as *mut usize;
-        loop {
-            let arg = task.transfer_raw_ptr(start);
-            if *arg == 0 {
-                break;
-            }
-            args.push(*arg);
-            start = unsafe { start.add(1) };
-        }
+    let stack_limit = task.access_inner().resource_limits.cur(RLimitRes::Stack);
+    let mut remain = min(stack_limit, USER_STACK_MAX_SIZE as u64) as usize / 4;
+    let args = copy_strings(task, args_ptr, &mut remain)?;
+    let envs = copy_strings(task, env_ptr, &mut remain)?;
+    Ok((args, envs))
+}
+
+/// 取出用户地址空间中 `ptr` 处以空指针结尾的指针数组所指向的所有字符串，每个字符串都以 '\0' 结尾
+///
+/// 字符串与指针占用的空间从 `remain` 中扣除，空间不足或单个字符串过长时返回 `E2BIG`。
+/// 指针数组需要按照指针的大小对齐，否则返回 `EFAULT`。
+fn copy_strings(task: &Arc<Task>, ptr: usize, remain: &mut usize) -> AlienResult<Vec<String>> {
+    let mut strings = Vec::new();
+    if ptr == 0 {
+        return Ok(strings);
     }
-    let args = args
-        .into_iter()
-        .map(|arg| {
-            let mut arg = task.transfer_str(arg as *const u8);
-            arg.push('\0');
-            arg
-        })
-        .collect::<Vec<String>>();
-    let mut envs = Vec::new();
-    if env_ptr != 0 {
-        let mut start = env_ptr as *mut usize;
-        loop {
-            let env = task.transfer_raw_ptr(start);
-            if *env == 0 {
-                break;
-            }
-            envs.push(*env);
-            start = unsafe { start.add(1) };
+    if ptr % size_of::<usize>() != 0 {
+        return Err(LinuxErrno::EFAULT);
+    }
+    let mut addr = ptr;
+    loop {
+        *remain = remain
+            .checked_sub(size_of::<usize>())
+            .ok_or(LinuxErrno::E2BIG)?;
+        let str_ptr = task.read_user(UserPtr::<usize>::new(addr))?;
+        if str_ptr == 0 {
+            break;
         }
+        let mut string = task
+            .strncpy_from_user(str_ptr as *const u8, MAX_ARG_STRLEN)
+            .map_err(|e| match e {
+                LinuxErrno::ENAMETOOLONG => LinuxErrno::E2BIG,
+                e => e,
+            })?;
+        string.push('\0');
+        *remain = remain.checked_sub(string.len()).ok_or(LinuxErrno::E2BIG)?;
+        strings.push(string);
+        addr += size_of::<usize>();
     }
-    let envs = envs
-        .into_iter()
-        .map(|env| {
-            let mut env = task.transfer_str(env as *const u8);
-            env.push('\0');
-            env
-        })
-        .collect::<Vec<String>>();
-    (args, envs)
+    Ok(strings)
 }
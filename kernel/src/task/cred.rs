@@ -0,0 +1,490 @@
+//! 进程的用户凭证(uid/gid)与相关的系统调用。
+//!
+//! 与 Linux 类似，每个任务都拥有真实、有效、保存的用户 id 与组 id，以及一组附加组。
+//! 文件访问时使用的是 fsuid/fsgid，它们跟随有效 id 变化。
+//! 这里实现的能力(capabilities)是一个简化版本：root 拥有全部能力，
+//! 当进程从 root 切换为普通用户时按照 Linux 的规则清除能力。
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use bitflags::bitflags;
+use constants::{AlienResult, LinuxErrno};
+use ksync::Mutex;
+use syscall_table::syscall_func;
+
+use crate::{
+    mm::uaccess::{UserPtr, UserSlice},
+    task::current_task,
+};
+
+/// 附加组数量的上限
+const NGROUPS_MAX: usize = 65536;
+
+/// 文件的 set-user-ID 位
+pub const S_ISUID: u32 = 0o4000;
+/// 文件的 set-group-ID 位
+pub const S_ISGID: u32 = 0o2000;
+/// 组执行权限位
+pub const S_IXGRP: u32 = 0o0010;
+
+bitflags! {
+    /// 进程拥有的能力，位的定义与 Linux 保持一致
+    pub struct Capabilities: u64 {
+        const CHOWN = 1 << 0;
+        const DAC_OVERRIDE = 1 << 1;
+        const DAC_READ_SEARCH = 1 << 2;
+        const FOWNER = 1 << 3;
+        const FSETID = 1 << 4;
+        const KILL = 1 << 5;
+        const SETGID = 1 << 6;
+        const SETUID = 1 << 7;
+        const SETPCAP = 1 << 8;
+        const NET_BIND_SERVICE = 1 << 10;
+        const NET_ADMIN = 1 << 12;
+        const NET_RAW = 1 << 13;
+        const IPC_LOCK = 1 << 14;
+        const SYS_CHROOT = 1 << 18;
+        const SYS_PTRACE = 1 << 19;
+        const SYS_ADMIN = 1 << 21;
+        const SYS_NICE = 1 << 23;
+        const SYS_RESOURCE = 1 << 24;
+    }
+}
+
+/// 每个真实用户的任务计数器，计数包括尚未被回收的任务，用于 RLIMIT_NPROC 的检查
+static USER_TASKS: Mutex<BTreeMap<u32, Arc<AtomicUsize>>> = Mutex::new(BTreeMap::new());
+
+/// 获取真实用户 `uid` 的任务计数器
+fn user_tasks(uid: u32) -> Arc<AtomicUsize> {
+    USER_TASKS
+        .lock()
+        .entry(uid)
+        .or_insert_with(|| Arc::new(AtomicUsize::new(0)))
+        .clone()
+}
+
+/// 任务的用户凭证
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// 真实用户 id
+    pub ruid: u32,
+    /// 有效用户 id
+    pub euid: u32,
+    /// 保存的用户 id
+    pub suid: u32,
+    /// 访问文件系统时使用的用户 id
+    pub fsuid: u32,
+    /// 真实组 id
+    pub rgid: u32,
+    /// 有效组 id
+    pub egid: u32,
+    /// 保存的组 id
+    pub sgid: u32,
+    /// 访问文件系统时使用的组 id
+    pub fsgid: u32,
+    /// 附加组
+    pub groups: Vec<u32>,
+    /// 当前生效的能力
+    pub cap_effective: Capabilities,
+    /// 允许拥有的能力
+    pub cap_permitted: Capabilities,
+    /// 真实用户 `ruid` 的任务计数器，真实用户相同的凭证共享同一个计数器
+    tasks: Arc<AtomicUsize>,
+}
+
+impl Credentials {
+    /// 创建 root 用户的凭证，拥有全部能力
+    pub fn root() -> Self {
+        Self {
+            ruid: 0,
+            euid: 0,
+            suid: 0,
+            fsuid: 0,
+            rgid: 0,
+            egid: 0,
+            sgid: 0,
+            fsgid: 0,
+            groups: Vec::new(),
+            cap_effective: Capabilities::all(),
+            cap_permitted: Capabilities::all(),
+            tasks: user_tasks(0),
+        }
+    }
+
+    /// 真实用户拥有的任务数量
+    pub fn task_count(&self) -> usize {
+        self.tasks.load(Ordering::Relaxed)
+    }
+
+    /// 创建任务时增加真实用户的任务数量
+    pub fn inc_tasks(&self) {
+        self.tasks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 回收任务时减少真实用户的任务数量
+    pub fn dec_tasks(&self) {
+        self.tasks.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// 是否拥有能力 `cap`
+    pub fn has_cap(&self, cap: Capabilities) -> bool {
+        self.cap_effective.contains(cap)
+    }
+
+    /// 是否属于组 `gid`(包括 fsgid 与附加组)
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.fsgid == gid || self.groups.contains(&gid)
+    }
+
+    /// 用于 access/faccessat 的凭证：使用真实 id 代替有效 id 进行检查
+    pub fn for_access(&self) -> Self {
+        let mut cred = self.clone();
+        cred.fsuid = self.ruid;
+        cred.fsgid = self.rgid;
+        if self.ruid != 0 {
+            cred.cap_effective = Capabilities::empty();
+        } else {
+            cred.cap_effective = self.cap_permitted;
+        }
+        cred
+    }
+
+    /// 根据文件的所有者 `uid`、所属组 `gid` 与权限位 `perm`(rwxrwxrwx)，
+    /// 检查是否拥有 `mask`(r=4, w=2, x=1) 所指明的访问权限。
+    pub fn permission(&self, uid: u32, gid: u32, perm: u32, mask: u32, is_dir: bool) -> bool {
+        let mask = mask & 0o7;
+        let granted = if self.fsuid == uid {
+            (perm >> 6) & 0o7
+        } else if self.in_group(gid) {
+            (perm >> 3) & 0o7
+        } else {
+            perm & 0o7
+        };
+        if granted & mask == mask {
+            return true;
+        }
+        if self.has_cap(Capabilities::DAC_OVERRIDE) {
+            // 普通文件只要有任意一个执行位，root 就可以执行
+            if mask & 0o1 == 0 || is_dir || perm & 0o111 != 0 {
+                return true;
+            }
+        }
+        if self.has_cap(Capabilities::DAC_READ_SEARCH) {
+            if mask == 0o4 || (is_dir && mask & 0o2 == 0) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 执行带有 setuid/setgid 位的文件时，更新有效 id 与保存的 id
+    pub fn apply_exec(&mut self, mode: u32, uid: u32, gid: u32) {
+        let old = (self.ruid, self.euid, self.suid);
+        if mode & S_ISUID != 0 {
+            self.euid = uid;
+        }
+        // 没有组执行权限时，S_ISGID 表示强制锁而不是 setgid
+        if mode & S_ISGID != 0 && mode & S_IXGRP != 0 {
+            self.egid = gid;
+        }
+        self.suid = self.euid;
+        self.sgid = self.egid;
+        self.fsuid = self.euid;
+        self.fsgid = self.egid;
+        self.fix_caps(old);
+        if self.euid == 0 {
+            self.cap_permitted = Capabilities::all();
+            self.cap_effective = Capabilities::all();
+        }
+    }
+
+    /// 用户 id 变化后，按照 Linux 的规则调整能力
+    fn fix_caps(&mut self, old: (u32, u32, u32)) {
+        let (old_ruid, old_euid, old_suid) = old;
+        if (old_ruid == 0 || old_euid == 0 || old_suid == 0)
+            && self.ruid != 0
+            && self.euid != 0
+            && self.suid != 0
+        {
+            self.cap_permitted = Capabilities::empty();
+            self.cap_effective = Capabilities::empty();
+        }
+        if old_euid == 0 && self.euid != 0 {
+            self.cap_effective = Capabilities::empty();
+        }
+        if old_euid != 0 && self.euid == 0 {
+            self.cap_effective = self.cap_permitted;
+        }
+    }
+
+    /// 设置真实、有效与保存的用户 id，`None` 表示保持不变。
+    ///
+    /// 真实用户 id 改变时，任务从原用户的计数中转移到新用户的计数中。
+    fn set_resuid(&mut self, ruid: Option<u32>, euid: Option<u32>, suid: Option<u32>) {
+        let old = (self.ruid, self.euid, self.suid);
+        if let Some(ruid) = ruid.filter(|ruid| *ruid != self.ruid) {
+            self.dec_tasks();
+            self.ruid = ruid;
+            self.tasks = user_tasks(ruid);
+            self.inc_tasks();
+        }
+        if let Some(euid) = euid {
+            self.euid = euid;
+        }
+        if let Some(suid) = suid {
+            self.suid = suid;
+        }
+        self.fsuid = self.euid;
+        self.fix_caps(old);
+    }
+
+    /// 设置真实、有效与保存的组 id，`None` 表示保持不变
+    fn set_resgid(&mut self, rgid: Option<u32>, egid: Option<u32>, sgid: Option<u32>) {
+        if let Some(rgid) = rgid {
+            self.rgid = rgid;
+        }
+        if let Some(egid) = egid {
+            self.egid = egid;
+        }
+        if let Some(sgid) = sgid {
+            self.sgid = sgid;
+        }
+        self.fsgid = self.egid;
+    }
+}
+
+/// 将系统调用中的 -1 转换为 `None`
+pub fn optional_id(id: usize) -> Option<u32> {
+    if id as u32 == u32::MAX {
+        None
+    } else {
+        Some(id as u32)
+    }
+}
+
+/// 获取用户 id。
+#[syscall_func(174)]
+pub fn getuid() -> isize {
+    current_task().unwrap().access_inner().cred.ruid as isize
+}
+
+/// 获取有效用户 id，即相当于哪个用户的权限。
+#[syscall_func(175)]
+pub fn geteuid() -> isize {
+    current_task().unwrap().access_inner().cred.euid as isize
+}
+
+/// 获取用户组 id。
+#[syscall_func(176)]
+pub fn getgid() -> isize {
+    current_task().unwrap().access_inner().cred.rgid as isize
+}
+
+/// 获取有效用户组 id，即相当于哪个用户组的权限。
+#[syscall_func(177)]
+pub fn getegid() -> isize {
+    current_task().unwrap().access_inner().cred.egid as isize
+}
+
+/// 一个系统调用，用于设置进程的用户 id。
+///
+/// 拥有 CAP_SETUID 能力时，同时设置真实、有效与保存的用户 id；
+/// 否则只有当 `uid` 等于真实或保存的用户 id 时，才能将有效用户 id 设置为 `uid`，否则返回 EPERM。
+///
+/// Reference: [setuid](https://man7.org/linux/man-pages/man2/setuid.2.html)
+#[syscall_func(146)]
+pub fn setuid(uid: usize) -> AlienResult<isize> {
+    let uid = optional_id(uid).ok_or(LinuxErrno::EINVAL)?;
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let cred = &mut inner.cred;
+    if cred.has_cap(Capabilities::SETUID) {
+        cred.set_resuid(Some(uid), Some(uid), Some(uid));
+    } else if uid == cred.ruid || uid == cred.suid {
+        cred.set_resuid(None, Some(uid), None);
+    } else {
+        return Err(LinuxErrno::EPERM);
+    }
+    Ok(0)
+}
+
+/// 一个系统调用，用于设置进程的组 id，规则与 [`setuid`] 相同，需要的能力为 CAP_SETGID。
+///
+/// Reference: [setgid](https://man7.org/linux/man-pages/man2/setgid.2.html)
+#[syscall_func(144)]
+pub fn setgid(gid: usize) -> AlienResult<isize> {
+    let gid = optional_id(gid).ok_or(LinuxErrno::EINVAL)?;
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let cred = &mut inner.cred;
+    if cred.has_cap(Capabilities::SETGID) {
+        cred.set_resgid(Some(gid), Some(gid), Some(gid));
+    } else if gid == cred.rgid || gid == cred.sgid {
+        cred.set_resgid(None, Some(gid), None);
+    } else {
+        return Err(LinuxErrno::EPERM);
+    }
+    Ok(0)
+}
+
+/// 一个系统调用，用于设置进程的真实与有效用户 id，传入 -1 表示保持不变。
+///
+/// 没有 CAP_SETUID 能力时，`ruid` 只能为当前的真实或有效用户 id，
+/// `euid` 只能为当前的真实、有效或保存的用户 id。
+/// 如果设置了真实用户 id，或者有效用户 id 被设置为与原真实用户 id 不同的值，保存的用户 id 将被设置为新的有效用户 id。
+///
+/// Reference: [setreuid](https://man7.org/linux/man-pages/man2/setreuid.2.html)
+#[syscall_func(145)]
+pub fn setreuid(ruid: usize, euid: usize) -> AlienResult<isize> {
+    let (ruid, euid) = (optional_id(ruid), optional_id(euid));
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let cred = &mut inner.cred;
+    if !cred.has_cap(Capabilities::SETUID) {
+        if ruid.is_some_and(|id| id != cred.ruid && id != cred.euid) {
+            return Err(LinuxErrno::EPERM);
+        }
+        if euid.is_some_and(|id| id != cred.ruid && id != cred.euid && id != cred.suid) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    let suid = if ruid.is_some() || euid.is_some_and(|id| id != cred.ruid) {
+        Some(euid.unwrap_or(cred.euid))
+    } else {
+        None
+    };
+    cred.set_resuid(ruid, euid, suid);
+    Ok(0)
+}
+
+/// 一个系统调用，用于设置进程的真实与有效组 id，规则与 [`setreuid`] 相同。
+///
+/// Reference: [setregid](https://man7.org/linux/man-pages/man2/setregid.2.html)
+#[syscall_func(143)]
+pub fn setregid(rgid: usize, egid: usize) -> AlienResult<isize> {
+    let (rgid, egid) = (optional_id(rgid), optional_id(egid));
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let cred = &mut inner.cred;
+    if !cred.has_cap(Capabilities::SETGID) {
+        if rgid.is_some_and(|id| id != cred.rgid && id != cred.egid) {
+            return Err(LinuxErrno::EPERM);
+        }
+        if egid.is_some_and(|id| id != cred.rgid && id != cred.egid && id != cred.sgid) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    let sgid = if rgid.is_some() || egid.is_some_and(|id| id != cred.rgid) {
+        Some(egid.unwrap_or(cred.egid))
+    } else {
+        None
+    };
+    cred.set_resgid(rgid, egid, sgid);
+    Ok(0)
+}
+
+/// 一个系统调用，用于设置进程的真实、有效与保存的用户 id，传入 -1 表示保持不变。
+///
+/// 没有 CAP_SETUID 能力时，每个新的 id 都必须是当前真实、有效或保存的用户 id 之一，否则返回 EPERM。
+///
+/// Reference: [setresuid](https://man7.org/linux/man-pages/man2/setresuid.2.html)
+#[syscall_func(147)]
+pub fn setresuid(ruid: usize, euid: usize, suid: usize) -> AlienResult<isize> {
+    let ids = [optional_id(ruid), optional_id(euid), optional_id(suid)];
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let cred = &mut inner.cred;
+    if !cred.has_cap(Capabilities::SETUID) {
+        let allowed = [cred.ruid, cred.euid, cred.suid];
+        if ids.iter().flatten().any(|id| !allowed.contains(id)) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    cred.set_resuid(ids[0], ids[1], ids[2]);
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取进程的真实、有效与保存的用户 id。
+///
+/// Reference: [getresuid](https://man7.org/linux/man-pages/man2/getresuid.2.html)
+#[syscall_func(148)]
+pub fn getresuid(ruid: *mut u32, euid: *mut u32, suid: *mut u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let (r, e, s) = (inner.cred.ruid, inner.cred.euid, inner.cred.suid);
+    inner.write_user(UserPtr::from(ruid), &r)?;
+    inner.write_user(UserPtr::from(euid), &e)?;
+    inner.write_user(UserPtr::from(suid), &s)?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于设置进程的真实、有效与保存的组 id，规则与 [`setresuid`] 相同。
+///
+/// Reference: [setresgid](https://man7.org/linux/man-pages/man2/setresgid.2.html)
+#[syscall_func(149)]
+pub fn setresgid(rgid: usize, egid: usize, sgid: usize) -> AlienResult<isize> {
+    let ids = [optional_id(rgid), optional_id(egid), optional_id(sgid)];
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let cred = &mut inner.cred;
+    if !cred.has_cap(Capabilities::SETGID) {
+        let allowed = [cred.rgid, cred.egid, cred.sgid];
+        if ids.iter().flatten().any(|id| !allowed.contains(id)) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    cred.set_resgid(ids[0], ids[1], ids[2]);
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取进程的真实、有效与保存的组 id。
+///
+/// Reference: [getresgid](https://man7.org/linux/man-pages/man2/getresuid.2.html)
+#[syscall_func(150)]
+pub fn getresgid(rgid: *mut u32, egid: *mut u32, sgid: *mut u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let (r, e, s) = (inner.cred.rgid, inner.cred.egid, inner.cred.sgid);
+    inner.write_user(UserPtr::from(rgid), &r)?;
+    inner.write_user(UserPtr::from(egid), &e)?;
+    inner.write_user(UserPtr::from(sgid), &s)?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取进程的附加组。
+///
+/// `size` 为 0 时只返回附加组的数量；`size` 小于附加组的数量时返回 EINVAL。
+///
+/// Reference: [getgroups](https://man7.org/linux/man-pages/man2/getgroups.2.html)
+#[syscall_func(158)]
+pub fn getgroups(size: usize, list: *mut u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let groups = inner.cred.groups.clone();
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if size < groups.len() {
+        return Err(LinuxErrno::EINVAL);
+    }
+    inner.write_user_slice(UserSlice::new(list as usize, groups.len()), &groups)?;
+    Ok(groups.len() as isize)
+}
+
+/// 一个系统调用，用于设置进程的附加组，需要 CAP_SETGID 能力。
+///
+/// Reference: [setgroups](https://man7.org/linux/man-pages/man2/setgroups.2.html)
+#[syscall_func(159)]
+pub fn setgroups(size: usize, list: *const u32) -> AlienResult<isize> {
+    if size > NGROUPS_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    if !inner.cred.has_cap(Capabilities::SETGID) {
+        return Err(LinuxErrno::EPERM);
+    }
+    let groups = inner.read_user_slice(UserSlice::new(list as usize, size))?;
+    inner.cred.groups = groups;
+    Ok(0)
+}
@@ -15,10 +15,14 @@ use vfs::kfile::File;
 
 use crate::{
     fs::stdio::{STDIN, STDOUT},
-    mm::map::MMapInfo,
+    ipc::SignalStack,
+    mm::{map::MMapInfo, mlock::MlockallFlags},
     task::{
         context::Context,
-        resource::{HeapInfo, TidHandle},
+        cred::Credentials,
+        namespace::{PidHandle, INIT_NAMESPACES, ROOT_PID_NAMESPACE},
+        ptrace::PtraceState,
+        resource::{HeapInfo, ResourceLimits, TidHandle},
         stack::Stack,
         task::{TaskInner, TaskTimer},
         FsContext, StatisticalData, Task, TaskState, GLOBAL_TASK_MANAGER,
@@ -30,6 +34,7 @@ type FdManager = MinimalManager<Arc<dyn File>>;
 pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
     let tid = TidHandle::new().ok_or(AlienError::ENOSPC)?;
     let pid = tid.0;
+    let ns_pid = PidHandle::new(&ROOT_PID_NAMESPACE, tid.0)?;
     let k_stack = Stack::new(USER_KERNEL_STACK_SIZE / FRAME_SIZE).ok_or(AlienError::ENOMEM)?;
     let kspace = kernel_space();
     let cwd = vfs::system_root_fs();
@@ -39,6 +44,7 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
         tid,
         kernel_stack: k_stack,
         pid,
+        ns_pid,
         inner: Mutex::new(TaskInner {
             name: name.to_string(),
             threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -60,7 +66,8 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             timer: TaskTimer::default(),
             exit_code: 0,
             heap: Arc::new(Mutex::new(HeapInfo::new(0, 0))),
-            mmap: MMapInfo::new(),
+            // 内核线程没有用户地址空间
+            mmap: MMapInfo::new(0, 0),
             signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
             signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
             set_child_tid: 0,
@@ -77,7 +84,16 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             unmask: 0o022,
             // user mode stack info
             stack: 0..0,
+            resource_limits: ResourceLimits::new(),
+            cred: Credentials::root(),
             need_wait: 0,
+            mlockall: MlockallFlags::empty(),
+            sig_alt_stack: SignalStack::default(),
+            personality: 0,
+            auxv: Vec::new(),
+            ptrace: PtraceState::default(),
+            vfork_done: None,
+            ns: INIT_NAMESPACES.clone(),
         }),
         send_sigchld_when_exit: false,
     };
@@ -1,14 +1,30 @@
 //! Alien 中有关进程管理的相关数据结构
 //!
+//! [`binfmt`] 子模块定义了 Alien 支持的可执行文件格式，包括 ELF、脚本与 binfmt_misc。
 //! [`context`] 子模块定义了 Alien 中线程上下文的相关结构.
+//! [`coredump`] 子模块负责在进程因信号终止时生成 core 文件。
 //! [`cpu`] 子模块中指明了 Alien 中有关进程的系统调用 和 多核的相关支持。
+//! [`cred`] 子模块定义了 Alien 中进程的用户凭证及相关的系统调用。
 //! [`heap`] 子模块定义了 Alien 记录进程堆空间的相关信息的结构。
+//! [`namespace`] 子模块实现了 UTS 与 pid 命名空间。
+//! [`pidfd`] 子模块定义了指向进程的文件描述符 pidfd 及相关的系统调用。
+//! [`ptrace`] 子模块实现了用户态调试器使用的进程跟踪。
 //! [`schedule`] 子模块指明了 Alien 中有关 CPU 调度的相关机制
 //! [`stack`] 子模块定义了 Alien 中有关内核栈的相关结构。
 //! [`task`] 子模块定义了 Alien 中有关进程控制块的定义。
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{sync::Arc, vec, vec::Vec};
 
+pub use coredump::do_coredump;
 pub use cpu::*;
+pub use cred::{optional_id, Capabilities, Credentials, S_ISGID, S_ISUID, S_IXGRP};
+pub use namespace::{
+    find_process_from_user, pid_for_receiver, pid_from_user, pid_to_user, HOST_NAME_MAX,
+};
+pub use pidfd::pidfd_task;
+pub use ptrace::{
+    may_trace, ptrace_kill_wakeup, ptrace_signal_stop, ptrace_step_done, ptrace_syscall_stop,
+};
+pub use resource::{RLimitRes, RLIM_INFINITY};
 use shim::{KTask, KTaskShim};
 use smpscheduler::FifoTask;
 use spin::Lazy;
@@ -18,9 +34,15 @@ use timer::get_time_ms;
 pub use crate::task::task::FsContext;
 use crate::{fs::read_all, task::schedule::schedule_now};
 
+mod binfmt;
 mod context;
+mod coredump;
 mod cpu;
+mod cred;
 mod kthread;
+mod namespace;
+mod pidfd;
+mod ptrace;
 mod resource;
 pub mod schedule;
 mod stack;
@@ -35,8 +57,60 @@ pub static INIT_PROCESS: Lazy<Arc<Task>> = Lazy::new(|| {
     Arc::new(task)
 });
 
+/// 从初始进程开始沿着进程树查找进程号为 `pid` 的进程
+pub fn find_process(pid: usize) -> Option<Arc<Task>> {
+    let mut stack = vec![INIT_PROCESS.clone()];
+    while let Some(task) = stack.pop() {
+        if task.pid == pid && task.get_tid() as usize == pid {
+            return Some(task);
+        }
+        stack.extend(task.children());
+    }
+    None
+}
+
+/// 从初始进程开始沿着进程树查找线程号为 `tid` 的线程
+pub fn find_task(tid: usize) -> Option<Arc<Task>> {
+    let mut stack = vec![INIT_PROCESS.clone()];
+    while let Some(task) = stack.pop() {
+        if task.get_tid() as usize == tid {
+            return Some(task);
+        }
+        stack.extend(task.children());
+    }
+    None
+}
+
+/// 从初始进程开始沿着进程树查找进程 `pid` 中所有未退出的线程
+pub fn process_threads(pid: usize) -> Vec<Arc<Task>> {
+    let mut threads = Vec::new();
+    let mut stack = vec![INIT_PROCESS.clone()];
+    while let Some(task) = stack.pop() {
+        if task.pid == pid && !matches!(task.state(), TaskState::Zombie | TaskState::Terminated) {
+            threads.push(task.clone());
+        }
+        stack.extend(task.children());
+    }
+    threads
+}
+
+/// 从初始进程开始沿着进程树获取所有未退出的任务
+pub fn all_tasks() -> Vec<Arc<Task>> {
+    let mut tasks = Vec::new();
+    let mut stack = vec![INIT_PROCESS.clone()];
+    while let Some(task) = stack.pop() {
+        if !matches!(task.state(), TaskState::Zombie | TaskState::Terminated) {
+            tasks.push(task.clone());
+        }
+        stack.extend(task.children());
+    }
+    tasks
+}
+
 /// 将初始进程加入进程池中进行调度
 pub fn init_task() {
+    binfmt::init_binfmt_misc();
+    coredump::init_coredump();
     kthread::ktread_create(kthread_init, "kthread_test").unwrap();
     let task = INIT_PROCESS.clone();
     GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
@@ -88,13 +162,15 @@ impl KTaskShim for DriverTaskImpl {
     fn schedule_now(&self, task: Arc<dyn KTask>) {
         schedule_now(task.downcast_arc::<Task>().map_err(|_| ()).unwrap());
     }
-    fn transfer_ptr_raw(&self, ptr: usize) -> usize {
+    fn copy_to_user(&self, dst: usize, src: &[u8]) -> bool {
         let task = current_task().unwrap();
-        task.transfer_raw(ptr)
+        let res = task.access_inner().copy_to_user_bytes(dst, src);
+        res.is_ok()
     }
-    fn transfer_buf_raw(&self, src: usize, size: usize) -> Vec<&mut [u8]> {
+    fn copy_from_user(&self, src: usize, dst: &mut [u8]) -> bool {
         let task = current_task().unwrap();
-        task.transfer_buffer(src as *const u8, size)
+        let res = task.access_inner().copy_from_user_bytes(src, dst);
+        res.is_ok()
     }
 }
 
@@ -0,0 +1,529 @@
+//! Alien 中的命名空间，用于在同一个内核上并列运行相互隔离的环境。
+//!
+//! 目前支持三种命名空间：
+//! + UTS 命名空间：每个命名空间拥有自己的主机名与域名，由 `uname` 返回，可以通过 `sethostname`/`setdomainname` 修改。
+//! + pid 命名空间：任务仍然以全局唯一的 tid 作为标识，[`PidNamespace`] 记录命名空间中的 pid 与全局 tid 的对应关系，
+//!   系统调用在用户使用的 pid 与全局 tid 之间进行转换。命名空间中的第一个进程的 pid 为 1，它收养命名空间中的孤儿进程，
+//!   退出时命名空间中的其它任务都被终止。`/proc` 由所有命名空间共享，`/proc/<pid>` 中的 pid 与文件中的 pid
+//!   都按读取者所在的命名空间解释。
+//! + mount 命名空间：每个命名空间拥有自己的根目录(可以通过 pivot_root 修改)与挂载表 [`MountNamespace`]，
+//!   [`user_path_at`](crate::fs::user_path_at) 逐级解析路径时查询当前任务所在命名空间的挂载表。
+//!   新的命名空间复制创建者的挂载表，此后两者的挂载与卸载互不影响；系统启动时挂载的文件系统记录在目录项上，由所有命名空间共享。
+//!
+//! 命名空间可以通过 clone 的 `CLONE_NEWUTS`/`CLONE_NEWPID`/`CLONE_NEWNS` 标志或者 [`unshare`] 创建，
+//! 通过 [`setns`] 加入 pidfd 所指向的进程的命名空间。
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    fmt::{Debug, Formatter},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use constants::{signal::SignalNumber, task::CloneFlags, AlienResult, LinuxErrno};
+use ksync::Mutex;
+use spin::Lazy;
+use syscall_table::syscall_func;
+use vfs::system_root_fs;
+use vfscore::{dentry::VfsDentry, path::VfsPath};
+
+use crate::{
+    ipc::{process_exists, send_signal},
+    task::{
+        current_task, find_process, find_task, pidfd::PidFd, Capabilities, Task, TaskState,
+        INIT_PROCESS,
+    },
+};
+
+/// pid 命名空间嵌套的最大层数
+const MAX_PID_NS_LEVEL: usize = 32;
+/// 主机名与域名的最大长度
+pub const HOST_NAME_MAX: usize = 64;
+
+/// 初始的 pid 命名空间，其中的 pid 就是全局的 tid
+pub static ROOT_PID_NAMESPACE: Lazy<Arc<PidNamespace>> = Lazy::new(|| {
+    Arc::new(PidNamespace {
+        parent: None,
+        level: 0,
+        pids: Mutex::new(PidMap::default()),
+        dead: AtomicBool::new(false),
+    })
+});
+
+/// 初始进程与内核线程所在的命名空间
+pub static INIT_NAMESPACES: Lazy<Namespaces> = Lazy::new(|| Namespaces {
+    uts: Arc::new(UtsNamespace::new(b"Alien", b"RustOS")),
+    mnt: Arc::new(MountNamespace::new(system_root_fs(), Vec::new())),
+    pid_for_children: ROOT_PID_NAMESPACE.clone(),
+});
+
+/// 任务所在的命名空间
+#[derive(Debug, Clone)]
+pub struct Namespaces {
+    /// UTS 命名空间
+    pub uts: Arc<UtsNamespace>,
+    /// mount 命名空间
+    pub mnt: Arc<MountNamespace>,
+    /// 子进程所在的 pid 命名空间，任务自己所在的 pid 命名空间见 [`Task::pid_namespace`]
+    pub pid_for_children: Arc<PidNamespace>,
+}
+
+impl Namespaces {
+    /// 根据 clone 或 unshare 的标志 `flags` 创建新的命名空间，其余的命名空间与 `self` 共享。
+    pub fn copy(&self, flags: CloneFlags) -> AlienResult<Self> {
+        Ok(Self {
+            uts: if flags.contains(CloneFlags::CLONE_NEWUTS) {
+                Arc::new(UtsNamespace::new(
+                    &self.uts.hostname.lock(),
+                    &self.uts.domainname.lock(),
+                ))
+            } else {
+                self.uts.clone()
+            },
+            mnt: if flags.contains(CloneFlags::CLONE_NEWNS) {
+                Arc::new(MountNamespace::new(
+                    self.mnt.root(),
+                    self.mnt.mounts.lock().clone(),
+                ))
+            } else {
+                self.mnt.clone()
+            },
+            pid_for_children: if flags.contains(CloneFlags::CLONE_NEWPID) {
+                self.pid_for_children.new_child()?
+            } else {
+                self.pid_for_children.clone()
+            },
+        })
+    }
+}
+
+/// UTS 命名空间，记录主机名与域名
+#[derive(Debug)]
+pub struct UtsNamespace {
+    /// 主机名，不包含结尾的 `\0`
+    pub hostname: Mutex<Vec<u8>>,
+    /// 域名，不包含结尾的 `\0`
+    pub domainname: Mutex<Vec<u8>>,
+}
+
+impl UtsNamespace {
+    fn new(hostname: &[u8], domainname: &[u8]) -> Self {
+        Self {
+            hostname: Mutex::new(hostname.to_vec()),
+            domainname: Mutex::new(domainname.to_vec()),
+        }
+    }
+}
+
+/// 两个目录项是否是同一个目录项
+fn same_dentry(a: &Arc<dyn VfsDentry>, b: &Arc<dyn VfsDentry>) -> bool {
+    Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+}
+
+/// 目录项 `dentry` 是否是 `dir` 本身或者位于 `dir` 之下
+fn is_under(dentry: &Arc<dyn VfsDentry>, dir: &Arc<dyn VfsDentry>) -> bool {
+    let mut current = Some(dentry.clone());
+    while let Some(dentry) = current {
+        if same_dentry(&dentry, dir) {
+            return true;
+        }
+        current = dentry.parent();
+    }
+    false
+}
+
+/// mount 命名空间中的一个挂载
+#[derive(Clone)]
+struct Mount {
+    /// 挂载点，为挂载时解析得到的目录项
+    point: Arc<dyn VfsDentry>,
+    /// 挂载的文件系统的根目录
+    root: Arc<dyn VfsDentry>,
+}
+
+/// mount 命名空间
+///
+/// 挂载表按照挂载的先后顺序记录，同一个挂载点上后挂载的文件系统覆盖先挂载的文件系统。
+pub struct MountNamespace {
+    /// 命名空间的根目录
+    root: Mutex<Arc<dyn VfsDentry>>,
+    /// 在该命名空间中挂载的文件系统
+    mounts: Mutex<Vec<Mount>>,
+}
+
+impl Debug for MountNamespace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MountNamespace")
+            .field("root", &self.root.lock().path())
+            .field("mounts", &self.mounts.lock().len())
+            .finish()
+    }
+}
+
+impl MountNamespace {
+    fn new(root: Arc<dyn VfsDentry>, mounts: Vec<Mount>) -> Self {
+        Self {
+            root: Mutex::new(root),
+            mounts: Mutex::new(mounts),
+        }
+    }
+
+    /// 获取命名空间的根目录
+    pub fn root(&self) -> Arc<dyn VfsDentry> {
+        self.root.lock().clone()
+    }
+
+    /// 修改命名空间的根目录
+    pub fn set_root(&self, root: Arc<dyn VfsDentry>) {
+        *self.root.lock() = root;
+    }
+
+    /// 将根目录为 `root` 的文件系统挂载到目录 `point` 上
+    pub fn mount(&self, point: Arc<dyn VfsDentry>, root: Arc<dyn VfsDentry>) {
+        self.mounts.lock().push(Mount { point, root });
+    }
+
+    /// 卸载根目录为 `root` 的文件系统。
+    ///
+    /// `root` 不是该命名空间中挂载的文件系统时返回 `EINVAL`，其中还有其它挂载时返回 `EBUSY`。
+    pub fn umount(&self, root: &Arc<dyn VfsDentry>) -> AlienResult<()> {
+        let mut mounts = self.mounts.lock();
+        let index = mounts
+            .iter()
+            .rposition(|mount| same_dentry(&mount.root, root))
+            .ok_or(LinuxErrno::EINVAL)?;
+        if mounts.iter().any(|mount| is_under(&mount.point, root)) {
+            return Err(LinuxErrno::EBUSY);
+        }
+        mounts.remove(index);
+        Ok(())
+    }
+
+    /// `dentry` 是挂载点时，返回挂载在其上的(最上层的)文件系统的根目录
+    pub fn mounted_root(&self, dentry: &Arc<dyn VfsDentry>) -> Option<Arc<dyn VfsDentry>> {
+        let mounts = self.mounts.lock();
+        let mut root = None;
+        while let Some(mount) = mounts
+            .iter()
+            .rev()
+            .find(|mount| same_dentry(&mount.point, root.as_ref().unwrap_or(dentry)))
+        {
+            root = Some(mount.root.clone());
+        }
+        root
+    }
+
+    /// 在目录 `dir` 中查找名为 `name` 的目录项，`root` 为当前任务的根目录。
+    ///
+    /// 查找结果为挂载点时返回挂载在其上的文件系统的根目录；`..` 不会越过 `root`，
+    /// 在该命名空间中挂载的文件系统的根目录的 `..` 为挂载点的父目录。
+    pub fn lookup(
+        &self,
+        root: &Arc<dyn VfsDentry>,
+        dir: Arc<dyn VfsDentry>,
+        name: &str,
+    ) -> AlienResult<Arc<dyn VfsDentry>> {
+        if name != ".." {
+            let dentry = VfsPath::new(root.clone(), dir).join(name)?.open(None)?;
+            return Ok(self.mounted_root(&dentry).unwrap_or(dentry));
+        }
+        let mut dir = dir;
+        loop {
+            if same_dentry(&dir, root) {
+                return Ok(dir);
+            }
+            let point = self
+                .mounts
+                .lock()
+                .iter()
+                .rev()
+                .find(|mount| same_dentry(&mount.root, &dir))
+                .map(|mount| mount.point.clone());
+            match point {
+                Some(point) => dir = point,
+                None => break,
+            }
+        }
+        Ok(VfsPath::new(root.clone(), dir).join("..")?.open(None)?)
+    }
+}
+
+/// pid 命名空间中的 pid 与全局 tid 的对应关系
+#[derive(Debug, Default)]
+struct PidMap {
+    /// 命名空间中的 pid 到全局 tid
+    tids: BTreeMap<usize, usize>,
+    /// 全局 tid 到命名空间中的 pid
+    pids: BTreeMap<usize, usize>,
+}
+
+/// pid 命名空间
+///
+/// 任务在其所在的命名空间以及所有祖先命名空间中都有一个 pid，初始命名空间中的 pid 就是全局的 tid，不需要记录。
+#[derive(Debug)]
+pub struct PidNamespace {
+    parent: Option<Arc<PidNamespace>>,
+    level: usize,
+    pids: Mutex<PidMap>,
+    /// 命名空间中 pid 为 1 的进程已经退出，此后不能再在其中创建任务
+    dead: AtomicBool,
+}
+
+impl PidNamespace {
+    /// 创建一个以 `self` 为父命名空间的 pid 命名空间，嵌套过深时返回 `ENOSPC`
+    fn new_child(self: &Arc<Self>) -> AlienResult<Arc<Self>> {
+        if self.level >= MAX_PID_NS_LEVEL {
+            return Err(LinuxErrno::ENOSPC);
+        }
+        Ok(Arc::new(Self {
+            parent: Some(self.clone()),
+            level: self.level + 1,
+            pids: Mutex::new(PidMap::default()),
+            dead: AtomicBool::new(false),
+        }))
+    }
+
+    /// 全局 tid 为 `tid` 的任务在该命名空间中的 pid，任务不在该命名空间及其子孙命名空间中时返回 None
+    pub fn pid_of(&self, tid: usize) -> Option<usize> {
+        if self.parent.is_none() {
+            return Some(tid);
+        }
+        self.pids.lock().pids.get(&tid).copied()
+    }
+
+    /// 该命名空间中的 `pid` 对应的全局 tid
+    pub fn tid_of(&self, pid: usize) -> Option<usize> {
+        if self.parent.is_none() {
+            return Some(pid);
+        }
+        self.pids.lock().tids.get(&pid).copied()
+    }
+
+    /// `self` 是否是 `other` 本身或者其祖先
+    pub fn is_ancestor_of(self: &Arc<Self>, other: &Arc<Self>) -> bool {
+        let mut ns = Some(other.clone());
+        while let Some(current) = ns {
+            if Arc::ptr_eq(self, &current) {
+                return true;
+            }
+            ns = current.parent.clone();
+        }
+        false
+    }
+
+    /// 为全局 tid 为 `tid` 的任务分配该命名空间中最小的可用 pid
+    fn alloc(&self, tid: usize) -> AlienResult<usize> {
+        if self.parent.is_none() {
+            return Ok(tid);
+        }
+        if self.dead.load(Ordering::Acquire) {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        let mut pids = self.pids.lock();
+        let pid = (1..).find(|pid| !pids.tids.contains_key(pid)).unwrap();
+        pids.tids.insert(pid, tid);
+        pids.pids.insert(tid, pid);
+        Ok(pid)
+    }
+
+    /// 释放全局 tid 为 `tid` 的任务在该命名空间中的 pid
+    fn free(&self, tid: usize) {
+        let mut pids = self.pids.lock();
+        if let Some(pid) = pids.pids.remove(&tid) {
+            pids.tids.remove(&pid);
+        }
+    }
+}
+
+/// 任务在其所在的 pid 命名空间以及所有祖先命名空间中的 pid，与 [`TidHandle`](super::resource::TidHandle) 一起释放
+#[derive(Debug)]
+pub struct PidHandle {
+    ns: Arc<PidNamespace>,
+    tid: usize,
+}
+
+impl PidHandle {
+    /// 在 `ns` 及其所有祖先命名空间中为全局 tid 为 `tid` 的任务分配 pid
+    pub fn new(ns: &Arc<PidNamespace>, tid: usize) -> AlienResult<Self> {
+        // 分配失败时，已经分配的 pid 随着 handle 一起释放
+        let handle = Self {
+            ns: ns.clone(),
+            tid,
+        };
+        let mut current = Some(ns.clone());
+        while let Some(ns) = current {
+            ns.alloc(tid)?;
+            current = ns.parent.clone();
+        }
+        Ok(handle)
+    }
+
+    /// 任务所在的 pid 命名空间
+    pub fn ns(&self) -> &Arc<PidNamespace> {
+        &self.ns
+    }
+
+    /// 任务在其所在的 pid 命名空间中的 pid
+    pub fn pid(&self) -> usize {
+        self.ns.pid_of(self.tid).unwrap()
+    }
+}
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        let mut current = Some(self.ns.clone());
+        while let Some(ns) = current {
+            ns.free(self.tid);
+            current = ns.parent.clone();
+        }
+    }
+}
+
+/// 将全局的 tid 转换为当前任务所在的 pid 命名空间中的 pid，在其中不可见的任务返回 0
+pub fn pid_to_user(tid: usize) -> isize {
+    let task = current_task().unwrap();
+    task.pid_namespace().pid_of(tid).unwrap_or(0) as isize
+}
+
+/// 将全局的 tid 转换为任务 `receiver` 所在的 pid 命名空间中的 pid，在其中不可见的任务返回 0
+///
+/// 用于填写发送给 `receiver` 的信号信息中的 pid。
+pub fn pid_for_receiver(receiver: &Task, tid: usize) -> usize {
+    receiver.pid_namespace().pid_of(tid).unwrap_or(0)
+}
+
+/// 将当前任务所在的 pid 命名空间中的 `pid` 转换为全局的 tid
+pub fn pid_from_user(pid: usize) -> Option<usize> {
+    let task = current_task().unwrap();
+    task.pid_namespace().tid_of(pid)
+}
+
+/// 查找当前任务所在的 pid 命名空间中 pid 为 `pid` 的进程
+pub fn find_process_from_user(pid: usize) -> Option<Arc<Task>> {
+    pid_from_user(pid).and_then(find_process)
+}
+
+/// 获取在 `task` 退出后收养其子进程的进程。
+///
+/// 为 `task` 所在的 pid 命名空间中 pid 为 1 的进程；位于初始命名空间，或者该进程就是 `task` 所在的进程、已经退出时，为初始进程。
+pub fn child_reaper(task: &Arc<Task>) -> Arc<Task> {
+    let ns = task.pid_namespace();
+    if ns.parent.is_none() {
+        return INIT_PROCESS.clone();
+    }
+    ns.tid_of(1)
+        .filter(|tid| *tid != task.pid)
+        .and_then(find_task)
+        .filter(|reaper| !matches!(reaper.state(), TaskState::Zombie | TaskState::Terminated))
+        .unwrap_or_else(|| INIT_PROCESS.clone())
+}
+
+/// 任务 `task` 退出时调用，如果它是 pid 命名空间中 pid 为 1 的进程，则终止命名空间中的其它任务
+pub fn exit_pid_namespace(task: &Arc<Task>) {
+    let ns = task.pid_namespace();
+    let tid = task.get_tid() as usize;
+    if ns.parent.is_none() || ns.pid_of(tid) != Some(1) {
+        return;
+    }
+    ns.dead.store(true, Ordering::Release);
+    let tids = ns.pids.lock().pids.keys().copied().collect::<Vec<_>>();
+    tids.into_iter()
+        .filter(|other| *other != tid)
+        .for_each(|other| send_signal(other, SignalNumber::SIGKILL as usize));
+}
+
+/// 检查创建 `flags` 中的新命名空间的权限与标志的组合，`flags` 中不包含创建命名空间的标志时总是成功。
+///
+/// 创建命名空间需要拥有 CAP_SYS_ADMIN 能力，否则返回 `EPERM`；`CLONE_NEWPID` 不能与 `CLONE_THREAD` 一起使用，
+/// `CLONE_NEWNS` 不能与 `CLONE_FS` 一起使用，否则返回 `EINVAL`。
+pub fn check_new_namespaces(flags: CloneFlags) -> AlienResult<()> {
+    let new_pid = flags.contains(CloneFlags::CLONE_NEWPID);
+    let new_ns = flags.contains(CloneFlags::CLONE_NEWNS);
+    if !new_pid && !new_ns && !flags.contains(CloneFlags::CLONE_NEWUTS) {
+        return Ok(());
+    }
+    if new_pid && flags.contains(CloneFlags::CLONE_THREAD)
+        || new_ns && flags.contains(CloneFlags::CLONE_FS)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(LinuxErrno::EPERM);
+    }
+    Ok(())
+}
+
+/// 可以通过 unshare 与 setns 使用的命名空间的标志
+fn namespace_flags() -> CloneFlags {
+    CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWUTS | CloneFlags::CLONE_NEWPID
+}
+
+/// 一个系统调用，使当前任务离开原来的命名空间，进入新创建的命名空间。
+///
+/// `flags` 可以包含 `CLONE_NEWUTS`、`CLONE_NEWNS` 与 `CLONE_NEWPID`，包含其它标志时返回 `EINVAL`。
+/// 新的 mount 命名空间复制原命名空间的根目录与挂载表。`CLONE_NEWPID` 不改变当前任务所在的 pid 命名空间，
+/// 之后创建的子进程位于新的 pid 命名空间中，第一个子进程的 pid 为 1。需要拥有 CAP_SYS_ADMIN 能力，否则返回 `EPERM`。
+///
+/// Reference: [unshare](https://man7.org/linux/man-pages/man2/unshare.2.html)
+#[syscall_func(97)]
+pub fn unshare(flags: usize) -> AlienResult<isize> {
+    if flags & !(namespace_flags().bits() as usize) != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let flags = CloneFlags::from_bits_truncate(flags as u32);
+    check_new_namespaces(flags)?;
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    inner.ns = inner.ns.copy(flags)?;
+    Ok(0)
+}
+
+/// 一个系统调用，使当前任务加入 pidfd `fd` 所指向的进程的命名空间。
+///
+/// `nstype` 指明要加入的命名空间，可以包含 `CLONE_NEWUTS`、`CLONE_NEWNS` 与 `CLONE_NEWPID`，为 0 或包含其它标志时返回 `EINVAL`。
+/// 加入 mount 命名空间后，当前任务的根目录与工作目录被设置为该命名空间的根目录；加入 pid 命名空间只影响之后创建的子进程，
+/// 且该命名空间必须是当前任务所在的 pid 命名空间或其子孙命名空间，否则返回 `EINVAL`。
+/// 目前不支持 `/proc/[pid]/ns` 中的命名空间文件，`fd` 不是 pidfd 时返回 `EINVAL`。
+///
+/// 需要拥有 CAP_SYS_ADMIN 能力，否则返回 `EPERM`；目标进程已经退出时返回 `ESRCH`。
+///
+/// Reference: [setns](https://man7.org/linux/man-pages/man2/setns.2.html)
+#[syscall_func(268)]
+pub fn setns(fd: usize, nstype: usize) -> AlienResult<isize> {
+    if nstype == 0 || nstype & !(namespace_flags().bits() as usize) != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let nstype = CloneFlags::from_bits_truncate(nstype as u32);
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let target = file
+        .downcast_arc::<PidFd>()
+        .map_err(|_| LinuxErrno::EINVAL)?
+        .task()?;
+    if !process_exists(target.get_pid() as usize) {
+        return Err(LinuxErrno::ESRCH);
+    }
+    if !task.access_inner().cred.has_cap(Capabilities::SYS_ADMIN) {
+        return Err(LinuxErrno::EPERM);
+    }
+    let target_pid_ns = target.pid_namespace().clone();
+    if nstype.contains(CloneFlags::CLONE_NEWPID)
+        && !task.pid_namespace().is_ancestor_of(&target_pid_ns)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let target_ns = target.access_inner().ns.clone();
+    let mut inner = task.access_inner();
+    if nstype.contains(CloneFlags::CLONE_NEWUTS) {
+        inner.ns.uts = target_ns.uts;
+    }
+    if nstype.contains(CloneFlags::CLONE_NEWNS) {
+        let root = target_ns.mnt.root();
+        inner.ns.mnt = target_ns.mnt;
+        inner.fs_info.root = root.clone();
+        inner.fs_info.cwd = root;
+    }
+    if nstype.contains(CloneFlags::CLONE_NEWPID) {
+        inner.ns.pid_for_children = target_pid_ns;
+    }
+    Ok(0)
+}
@@ -0,0 +1,264 @@
+//! pidfd 是指向一个进程的文件描述符。
+//!
+//! 与 pid 不同，pidfd 始终指向创建它时的进程，不会因为进程被回收后 pid 被重新分配而指向其它进程。
+//! 进程中所有的线程退出后 pidfd 变为可读，因此可以通过 poll/select 等待一个不是自己子进程的进程退出。
+//! pidfd 由 [`pidfd_open`] 或者 clone/clone3 的 `CLONE_PIDFD` 标志创建，总是设置了 close-on-exec。
+//!
+//! 与管道相同，每个 pidfd 在 pipefs 中有一个匿名的目录项，fchmod、fgetxattr 等需要目录项的操作对其返回错误。
+use alloc::{
+    format,
+    sync::{Arc, Weak},
+};
+use core::{
+    fmt::{Debug, Formatter},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, LinuxErrno,
+};
+use ksync::Mutex;
+use syscall_table::syscall_func;
+use vfs::{
+    kfile::File,
+    pipefs::{PipeFsDirInodeImpl, PIPE_FS_ROOT},
+};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use crate::{
+    ipc::process_exists,
+    task::{current_task, find_process, pid_from_user, ptrace::may_trace, Task},
+};
+
+/// pidfd_open 的标志，以非阻塞的方式打开 pidfd，与 `O_NONBLOCK` 相同
+const PIDFD_NONBLOCK: usize = 0o4000;
+
+/// 用于生成 pidfd 在 pipefs 中的文件名
+static PIDFD: AtomicUsize = AtomicUsize::new(0);
+
+/// 指向一个进程的文件
+pub struct PidFd {
+    /// 只保存进程控制块的弱引用，父进程回收子进程时要求没有其它的引用
+    task: Weak<Task>,
+    open_flag: Mutex<OpenFlags>,
+    /// pipefs 中的匿名目录项
+    dentry: Arc<dyn VfsDentry>,
+}
+
+impl Debug for PidFd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PidFd")
+            .field("name", &self.dentry.name())
+            .field("open_flag", &self.open_flag)
+            .finish()
+    }
+}
+
+/// pipefs 中的目录
+fn pipefs_dir() -> AlienResult<Arc<PipeFsDirInodeImpl>> {
+    PIPE_FS_ROOT
+        .get()
+        .unwrap()
+        .inode()?
+        .downcast_arc::<PipeFsDirInodeImpl>()
+        .map_err(|_| LinuxErrno::EINVAL)
+}
+
+impl PidFd {
+    /// 创建一个指向进程 `task` 的 pidfd
+    pub fn new(task: &Arc<Task>, open_flag: OpenFlags) -> AlienResult<Self> {
+        let root = PIPE_FS_ROOT.get().unwrap();
+        let name = format!("pidfd:{}", PIDFD.fetch_add(1, Ordering::AcqRel));
+        let inode =
+            pipefs_dir()?.add_file_manually(&name, Arc::new(PidFdInode), "rw-------".into())?;
+        let dentry = root.i_insert(&name, inode)?;
+        Ok(Self {
+            task: Arc::downgrade(task),
+            open_flag: Mutex::new(open_flag | OpenFlags::O_CLOEXEC),
+            dentry,
+        })
+    }
+
+    /// 获取 pidfd 指向的进程，进程已经被回收时返回 `ESRCH`
+    pub fn task(&self) -> AlienResult<Arc<Task>> {
+        self.task.upgrade().ok_or(LinuxErrno::ESRCH)
+    }
+
+    /// 进程中的所有线程是否都已经退出
+    fn exited(&self) -> bool {
+        self.task
+            .upgrade()
+            .map_or(true, |task| !process_exists(task.get_pid() as usize))
+    }
+}
+
+impl File for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        Err(LinuxErrno::ENOSYS)
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        true
+    }
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let mut res = PollEvents::empty();
+        if event.contains(PollEvents::IN) && self.exited() {
+            res |= PollEvents::IN;
+        }
+        Ok(res)
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        let name = self.dentry.name();
+        let _ = PIPE_FS_ROOT.get().unwrap().remove(&name);
+        if let Ok(dir) = pipefs_dir() {
+            let _ = dir.remove_manually(&name);
+        }
+    }
+}
+
+/// pidfd 在 pipefs 中对应的 inode，不支持读写与修改属性
+struct PidFdInode;
+
+impl VfsFile for PidFdInode {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+}
+
+impl VfsInode for PidFdInode {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::NoSys)
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Err(VfsError::NoSys)
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
+
+/// 获取当前进程的文件描述符 `fd` 所指向的进程
+///
+/// `fd` 无效或者不是 pidfd 时返回 `EBADF`，进程已经被回收时返回 `ESRCH`。
+pub fn pidfd_task(fd: usize) -> AlienResult<Arc<Task>> {
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let pidfd = file
+        .downcast_arc::<PidFd>()
+        .map_err(|_| LinuxErrno::EBADF)?;
+    pidfd.task()
+}
+
+/// 一个系统调用，创建一个指向进程 `pid` 的 pidfd。
+///
+/// `flags` 只能为 0 或 `PIDFD_NONBLOCK`，否则返回 `EINVAL`；`pid` 不是一个进程(线程组的主线程)时返回 `ESRCH`。
+///
+/// 成功时返回新的文件描述符。
+///
+/// Reference: [pidfd_open](https://man7.org/linux/man-pages/man2/pidfd_open.2.html)
+#[syscall_func(434)]
+pub fn pidfd_open(pid: usize, flags: usize) -> AlienResult<isize> {
+    if flags & !PIDFD_NONBLOCK != 0 || (pid as isize) <= 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let pid = pid_from_user(pid).ok_or(LinuxErrno::ESRCH)?;
+    let target = find_process(pid).ok_or(LinuxErrno::ESRCH)?;
+    let open_flag = if flags & PIDFD_NONBLOCK != 0 {
+        OpenFlags::O_NONBLOCK
+    } else {
+        OpenFlags::empty()
+    };
+    let task = current_task().unwrap();
+    let fd = task
+        .add_file(Arc::new(PidFd::new(&target, open_flag)?))
+        .map_err(|_| LinuxErrno::EMFILE)?;
+    Ok(fd as isize)
+}
+
+/// 一个系统调用，复制 pidfd 所指向的进程中的文件描述符 `targetfd` 到当前进程中。
+///
+/// 当前进程需要拥有跟踪目标进程的权限，否则返回 `EPERM`；`flags` 目前必须为 0。
+/// 目标进程已经退出时返回 `ESRCH`，`targetfd` 无效时返回 `EBADF`。
+/// 由于 close-on-exec 标志记录在文件上而不是文件描述符上，新的文件描述符与目标进程中的保持一致。
+///
+/// 成功时返回新的文件描述符。
+///
+/// Reference: [pidfd_getfd](https://man7.org/linux/man-pages/man2/pidfd_getfd.2.html)
+#[syscall_func(438)]
+pub fn pidfd_getfd(pidfd: usize, targetfd: usize, flags: usize) -> AlienResult<isize> {
+    if flags != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let target = pidfd_task(pidfd)?;
+    if !process_exists(target.get_pid() as usize) {
+        return Err(LinuxErrno::ESRCH);
+    }
+    let task = current_task().unwrap();
+    let cred = task.access_inner().cred.clone();
+    let target_cred = target.access_inner().cred.clone();
+    if !may_trace(&cred, &target_cred) {
+        return Err(LinuxErrno::EPERM);
+    }
+    let file = target.get_file(targetfd).ok_or(LinuxErrno::EBADF)?;
+    let fd = task.add_file(file).map_err(|_| LinuxErrno::EMFILE)?;
+    Ok(fd as isize)
+}
@@ -0,0 +1,676 @@
+//! 进程跟踪(ptrace)，供 gdbserver、strace 等用户态调试工具使用。
+//!
+//! 跟踪者通过 `PTRACE_TRACEME`、`PTRACE_ATTACH` 或 `PTRACE_SEIZE` 跟踪一个线程，被跟踪的线程在以下情况下进入
+//! ptrace 停止状态([`TaskState::Traced`])，跟踪者通过 `wait4` 得到停止的状态 `(信号 << 8) | 0x7f`：
+//! + 信号递送停止：处理 SIGKILL 以外的信号之前停止，由跟踪者决定实际递送的信号，见 [`ptrace_signal_stop`]；
+//! + 系统调用停止：以 `PTRACE_SYSCALL` 恢复运行后在系统调用的入口与出口停止，信号为 SIGTRAP，
+//!   设置了 `PTRACE_O_TRACESYSGOOD` 时为 `SIGTRAP | 0x80`，见 [`ptrace_syscall_stop`]；
+//! + exec 事件停止：设置了 `PTRACE_O_TRACEEXEC` 时 execve 成功后停止，否则向线程发送 SIGTRAP。
+//!
+//! 停止的线程可以被读写内存与寄存器，寄存器的布局与 riscv64 Linux 的 `struct user_regs_struct` 相同。
+//! 硬件不支持单步执行，`PTRACE_SINGLESTEP` 在下一条指令可能执行的位置插入 `c.ebreak` 断点，
+//! 断点被触发时恢复原来的指令并向线程发送 SIGTRAP。断点写在线程的代码页中，为了不影响共享同一地址空间的其它线程，
+//! 地址空间被共享(多线程进程或者 vfork 创建的子进程)时 `PTRACE_SINGLESTEP` 返回 `EIO`。
+//!
+//! Reference: [ptrace](https://man7.org/linux/man-pages/man2/ptrace.2.html)
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{cmp::min, mem::size_of};
+
+use config::FRAME_SIZE;
+use constants::{signal::SignalNumber, AlienResult, LinuxErrno};
+use ksync::Mutex;
+use smpscheduler::FifoTask;
+use syscall_table::syscall_func;
+
+use crate::{
+    ipc::{
+        send_process_signal_info, send_signal,
+        sigqueue::{SignalInfo, CLD_TRAPPED, SIGNAL_MAX},
+    },
+    mm::uaccess::{UserPtr, UserSlice},
+    task::{
+        current_task, find_task, pid_for_receiver, pid_from_user, schedule::schedule,
+        task::TaskInner, Capabilities, Credentials, Task, TaskState, GLOBAL_TASK_MANAGER,
+    },
+};
+
+const PTRACE_TRACEME: usize = 0;
+const PTRACE_PEEKTEXT: usize = 1;
+const PTRACE_PEEKDATA: usize = 2;
+const PTRACE_PEEKUSER: usize = 3;
+const PTRACE_POKETEXT: usize = 4;
+const PTRACE_POKEDATA: usize = 5;
+const PTRACE_POKEUSER: usize = 6;
+const PTRACE_CONT: usize = 7;
+const PTRACE_KILL: usize = 8;
+const PTRACE_SINGLESTEP: usize = 9;
+const PTRACE_GETREGS: usize = 12;
+const PTRACE_SETREGS: usize = 13;
+const PTRACE_ATTACH: usize = 16;
+const PTRACE_DETACH: usize = 17;
+const PTRACE_SYSCALL: usize = 24;
+const PTRACE_SETOPTIONS: usize = 0x4200;
+const PTRACE_GETREGSET: usize = 0x4204;
+const PTRACE_SETREGSET: usize = 0x4205;
+const PTRACE_SEIZE: usize = 0x4206;
+
+/// 系统调用停止时的信号为 `SIGTRAP | 0x80`，以便与真正的 SIGTRAP 区分
+const PTRACE_O_TRACESYSGOOD: u32 = 0x1;
+/// execve 成功后进入 exec 事件停止
+const PTRACE_O_TRACEEXEC: u32 = 0x10;
+/// 跟踪者退出时向被跟踪的线程发送 SIGKILL
+const PTRACE_O_EXITKILL: u32 = 0x100000;
+/// 目前支持的选项
+const PTRACE_O_MASK: u32 = PTRACE_O_TRACESYSGOOD | PTRACE_O_TRACEEXEC | PTRACE_O_EXITKILL;
+/// exec 事件停止的状态中记录的事件
+const PTRACE_EVENT_EXEC: i32 = 4;
+
+/// PTRACE_GETREGSET 与 PTRACE_SETREGSET 中的通用寄存器
+const NT_PRSTATUS: usize = 1;
+/// `struct user_regs_struct` 中寄存器的个数
+const USER_REGS_NUM: usize = 32;
+/// 单步执行时插入的 `c.ebreak` 指令
+const C_EBREAK: u16 = 0x9002;
+
+/// 正在被跟踪的线程，从 tid 到线程。
+///
+/// 发送 SIGKILL 时通过它找到被跟踪的线程，不被跟踪的线程不需要加锁或遍历进程树。
+static TRACEES: Mutex<BTreeMap<usize, Weak<Task>>> = Mutex::new(BTreeMap::new());
+
+/// 被跟踪的线程恢复运行的方式
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+enum PtraceResume {
+    /// PTRACE_CONT
+    #[default]
+    Cont,
+    /// PTRACE_SYSCALL，在下一次系统调用的入口或出口停止
+    Syscall,
+    /// PTRACE_SINGLESTEP，执行一条指令后停止
+    SingleStep,
+}
+
+/// 线程的 ptrace 状态，同时记录了作为跟踪者与被跟踪者的信息
+#[derive(Debug, Default)]
+pub struct PtraceState {
+    /// 跟踪当前线程的跟踪者
+    tracer: Option<Weak<Task>>,
+    /// 当前线程正在跟踪的线程
+    tracees: Vec<Weak<Task>>,
+    /// 通过 PTRACE_SETOPTIONS 或 PTRACE_SEIZE 设置的选项
+    options: u32,
+    /// 处于 ptrace 停止状态时，跟踪者通过 wait4 得到的状态以及该状态是否已经被报告
+    stop: Option<(i32, bool)>,
+    /// 恢复运行的方式
+    resume: PtraceResume,
+    /// 跟踪者恢复运行时指定递送的信号，0 表示不递送信号
+    resume_signal: usize,
+    /// 单步执行时插入的断点的地址以及被替换的指令
+    breakpoints: Vec<(usize, u16)>,
+}
+
+impl PtraceState {
+    /// 当前线程是否被跟踪
+    pub fn is_traced(&self) -> bool {
+        self.tracer.is_some()
+    }
+}
+
+/// 使当前线程进入 ptrace 停止状态并通知跟踪者，跟踪者通过 wait4 得到 `status`。
+///
+/// 线程被跟踪者恢复运行后返回跟踪者指定递送的信号；跟踪者已经退出时返回 None。
+fn ptrace_stop(task: &Arc<Task>, status: i32) -> Option<usize> {
+    let (tracer, uid) = {
+        let mut inner = task.access_inner();
+        let tracer = inner.ptrace.tracer.as_ref()?.upgrade()?;
+        inner.ptrace.stop = Some((status, false));
+        inner.ptrace.resume_signal = 0;
+        inner.state = TaskState::Traced;
+        (tracer, inner.cred.ruid)
+    };
+    let info = SignalInfo::child(
+        SignalNumber::SIGCHLD as usize,
+        CLD_TRAPPED,
+        pid_for_receiver(&tracer, task.get_tid() as usize),
+        uid,
+        (status >> 8) & 0xff,
+    );
+    let _ = send_process_signal_info(tracer.pid, info);
+    drop(tracer);
+    schedule();
+    Some(task.access_inner().ptrace.resume_signal)
+}
+
+/// 向线程 `tid` 发送 SIGKILL 时调用，唤醒处于 ptrace 停止状态的线程，使其能够处理 SIGKILL 并退出。
+///
+/// SIGKILL 不会进入信号递送停止，线程被唤醒后直接被终止。线程没有被跟踪时直接返回。
+pub fn ptrace_kill_wakeup(tid: usize) {
+    let tracee = match TRACEES.lock().get(&tid).and_then(Weak::upgrade) {
+        Some(tracee) => tracee,
+        None => return,
+    };
+    let mut inner = tracee.access_inner();
+    if inner.state != TaskState::Traced {
+        return;
+    }
+    inner.ptrace.resume = PtraceResume::Cont;
+    inner.ptrace.resume_signal = 0;
+    inner.ptrace.stop = None;
+    inner.state = TaskState::Ready;
+    drop(inner);
+    GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(tracee)));
+}
+
+/// 被跟踪的线程处理信号 `signum` 之前调用，进入信号递送停止。
+///
+/// 返回跟踪者恢复运行时指定递送的信号，为 0 时信号被丢弃；信号为 SIGKILL 或者跟踪者已经退出时返回 `signum`。
+pub fn ptrace_signal_stop(task: &Arc<Task>, signum: usize) -> usize {
+    if signum == SignalNumber::SIGKILL as usize {
+        return signum;
+    }
+    ptrace_stop(task, (signum as i32) << 8 | 0x7f).unwrap_or(signum)
+}
+
+/// 在系统调用的入口与出口调用，线程以 PTRACE_SYSCALL 恢复运行时进入系统调用停止。
+///
+/// 跟踪者可以在入口停止时修改系统调用号与参数，在出口停止时修改返回值。
+pub fn ptrace_syscall_stop(task: &Arc<Task>) {
+    let options = {
+        let inner = task.access_inner();
+        if !inner.ptrace.is_traced() || inner.ptrace.resume != PtraceResume::Syscall {
+            return;
+        }
+        inner.ptrace.options
+    };
+    let mut signum = SignalNumber::SIGTRAP as i32;
+    if options & PTRACE_O_TRACESYSGOOD != 0 {
+        signum |= 0x80;
+    }
+    ptrace_stop(task, signum << 8 | 0x7f);
+}
+
+/// execve 成功后调用，原来的地址空间中单步执行的断点不再有效。
+///
+/// 设置了 PTRACE_O_TRACEEXEC 时进入 exec 事件停止，否则向被跟踪的线程发送 SIGTRAP。
+pub fn ptrace_exec(task: &Arc<Task>) {
+    let options = {
+        let mut inner = task.access_inner();
+        inner.ptrace.breakpoints.clear();
+        if !inner.ptrace.is_traced() {
+            return;
+        }
+        inner.ptrace.options
+    };
+    let sigtrap = SignalNumber::SIGTRAP as i32;
+    if options & PTRACE_O_TRACEEXEC != 0 {
+        ptrace_stop(task, (sigtrap | PTRACE_EVENT_EXEC << 8) << 8 | 0x7f);
+    } else {
+        send_signal(task.get_tid() as usize, sigtrap as usize);
+    }
+}
+
+/// 断点异常发生时调用，`pc` 为单步执行插入的断点时恢复所有断点处原来的指令并返回 true
+pub fn ptrace_step_done(task: &Arc<Task>, pc: usize) -> bool {
+    let mut inner = task.access_inner();
+    if !inner.ptrace.breakpoints.iter().any(|(addr, _)| *addr == pc) {
+        return false;
+    }
+    remove_breakpoints(&mut inner);
+    true
+}
+
+/// 跟踪者 `tracer` 是否正在跟踪线程号为 `pid` 的线程，`pid` 为 -1 时表示任意线程
+pub fn is_tracing(tracer: &Arc<Task>, pid: isize) -> bool {
+    tracer
+        .access_inner()
+        .ptrace
+        .tracees
+        .iter()
+        .filter_map(Weak::upgrade)
+        .any(|tracee| pid == -1 || tracee.get_tid() == pid)
+}
+
+/// 在 `wait4` 中调用，查找 `tracer` 跟踪的线程中线程号为 `pid`(为 -1 时表示任意线程)且有状态需要报告的线程，
+/// 返回其线程号与 wait4 得到的状态。
+///
+/// 进入 ptrace 停止状态的线程报告其停止的状态，每次停止只报告一次。已经退出的线程不再被跟踪，
+/// 跟踪者不是其父进程时报告其退出状态，之后仍然由其父进程回收。
+pub fn ptrace_wait(tracer: &Arc<Task>, pid: isize) -> Option<(isize, i32)> {
+    let tracees = tracer.access_inner().ptrace.tracees.clone();
+    for tracee in tracees.iter().filter_map(Weak::upgrade) {
+        if pid != -1 && tracee.get_tid() != pid {
+            continue;
+        }
+        let mut inner = tracee.access_inner();
+        if matches!(inner.state, TaskState::Zombie | TaskState::Terminated) {
+            let is_parent = inner
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.upgrade())
+                .map_or(false, |parent| parent.pid == tracer.pid);
+            let exit_code = inner.exit_code;
+            drop(inner);
+            untrace(tracer, &tracee);
+            if !is_parent {
+                return Some((tracee.get_tid(), exit_code));
+            }
+        } else if let Some((status, false)) = inner.ptrace.stop {
+            inner.ptrace.stop = Some((status, true));
+            return Some((tracee.get_tid(), status));
+        }
+    }
+    None
+}
+
+/// 线程退出时调用，停止对其跟踪的所有线程的跟踪，设置了 PTRACE_O_EXITKILL 的线程同时收到 SIGKILL
+pub fn ptrace_exit(task: &Arc<Task>) {
+    TRACEES.lock().remove(&(task.get_tid() as usize));
+    let tracees = core::mem::take(&mut task.access_inner().ptrace.tracees);
+    for tracee in tracees.iter().filter_map(Weak::upgrade) {
+        if tracee.access_inner().ptrace.options & PTRACE_O_EXITKILL != 0 {
+            send_signal(tracee.get_tid() as usize, SignalNumber::SIGKILL as usize);
+        }
+        detach(&tracee, 0);
+    }
+}
+
+/// `tracer` 开始跟踪 `tracee`
+fn link(tracer: &Arc<Task>, tracee: &Arc<Task>, options: u32) {
+    {
+        let mut inner = tracee.access_inner();
+        inner.ptrace.tracer = Some(Arc::downgrade(tracer));
+        inner.ptrace.options = options;
+        inner.ptrace.resume = PtraceResume::Cont;
+    }
+    TRACEES
+        .lock()
+        .insert(tracee.get_tid() as usize, Arc::downgrade(tracee));
+    tracer
+        .access_inner()
+        .ptrace
+        .tracees
+        .push(Arc::downgrade(tracee));
+}
+
+/// 将 `tracee` 从 `tracer` 跟踪的线程中移除
+fn untrace(tracer: &Arc<Task>, tracee: &Arc<Task>) {
+    tracer
+        .access_inner()
+        .ptrace
+        .tracees
+        .retain(|task| task.as_ptr() != Arc::as_ptr(tracee));
+}
+
+/// `tracer` 是否为 `tracee` 的跟踪者
+fn is_tracer_of(tracer: &Arc<Task>, tracee: &Arc<Task>) -> bool {
+    tracee
+        .access_inner()
+        .ptrace
+        .tracer
+        .as_ref()
+        .map_or(false, |task| task.as_ptr() == Arc::as_ptr(tracer))
+}
+
+/// 凭证为 `cred` 的跟踪者是否可以跟踪凭证为 `target` 的线程。
+///
+/// 跟踪者的真实用户 id 与组 id 需要与目标的真实、有效与保存的用户 id 与组 id 都相同，或者拥有 CAP_SYS_PTRACE 能力。
+pub fn may_trace(cred: &Credentials, target: &Credentials) -> bool {
+    if cred.has_cap(Capabilities::SYS_PTRACE) {
+        return true;
+    }
+    [target.ruid, target.euid, target.suid]
+        .iter()
+        .all(|uid| *uid == cred.ruid)
+        && [target.rgid, target.egid, target.sgid]
+            .iter()
+            .all(|gid| *gid == cred.rgid)
+}
+
+/// 当前线程开始跟踪 `tracee`，检查是否拥有跟踪的权限
+fn attach(tracer: &Arc<Task>, tracee: &Arc<Task>, options: u32) -> AlienResult<()> {
+    if tracee.pid == tracer.pid || tracee.pid == 1 {
+        return Err(LinuxErrno::EPERM);
+    }
+    let cred = tracer.access_inner().cred.clone();
+    {
+        let inner = tracee.access_inner();
+        if matches!(inner.state, TaskState::Zombie | TaskState::Terminated) {
+            return Err(LinuxErrno::ESRCH);
+        }
+        if inner.ptrace.is_traced() || !may_trace(&cred, &inner.cred) {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    link(tracer, tracee, options);
+    Ok(())
+}
+
+/// 检查 PTRACE_SETOPTIONS 与 PTRACE_SEIZE 中的选项，不支持的选项返回 EINVAL
+fn check_options(options: usize) -> AlienResult<u32> {
+    if options & !(PTRACE_O_MASK as usize) != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    Ok(options as u32)
+}
+
+/// 恢复停止的被跟踪线程 `tracee` 的运行，`signal` 为恢复后递送的信号，`tracee` 没有停止时返回 ESRCH
+fn resume_tracee(tracee: &Arc<Task>, resume: PtraceResume, signal: usize) -> AlienResult<()> {
+    if signal >= SIGNAL_MAX {
+        return Err(LinuxErrno::EIO);
+    }
+    let mut inner = tracee.access_inner();
+    if inner.state != TaskState::Traced {
+        return Err(LinuxErrno::ESRCH);
+    }
+    if resume == PtraceResume::SingleStep {
+        insert_step_breakpoints(&mut inner)?;
+    }
+    inner.ptrace.resume = resume;
+    inner.ptrace.resume_signal = signal;
+    inner.ptrace.stop = None;
+    inner.state = TaskState::Ready;
+    drop(inner);
+    GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(tracee.clone())));
+    Ok(())
+}
+
+/// 停止对 `tracee` 的跟踪，恢复单步执行的断点处原来的指令。`tracee` 处于停止状态时恢复其运行并递送信号 `signal`
+fn detach(tracee: &Arc<Task>, signal: usize) {
+    {
+        let mut inner = tracee.access_inner();
+        remove_breakpoints(&mut inner);
+        inner.ptrace.tracer = None;
+        inner.ptrace.options = 0;
+    }
+    TRACEES.lock().remove(&(tracee.get_tid() as usize));
+    let _ = resume_tracee(tracee, PtraceResume::Cont, signal);
+}
+
+/// 将 `data` 写入被跟踪线程的地址 `addr` 处，只读的页(如代码段)同样可以写入
+fn poke_bytes(inner: &mut TaskInner, addr: usize, data: &[u8]) -> AlienResult<()> {
+    let mut written = 0;
+    while written < data.len() {
+        let addr = addr.checked_add(written).ok_or(LinuxErrno::EIO)?;
+        let physical = inner.ptrace_writable_addr(addr)?;
+        let len = min(FRAME_SIZE - addr % FRAME_SIZE, data.len() - written);
+        unsafe { (physical as *mut u8).copy_from_nonoverlapping(data[written..].as_ptr(), len) };
+        written += len;
+    }
+    Ok(())
+}
+
+/// 恢复单步执行插入的断点处原来的指令
+fn remove_breakpoints(inner: &mut TaskInner) {
+    let breakpoints = core::mem::take(&mut inner.ptrace.breakpoints);
+    for (addr, insn) in breakpoints {
+        let _ = poke_bytes(inner, addr, &insn.to_le_bytes());
+    }
+}
+
+/// 在被跟踪线程的下一条指令可能执行的位置插入断点。
+///
+/// 地址空间与其它线程共享时，断点会被其它线程执行到，此时返回 `EIO`。
+/// 无法写入的位置不插入断点，线程执行到那里时会因为缺页收到 SIGSEGV。
+fn insert_step_breakpoints(inner: &mut TaskInner) -> AlienResult<()> {
+    if Arc::strong_count(&inner.address_space) > 1 {
+        return Err(LinuxErrno::EIO);
+    }
+    let regs = inner.trap_frame().user_regs();
+    let pc = regs[0];
+    let mut half = [0u8; 2];
+    inner
+        .copy_from_user_bytes(pc, &mut half)
+        .map_err(|_| LinuxErrno::EIO)?;
+    let mut insn = u16::from_le_bytes(half) as u32;
+    if insn & 0b11 == 0b11 {
+        // 32 位指令可能跨越两页
+        inner
+            .copy_from_user_bytes(pc + 2, &mut half)
+            .map_err(|_| LinuxErrno::EIO)?;
+        insn |= (u16::from_le_bytes(half) as u32) << 16;
+    }
+    for target in next_pcs(insn, pc, &regs).into_iter().flatten() {
+        if inner
+            .ptrace
+            .breakpoints
+            .iter()
+            .any(|(addr, _)| *addr == target)
+        {
+            continue;
+        }
+        let mut origin = [0u8; 2];
+        if inner.copy_from_user_bytes(target, &mut origin).is_err()
+            || poke_bytes(inner, target, &C_EBREAK.to_le_bytes()).is_err()
+        {
+            continue;
+        }
+        inner
+            .ptrace
+            .breakpoints
+            .push((target, u16::from_le_bytes(origin)));
+    }
+    Ok(())
+}
+
+/// 取出 `insn` 的第 `lo` 到 `hi` 位
+fn bits(insn: u32, hi: u32, lo: u32) -> usize {
+    ((insn >> lo) & ((1 << (hi - lo + 1)) - 1)) as usize
+}
+
+/// 将 `width` 位的立即数符号扩展为 64 位
+fn sign_extend(value: usize, width: u32) -> usize {
+    (((value << (64 - width)) as isize) >> (64 - width)) as usize
+}
+
+/// 计算位于 `pc` 的指令 `insn` 执行后下一条指令可能的地址，条件分支同时返回跳转与不跳转时的地址
+fn next_pcs(insn: u32, pc: usize, regs: &[usize; USER_REGS_NUM]) -> [Option<usize>; 2] {
+    // user_regs_struct 中 x0 的位置保存的是 pc
+    let reg = |index: usize| if index == 0 { 0 } else { regs[index] };
+    if insn & 0b11 != 0b11 {
+        let next = pc + 2;
+        return match (insn & 0b11, bits(insn, 15, 13)) {
+            // c.j
+            (0b01, 0b101) => {
+                let imm = bits(insn, 12, 12) << 11
+                    | bits(insn, 11, 11) << 4
+                    | bits(insn, 10, 9) << 8
+                    | bits(insn, 8, 8) << 10
+                    | bits(insn, 7, 7) << 6
+                    | bits(insn, 6, 6) << 7
+                    | bits(insn, 5, 3) << 1
+                    | bits(insn, 2, 2) << 5;
+                [Some(pc.wrapping_add(sign_extend(imm, 12))), None]
+            }
+            // c.beqz, c.bnez
+            (0b01, 0b110 | 0b111) => {
+                let imm = bits(insn, 12, 12) << 8
+                    | bits(insn, 11, 10) << 3
+                    | bits(insn, 6, 5) << 6
+                    | bits(insn, 4, 3) << 1
+                    | bits(insn, 2, 2) << 5;
+                [Some(next), Some(pc.wrapping_add(sign_extend(imm, 9)))]
+            }
+            // c.jr, c.jalr
+            (0b10, 0b100) if bits(insn, 11, 7) != 0 && bits(insn, 6, 2) == 0 => {
+                [Some(reg(bits(insn, 11, 7)) & !1), None]
+            }
+            _ => [Some(next), None],
+        };
+    }
+    let next = pc + 4;
+    match insn & 0x7f {
+        // jal
+        0x6f => {
+            let imm = bits(insn, 31, 31) << 20
+                | bits(insn, 30, 21) << 1
+                | bits(insn, 20, 20) << 11
+                | bits(insn, 19, 12) << 12;
+            [Some(pc.wrapping_add(sign_extend(imm, 21))), None]
+        }
+        // jalr
+        0x67 => {
+            let target = reg(bits(insn, 19, 15)).wrapping_add(sign_extend(bits(insn, 31, 20), 12));
+            [Some(target & !1), None]
+        }
+        // beq, bne, blt, bge, bltu, bgeu
+        0x63 => {
+            let imm = bits(insn, 31, 31) << 12
+                | bits(insn, 30, 25) << 5
+                | bits(insn, 11, 8) << 1
+                | bits(insn, 7, 7) << 11;
+            [Some(next), Some(pc.wrapping_add(sign_extend(imm, 13)))]
+        }
+        _ => [Some(next), None],
+    }
+}
+
+/// 将 PTRACE_PEEKUSER 与 PTRACE_POKEUSER 中 `struct user_regs_struct` 内的偏移转换为寄存器的下标
+fn user_reg_index(offset: usize) -> AlienResult<usize> {
+    if offset % size_of::<usize>() != 0 || offset >= USER_REGS_NUM * size_of::<usize>() {
+        return Err(LinuxErrno::EIO);
+    }
+    Ok(offset / size_of::<usize>())
+}
+
+/// PTRACE_GETREGSET 与 PTRACE_SETREGSET，目前只支持通用寄存器(NT_PRSTATUS)。
+///
+/// `iov` 指向用户的 `struct iovec`，读写的寄存器个数受其长度限制，完成后其长度被更新为实际读写的字节数。
+fn access_regset(
+    task: &Arc<Task>,
+    tracee: &Arc<Task>,
+    kind: usize,
+    iov: usize,
+    set: bool,
+) -> AlienResult<()> {
+    if kind != NT_PRSTATUS {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let [base, len] = task.read_user(UserPtr::<[usize; 2]>::new(iov))?;
+    let count = min(len / size_of::<usize>(), USER_REGS_NUM);
+    let frame = tracee.trap_frame();
+    let mut regs = frame.user_regs();
+    if set {
+        let values = task.read_user_slice(UserSlice::<usize>::new(base, count))?;
+        regs[..count].copy_from_slice(&values);
+        frame.set_user_regs(&regs);
+    } else {
+        task.write_user_slice(UserSlice::new(base, count), &regs[..count])?;
+    }
+    // 更新 iov_len
+    task.write_user(
+        UserPtr::<usize>::new(iov + size_of::<usize>()),
+        &(count * size_of::<usize>()),
+    )
+}
+
+/// 一个系统调用，用于跟踪与控制另一个线程，供调试器使用。
+///
+/// `request` 指明要进行的操作，`pid` 为被跟踪线程的线程号，`addr` 与 `data` 的含义由 `request` 决定。
+/// 目前支持的操作包括：
+/// + `PTRACE_TRACEME`：由父进程跟踪当前线程；
+/// + `PTRACE_ATTACH` / `PTRACE_SEIZE`：跟踪线程 `pid`，前者同时向其发送 SIGSTOP，后者以 `data` 为选项；
+/// + `PTRACE_PEEKTEXT` / `PTRACE_PEEKDATA` / `PTRACE_PEEKUSER`：读取被跟踪线程 `addr` 处的内存或 `struct user_regs_struct` 中偏移为 `addr` 的寄存器，结果写入 `data` 指向的位置；
+/// + `PTRACE_POKETEXT` / `PTRACE_POKEDATA` / `PTRACE_POKEUSER`：将 `data` 写入相应的内存或寄存器；
+/// + `PTRACE_GETREGS` / `PTRACE_SETREGS`：读写 `data` 指向的 `struct user_regs_struct`；
+/// + `PTRACE_GETREGSET` / `PTRACE_SETREGSET`：以 `addr` 为类型(只支持 `NT_PRSTATUS`)读写 `data` 指向的 `struct iovec` 所描述的缓冲区；
+/// + `PTRACE_SETOPTIONS`：设置选项，支持 `PTRACE_O_TRACESYSGOOD`、`PTRACE_O_TRACEEXEC` 与 `PTRACE_O_EXITKILL`；
+/// + `PTRACE_CONT` / `PTRACE_SYSCALL` / `PTRACE_SINGLESTEP`：恢复被跟踪线程的运行并递送信号 `data`；
+/// + `PTRACE_KILL`：向被跟踪线程发送 SIGKILL；
+/// + `PTRACE_DETACH`：停止跟踪，恢复被跟踪线程的运行并递送信号 `data`。
+///
+/// 除 `PTRACE_TRACEME`、`PTRACE_ATTACH`、`PTRACE_SEIZE` 与 `PTRACE_KILL` 外，被跟踪线程必须处于 ptrace 停止状态，否则返回 `ESRCH`。
+/// 没有跟踪权限时返回 `EPERM`，访问被跟踪线程的内存失败或者操作不支持时返回 `EIO`。
+///
+/// Reference: [ptrace](https://man7.org/linux/man-pages/man2/ptrace.2.html)
+#[syscall_func(117)]
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    if request == PTRACE_TRACEME {
+        let parent = task
+            .access_inner()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .ok_or(LinuxErrno::EPERM)?;
+        if task.access_inner().ptrace.is_traced() {
+            return Err(LinuxErrno::EPERM);
+        }
+        link(&parent, task, 0);
+        return Ok(0);
+    }
+    let pid = pid_from_user(pid).ok_or(LinuxErrno::ESRCH)?;
+    let tracee = find_task(pid).ok_or(LinuxErrno::ESRCH)?;
+    match request {
+        PTRACE_ATTACH => {
+            attach(task, &tracee, 0)?;
+            send_signal(pid, SignalNumber::SIGSTOP as usize);
+            return Ok(0);
+        }
+        PTRACE_SEIZE => {
+            if addr != 0 {
+                return Err(LinuxErrno::EIO);
+            }
+            attach(task, &tracee, check_options(data)?)?;
+            return Ok(0);
+        }
+        _ => {}
+    }
+    if !is_tracer_of(task, &tracee) {
+        return Err(LinuxErrno::ESRCH);
+    }
+    if request == PTRACE_KILL {
+        send_signal(pid, SignalNumber::SIGKILL as usize);
+        let _ = resume_tracee(&tracee, PtraceResume::Cont, 0);
+        return Ok(0);
+    }
+    if tracee.state() != TaskState::Traced {
+        return Err(LinuxErrno::ESRCH);
+    }
+    match request {
+        PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+            let value = tracee
+                .access_inner()
+                .read_user(UserPtr::<usize>::new(addr))
+                .map_err(|_| LinuxErrno::EIO)?;
+            task.write_user(UserPtr::new(data), &value)?;
+        }
+        PTRACE_PEEKUSER => {
+            let value = tracee.trap_frame().user_regs()[user_reg_index(addr)?];
+            task.write_user(UserPtr::new(data), &value)?;
+        }
+        PTRACE_POKETEXT | PTRACE_POKEDATA => {
+            poke_bytes(&mut tracee.access_inner(), addr, &data.to_le_bytes())
+                .map_err(|_| LinuxErrno::EIO)?;
+        }
+        PTRACE_POKEUSER => {
+            let index = user_reg_index(addr)?;
+            let frame = tracee.trap_frame();
+            let mut regs = frame.user_regs();
+            regs[index] = data;
+            frame.set_user_regs(&regs);
+        }
+        PTRACE_GETREGS => {
+            let regs = tracee.trap_frame().user_regs();
+            task.write_user(UserPtr::new(data), &regs)?;
+        }
+        PTRACE_SETREGS => {
+            let regs = task.read_user(UserPtr::<[usize; USER_REGS_NUM]>::new(data))?;
+            tracee.trap_frame().set_user_regs(&regs);
+        }
+        PTRACE_GETREGSET => access_regset(task, &tracee, addr, data, false)?,
+        PTRACE_SETREGSET => access_regset(task, &tracee, addr, data, true)?,
+        PTRACE_SETOPTIONS => tracee.access_inner().ptrace.options = check_options(data)?,
+        PTRACE_CONT => resume_tracee(&tracee, PtraceResume::Cont, data)?,
+        PTRACE_SYSCALL => resume_tracee(&tracee, PtraceResume::Syscall, data)?,
+        PTRACE_SINGLESTEP => resume_tracee(&tracee, PtraceResume::SingleStep, data)?,
+        PTRACE_DETACH => {
+            untrace(task, &tracee);
+            detach(&tracee, data);
+        }
+        _ => return Err(LinuxErrno::EIO),
+    }
+    Ok(0)
+}
@@ -1,4 +1,5 @@
-use config::MAX_THREAD_NUM;
+use config::{MAX_FD_NUM, MAX_THREAD_NUM};
+use constants::{LinuxErrno, PrLimit};
 use ksync::Mutex;
 use small_index::IndexAllocator;
 use spin::Lazy;
@@ -53,3 +54,111 @@ impl HeapInfo {
         addr >= self.start && addr < self.end
     }
 }
+
+/// 资源限制的种类数
+pub const RLIM_NLIMITS: usize = 16;
+/// 表示对资源不加限制
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// 可以通过 prlimit64 设置的资源种类
+///
+/// Reference: <https://man7.org/linux/man-pages/man2/prlimit.2.html>
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RLimitRes {
+    /// 进程可以使用的 CPU 时间(秒)
+    Cpu = 0,
+    /// 进程可以创建的文件的最大大小
+    Fsize = 1,
+    /// 进程数据段(堆与私有可写映射)的最大大小
+    Data = 2,
+    /// 进程栈的最大大小
+    Stack = 3,
+    /// core dump 文件的最大大小
+    Core = 4,
+    /// 驻留内存的最大大小(未使用)
+    Rss = 5,
+    /// 可以创建的最大任务数
+    Nproc = 6,
+    /// 可以打开的最大文件描述符数 + 1
+    Nofile = 7,
+    /// 可以锁定在内存中的最大字节数
+    Memlock = 8,
+    /// 进程虚拟地址空间的最大大小
+    As = 9,
+    Locks = 10,
+    Sigpending = 11,
+    Msgqueue = 12,
+    Nice = 13,
+    Rtprio = 14,
+    Rttime = 15,
+}
+
+impl TryFrom<usize> for RLimitRes {
+    type Error = LinuxErrno;
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        let res = match value {
+            0 => RLimitRes::Cpu,
+            1 => RLimitRes::Fsize,
+            2 => RLimitRes::Data,
+            3 => RLimitRes::Stack,
+            4 => RLimitRes::Core,
+            5 => RLimitRes::Rss,
+            6 => RLimitRes::Nproc,
+            7 => RLimitRes::Nofile,
+            8 => RLimitRes::Memlock,
+            9 => RLimitRes::As,
+            10 => RLimitRes::Locks,
+            11 => RLimitRes::Sigpending,
+            12 => RLimitRes::Msgqueue,
+            13 => RLimitRes::Nice,
+            14 => RLimitRes::Rtprio,
+            15 => RLimitRes::Rttime,
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        Ok(res)
+    }
+}
+
+/// 记录进程的各项资源限制，每一项为 (软限制, 硬限制)。fork 时被复制，exec 时保持不变
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    limits: [(u64, u64); RLIM_NLIMITS],
+}
+
+impl ResourceLimits {
+    /// 创建一组默认的资源限制
+    pub fn new() -> Self {
+        let mut limits = [(RLIM_INFINITY, RLIM_INFINITY); RLIM_NLIMITS];
+        limits[RLimitRes::Stack as usize] = (8 * 1024 * 1024, RLIM_INFINITY);
+        limits[RLimitRes::Core as usize] = (0, RLIM_INFINITY);
+        limits[RLimitRes::Nproc as usize] = (MAX_THREAD_NUM as u64, MAX_THREAD_NUM as u64);
+        limits[RLimitRes::Nofile as usize] = (MAX_FD_NUM as u64, MAX_FD_NUM as u64);
+        limits[RLimitRes::Memlock as usize] = (8 * 1024 * 1024, 8 * 1024 * 1024);
+        limits[RLimitRes::Sigpending as usize] = (MAX_THREAD_NUM as u64, MAX_THREAD_NUM as u64);
+        limits[RLimitRes::Msgqueue as usize] = (819200, 819200);
+        limits[RLimitRes::Nice as usize] = (0, 0);
+        limits[RLimitRes::Rtprio as usize] = (0, 0);
+        Self { limits }
+    }
+
+    /// 获取某项资源的限制
+    pub fn get(&self, resource: RLimitRes) -> PrLimit {
+        let (cur, max) = self.limits[resource as usize];
+        PrLimit::new(cur, max)
+    }
+
+    /// 设置某项资源的限制
+    pub fn set(&mut self, resource: RLimitRes, limit: &PrLimit) {
+        self.limits[resource as usize] = (limit.rlim_cur, limit.rlim_max);
+    }
+
+    /// 获取某项资源的软限制
+    pub fn cur(&self, resource: RLimitRes) -> u64 {
+        self.limits[resource as usize].0
+    }
+
+    /// 获取某项资源的硬限制
+    pub fn max(&self, resource: RLimitRes) -> u64 {
+        self.limits[resource as usize].1
+    }
+}
@@ -6,17 +6,20 @@ use constants::signal::SignalNumber;
 use smpscheduler::FifoTask;
 
 use crate::{
-    ipc::send_signal,
+    ipc::{
+        send_process_signal_info,
+        sigqueue::{SignalInfo, CLD_DUMPED, CLD_EXITED, CLD_KILLED},
+    },
     task::{
-        context::switch, cpu::current_cpu, take_current_task, task::TaskState, Task,
-        GLOBAL_TASK_MANAGER,
+        context::switch, cpu::current_cpu, pid_for_receiver, take_current_task, task::TaskState,
+        Task, GLOBAL_TASK_MANAGER,
     },
 };
 
 /// 在 CPU 启动并初始化完毕后初次进入用户态时，或者在一个任务将要让渡 CPU 时 将会执行该函数。
 ///
 /// 如果当前 CPU 上有任务正在执行，那么将根据该任务当前的状态进行操作。
-/// - 如果该任务处于睡眠或等待状态，将会把其任务的控制块取出丢弃掉。
+/// - 如果该任务处于睡眠、等待或 ptrace 停止状态，将会把其任务的控制块取出丢弃掉。
 /// - 如果该任务处于僵尸状态，将会向其父进程发送信号，令其回收该任务的控制块。
 /// - 如果该任务处于其他状态，我们将其放入线程池中等待下一次分配。
 ///
@@ -52,20 +55,31 @@ pub fn schedule() {
 pub fn schedule_now(task: Arc<Task>) {
     let context = task.get_context_mut_raw_ptr();
     match task.state() {
-        TaskState::Waiting => {
+        TaskState::Waiting | TaskState::Traced => {
             drop(task);
         }
         TaskState::Zombie => {
             // 退出时向父进程发送信号，其中选项可被 sys_clone 控制
+            // 信号中携带子进程在父进程的 pid 命名空间中的 pid、真实用户 id 以及退出码
             if task.send_sigchld_when_exit || task.pid == task.tid.0 {
-                let parent = task
-                    .access_inner()
-                    .parent
-                    .as_ref()
-                    .unwrap()
-                    .upgrade()
-                    .unwrap();
-                send_signal(parent.pid, SignalNumber::SIGCHLD as usize);
+                let inner = task.access_inner();
+                let parent = inner.parent.as_ref().unwrap().upgrade().unwrap();
+                // 被信号终止时退出码的低 7 位为信号，0x80 表示生成了 core 文件
+                let exit_code = inner.exit_code;
+                let (code, status) = match exit_code & 0x7f {
+                    0 => (CLD_EXITED, (exit_code >> 8) & 0xff),
+                    signum if exit_code & 0x80 != 0 => (CLD_DUMPED, signum),
+                    signum => (CLD_KILLED, signum),
+                };
+                let info = SignalInfo::child(
+                    SignalNumber::SIGCHLD as usize,
+                    code,
+                    pid_for_receiver(&parent, task.pid),
+                    inner.cred.ruid,
+                    status,
+                );
+                drop(inner);
+                let _ = send_process_signal_info(parent.pid, info);
             }
             task.terminate(); // release some resources
         }
@@ -12,9 +12,12 @@ pub struct Stack {
 }
 
 impl Stack {
-    /// 通过帧的个数创建一块新的 内核栈
+    /// 通过帧的个数创建一块新的 内核栈，物理页不足时返回 `None`
     pub fn new(pages: usize) -> Option<Stack> {
         let frames = alloc_frames(pages);
+        if frames.is_null() {
+            return None;
+        }
         Some(Stack {
             start_ptr: frames as usize,
             pages,
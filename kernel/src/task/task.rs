@@ -12,8 +12,11 @@ use alloc::{
     vec::Vec,
 };
 use core::{
+    cmp::{max, min},
     fmt::{Debug, Formatter},
+    mem::{size_of, MaybeUninit},
     ops::Range,
+    sync::atomic::AtomicBool,
 };
 
 use bit_field::BitField;
@@ -26,32 +29,57 @@ use constants::{
     sys::TimeVal,
     task::CloneFlags,
     time::TimerType,
-    AlienError, AlienResult, LinuxErrno, PrLimit, PrLimitRes,
+    AlienError, AlienResult, LinuxErrno, PrLimit,
 };
 use gmanager::MinimalManager;
 use ksync::{Mutex, MutexGuard};
 use mem::{kernel_satp, VmmPageAllocator, FRAME_REF_MANAGER};
 use page_table::{
-    addr::{align_down_4k, align_up_4k, VirtAddr},
+    addr::{align_down_4k, align_up_4k, PhysAddr, VirtAddr},
     pte::MappingFlags,
     table::Sv39PageTable,
 };
+use platform::config::CLOCK_FREQ;
 use timer::{read_timer, ITimerVal, TimeNow, ToClock};
-use vfs::kfile::File;
-use vfscore::dentry::VfsDentry;
+use vfs::{
+    kfile::{File, KernelFile},
+    page_cache::PageCache,
+};
+use vfscore::{dentry::VfsDentry, utils::VfsNodeType};
 
 use crate::{
     fs::stdio::{STDIN, STDOUT},
-    ipc::{global_register_signals, ShmInfo},
+    ipc::{global_register_signals, ShmInfo, SignalStack},
     mm::{
+        aslr::VaLayout,
+        elf::ELFError,
         loader::{
             build_cow_address_space, build_elf_address_space, build_thread_address_space, UserStack,
         },
-        map::{MMapInfo, MMapRegion, ProtFlags},
+        map::{
+            MMapInfo, MMapRegion, MadviseAdvice, MremapFlags, ProtFlags, GROWSDOWN_GAP,
+            MAP_FIXED_NOREPLACE, MAP_GROWSDOWN,
+        },
+        mlock::{lock_range, locked_bytes, move_locked_range, unlock_range, MlockallFlags},
+        oom::register_address_space,
+        stat::MemUsage,
+        swap::{
+            fork_swapped_pages, move_swapped_pages, release_swapped_pages, swap_in, swapped_pages,
+            track_anon_page,
+        },
+        thp::{
+            huge_page_enabled, map_huge_page, release_all_huge_pages, release_huge_pages,
+            split_huge_page, split_huge_pages,
+        },
+        uaccess::{UserBuffers, UserPtr, UserRef, UserSlice},
     },
+    random::get_random_bytes,
     task::{
         context::Context,
-        resource::{HeapInfo, TidHandle},
+        cred::{Capabilities, Credentials},
+        namespace::{Namespaces, PidHandle, PidNamespace, INIT_NAMESPACES, ROOT_PID_NAMESPACE},
+        ptrace::PtraceState,
+        resource::{HeapInfo, RLimitRes, ResourceLimits, TidHandle, RLIM_INFINITY},
         stack::Stack,
     },
     trap::{trap_common_read_file, trap_return, user_trap_vector, TrapFrame},
@@ -61,6 +89,8 @@ type FdManager = MinimalManager<Arc<dyn File>>;
 
 #[derive(Debug)]
 pub struct Task {
+    /// 任务在其所在的 pid 命名空间中的 pid，需要在 tid 被回收之前释放
+    pub ns_pid: PidHandle,
     /// 任务的唯一标识
     pub tid: TidHandle,
     /// 作为进程时，pid == tid；作为线程时，pid 为其线程组 leader (父进程)的 tid 号。
@@ -134,8 +164,26 @@ pub struct TaskInner {
     pub unmask: usize,
     /// 栈空间的信息
     pub stack: Range<usize>,
+    /// 进程的资源限制
+    pub resource_limits: ResourceLimits,
+    /// 用户凭证
+    pub cred: Credentials,
     /// 是否需要等待
     pub need_wait: u8,
+    /// 通过 mlockall 设置的标志，包含 `MCL_FUTURE` 时之后创建的映射也被锁定
+    pub mlockall: MlockallFlags,
+    /// 通过 sigaltstack 设置的信号处理备用栈
+    pub sig_alt_stack: SignalStack,
+    /// 通过 personality 设置的执行域，fork 与 execve 后保留
+    pub personality: u32,
+    /// execve 时放入用户栈的辅助向量，不包含结尾的 `AT_NULL`
+    pub auxv: Vec<(usize, usize)>,
+    /// ptrace 的跟踪状态，fork 时不继承
+    pub ptrace: PtraceState,
+    /// 以 CLONE_VFORK 创建时，父进程等待的完成标志，子进程执行新程序或退出时设置
+    pub vfork_done: Option<Arc<AtomicBool>>,
+    /// 任务所在的命名空间
+    pub ns: Namespaces,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -194,6 +242,8 @@ pub struct StatisticalData {
 
     pub tms_cutime: usize,
     pub tms_cstime: usize,
+    /// The cpu time when SIGXCPU was sent last time. --ticks
+    pub xcpu_time: usize,
 }
 
 impl StatisticalData {
@@ -207,18 +257,9 @@ impl StatisticalData {
             last_stime: now,
             tms_cutime: 0,
             tms_cstime: 0,
+            xcpu_time: 0,
         }
     }
-    /// 清除当前 `StatisticalData` 结构中储存的数据，并将 `last_utime` 和 `last_stime` 的值置为 当前的时间
-    pub fn clear(&mut self) {
-        let now = read_timer();
-        self.tms_utime = 0;
-        self.tms_stime = 0;
-        self.last_utime = now;
-        self.last_stime = now;
-        self.tms_cutime = 0;
-        self.tms_cstime = 0;
-    }
 }
 
 #[derive(Clone)]
@@ -257,6 +298,8 @@ pub enum TaskState {
     // Sleeping,
     /// 等待一个事件
     Waiting,
+    /// 被 ptrace 跟踪的任务停止，等待跟踪者恢复其运行
+    Traced,
     /// 僵尸态，等待父进程回收资源
     Zombie,
     /// 终止态
@@ -273,7 +316,9 @@ impl Task {
             if let Some(parent) = parent {
                 let parent = parent.upgrade();
                 if let Some(parent) = parent {
-                    parent.remove_child_by_tid(self.get_tid());
+                    if parent.remove_child_by_tid(self.get_tid()).is_some() {
+                        self.access_inner().cred.dec_tasks();
+                    }
                     assert_eq!(Arc::strong_count(&self), 1);
                 }
             }
@@ -282,6 +327,11 @@ impl Task {
         self.access_inner().state = TaskState::Terminated;
     }
 
+    /// 获取任务所在的 pid 命名空间
+    pub fn pid_namespace(&self) -> &Arc<PidNamespace> {
+        self.ns_pid.ns()
+    }
+
     /// 获取进程的 pid 号
     #[inline]
     pub fn get_pid(&self) -> isize {
@@ -464,73 +514,39 @@ impl Task {
         Ok(file)
     }
 
-    /// 获取一个虚拟地址 `ptr` 的实际物理地址
-    pub fn transfer_raw(&self, ptr: usize) -> usize {
-        self.access_inner().transfer_raw(ptr)
+    /// 从用户地址空间的 `ptr` 处读取一个以 '\0' 结尾、长度不超过 `limit` 的字符串，见 [`TaskInner::strncpy_from_user`]。
+    pub fn strncpy_from_user(&self, ptr: *const u8, limit: usize) -> AlienResult<String> {
+        self.access_inner().strncpy_from_user(ptr as usize, limit)
     }
 
-    /// 获取一个虚拟地址 `ptr` 对应的 T 类型数据 的 可变引用
-    pub fn transfer_raw_ptr<T>(&self, ptr: *mut T) -> &'static mut T {
-        self.access_inner().transfer_raw_ptr_mut(ptr)
+    /// 读取用户地址空间中 `ptr` 处的 T 类型数据，见 [`TaskInner::read_user`]
+    pub fn read_user<T: Copy>(&self, ptr: UserPtr<T>) -> AlienResult<T> {
+        self.access_inner().read_user(ptr)
     }
 
-    /// 通过用户地址空间中一个字符串的首指针 `ptr`，获取一个字符串。
-    pub fn transfer_str(&self, ptr: *const u8) -> String {
-        // we need check the ptr and len before transfer indeed
-        let mut start = ptr as usize;
-        let end = start + 128; //todo! string len is unknown
-        let address_space = self.access_inner().address_space.clone();
-        while start < end {
-            let (_phy, flag, _) = address_space
-                .lock()
-                .query(VirtAddr::from(start))
-                .expect(format!("transfer_buffer: {:x} failed", start).as_str());
-            if !flag.contains(MappingFlags::V) {
-                error!("transfer_str flag: {:?}, addr:{:#x}", flag, start);
-                let res = self
-                    .access_inner()
-                    .invalid_page_solver(align_down_4k(start))
-                    .unwrap();
-                if res.is_some() {
-                    let (file, buf, offset) = res.unwrap();
-                    if file.is_some() {
-                        trap_common_read_file(file.unwrap(), buf, offset);
-                    }
-                }
-            }
-            start += FRAME_SIZE;
-        }
-        self.access_inner().transfer_str(ptr)
+    /// 将 `value` 写入用户地址空间中的 `ptr` 处，见 [`TaskInner::write_user`]
+    pub fn write_user<T: Copy>(&self, ptr: UserPtr<T>, value: &T) -> AlienResult<()> {
+        self.access_inner().write_user(ptr, value)
     }
 
-    /// 通过用户地址空间中一个缓冲区的指针 `ptr` 和 缓冲区的长度 `len`，得到一组对用户地址空间中缓冲区的可变引用，每一组引用的长度为 4K
-    pub fn transfer_buffer<T: Debug>(&self, ptr: *const T, len: usize) -> Vec<&'static mut [T]> {
-        // we need check the ptr and len before transfer indeed
-        let mut start = ptr as usize;
-        let end = start + len;
-        let address_space = self.access_inner().address_space.clone();
-        start = align_down_4k(start);
-        while start < end {
-            let (_phy, flag, _) = address_space
-                .lock()
-                .query(VirtAddr::from(start))
-                .expect(format!("transfer_buffer: {:x} failed", start).as_str());
-            if !flag.contains(MappingFlags::V) {
-                error!("transfer_buffer flag: {:?}, addr:{:#x}", flag, start);
-                let res = self
-                    .access_inner()
-                    .invalid_page_solver(align_down_4k(start))
-                    .unwrap();
-                if res.is_some() {
-                    let (file, buf, offset) = res.unwrap();
-                    if file.is_some() {
-                        trap_common_read_file(file.unwrap(), buf, offset);
-                    }
-                }
-            }
-            start += FRAME_SIZE;
-        }
-        self.access_inner().transfer_buffer(ptr, len)
+    /// 读取用户地址空间中的缓冲区 `slice`，见 [`TaskInner::read_user_slice`]
+    pub fn read_user_slice<T: Copy>(&self, slice: UserSlice<T>) -> AlienResult<Vec<T>> {
+        self.access_inner().read_user_slice(slice)
+    }
+
+    /// 将 `data` 写入用户地址空间中的缓冲区 `slice`，见 [`TaskInner::write_user_slice`]
+    pub fn write_user_slice<T: Copy>(&self, slice: UserSlice<T>, data: &[T]) -> AlienResult<()> {
+        self.access_inner().write_user_slice(slice, data)
+    }
+
+    /// 获取用户地址空间中的缓冲区 `slice` 对应的一组只读片段，见 [`TaskInner::readable_buffers`]
+    pub fn readable_buffers(&self, slice: UserSlice<u8>) -> AlienResult<UserBuffers<false>> {
+        self.access_inner().readable_buffers(slice)
+    }
+
+    /// 获取用户地址空间中的缓冲区 `slice` 对应的一组可写片段，见 [`TaskInner::writable_buffers`]
+    pub fn writable_buffers(&self, slice: UserSlice<u8>) -> AlienResult<UserBuffers<true>> {
+        self.access_inner().writable_buffers(slice)
     }
 }
 
@@ -546,27 +562,221 @@ impl TaskInner {
     }
 
     /// 获取当前进程对于资源的限制
-    pub fn get_prlimit(&self, resource: PrLimitRes) -> PrLimit {
-        match resource {
-            PrLimitRes::RlimitStack => PrLimit::new(USER_STACK_SIZE as u64, USER_STACK_SIZE as u64),
-            PrLimitRes::RlimitNofile => {
-                let max_fd = self.fd_table.lock().max();
-                PrLimit::new(max_fd as u64, max_fd as u64)
+    pub fn get_prlimit(&self, resource: RLimitRes) -> PrLimit {
+        self.resource_limits.get(resource)
+    }
+
+    /// 设置当前进程对于资源的限制
+    ///
+    /// 软限制不能超过硬限制；对于 `RLIMIT_NOFILE`，硬限制不能超过系统支持的最大描述符数 [`MAX_FD_NUM`]。
+    pub fn set_prlimit(&mut self, resource: RLimitRes, value: PrLimit) -> AlienResult<()> {
+        if value.rlim_cur > value.rlim_max {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if resource == RLimitRes::Nofile {
+            if value.rlim_max > MAX_FD_NUM as u64 {
+                return Err(LinuxErrno::EPERM);
             }
-            PrLimitRes::RlimitAs => PrLimit::new(u64::MAX, u64::MAX),
+            self.fd_table.lock().set_max(value.rlim_cur as usize);
         }
+        self.resource_limits.set(resource, &value);
+        Ok(())
     }
 
-    /// 设置当前进程对于资源的限制
-    pub fn set_prlimit(&mut self, resource: PrLimitRes, value: PrLimit) {
-        match resource {
-            PrLimitRes::RlimitStack => {}
-            PrLimitRes::RlimitNofile => {
-                let new_max_fd = value.rlim_cur;
-                self.fd_table.lock().set_max(new_max_fd as usize);
+    /// 检查进程使用的 CPU 时间是否超出了 `RLIMIT_CPU` 的限制。
+    ///
+    /// 超出硬限制时返回 SIGKILL；超出软限制后，每多使用一秒 CPU 时间返回一次 SIGXCPU。
+    pub fn check_cpu_limit(&mut self) -> Option<SignalNumber> {
+        let soft = self.resource_limits.cur(RLimitRes::Cpu);
+        let hard = self.resource_limits.max(RLimitRes::Cpu);
+        if soft == RLIM_INFINITY && hard == RLIM_INFINITY {
+            return None;
+        }
+        let data = &mut self.statistical_data;
+        let used = data.tms_utime + data.tms_stime;
+        if hard != RLIM_INFINITY && (used / CLOCK_FREQ) as u64 >= hard {
+            return Some(SignalNumber::SIGKILL);
+        }
+        if soft != RLIM_INFINITY && (used / CLOCK_FREQ) as u64 >= soft {
+            if data.xcpu_time == 0 || used - data.xcpu_time >= CLOCK_FREQ {
+                data.xcpu_time = used;
+                return Some(SignalNumber::SIGXCPU);
+            }
+        }
+        None
+    }
+
+    /// 计算进程地址空间中堆、内存映射区与栈的总大小，用于 `RLIMIT_AS` 的检查
+    fn vm_size(&self) -> usize {
+        let heap = self.heap.lock();
+        let mmap_size: usize = self.mmap.regions().map(|region| region.map_len).sum();
+        (heap.end - heap.start) + mmap_size + (self.stack.end - self.stack.start)
+    }
+
+    /// 计算进程数据段的大小(堆与私有可写映射)，用于 `RLIMIT_DATA` 的检查
+    fn data_size(&self) -> usize {
+        let heap = self.heap.lock();
+        let mmap_size: usize = self
+            .mmap
+            .regions()
+            .filter(|region| {
+                region.prot.contains(ProtFlags::PROT_WRITE)
+                    && !region.flags.contains(MapFlags::MAP_SHARED)
+            })
+            .map(|region| region.map_len)
+            .sum();
+        (heap.end - heap.start) + mmap_size
+    }
+
+    /// 统计进程的内存使用情况，见 [`MemUsage`]
+    ///
+    /// 页表中有效且用户态可访问的页计入驻留集，页表拥有的页为匿名页，代码段等只读的 ELF 段除外；
+    /// 页表不拥有的页来自页缓存、共享内存或透明大页，根据其所在的区域分类。
+    pub fn mem_usage(&self) -> MemUsage {
+        let heap = self.heap.lock();
+        let heap_range = heap.start..heap.end;
+        drop(heap);
+        let mut usage = MemUsage {
+            size: self.vm_size(),
+            data: self.data_size(),
+            stack: self.stack.len(),
+            locked: locked_bytes(&self.address_space, 0..usize::MAX),
+            swap: swapped_pages(&self.address_space),
+            ..MemUsage::default()
+        };
+        let mut space = self.address_space.lock();
+        let records = space
+            .get_record_mut()
+            .iter()
+            .map(|(addr, owned)| (*addr, *owned))
+            .collect::<Vec<_>>();
+        for (addr, owned) in records {
+            let (flags, pages) = match space.query(addr) {
+                Ok((_, flags, size)) => (flags, usize::from(size) / FRAME_SIZE),
+                Err(_) => continue,
+            };
+            if !flags.contains(MappingFlags::U) {
+                continue;
+            }
+            let addr = addr.as_usize();
+            let region = self.mmap.get_region(addr);
+            let private = heap_range.contains(&addr) || self.stack.contains(&addr);
+            // ELF 段在加载时全部映射，不属于任何区域
+            let elf = owned && region.is_none() && !private;
+            if elf {
+                usage.size += pages * FRAME_SIZE;
+                if flags.contains(MappingFlags::W) {
+                    usage.data += pages * FRAME_SIZE;
+                } else if flags.contains(MappingFlags::X) {
+                    usage.text += pages * FRAME_SIZE;
+                }
+            }
+            if !flags.contains(MappingFlags::V) {
+                continue;
+            }
+            let counter = match region {
+                _ if elf && !flags.contains(MappingFlags::W) => &mut usage.file,
+                // 页表拥有的页都是进程私有的副本
+                _ if owned || private => &mut usage.anon,
+                Some(region) if region.fd.is_some() => &mut usage.file,
+                // 私有匿名映射中的透明大页
+                Some(region) if !region.flags.contains(MapFlags::MAP_SHARED) => &mut usage.anon,
+                _ => &mut usage.shmem,
+            };
+            *counter += pages;
+        }
+        usage
+    }
+
+    /// 检查在地址空间中新增 `len` 字节后是否会超出 `RLIMIT_AS` 的限制，
+    /// 如果 `is_data` 为真，还需要检查是否会超出 `RLIMIT_DATA` 的限制
+    fn check_vm_limit(&self, len: usize, is_data: bool) -> AlienResult<()> {
+        let as_limit = self.resource_limits.cur(RLimitRes::As);
+        if as_limit != RLIM_INFINITY && (self.vm_size() + len) as u64 > as_limit {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        let data_limit = self.resource_limits.cur(RLimitRes::Data);
+        if is_data && data_limit != RLIM_INFINITY && (self.data_size() + len) as u64 > data_limit {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        Ok(())
+    }
+
+    /// 尝试向下拓展用户栈，使其包含地址 `addr`。
+    ///
+    /// 栈的总大小不能超过 `RLIMIT_STACK` 的软限制与 [`USER_STACK_MAX_SIZE`] 中的较小值，
+    /// 且拓展的部分不能与堆或已有的内存映射区重叠，栈的下方还需保留一个空闲的保护页。
+    fn grow_stack(&mut self, addr: usize) -> AlienResult<()> {
+        let addr = align_down_4k(addr);
+        if addr >= self.stack.start {
+            return Ok(());
+        }
+        let limit = self.resource_limits.cur(RLimitRes::Stack);
+        let limit = core::cmp::min(limit, USER_STACK_MAX_SIZE as u64) as usize;
+        if self.stack.end - addr > limit {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        // 栈的下方始终保留一个空闲页作为保护页，防止栈与堆或其它映射区域相接
+        let guard = addr.saturating_sub(FRAME_SIZE);
+        if addr < FRAME_SIZE || guard < self.heap.lock().end {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        if self.mmap.first_overlap(guard..self.stack.start).is_some() {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        self.check_vm_limit(self.stack.start - addr, false)?;
+        trace!("grow user stack: {:#x} -> {:#x}", self.stack.start, addr);
+        self.address_space
+            .lock()
+            .map_region_no_target(
+                VirtAddr::from(addr),
+                self.stack.start - addr,
+                "RWUAD".into(), // no V flag
+                false,
+                true,
+            )
+            .map_err(|_| LinuxErrno::ENOMEM)?;
+        self.stack.start = addr;
+        Ok(())
+    }
+
+    /// 判断缺页地址 `addr` 是否位于用户栈下方为其预留的拓展区域中
+    fn in_stack_gap(&self, addr: usize) -> bool {
+        addr < self.stack.start
+            && addr >= self.stack.end.saturating_sub(USER_STACK_MAX_SIZE)
+            && self.mmap.get_region(addr).is_none()
+    }
+
+    /// 向下拓展 `addr` 之上最近的 `MAP_GROWSDOWN` 映射区域，使其覆盖 `addr` 所在的页
+    ///
+    /// `addr` 与该区域的距离不能超过 [`GROWSDOWN_GAP`]，拓展的部分不能与其它映射重叠，且该区域下方始终保留一个空闲页作为保护页。
+    fn grow_mmap_down(&mut self, addr: usize) -> bool {
+        let addr = align_down_4k(addr);
+        let region = match self.mmap.next_region(addr) {
+            Some(region) if region.grows_down && region.start - addr <= GROWSDOWN_GAP => {
+                region.clone()
             }
-            PrLimitRes::RlimitAs => {}
+            _ => return false,
+        };
+        if addr < FRAME_SIZE || !self.range_free(addr - FRAME_SIZE..region.start) {
+            return false;
         }
+        let is_data = region.prot.contains(ProtFlags::PROT_WRITE);
+        if self.check_vm_limit(region.start - addr, is_data).is_err() {
+            return false;
+        }
+        if self.map_lazy(&region, addr..region.start).is_err() {
+            return false;
+        }
+        trace!("grow mmap region: {:#x} -> {:#x}", region.start, addr);
+        self.mmap.remove_region(region.start);
+        let mut region = region;
+        let grow = region.start - addr;
+        region.start = addr;
+        region.len += grow;
+        region.map_len += grow;
+        self.mmap.add_region(region);
+        true
     }
 
     /// 返回 trap 上下文的一个可变指针
@@ -604,13 +814,12 @@ impl TaskInner {
             // 这里假定是 sigreturn 触发的，即用户的信号处理函数 return 了(cancel_handler)
             // 也就是说信号触发时的 sp 就是现在的 sp
             let sp = trap_frame.regs()[2];
-            // 获取可能被修改的 pc
-            let phy_sp = self.transfer_raw(sp);
-
-            let pc = unsafe { (*(phy_sp as *const SignalUserContext)).get_pc() };
+            // 获取可能被修改的 pc，用户栈上的上下文已经无法访问时保持原来的 pc
+            let context = self.read_user(UserPtr::<SignalUserContext>::new(sp));
             *trap_frame = old_trap_frame;
-            if self.signal_set_siginfo {
+            if let (true, Ok(context)) = (self.signal_set_siginfo, context) {
                 // 更新用户修改的 pc
+                let pc = context.get_pc();
                 trap_frame.set_sepc(pc);
                 warn!("sig return sp = {:x} pc = {:x}", sp, pc);
             }
@@ -620,252 +829,321 @@ impl TaskInner {
         }
     }
 
-    /// 获取一个虚拟地址 `ptr` 的实际物理地址
-    pub fn transfer_raw(&mut self, ptr: usize) -> usize {
-        let (phy, flag, _) = self
-            .address_space
+    /// 在向用户栈写入信号处理的上下文前调用，确保 `range` 范围内的每一页都可以写入。
+    ///
+    /// 与用户态的写操作相同，必要时会拓展用户栈或复制写时复制的页；
+    /// 若栈已经达到 `RLIMIT_STACK` 的限制或范围不在可写的映射中则返回错误。
+    pub fn prepare_signal_frame(&mut self, range: Range<usize>) -> AlienResult<()> {
+        for addr in (align_down_4k(range.start)..range.end).step_by(FRAME_SIZE) {
+            self.user_writable_addr(addr)?;
+        }
+        Ok(())
+    }
+
+    /// 查询用户地址 `addr` 对应的物理地址，要求其所在的页有效、用户态可以访问且包含 `flags` 中的权限
+    fn query_user_addr(&self, addr: usize, flags: MappingFlags) -> Option<usize> {
+        self.address_space
             .lock()
-            .query(VirtAddr::from(ptr))
-            .unwrap();
-        if !flag.contains(MappingFlags::V) {
-            error!("[transfer_raw] invalid page {:?}, ptr:{:#x}", flag, ptr);
-            self.invalid_page_solver(ptr).unwrap();
-            let (phy, flag, _) = self
-                .address_space
-                .lock()
-                .query(VirtAddr::from(ptr))
-                .unwrap();
-            assert!(flag.contains(MappingFlags::V));
-            return phy.as_usize();
+            .query(VirtAddr::from(addr))
+            .ok()
+            .filter(|(_, flag, _)| flag.contains(MappingFlags::V | MappingFlags::U | flags))
+            .map(|(phy, _, _)| phy.as_usize())
+    }
+
+    /// 获取用户地址 `addr` 对应的物理地址，与用户态的读操作相同，必要时会处理缺页。
+    ///
+    /// 地址不在可读的映射中时返回 `EFAULT`。
+    pub fn user_readable_addr(&mut self, addr: usize) -> AlienResult<usize> {
+        if let Some(phy) = self.query_user_addr(addr, MappingFlags::R) {
+            return Ok(phy);
+        }
+        let res = self
+            .do_load_page_fault(addr)
+            .map_err(|_| LinuxErrno::EFAULT)?;
+        if let Some((Some(file), buf, offset)) = res {
+            trap_common_read_file(file, buf, offset);
+        }
+        self.query_user_addr(addr, MappingFlags::R)
+            .ok_or(LinuxErrno::EFAULT)
+    }
+
+    /// 获取用户地址 `addr` 对应的物理地址，与用户态的写操作相同，必要时会拓展用户栈或复制写时复制的页。
+    ///
+    /// 地址不在可写的映射中，或栈已经达到 `RLIMIT_STACK` 的限制时返回 `EFAULT`。
+    pub fn user_writable_addr(&mut self, addr: usize) -> AlienResult<usize> {
+        if let Some(phy) = self.query_user_addr(addr, MappingFlags::W) {
+            return Ok(phy);
+        }
+        let res = self
+            .do_store_page_fault(addr)
+            .map_err(|_| LinuxErrno::EFAULT)?;
+        if let Some((Some(file), buf, offset)) = res {
+            trap_common_read_file(file, buf, offset);
         }
-        phy.as_usize()
+        self.query_user_addr(addr, MappingFlags::W)
+            .ok_or(LinuxErrno::EFAULT)
     }
 
-    /// 获取 虚拟地址空间中的以 `ptr` 为起始地址，以 '\0' 结尾的字符串
-    pub fn transfer_str(&self, ptr: *const u8) -> String {
-        let mut res = String::new();
-        let physical = self
+    /// 获取跟踪者通过 ptrace 写入用户地址 `addr` 时使用的物理地址。
+    ///
+    /// 与 [`TaskInner::user_writable_addr`] 不同，只读的页(如代码段)同样可以写入，以便插入断点；
+    /// 这样的页与其它进程共享时，先为当前进程复制一份私有的页，写入不会影响其它进程。
+    /// 共享映射中的只读页不能写入，此时返回 `EIO`。
+    pub fn ptrace_writable_addr(&mut self, addr: usize) -> AlienResult<usize> {
+        let phy = self.user_readable_addr(addr)?;
+        let v_addr = VirtAddr::from(align_down_4k(addr));
+        let (old_phy, flags, page_size) = self
             .address_space
             .lock()
-            .query(VirtAddr::from(ptr as usize));
-        if physical.is_err() {
-            return res;
+            .query(v_addr)
+            .map_err(|_| LinuxErrno::EFAULT)?;
+        if flags.contains(MappingFlags::W) {
+            return Ok(phy);
         }
-        let (physical, _, _) = physical.unwrap();
-        let mut physical = physical.as_usize();
-        loop {
-            let c = unsafe { &*(physical as *const u8) };
-            if *c == 0 {
-                break;
-            }
-            res.push(*c as char);
-            physical += 1;
+        if flags.contains(MappingFlags::RSD) {
+            return self.user_writable_addr(addr);
         }
-        res
+        if usize::from(page_size) != FRAME_SIZE
+            || self.address_space.lock().get_record_mut().get(&v_addr) != Some(&true)
+        {
+            return Err(LinuxErrno::EIO);
+        }
+        let page_number = old_phy.as_usize() >> FRAME_BITS;
+        if FRAME_REF_MANAGER.lock().get_ref(page_number) == 1 {
+            return Ok(phy);
+        }
+        let new_phy = self
+            .address_space
+            .lock()
+            .modify_pte_flags(v_addr, flags, true)
+            .map_err(|_| LinuxErrno::ENOMEM)?
+            .unwrap();
+        unsafe {
+            core::ptr::copy(
+                old_phy.as_usize() as *const u8,
+                new_phy.as_usize() as *mut u8,
+                FRAME_SIZE,
+            );
+        }
+        FRAME_REF_MANAGER.lock().dec_ref(page_number);
+        Ok(new_phy.as_usize() + addr % FRAME_SIZE)
     }
 
-    /// 从物理地址的 `src` 处取一个长度为 `len` 类型为 T 的缓冲区 赋到 用户虚拟地址空间下的 `dst` 处
-    pub fn copy_to_user_buffer<T: 'static + Copy>(
-        &mut self,
-        src: *const T,
-        dst: *mut T,
-        len: usize,
-    ) {
-        let size = core::mem::size_of::<T>() * len;
-        if VirtAddr::from(dst as usize).align_down_4k()
-            == VirtAddr::from(dst as usize + size - 1).align_down_4k()
-        {
-            // the src and dst are in same page
-            let dst = self.transfer_raw(dst as usize);
-            unsafe {
-                core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, size);
+    /// 生成 core 文件时读取用户地址 `addr` 所在的页，被换出的页会被读回。
+    ///
+    /// 该页没有物理页时返回 false，此时不会为其分配物理页。
+    pub fn read_page_for_dump(&self, addr: usize, buf: &mut [u8]) -> bool {
+        let addr = addr & !(FRAME_SIZE - 1);
+        let mut phy = self.query_user_addr(addr, MappingFlags::empty());
+        if phy.is_none() && swap_in(&self.address_space, addr).unwrap_or(false) {
+            phy = self.query_user_addr(addr, MappingFlags::empty());
+        }
+        match phy {
+            Some(phy) => {
+                let len = min(buf.len(), FRAME_SIZE);
+                unsafe { (phy as *const u8).copy_to_nonoverlapping(buf.as_mut_ptr(), len) };
+                true
             }
-        } else {
-            let bufs = self.transfer_buffer(dst as *const u8, size);
-            let src = unsafe { core::slice::from_raw_parts(src as *const u8, size) };
-            let mut start = 0;
-            let src_len = src.len();
-            for buffer in bufs {
-                let len = if start + buffer.len() > src_len {
-                    src_len - start
-                } else {
-                    buffer.len()
-                };
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        src.as_ptr().add(start),
-                        buffer.as_mut_ptr(),
-                        len,
-                    );
-                }
-                start += len;
+            None => false,
+        }
+    }
+
+    /// 获取虚拟地址空间中以 `ptr` 为起始地址，以 '\0' 结尾的字符串，结果不包含结尾的 '\0'。
+    ///
+    /// 字符串可以跨越多页，每一页在读取前都会被逐一检查，必要时处理缺页；
+    /// 地址无效时返回 `EFAULT`，前 `limit` 个字节中没有 '\0' 时返回 `ENAMETOOLONG`。
+    pub fn strncpy_from_user(&mut self, ptr: usize, limit: usize) -> AlienResult<String> {
+        if ptr == 0 {
+            return Err(LinuxErrno::EFAULT);
+        }
+        let mut res = Vec::new();
+        let mut addr = ptr;
+        while res.len() < limit {
+            // 只读取到当前页的末尾，下一页可能没有被映射
+            let len = min(FRAME_SIZE - addr % FRAME_SIZE, limit - res.len());
+            let start = res.len();
+            res.resize(start + len, 0);
+            self.copy_from_user_bytes(addr, &mut res[start..])?;
+            if let Some(end) = res[start..].iter().position(|&c| c == 0) {
+                res.truncate(start + end);
+                return Ok(String::from_utf8(res)
+                    .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()));
             }
+            addr = addr.checked_add(len).ok_or(LinuxErrno::EFAULT)?;
         }
+        Err(LinuxErrno::ENAMETOOLONG)
     }
 
-    /// 从用户虚拟地址空间的 `src` 处取一个长度为 `len` 类型为 T 的缓冲区 赋到 物理地址下的 `dst` 处
-    pub fn copy_from_user_buffer<T: 'static + Copy>(
+    /// 在持有地址空间的锁时访问用户地址 `addr` 所在的页，`access` 的参数为该地址对应的物理地址。
+    ///
+    /// 先通过 `resolve` 检查地址并处理缺页，再在持有地址空间的锁时确认该地址仍然以 `flags` 权限映射到同一物理页，
+    /// 然后调用 `access`。调用期间其它线程无法解除该页的映射或者将其换出，物理页不会被释放；
+    /// 两次检查之间映射发生了变化时重新处理。
+    fn with_user_page<R>(
         &mut self,
-        src: *const T,
-        dst: *mut T,
-        len: usize,
-    ) {
-        let size = core::mem::size_of::<T>() * len;
-        if VirtAddr::from(src as usize).align_down_4k()
-            == VirtAddr::from(src as usize + size - 1).align_down_4k()
-        {
-            // the src and dst are in same page
-            let src = self.transfer_raw(src as usize);
-            unsafe {
-                core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, size);
-            }
-        } else {
-            let mut bufs = self.transfer_buffer(src as *const u8, size);
-            let dst = unsafe { core::slice::from_raw_parts_mut(dst as *mut u8, size) };
-            let mut start = 0;
-            let dst_len = dst.len();
-            for buffer in bufs.iter_mut() {
-                let len = if start + buffer.len() > dst_len {
-                    dst_len - start
-                } else {
-                    buffer.len()
-                };
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        buffer.as_ptr(),
-                        dst.as_mut_ptr().add(start),
-                        len,
-                    );
-                }
-                start += len;
+        addr: usize,
+        flags: MappingFlags,
+        resolve: fn(&mut Self, usize) -> AlienResult<usize>,
+        access: impl FnOnce(usize) -> R,
+    ) -> AlienResult<R> {
+        let (physical, _table) = loop {
+            let physical = resolve(self, addr)?;
+            let table = self.address_space.lock();
+            let mapped = table
+                .query(VirtAddr::from(addr))
+                .ok()
+                .filter(|(_, flag, _)| flag.contains(MappingFlags::V | MappingFlags::U | flags))
+                .map(|(phy, _, _)| phy.as_usize());
+            if mapped == Some(physical) {
+                break (physical, table);
             }
+        };
+        Ok(access(physical))
+    }
+
+    /// 从用户地址空间的 `src` 处复制 `dst.len()` 个字节到 `dst`，逐页检查地址，地址无效时返回 `EFAULT`
+    pub fn copy_from_user_bytes(&mut self, src: usize, dst: &mut [u8]) -> AlienResult<()> {
+        let mut copied = 0;
+        while copied < dst.len() {
+            let addr = src.checked_add(copied).ok_or(LinuxErrno::EFAULT)?;
+            let len = min(FRAME_SIZE - addr % FRAME_SIZE, dst.len() - copied);
+            let to = dst[copied..].as_mut_ptr();
+            self.with_user_page(
+                addr,
+                MappingFlags::R,
+                Self::user_readable_addr,
+                |physical| unsafe { core::ptr::copy(physical as *const u8, to, len) },
+            )?;
+            copied += len;
         }
+        Ok(())
     }
 
-    /// 从物理空间下的 `src` 处取一个 T 类型的数据 赋给 虚拟地址空间下的 `dst` 处
-    pub fn copy_to_user<T: 'static + Copy>(&mut self, src: *const T, dst: *mut T) {
-        // self.copy_to_user_buffer(src, dst, 1);
-        let size = core::mem::size_of::<T>();
-        if VirtAddr::from(dst as usize).align_down_4k()
-            == VirtAddr::from(dst as usize + size - 1).align_down_4k()
-        {
-            // the src and dst are in same page
-            let dst = self.transfer_raw(dst as usize);
-            unsafe {
-                core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, size);
-            }
-        } else {
-            let bufs = self.transfer_buffer(dst as *const u8, size);
-            let src = unsafe { core::slice::from_raw_parts(src as *const u8, size) };
-            let mut start = 0;
-            let src_len = src.len();
-            for buffer in bufs {
-                let len = if start + buffer.len() > src_len {
-                    src_len - start
-                } else {
-                    buffer.len()
-                };
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        src.as_ptr().add(start),
-                        buffer.as_mut_ptr(),
-                        len,
-                    );
-                }
-                start += len;
-            }
+    /// 将 `src` 复制到用户地址空间的 `dst` 处，逐页检查地址，地址无效时返回 `EFAULT`
+    pub fn copy_to_user_bytes(&mut self, dst: usize, src: &[u8]) -> AlienResult<()> {
+        let mut copied = 0;
+        while copied < src.len() {
+            let addr = dst.checked_add(copied).ok_or(LinuxErrno::EFAULT)?;
+            let len = min(FRAME_SIZE - addr % FRAME_SIZE, src.len() - copied);
+            let from = src[copied..].as_ptr();
+            self.with_user_page(
+                addr,
+                MappingFlags::W,
+                Self::user_writable_addr,
+                |physical| unsafe { core::ptr::copy(from, physical as *mut u8, len) },
+            )?;
+            copied += len;
         }
+        Ok(())
     }
 
-    /// 从用户虚拟地址空间的 `src` 处取一个 T 类型的数据 赋给 物理地址下的 `dst` 处
-    pub fn copy_from_user<T: 'static + Copy>(&mut self, src: *const T, dst: *mut T) {
-        // self.copy_from_user_buffer(src, dst, 1);
-        let size = core::mem::size_of::<T>();
-        if VirtAddr::from(src as usize).align_down_4k()
-            == VirtAddr::from(src as usize + size - 1).align_down_4k()
-        {
-            // the src and dst are in same page
-            let src = self.transfer_raw(src as usize);
-            unsafe {
-                core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, size);
-            }
+    /// 读取用户地址空间中 `ptr` 处的 T 类型数据
+    pub fn read_user<T: Copy>(&mut self, ptr: UserPtr<T>) -> AlienResult<T> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>())
+        };
+        self.copy_from_user_bytes(ptr.addr(), bytes)?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    /// 将 `value` 写入用户地址空间中的 `ptr` 处
+    pub fn write_user<T: Copy>(&mut self, ptr: UserPtr<T>, value: &T) -> AlienResult<()> {
+        let bytes =
+            unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+        self.copy_to_user_bytes(ptr.addr(), bytes)
+    }
+
+    /// 读取用户地址空间中的缓冲区 `slice`，缓冲区的长度来自用户，内存不足时返回 `ENOMEM`
+    pub fn read_user_slice<T: Copy>(&mut self, slice: UserSlice<T>) -> AlienResult<Vec<T>> {
+        let size = slice
+            .len()
+            .checked_mul(size_of::<T>())
+            .ok_or(LinuxErrno::EFAULT)?;
+        let mut res = Vec::<T>::new();
+        res.try_reserve_exact(slice.len())
+            .map_err(|_| LinuxErrno::ENOMEM)?;
+        let bytes = unsafe { core::slice::from_raw_parts_mut(res.as_mut_ptr() as *mut u8, size) };
+        self.copy_from_user_bytes(slice.addr(), bytes)?;
+        unsafe { res.set_len(slice.len()) };
+        Ok(res)
+    }
+
+    /// 将 `data` 写入用户地址空间中的缓冲区 `slice`，最多写入 `slice.len()` 个数据
+    pub fn write_user_slice<T: Copy>(
+        &mut self,
+        slice: UserSlice<T>,
+        data: &[T],
+    ) -> AlienResult<()> {
+        let len = min(slice.len(), data.len());
+        let bytes = unsafe {
+            core::slice::from_raw_parts(data.as_ptr() as *const u8, len * size_of::<T>())
+        };
+        self.copy_to_user_bytes(slice.addr(), bytes)
+    }
+
+    /// 将用户地址空间中的缓冲区 `slice` 转换为一组物理地址下的片段，每一段都不跨页，见 [`UserBuffers`]。
+    ///
+    /// `WRITABLE` 为 true 时要求缓冲区可写，必要时复制写时复制的页；缓冲区中有无效地址时返回 `EFAULT`。
+    fn user_buffers<const WRITABLE: bool>(
+        &mut self,
+        slice: UserSlice<u8>,
+    ) -> AlienResult<UserBuffers<WRITABLE>> {
+        let end = slice
+            .addr()
+            .checked_add(slice.len())
+            .ok_or(LinuxErrno::EFAULT)?;
+        let (flags, resolve): (_, fn(&mut Self, usize) -> AlienResult<usize>) = if WRITABLE {
+            (MappingFlags::W, Self::user_writable_addr)
         } else {
-            let mut bufs = self.transfer_buffer(src as *const u8, size);
-            let dst = unsafe { core::slice::from_raw_parts_mut(dst as *mut u8, size) };
-            let mut start = 0;
-            let dst_len = dst.len();
-            for buffer in bufs.iter_mut() {
-                let len = if start + buffer.len() > dst_len {
-                    dst_len - start
-                } else {
-                    buffer.len()
-                };
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        buffer.as_ptr(),
-                        dst.as_mut_ptr().add(start),
-                        len,
-                    );
-                }
-                start += len;
-            }
+            (MappingFlags::R, Self::user_readable_addr)
+        };
+        let mut start = slice.addr();
+        let mut bufs = UserBuffers::new();
+        while start < end {
+            let len = min(FRAME_SIZE - start % FRAME_SIZE, end - start);
+            self.with_user_page(start, flags, resolve, |physical| bufs.push(physical, len))?;
+            start += len;
         }
+        Ok(bufs)
     }
 
-    /// 将在进程的虚拟空间中的一段缓冲区的首地址 `ptr` 和 长度 `len` 转换为 实地址下的一组页
-    pub fn transfer_buffer<T: Debug>(
+    /// 获取用户地址空间中的缓冲区 `slice` 对应的一组只读片段，见 [`TaskInner::user_buffers`]
+    pub fn readable_buffers(&mut self, slice: UserSlice<u8>) -> AlienResult<UserBuffers<false>> {
+        self.user_buffers(slice)
+    }
+
+    /// 获取用户地址空间中的缓冲区 `slice` 对应的一组可写片段，见 [`TaskInner::user_buffers`]
+    pub fn writable_buffers(&mut self, slice: UserSlice<u8>) -> AlienResult<UserBuffers<true>> {
+        self.user_buffers(slice)
+    }
+
+    /// 获取用户地址空间中 `ptr` 处的 T 类型数据的引用，见 [`UserRef`]。
+    ///
+    /// `ptr` 需要按照 T 的大小对齐且不能跨页，否则返回 `EINVAL`；地址无效时返回 `EFAULT`。
+    fn user_ref_with<T>(
         &mut self,
-        ptr: *const T,
-        len: usize,
-    ) -> Vec<&'static mut [T]> {
-        let mut start = ptr as usize;
-        let end = start + len;
-        let mut v = Vec::new();
-        while start < end {
-            let (start_phy, flag, _) = self
-                .address_space
-                .lock()
-                .query(VirtAddr::from(start))
-                .expect(format!("transfer_buffer: {:x} failed", start).as_str());
-            if !flag.contains(MappingFlags::V) {
-                panic!("transfer_buffer: {:x} not mapped", start);
-            }
-            // start_phy向上取整到FRAME_SIZE
-            let bound = (start & !(FRAME_SIZE - 1)) + FRAME_SIZE;
-            let len = if bound > end {
-                end - start
-            } else {
-                bound - start
-            };
-            unsafe {
-                let buf = core::slice::from_raw_parts_mut(start_phy.as_usize() as *mut T, len);
-                v.push(buf);
-            }
-            start = bound;
+        ptr: UserPtr<T>,
+        flags: MappingFlags,
+        resolve: fn(&mut Self, usize) -> AlienResult<usize>,
+    ) -> AlienResult<UserRef<T>> {
+        if ptr.addr() % core::mem::align_of::<T>() != 0
+            || ptr.addr() % FRAME_SIZE + size_of::<T>() > FRAME_SIZE
+        {
+            return Err(LinuxErrno::EINVAL);
         }
-        v
+        self.with_user_page(ptr.addr(), flags, resolve, UserRef::new)
     }
 
-    /// 将一个在进程的虚拟空间中的虚地址 转换为一个实地址的可变引用
-    pub fn transfer_raw_ptr_mut<T>(&self, ptr: *mut T) -> &'static mut T {
-        let (physical, flag, _) = self
-            .address_space
-            .lock()
-            .query(VirtAddr::from(ptr as usize))
-            .expect(format!("{} transfer_raw_ptr: {:#x} failed", self.name, ptr as usize).as_str());
-        assert!(flag.contains(MappingFlags::V));
-        unsafe { &mut *(physical.as_usize() as *mut T) }
+    /// 获取用户地址空间中 `ptr` 处的 T 类型数据的只读引用，如等待时读取 futex 字
+    pub fn user_ref<T>(&mut self, ptr: UserPtr<T>) -> AlienResult<UserRef<T>> {
+        self.user_ref_with(ptr, MappingFlags::R, Self::user_readable_addr)
     }
 
-    /// 将一个在进程的虚拟空间中的虚地址 转换为一个实地址的不可变引用
-    pub fn transfer_raw_ptr<T>(&self, ptr: *const T) -> &'static T {
-        let (physical, flag, _) = self
-            .address_space
-            .lock()
-            .query(VirtAddr::from(ptr as usize))
-            .expect(format!("{} transfer_raw_ptr: {:#x} failed", self.name, ptr as usize).as_str());
-        assert!(flag.contains(MappingFlags::V));
-        unsafe { &*(physical.as_usize() as *const T) }
+    /// 获取用户地址空间中 `ptr` 处的 T 类型数据的引用并要求其可写，用于通过原子类型修改用户内存，如 futex 字。
+    /// 不可写时返回 `EFAULT`。
+    pub fn user_ref_mut<T>(&mut self, ptr: UserPtr<T>) -> AlienResult<UserRef<T>> {
+        self.user_ref_with(ptr, MappingFlags::W, Self::user_writable_addr)
     }
 
     /// 当进程回到用户态时，需要更新进程在内核态下的运行时间
@@ -942,24 +1220,52 @@ impl TaskInner {
         self.heap.lock().clone()
     }
 
-    #[allow(unused)]
-    /// (待实现)缩减堆空间
-    pub fn shrink_heap(_addr: usize) -> Result<usize, AlienError> {
-        todo!()
+    /// 缩减堆空间，释放 `addr` 之后的整页
+    pub fn shrink_heap(&mut self, addr: usize) -> Result<usize, AlienError> {
+        let mut heap = self.heap.lock();
+        let end = align_up_4k(addr);
+        if end < heap.end {
+            trace!("shrink heap: {:#x} -- {:#x}", end, heap.end);
+            split_huge_page(&self.address_space, end);
+            self.address_space
+                .lock()
+                .unmap_region(VirtAddr::from(end), heap.end - end)
+                .unwrap();
+            release_huge_pages(&self.address_space, end..heap.end);
+            release_swapped_pages(&self.address_space, end..heap.end);
+            unlock_range(&self.address_space, end..heap.end);
+            heap.end = end;
+        }
+        heap.current = addr;
+        Ok(heap.current)
     }
 
     /// 拓展堆空间
     pub fn extend_heap(&mut self, addr: usize) -> Result<usize, AlienError> {
-        let mut heap = self.heap.lock();
-        heap.current = addr;
-        if addr < heap.end {
+        let heap_end = self.heap.lock().end;
+        if addr < heap_end {
+            let mut heap = self.heap.lock();
+            heap.current = addr;
             return Ok(heap.current);
         }
-        let addition = addr - heap.end;
+        let addition = addr - heap_end;
         // increase heap size
-        let end = heap.end;
+        let end = heap_end;
         // align addition to PAGE_SIZE
         let addition = (addition + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+        // the heap can't grow into the user stack
+        if end + addition > self.stack.start {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        // the heap can't grow into a memory mapping
+        if self.mmap.first_overlap(end..end + addition).is_some() {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        self.check_vm_limit(addition, true)?;
+        let lock_future = self.mlockall.contains(MlockallFlags::MCL_FUTURE);
+        if lock_future {
+            self.check_memlock_limit(end..end + addition)?;
+        }
         trace!("extend heap: {:#x} -- {:#x}", end, addition);
         self.address_space
             .lock()
@@ -970,17 +1276,24 @@ impl TaskInner {
                 false,
                 true,
             )
-            .unwrap();
-        let new_end = end + addition;
-        heap.end = new_end;
-        Ok(heap.current)
+            .map_err(|_| LinuxErrno::ENOMEM)?;
+        let mut heap = self.heap.lock();
+        heap.current = addr;
+        heap.end = end + addition;
+        drop(heap);
+        if lock_future {
+            let populate = !self.mlockall.contains(MlockallFlags::MCL_ONFAULT);
+            self.mlock(end..end + addition, populate)?;
+        }
+        Ok(addr)
     }
 
     /// 在虚拟空间中创建内存映射。
     /// + `start`: 所要创建的映射区的起始地址。当该值为0时，内核将自动为其分配一段内存空间创建内存映射。该值在函数运行过程中将被调整为与4K对齐。
+    ///   没有指定 `MAP_FIXED` 时该值只是一个提示，所指的范围已被占用时内核会另外查找空闲的地址。
     /// + `len`: 指明所要创建的映射区的长度。该值在函数运行过程中将被调整为与4K对齐。
     /// + `prot`: 指明创建内存映射区的初始保护位。具体可见[`ProtFlags`]。
-    /// + `flags`: 指明mmap操作的相关设置。具体可见[`MapFlags`]。
+    /// + `flags`: 指明mmap操作的相关设置。具体可见[`MapFlags`]，此外还支持 [`MAP_FIXED_NOREPLACE`] 与 [`MAP_GROWSDOWN`]。
     /// + `fd`: 指明要创建内存映射的文件的文件描述符。
     /// + `offset`: 将从文件中偏移量为`offset`处开始映射。该值需要和4K对齐。
     ///
@@ -990,17 +1303,21 @@ impl TaskInner {
         start: usize,
         len: usize,
         prot: ProtFlags,
-        flags: MapFlags,
+        raw_flags: u32,
         fd: usize,
         offset: usize,
     ) -> AlienResult<usize> {
+        let flags = MapFlags::from_bits_truncate(raw_flags);
+        let noreplace = raw_flags & MAP_FIXED_NOREPLACE != 0;
+        let fixed = flags.contains(MapFlags::MAP_FIXED) || noreplace;
         // start == 0 表明需要OS为其找一段内存，而 MAP_FIXED 表明必须 mmap 在固定位置。两者是冲突的
-        if start == 0 && flags.contains(MapFlags::MAP_FIXED) {
+        if len == 0 || (start == 0 && fixed) {
             return Err(LinuxErrno::EINVAL);
         }
 
         // if the map in heap, now we ignore it
-        if self.heap.lock().contains(start) && self.heap.lock().contains(start + len) {
+        if self.heap.lock().contains(start) && self.heap.lock().contains(start.saturating_add(len))
+        {
             return Ok(start);
         }
 
@@ -1014,145 +1331,757 @@ impl TaskInner {
                 .get(fd)
                 .map_err(|_| LinuxErrno::EBADF)?
                 .ok_or(LinuxErrno::EBADF)?; // EBADF
+                                            // 只有文件系统中的普通文件与设备文件可以被映射，管道、套接字、pidfd 与目录不能被映射
+            let mappable = file.clone().downcast_arc::<KernelFile>().is_ok()
+                && file.inode().inode_type() != VfsNodeType::Dir;
+            if !mappable {
+                return Err(LinuxErrno::ENODEV);
+            }
             Some(file)
         };
-        // todo!
-        // for dynamic link, the linker will map the elf file to the same address
-        // we must satisfy this requirement
-        let mut start = align_down_4k(start);
-        let v_range = if prot.contains(ProtFlags::PROT_EXEC) {
-            let len = align_up_4k(len);
-            if start > self.heap.lock().start {
-                // the mmap region is in heap
-                return Err(LinuxErrno::EINVAL);
-            }
-            if let Some(_region) = self.mmap.get_region(start) {
-                return Err(LinuxErrno::EINVAL);
-            }
-            if start == 0 {
-                start = 0x1000;
+        // 映射不能越过用户栈以及其上的 trap 上下文与跳板页
+        let top = self.mmap.top();
+        if len > top {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        let start = align_down_4k(start);
+        let map_len = align_up_4k(len);
+        let in_user_space = start.checked_add(map_len).map_or(false, |end| end <= top);
+        let v_range = if fixed {
+            if !in_user_space {
+                return Err(LinuxErrno::ENOMEM);
             }
-            start..start + len
-        } else if flags.contains(MapFlags::MAP_FIXED) {
-            let len = align_up_4k(len);
-            if start > self.heap.lock().start {
+            let range = start..start + map_len;
+            let heap = self.heap.lock();
+            if range.start < heap.end && heap.start < range.end {
                 error!("mmap fixed address conflict with heap");
                 return Err(LinuxErrno::EINVAL);
             }
-            // check if the region is already mapped
-            if let Some(region) = self.mmap.get_region(start) {
-                // split the region
-                let (left, mut right) = region.split(start);
-                // delete the old region
-                self.mmap.remove_region(region.start);
-                // add the left region
-                self.mmap.add_region(left);
-                if start + len < right.start + right.map_len {
-                    // slice the right region
-                    trace!(
-                        "again slice the right region:{:#x?}, len:{:#x}",
-                        right.start,
-                        right.len
-                    );
-                    let (mut left, right) = right.split(start + len);
-                    // add the right region
-                    self.mmap.add_region(right);
-                    // update prot and flags
-                    left.set_prot(prot);
-                    left.set_flags(flags);
-                    left.offset = offset;
-                    left.fd = fd;
-                    self.mmap.add_region(left);
-                } else {
-                    trace!(
-                        "directly add the right region:{:#x?}, len:{:#x}",
-                        right.start,
-                        right.len
-                    );
-                    // update prot and flags
-                    right.set_prot(prot);
-                    right.set_flags(flags);
-                    right.offset = offset;
-                    right.fd = fd;
-                    self.mmap.add_region(right);
+            drop(heap);
+            if noreplace {
+                if !self.range_free(range.clone()) {
+                    return Err(LinuxErrno::EEXIST);
+                }
+            } else {
+                self.unmap_range(range.clone());
+                // 不属于任何映射区域的页(如动态链接器覆盖的程序段)同样被替换
+                let mut address_space = self.address_space.lock();
+                for addr in range.clone().step_by(FRAME_SIZE) {
+                    if address_space.query(VirtAddr::from(addr)).is_ok() {
+                        address_space
+                            .unmap_region(VirtAddr::from(addr), FRAME_SIZE)
+                            .unwrap();
+                    }
                 }
-                return Ok(start);
             }
-            start..start + len
+            range
+        } else if start != 0 && in_user_space && self.range_free(start..start + map_len) {
+            start..start + map_len
         } else {
-            let v_range = self.mmap.alloc(len);
-            v_range
+            self.mmap.alloc(map_len).ok_or(LinuxErrno::ENOMEM)?
         };
 
-        let region = MMapRegion::new(
-            v_range.start,
-            len,
-            v_range.end - v_range.start,
-            prot,
-            flags,
-            fd,
-            offset,
-        );
+        let is_data = prot.contains(ProtFlags::PROT_WRITE) && !flags.contains(MapFlags::MAP_SHARED);
+        self.check_vm_limit(map_len, is_data)?;
+
+        let mut region = MMapRegion::new(v_range.start, len, map_len, prot, flags, fd, offset);
+        region.grows_down = raw_flags & MAP_GROWSDOWN != 0 && region.fd.is_none();
         // warn!("add mmap region:{:#x?}",region);
+        // 共享文件映射在缺页时直接映射文件的缓存页，其余映射延迟分配物理页
+        if self.mlockall.contains(MlockallFlags::MCL_FUTURE) {
+            self.check_memlock_limit(v_range.clone())?;
+        }
+        self.map_lazy(&region, v_range.clone())?;
         self.mmap.add_region(region);
-        let start = v_range.start;
-        let mut map_flags: MappingFlags = prot.into(); // no V  flag
-        map_flags |= "AD".into();
-        self.address_space
-            .lock()
-            .map_region_no_target(
-                VirtAddr::from(start),
-                v_range.end - start,
-                map_flags,
-                false,
-                true,
-            )
-            .unwrap();
-        Ok(start)
+        self.mmap.merge(v_range.start);
+        if self.mlockall.contains(MlockallFlags::MCL_FUTURE) {
+            let populate = !self.mlockall.contains(MlockallFlags::MCL_ONFAULT);
+            if let Err(err) = self.mlock(v_range.clone(), populate) {
+                self.unmap_range(v_range);
+                return Err(err);
+            }
+        }
+        Ok(v_range.start)
     }
 
-    /// 用于在进程的虚拟内存空间中消除一段内存映射。传入的`start`需要是某段内存映射的首地址，`len`需要是该段内存映射的长度。
+    /// 用于在进程的虚拟内存空间中消除 `[start, start + len)` 范围内的内存映射。该范围可以只覆盖映射区域的一部分，也可以跨越多个区域，
+    /// 部分被覆盖的区域会被拆分。
     pub fn unmap(&mut self, start: usize, len: usize) -> Result<(), isize> {
-        // check whether the start is in mmap
-        let x = self.mmap.get_region(start);
-        if x.is_none() {
+        if start % FRAME_SIZE != 0 || len == 0 {
             return Err(LinuxErrno::EINVAL.into());
         }
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        let region = x.unwrap();
-        if region.start != start || len != region.len {
-            return Err(LinuxErrno::EINVAL.into());
+        self.unmap_range(start..start + align_up_4k(len));
+        Ok(())
+    }
+
+    /// 调整内存映射区域 `[old_addr, old_addr + old_size)` 的大小为 `new_size`，必要时将其移动到新的位置，返回调整后的起始地址。
+    ///
+    /// 缩小映射时释放多余的页；扩大映射时优先在原地扩展，原地没有足够的空间时，只有指定了 `MREMAP_MAYMOVE` 才会移动映射。
+    /// 移动映射时直接移动页表项，写时复制页、被换出的页以及共享文件映射的缓存页都保持原有的状态。
+    pub fn remap(
+        &mut self,
+        old_addr: usize,
+        old_size: usize,
+        new_size: usize,
+        flags: MremapFlags,
+        new_addr: usize,
+    ) -> AlienResult<usize> {
+        let old_len = align_up_4k(old_size);
+        let new_len = align_up_4k(new_size);
+        if old_addr % FRAME_SIZE != 0 || old_len == 0 || new_len == 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let fixed = flags.contains(MremapFlags::MREMAP_FIXED);
+        if fixed && !flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if fixed
+            && (new_addr % FRAME_SIZE != 0
+                || (new_addr < old_addr + old_len && old_addr < new_addr + new_len))
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let region = self.mmap.get_region(old_addr).ok_or(LinuxErrno::EFAULT)?;
+        if old_addr + old_len > region.start + region.map_len {
+            return Err(LinuxErrno::EFAULT);
+        }
+        let is_data = region.prot.contains(ProtFlags::PROT_WRITE)
+            && !region.flags.contains(MapFlags::MAP_SHARED);
+        if new_len > old_len {
+            self.check_vm_limit(new_len - old_len, is_data)?;
+        }
+        let mut region = self.isolate_region(old_addr, old_addr + old_len);
+        if new_len < old_len {
+            self.unmap_pages(&region, old_addr + new_len..old_addr + old_len);
+            region.len = new_size;
+            region.map_len = new_len;
+            self.mmap.add_region(region.clone());
+            if !fixed {
+                self.mmap.merge(old_addr);
+                return Ok(old_addr);
+            }
+        }
+        if !fixed {
+            let grow = old_addr + old_len..old_addr + new_len;
+            if self.range_free(grow.clone()) {
+                self.map_lazy(&region, grow)?;
+                region.len = new_size;
+                region.map_len = new_len;
+                self.mmap.add_region(region);
+                self.mmap.merge(old_addr);
+                return Ok(old_addr);
+            }
+            if !flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+                return Err(LinuxErrno::ENOMEM);
+            }
+        }
+        // 原有区域在确定新的位置之前保留在树中，新的位置不会与其重叠
+        let dest = if fixed {
+            self.unmap_range(new_addr..new_addr + new_len);
+            if !self.range_free(new_addr..new_addr + new_len) {
+                return Err(LinuxErrno::EINVAL);
+            }
+            new_addr
+        } else {
+            self.mmap.alloc(new_len).ok_or(LinuxErrno::ENOMEM)?.start
+        };
+        let moved = min(region.map_len, new_len);
+        split_huge_pages(&self.address_space, old_addr..old_addr + moved);
+        self.move_pages(&region, old_addr, dest, moved)?;
+        move_locked_range(&self.address_space, old_addr..old_addr + moved, dest);
+        self.mmap.remove_region(old_addr);
+        region.start = dest;
+        region.len = new_size;
+        region.map_len = new_len;
+        let res = self.map_lazy(&region, dest + moved..dest + new_len);
+        self.mmap.add_region(region);
+        self.mmap.merge(dest);
+        res.map(|_| dest)
+    }
+
+    /// 将内存映射区域在 `start` 与 `end` 处拆分，返回位于 `[start, end)` 中的部分。两个地址都需要与页对齐，且位于同一个区域之中
+    fn isolate_region(&mut self, start: usize, end: usize) -> MMapRegion {
+        split_huge_page(&self.address_space, start);
+        split_huge_page(&self.address_space, end);
+        let mut region = self.mmap.get_region(start).unwrap().clone();
+        self.mmap.remove_region(region.start);
+        if region.start < start {
+            let (left, right) = region.split(start);
+            self.mmap.add_region(left);
+            region = right;
+        }
+        if end < region.start + region.map_len {
+            let (left, right) = region.split(end);
+            self.mmap.add_region(right);
+            region = left;
+        }
+        self.mmap.add_region(region.clone());
+        region
+    }
+
+    /// 判断 `range` 范围内没有任何映射，也不与堆和栈重叠
+    fn range_free(&self, range: Range<usize>) -> bool {
+        if range.is_empty() {
+            return true;
+        }
+        let heap = self.heap.lock();
+        if range.start < heap.end && heap.start < range.end {
+            return false;
+        }
+        drop(heap);
+        if range.start < self.stack.end && self.stack.start < range.end {
+            return false;
+        }
+        if self.mmap.first_overlap(range.clone()).is_some() {
+            return false;
+        }
+        let address_space = self.address_space.lock();
+        range
+            .step_by(FRAME_SIZE)
+            .all(|addr| address_space.query(VirtAddr::from(addr)).is_err())
+    }
+
+    /// 解除 `range` 范围内的所有内存映射，与该范围部分重叠的映射区域会被拆分
+    fn unmap_range(&mut self, range: Range<usize>) {
+        loop {
+            let overlap = self.mmap.first_overlap(range.clone()).map(|region| {
+                (
+                    max(range.start, region.start),
+                    min(range.end, region.start + region.map_len),
+                )
+            });
+            let (start, end) = match overlap {
+                Some(overlap) => overlap,
+                None => break,
+            };
+            let region = self.isolate_region(start, end);
+            self.unmap_pages(&region, start..end);
+            self.mmap.remove_region(start);
+        }
+    }
+
+    /// 解除内存映射区域 `region` 中 `range` 范围内的页
+    fn unmap_pages(&self, region: &MMapRegion, range: Range<usize>) {
+        unlock_range(&self.address_space, range.clone());
+        if let Some(cache) = region.page_cache() {
+            self.release_shared_region(region, &cache, range);
+            return;
         }
         self.address_space
             .lock()
-            .unmap_region(VirtAddr::from(start), region.map_len)
+            .unmap_region(VirtAddr::from(range.start), range.len())
             .unwrap();
-        self.mmap.remove_region(start);
+        release_huge_pages(&self.address_space, range.clone());
+        release_swapped_pages(&self.address_space, range);
+    }
+
+    /// 为内存映射区域 `region` 中 `range` 范围内的页建立延迟分配的映射，共享文件映射在缺页时才会映射缓存页
+    fn map_lazy(&self, region: &MMapRegion, range: Range<usize>) -> AlienResult<()> {
+        if range.is_empty() || region.page_cache().is_some() {
+            return Ok(());
+        }
+        let mut map_flags: MappingFlags = region.prot.into(); // no V  flag
+        map_flags |= "AD".into();
+        self.address_space
+            .lock()
+            .map_region_no_target(
+                VirtAddr::from(range.start),
+                range.len(),
+                map_flags,
+                false,
+                true,
+            )
+            .map_err(|_| LinuxErrno::ENOMEM)?;
+        Ok(())
+    }
+
+    /// 将 `[from, from + len)` 范围内的页表项移动到 `to` 处，页的内容与引用计数都保持不变
+    fn move_pages(
+        &self,
+        region: &MMapRegion,
+        from: usize,
+        to: usize,
+        len: usize,
+    ) -> AlienResult<()> {
+        let mut anon_pages = Vec::new();
+        let mut address_space = self.address_space.lock();
+        for offset in (0..len).step_by(FRAME_SIZE) {
+            let old = VirtAddr::from(from + offset);
+            let new = VirtAddr::from(to + offset);
+            let (phy, flags, _) = match address_space.query(old) {
+                Ok(res) => res,
+                Err(_) => continue,
+            };
+            if flags.contains(MappingFlags::V) {
+                // 页表只负责释放它拥有的页，移动后由新的页表项拥有该页
+                let owned = address_space.get_record_mut().get(&old) == Some(&true);
+                address_space
+                    .map_region(new, phy, FRAME_SIZE, flags, false)
+                    .map_err(|_| LinuxErrno::ENOMEM)?;
+                if owned {
+                    address_space.get_record_mut().insert(new, true);
+                    address_space.get_record_mut().insert(old, false);
+                }
+                if region.fd.is_none() {
+                    anon_pages.push(new.as_usize());
+                }
+            } else {
+                // 尚未分配或者已经被换出的页
+                address_space
+                    .map_region_no_target(new, FRAME_SIZE, flags, false, true)
+                    .map_err(|_| LinuxErrno::ENOMEM)?;
+            }
+            address_space.unmap_region(old, FRAME_SIZE).unwrap();
+        }
+        drop(address_space);
+        move_swapped_pages(&self.address_space, from..from + len, to);
+        anon_pages
+            .into_iter()
+            .for_each(|addr| track_anon_page(&self.address_space, addr));
         Ok(())
     }
 
-    /// 设置内存映射的保护位，函数会检查传入的`start`和`len`所指示的内存映射区是否已经处于被映射状态，如果是，则将对应内存映射区的保护位与`prot`做或运算。
+    /// 解除共享文件映射区域中 `range` 范围内已经映射的缓存页，并将其中的修改写回文件
+    fn release_shared_region(
+        &self,
+        region: &MMapRegion,
+        cache: &Arc<Mutex<PageCache>>,
+        range: Range<usize>,
+    ) {
+        let mut address_space = self.address_space.lock();
+        let mut cache = cache.lock();
+        for addr in range.clone().step_by(FRAME_SIZE) {
+            let flags = match address_space.query(VirtAddr::from(addr)) {
+                Ok((_, flags, _)) if flags.contains(MappingFlags::V) => flags,
+                _ => continue,
+            };
+            address_space
+                .unmap_region(VirtAddr::from(addr), FRAME_SIZE)
+                .unwrap();
+            let index = (region.offset + addr - region.start) / FRAME_SIZE;
+            cache.unmap_page(index, flags.contains(MappingFlags::W));
+        }
+        let offset = region.offset + range.start - region.start;
+        let _ = cache.sync_range(offset as u64, range.len());
+    }
+
+    /// 解除所有共享文件映射，在进程退出或执行新程序前调用，保证对映射的修改被写回文件
+    pub fn release_shared_mappings(&mut self) {
+        let regions = self
+            .mmap
+            .regions()
+            .filter_map(|region| region.page_cache().map(|cache| (region.clone(), cache)))
+            .collect::<Vec<_>>();
+        for (region, cache) in regions {
+            let range = region.start..region.start + region.map_len;
+            self.release_shared_region(&region, &cache, range);
+            self.mmap.remove_region(region.start);
+        }
+    }
+
+    /// 处理共享文件映射区域中的缺页，将文件的缓存页直接映射到 `addr` 处，使所有映射该文件的进程共享同一份数据
+    ///
+    /// `addr` 不在共享文件映射区域中或者已经被映射时返回 `false`，由普通的缺页处理流程处理。
+    fn map_shared_page(&mut self, addr: usize) -> AlienResult<bool> {
+        let addr = align_down_4k(addr);
+        let region = match self.mmap.get_region(addr) {
+            Some(region) => region,
+            None => return Ok(false),
+        };
+        let cache = match region.page_cache() {
+            Some(cache) => cache,
+            None => return Ok(false),
+        };
+        let mut address_space = self.address_space.lock();
+        match address_space.query(VirtAddr::from(addr)) {
+            Ok((_, flags, _)) if flags.contains(MappingFlags::V) => return Ok(false),
+            Ok(_) => {
+                // 覆盖了原有映射的残留页表项
+                let _ = address_space.unmap_region(VirtAddr::from(addr), FRAME_SIZE);
+            }
+            Err(_) => {}
+        }
+        let index = (region.offset + addr - region.start) / FRAME_SIZE;
+        let writable = region.prot.contains(ProtFlags::PROT_WRITE);
+        let phy = cache.lock().map_page(index, writable)?;
+        let mut map_flags: MappingFlags = region.prot.into();
+        map_flags |= "VAD".into();
+        // 缓存页属于页缓存，页表不会在解除映射时释放它
+        address_space
+            .map_region(
+                VirtAddr::from(addr),
+                PhysAddr::from(phy),
+                FRAME_SIZE,
+                map_flags,
+                false,
+            )
+            .map_err(|_| {
+                cache.lock().unmap_page(index, false);
+                LinuxErrno::ENOMEM
+            })?;
+        drop(address_space);
+        Self::readahead(region, addr);
+        Ok(true)
+    }
+
+    /// 将 `[start, start + len)` 范围内的内存映射区域的保护位设置为 `prot`，部分被覆盖的区域会被拆分，已经映射的页同时更新页表项。
+    ///
+    /// 范围内没有任何映射区域时，若 `start` 处存在映射(如程序段)则直接返回成功，否则返回 `ENOMEM`。
     pub fn map_protect(&mut self, start: usize, len: usize, prot: ProtFlags) -> AlienResult<()> {
-        // check whether the start is in mmap
-        let x = self.mmap.get_region_mut(start);
-        if x.is_none() {
+        if start % FRAME_SIZE != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let end = len
+            .checked_add(FRAME_SIZE - 1)
+            .and_then(|len| start.checked_add(len & !(FRAME_SIZE - 1)))
+            .ok_or(LinuxErrno::ENOMEM)?;
+        let range = start..end;
+        if self.mmap.first_overlap(range.clone()).is_none() {
             let res = self.address_space.lock().query(VirtAddr::from(start));
             return if res.is_err() {
-                Err(LinuxErrno::EINVAL)
+                Err(LinuxErrno::ENOMEM)
             } else {
                 Ok(())
             };
         }
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        let region = x.unwrap();
-        if start + len > region.start + region.len {
-            error!("start+len > region.start + region.len");
+        for region in self.update_regions(range, |region| region.prot = prot) {
+            self.protect_pages(&region, region.start..region.start + region.map_len);
+        }
+        Ok(())
+    }
+
+    /// 修改 `range` 范围内所有内存映射区域的属性，部分被覆盖的区域会被拆分，修改后的区域与属性相同的相邻区域合并。
+    ///
+    /// 返回修改后的各个区域(合并之前)。
+    fn update_regions(
+        &mut self,
+        range: Range<usize>,
+        update: impl Fn(&mut MMapRegion),
+    ) -> Vec<MMapRegion> {
+        let mut updated = Vec::new();
+        let mut next = range.start;
+        while let Some((begin, end)) = self.mmap.first_overlap(next..range.end).map(|region| {
+            (
+                max(next, region.start),
+                min(range.end, region.start + region.map_len),
+            )
+        }) {
+            let mut region = self.isolate_region(begin, end);
+            update(&mut region);
+            self.mmap.add_region(region.clone());
+            updated.push(region);
+            next = end;
+        }
+        updated
+            .iter()
+            .for_each(|region| self.mmap.merge(region.start));
+        updated
+    }
+
+    /// 按照内存映射区域 `region` 的保护位更新 `range` 范围内已有的页表项，写时复制页保持只读
+    fn protect_pages(&self, region: &MMapRegion, range: Range<usize>) {
+        if let Some(cache) = region.page_cache() {
+            // 缓存页在下一次缺页时按照新的保护位重新映射
+            self.release_shared_region(region, &cache, range);
+            return;
+        }
+        let prot: MappingFlags = region.prot.into();
+        let mut address_space = self.address_space.lock();
+        let mut addr = range.start;
+        while addr < range.end {
+            let (flags, size) = match address_space.query(VirtAddr::from(addr)) {
+                Ok((_, flags, size)) => (flags, usize::from(size)),
+                Err(_) => {
+                    addr += FRAME_SIZE;
+                    continue;
+                }
+            };
+            let mut new_flags = prot
+                | (flags
+                    & (MappingFlags::V | MappingFlags::A | MappingFlags::D | MappingFlags::RSD));
+            if flags.contains(MappingFlags::RSD) {
+                new_flags -= MappingFlags::W;
+            }
+            let _ = address_space.modify_pte_flags(VirtAddr::from(addr), new_flags, false);
+            // 大页只有一个页表项
+            addr += size;
+        }
+    }
+
+    /// 处理 madvise 对 `[start, start + len)` 范围内内存的建议，具体可见 [`MadviseAdvice`]
+    pub fn advise(&mut self, start: usize, len: usize, advice: MadviseAdvice) -> AlienResult<()> {
+        if start % FRAME_SIZE != 0 {
             return Err(LinuxErrno::EINVAL);
         }
-        region.prot |= prot;
+        let range = start..start + align_up_4k(len);
+        if range.is_empty() {
+            return Ok(());
+        }
+        if self.range_free(range.clone()) {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        match advice {
+            MadviseAdvice::Normal | MadviseAdvice::Random | MadviseAdvice::Sequential => {
+                self.update_regions(range, |region| region.access = advice);
+            }
+            MadviseAdvice::DontFork => {
+                self.update_regions(range, |region| region.dont_fork = true);
+            }
+            MadviseAdvice::DoFork => {
+                self.update_regions(range, |region| region.dont_fork = false);
+            }
+            MadviseAdvice::HugePage => {
+                self.update_regions(range, |region| region.huge = Some(true));
+            }
+            MadviseAdvice::NoHugePage => {
+                self.update_regions(range, |region| region.huge = Some(false));
+            }
+            MadviseAdvice::WillNeed => self.prefault_pages(range)?,
+            MadviseAdvice::DontNeed => self.discard_pages(range, false)?,
+            MadviseAdvice::Free => self.discard_pages(range, true)?,
+        }
+        Ok(())
+    }
+
+    /// 释放 `range` 范围内已经分配的物理页，之后访问这些页时重新缺页：匿名页读到全零的数据，
+    /// 私有文件映射重新读入文件内容，共享文件映射的修改被写回后重新映射缓存页。
+    ///
+    /// `anon_only` 为真时(`MADV_FREE`)范围内不能有文件映射。
+    fn discard_pages(&mut self, range: Range<usize>, anon_only: bool) -> AlienResult<()> {
+        let regions = self
+            .mmap
+            .regions()
+            .filter(|region| {
+                range.start < region.start + region.map_len && region.start < range.end
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if anon_only && regions.iter().any(|region| region.fd.is_some()) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        // 被锁定的页必须常驻内存
+        if locked_bytes(&self.address_space, range.clone()) > 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        split_huge_page(&self.address_space, range.start);
+        split_huge_page(&self.address_space, range.end);
+        for region in regions {
+            let start = max(range.start, region.start);
+            let end = min(range.end, region.start + region.map_len);
+            self.unmap_pages(&region, start..end);
+            self.map_lazy(&region, start..end)?;
+        }
+        let heap = self.heap.lock();
+        let heap_range = heap.start..heap.end;
+        drop(heap);
+        for anon in [heap_range, self.stack.clone()] {
+            let start = max(range.start, anon.start);
+            let end = min(range.end, anon.end);
+            if start >= end {
+                continue;
+            }
+            let mut address_space = self.address_space.lock();
+            address_space
+                .unmap_region(VirtAddr::from(start), end - start)
+                .unwrap();
+            address_space
+                .map_region_no_target(
+                    VirtAddr::from(start),
+                    end - start,
+                    "RWUAD".into(), // no V flag
+                    false,
+                    true,
+                )
+                .map_err(|_| LinuxErrno::ENOMEM)?;
+            drop(address_space);
+            release_huge_pages(&self.address_space, start..end);
+            release_swapped_pages(&self.address_space, start..end);
+        }
+        Ok(())
+    }
+
+    /// 预先读入 `range` 范围内的页：文件映射的页被读入页缓存，被换出的匿名页被换入
+    fn prefault_pages(&self, range: Range<usize>) -> AlienResult<()> {
+        for addr in range.step_by(FRAME_SIZE) {
+            let cache = self
+                .mmap
+                .get_region(addr)
+                .and_then(|region| region.fd.as_ref().map(|file| (region, file.page_cache())));
+            match cache {
+                Some((region, Some(cache))) => {
+                    let index = (region.offset + addr - region.start) / FRAME_SIZE;
+                    // 预读只是建议，读入失败时不返回错误
+                    let _ = cache.lock().readahead(index, 1);
+                }
+                Some((_, None)) => {}
+                None => {
+                    swap_in(&self.address_space, addr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 文件映射区域 `region` 中 `addr` 处的页缺页时，按照区域的访问模式预读之后的页
+    fn readahead(region: &MMapRegion, addr: usize) {
+        let count = region.access.readahead_pages();
+        let cache = region.fd.as_ref().and_then(|file| file.page_cache());
+        if let (Some(cache), true) = (cache, count > 0) {
+            let index = (region.offset + addr - region.start) / FRAME_SIZE;
+            let _ = cache.lock().readahead(index + 1, count);
+        }
+    }
+
+    /// 将 `addr` 处新分配的物理页清零，匿名页缺页时必须读到全零的数据
+    fn clear_page(&self, addr: usize) {
+        let (phy, _, size) = self
+            .address_space
+            .lock()
+            .query(VirtAddr::from(addr))
+            .unwrap();
+        unsafe {
+            core::ptr::write_bytes(phy.as_usize() as *mut u8, 0, usize::from(size));
+        }
+    }
+
+    /// 锁定 `range` 范围内的内存，`populate` 为真时预先为范围内的每一页分配物理页
+    ///
+    /// 范围内的每一页都必须属于某个映射，否则返回 `ENOMEM`。
+    pub fn mlock(&mut self, range: Range<usize>, populate: bool) -> AlienResult<()> {
+        if !self.range_mapped(range.clone()) {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        self.check_memlock_limit(range.clone())?;
+        if populate {
+            for addr in range.clone().step_by(FRAME_SIZE) {
+                self.populate_page(addr)?;
+            }
+        }
+        lock_range(&self.address_space, range);
+        Ok(())
+    }
+
+    /// 处理 mlockall，`MCL_CURRENT` 锁定当前所有的映射区域、堆与栈，`MCL_FUTURE` 使之后创建的映射也被锁定
+    pub fn mlockall(&mut self, flags: MlockallFlags) -> AlienResult<()> {
+        if flags.contains(MlockallFlags::MCL_CURRENT) {
+            let heap = self.heap.lock();
+            let heap_range = heap.start..heap.end;
+            drop(heap);
+            let mut ranges = self
+                .mmap
+                .regions()
+                .map(|region| region.start..region.start + region.map_len)
+                .collect::<Vec<_>>();
+            ranges.push(heap_range);
+            ranges.push(self.stack.clone());
+            ranges.retain(|range| !range.is_empty());
+            let total = ranges
+                .iter()
+                .map(|range| range.len() - locked_bytes(&self.address_space, range.clone()))
+                .sum::<usize>();
+            self.check_memlock_bytes(total)?;
+            let populate = !flags.contains(MlockallFlags::MCL_ONFAULT);
+            for range in ranges {
+                self.mlock(range, populate)?;
+            }
+        }
+        self.mlockall = flags & (MlockallFlags::MCL_FUTURE | MlockallFlags::MCL_ONFAULT);
         Ok(())
     }
 
+    /// 检查锁定 `range` 范围内的内存后，锁定的总大小是否超过 `RLIMIT_MEMLOCK` 的限制
+    fn check_memlock_limit(&self, range: Range<usize>) -> AlienResult<()> {
+        let locked = locked_bytes(&self.address_space, range.clone());
+        self.check_memlock_bytes(range.len() - locked)
+    }
+
+    /// 检查再锁定 `len` 字节后，锁定的总大小是否超过 `RLIMIT_MEMLOCK` 的限制
+    fn check_memlock_bytes(&self, len: usize) -> AlienResult<()> {
+        let limit = self.resource_limits.cur(RLimitRes::Memlock);
+        if limit == RLIM_INFINITY || self.cred.has_cap(Capabilities::IPC_LOCK) {
+            return Ok(());
+        }
+        if limit == 0 {
+            return Err(LinuxErrno::EPERM);
+        }
+        let locked = locked_bytes(&self.address_space, 0..usize::MAX);
+        if (locked + len) as u64 > limit {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        Ok(())
+    }
+
+    /// 查询 `range` 范围内的每一页是否驻留在内存中，文件映射的页只要在页缓存中即视为驻留。
+    ///
+    /// 范围内有未被映射的页时返回 `ENOMEM`。
+    pub fn mincore(&self, range: Range<usize>) -> AlienResult<Vec<u8>> {
+        if !self.range_mapped(range.clone()) {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        let address_space = self.address_space.lock();
+        let residency = range
+            .step_by(FRAME_SIZE)
+            .map(|addr| {
+                if let Ok((_, flags, _)) = address_space.query(VirtAddr::from(addr)) {
+                    if flags.contains(MappingFlags::V) {
+                        return 1;
+                    }
+                }
+                let region = match self.mmap.get_region(addr) {
+                    Some(region) => region,
+                    None => return 0,
+                };
+                match region.fd.as_ref().and_then(|file| file.page_cache()) {
+                    Some(cache) => {
+                        let index = (region.offset + addr - region.start) / FRAME_SIZE;
+                        cache.lock().contains_page(index) as u8
+                    }
+                    None => 0,
+                }
+            })
+            .collect();
+        Ok(residency)
+    }
+
+    /// 判断 `range` 范围内的每一页都属于某个映射(映射区域、堆、栈或程序段)
+    fn range_mapped(&self, range: Range<usize>) -> bool {
+        let heap = self.heap.lock();
+        let heap_range = heap.start..heap.end;
+        drop(heap);
+        let address_space = self.address_space.lock();
+        range.step_by(FRAME_SIZE).all(|addr| {
+            self.mmap.get_region(addr).is_some()
+                || heap_range.contains(&addr)
+                || self.stack.contains(&addr)
+                || address_space.query(VirtAddr::from(addr)).is_ok()
+        })
+    }
+
+    /// 为 `addr` 处的页分配物理页并读入其内容，页已经存在时什么也不做
+    fn populate_page(&mut self, addr: usize) -> AlienResult<()> {
+        if self.map_shared_page(addr)? {
+            return Ok(());
+        }
+        let valid = self
+            .address_space
+            .lock()
+            .query(VirtAddr::from(addr))
+            .map(|(_, flags, _)| flags.contains(MappingFlags::V));
+        match valid {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                if let Some((Some(file), buf, offset)) = self.invalid_page_solver(addr)? {
+                    trap_common_read_file(file, buf, offset);
+                }
+                Ok(())
+            }
+            Err(_) => Err(LinuxErrno::ENOMEM),
+        }
+    }
+
     /// 用于处理装入页异常
     pub fn do_load_page_fault(
         &mut self,
@@ -1160,11 +2089,22 @@ impl TaskInner {
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
         // check whether the addr is in mmap
         let addr = align_down_4k(addr);
+        if self.map_shared_page(addr)? {
+            return Ok(None);
+        }
+        if self.mmap.get_region(addr).is_none() && self.grow_mmap_down(addr) {
+            return self.invalid_page_solver(addr);
+        }
+        if self.in_stack_gap(addr) {
+            // 栈无法继续拓展时视为栈溢出，由调用者向任务发送 SIGSEGV
+            self.grow_stack(addr).map_err(|_| LinuxErrno::EFAULT)?;
+            return self.invalid_page_solver(addr);
+        }
         let (_phy, flags, page_size) = self
             .address_space
             .lock()
             .query(VirtAddr::from(addr))
-            .expect(format!("addr:{:#x}", addr).as_str());
+            .map_err(|_| LinuxErrno::EFAULT)?;
         trace!(
             "do load page fault:{:#x}, flags:{:?}, page_size:{:?}",
             addr,
@@ -1174,28 +2114,8 @@ impl TaskInner {
         if !flags.contains(MappingFlags::V) {
             return self.invalid_page_solver(addr);
         }
-        assert!(!flags.contains(MappingFlags::RSD));
-
-        let region = self.mmap.get_region(addr).ok_or(AlienError::EINVAL)?;
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        // update page table
-        let mut map_flags: MappingFlags = region.prot.into();
-        map_flags |= "V".into();
-
-        let mut address_space = self.address_space.lock();
-
-        let (_, flags, _) = address_space.query(VirtAddr::from(addr)).unwrap();
-        assert!(!flags.contains(MappingFlags::V));
-        address_space
-            .validate(VirtAddr::from(addr), map_flags)
-            .unwrap();
-        let (phy, _, size) = address_space.query(VirtAddr::from(addr)).unwrap();
-        let buf =
-            unsafe { core::slice::from_raw_parts_mut(phy.as_usize() as *mut u8, size.into()) };
-        let file = &region.fd;
-
-        let read_offset = region.offset + (addr - region.start);
-        Ok(Some((file.clone(), buf, read_offset as u64)))
+        // 页已经有效，说明访问违反了保护位(如 mprotect(PROT_NONE) 之后的读)
+        Err(LinuxErrno::EFAULT)
     }
 
     /// 用于处理无效页错误
@@ -1204,6 +2124,12 @@ impl TaskInner {
         addr: usize,
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
         trace!("invalid page fault at {:#x}", addr);
+        if self.map_shared_page(addr)? {
+            return Ok(None);
+        }
+        if swap_in(&self.address_space, addr)? {
+            return Ok(None);
+        }
         let is_mmap = self.mmap.get_region(addr);
         let is_heap = self.heap.lock().contains(addr);
 
@@ -1215,13 +2141,34 @@ impl TaskInner {
         }
         if is_heap {
             trace!("invalid page fault in heap");
+            let heap = self.heap.lock();
+            let bounds = heap.start..heap.end;
+            drop(heap);
+            if huge_page_enabled(None)
+                && map_huge_page(&self.address_space, addr, bounds, "RWUAD".into())
+            {
+                return Ok(None);
+            }
             let map_flags = "RWUVAD".into();
             self.address_space
                 .lock()
                 .validate(VirtAddr::from(addr), map_flags)
-                .unwrap();
+                .map_err(|_| LinuxErrno::ENOMEM)?;
+            self.clear_page(addr);
+            track_anon_page(&self.address_space, addr);
         } else if is_mmap.is_some() {
             let region = is_mmap.unwrap();
+            if region.fd.is_none()
+                && !region.flags.contains(MapFlags::MAP_SHARED)
+                && huge_page_enabled(region.huge)
+            {
+                let mut lazy_flags: MappingFlags = region.prot.into();
+                lazy_flags |= "AD".into();
+                let bounds = region.start..region.start + region.map_len;
+                if map_huge_page(&self.address_space, addr, bounds, lazy_flags) {
+                    return Ok(None);
+                }
+            }
             // assert_eq!(addr % FRAME_SIZE, 0);
             // update page table
             let mut map_flags: MappingFlags = region.prot.into();
@@ -1233,15 +2180,22 @@ impl TaskInner {
             self.address_space
                 .lock()
                 .validate(VirtAddr::from(addr).align_down_4k(), map_flags)
-                .unwrap();
+                .map_err(|_| LinuxErrno::ENOMEM)?;
             let (phy, flag, size) = self
                 .address_space
                 .lock()
                 .query(VirtAddr::from(addr))
                 .unwrap();
             assert!(flag.contains(MappingFlags::V));
+            if region.fd.is_none() {
+                track_anon_page(&self.address_space, addr);
+            } else {
+                Self::readahead(region, addr);
+            }
             let buf =
                 unsafe { core::slice::from_raw_parts_mut(phy.as_usize() as *mut u8, size.into()) };
+            // 匿名页以及文件末尾之后的部分读到全零的数据
+            buf.fill(0);
             let file = &region.fd;
             let read_offset = region.offset + (addr - region.start);
             return Ok(Some((file.clone(), buf, read_offset as u64)));
@@ -1251,7 +2205,9 @@ impl TaskInner {
             self.address_space
                 .lock()
                 .validate(VirtAddr::from(addr), map_flags)
-                .unwrap();
+                .map_err(|_| LinuxErrno::ENOMEM)?;
+            self.clear_page(addr);
+            track_anon_page(&self.address_space, addr);
         }
         Ok(None)
     }
@@ -1262,6 +2218,9 @@ impl TaskInner {
         addr: usize,
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
         let addr = align_down_4k(addr);
+        if self.map_shared_page(addr)? {
+            return Ok(None);
+        }
         let (_phy, flags, page_size) = self
             .address_space
             .lock()
@@ -1286,6 +2245,22 @@ impl TaskInner {
         o_addr: usize,
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
         let addr = align_down_4k(o_addr);
+        if self.map_shared_page(addr)? {
+            return Ok(None);
+        }
+        if self.mmap.get_region(addr).is_none() && self.grow_mmap_down(addr) {
+            return self.invalid_page_solver(addr);
+        }
+        if self.in_stack_gap(addr) {
+            // 栈无法继续拓展时视为栈溢出，由调用者向任务发送 SIGSEGV
+            self.grow_stack(addr).map_err(|_| LinuxErrno::EFAULT)?;
+            return self.invalid_page_solver(addr);
+        }
+        if let Some(region) = self.mmap.get_region(addr) {
+            if !region.prot.contains(ProtFlags::PROT_WRITE) {
+                return Err(LinuxErrno::EFAULT);
+            }
+        }
         let (phy, flags, page_size) = self
             .address_space
             .lock()
@@ -1309,15 +2284,10 @@ impl TaskInner {
         if !flags.contains(MappingFlags::V) {
             return self.invalid_page_solver(addr);
         }
-        // if !flags.contains(MappingFlags::RSD) {
-        //     return Ok(None);
-        // }
-        assert!(
-            flags.contains(MappingFlags::RSD),
-            "addr:{:#x} flags:{:?}",
-            o_addr,
-            flags
-        );
+        if !flags.contains(MappingFlags::RSD) {
+            // 不是写时复制页，写操作违反了页的保护位
+            return Err(LinuxErrno::EFAULT);
+        }
         // decrease the reference count
         let mut flags = flags | "W".into();
         flags -= MappingFlags::RSD;
@@ -1325,7 +2295,7 @@ impl TaskInner {
             .address_space
             .lock()
             .modify_pte_flags(VirtAddr::from(addr), flags, true)
-            .unwrap();
+            .map_err(|_| LinuxErrno::ENOMEM)?;
         assert!(new_phy.is_some());
         // copy data
         let src_ptr = phy.as_usize() as *const u8;
@@ -1345,21 +2315,66 @@ impl TaskInner {
 impl Task {
     /// 对进程的资源进行预回收，将会回收 trap 帧、子进程控制块列表、文件描述符表等资源。
     pub fn pre_recycle(&self) {
-        // recycle trap page
-        let trap_frame_ptr = self.trap_frame_ptr() as usize;
-        self.access_inner()
-            .address_space
-            .lock()
-            .unmap_region(VirtAddr::from(trap_frame_ptr), FRAME_SIZE)
-            .unwrap();
+        // 与父进程共享地址空间的子进程在离开时已经回收了 trap 页
+        let shared = self.leave_shared_address_space();
+        if !shared {
+            // recycle trap page
+            let trap_frame_ptr = self.trap_frame_ptr() as usize;
+            self.access_inner()
+                .address_space
+                .lock()
+                .unmap_region(VirtAddr::from(trap_frame_ptr), FRAME_SIZE)
+                .unwrap();
+        }
         let mut inner = self.inner.lock();
         // delete child process
         inner.children.clear();
         let thread_number = inner.thread_number;
         if thread_number == 0 {
-            let _ = inner.fd_table.lock().clear();
+            if !shared {
+                inner.release_shared_mappings();
+                unlock_range(&inner.address_space, 0..usize::MAX);
+                release_all_huge_pages(&inner.address_space);
+            }
+            // 以 CLONE_FILES 共享的文件描述符表仍被其它进程使用
+            if Arc::strong_count(&inner.fd_table) == 1 {
+                let _ = inner.fd_table.lock().clear();
+            }
             drop(inner);
+            vfs::proc::remove_process(self.pid);
+        }
+    }
+
+    /// 以 CLONE_VM 创建的子进程(如 vfork 创建的子进程)离开与父进程共享的地址空间。
+    ///
+    /// 解除其 trap 页的映射并归还在父进程中占用的线程序号，此后该进程作为独立的进程使用 0 号 trap 页。
+    /// 在执行新程序或者退出时调用，共享地址空间中的其它映射仍属于父进程，不能释放。
+    /// 当前任务是这样的子进程时返回 true，线程与独立的进程返回 false。
+    fn leave_shared_address_space(&self) -> bool {
+        let (thread_number, parent, address_space) = {
+            let inner = self.inner.lock();
+            (
+                inner.thread_number,
+                inner.parent.clone(),
+                inner.address_space.clone(),
+            )
+        };
+        if thread_number == 0 || self.pid != self.get_tid() as usize {
+            return false;
         }
+        let trap_frame_ptr = TRAP_CONTEXT_BASE - thread_number * FRAME_SIZE;
+        address_space
+            .lock()
+            .unmap_region(VirtAddr::from(trap_frame_ptr), FRAME_SIZE)
+            .unwrap();
+        if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+            let mut parent_inner = parent.inner.lock();
+            if Arc::ptr_eq(&parent_inner.address_space, &address_space) {
+                let _ = parent_inner.threads.remove(thread_number - 1);
+            }
+        }
+        self.inner.lock().thread_number = 0;
+        true
     }
 
     /// 获取进程的 `clear_child_tid` 字段
@@ -1371,28 +2386,34 @@ impl Task {
     pub fn from_elf(name: &str, elf: &[u8]) -> Option<Task> {
         let tid = TidHandle::new()?;
         let pid = tid.0;
+        let ns_pid = PidHandle::new(&ROOT_PID_NAMESPACE, tid.0).ok()?;
         // 创建进程地址空间
-        let mut args = vec![];
-        let elf_info = build_elf_address_space(elf, &mut args, "/bin/init");
+        let elf_info = build_elf_address_space(elf, "/bin/init", VaLayout::new(0));
         if elf_info.is_err() {
             return None;
         }
         let elf_info = elf_info.unwrap();
-        let address_space = elf_info.address_space;
+        let address_space = Arc::new(Mutex::new(elf_info.address_space));
+        register_address_space(pid, name, &address_space);
+        vfs::proc::add_process(pid);
         let k_stack = Stack::new(USER_KERNEL_STACK_SIZE / FRAME_SIZE)?;
         let k_stack_top = k_stack.top();
         let stack_info = elf_info.stack_top - USER_STACK_SIZE..elf_info.stack_top;
         let cwd = vfs::system_root_fs();
+        let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new()));
+        // 注册线程-信号对应关系
+        global_register_signals(tid.0, pid, signal_receivers.clone());
 
         let process = Task {
             tid,
             kernel_stack: k_stack,
             pid,
+            ns_pid,
             inner: Mutex::new(TaskInner {
                 name: name.to_string(),
                 threads: MinimalManager::new(MAX_THREAD_NUM),
                 thread_number: 0,
-                address_space: Arc::new(Mutex::new(address_space)),
+                address_space,
                 state: TaskState::Ready,
                 parent: None,
                 children: Vec::new(),
@@ -1412,9 +2433,16 @@ impl Task {
                     elf_info.heap_bottom,
                     elf_info.heap_bottom,
                 ))),
-                mmap: MMapInfo::new(),
+                mmap: {
+                    let mut mmap =
+                        MMapInfo::new(elf_info.mmap_base, elf_info.stack_top - USER_STACK_MAX_SIZE);
+                    if let Some(range) = elf_info.interp_range.clone() {
+                        mmap.reserve_range(range);
+                    }
+                    mmap
+                },
                 signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
-                signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
+                signal_receivers,
                 set_child_tid: 0,
                 clear_child_tid: 0,
                 trap_cx_before_signal: None,
@@ -1428,14 +2456,25 @@ impl Task {
                 },
                 unmask: 0o022,
                 stack: stack_info,
+                resource_limits: ResourceLimits::new(),
+                cred: Credentials::root(),
                 need_wait: 0,
+                mlockall: MlockallFlags::empty(),
+                sig_alt_stack: SignalStack::default(),
+                personality: 0,
+                auxv: Vec::new(),
+                ptrace: PtraceState::default(),
+                vfork_done: None,
+                ns: INIT_NAMESPACES.clone(),
             }),
             send_sigchld_when_exit: false,
         };
-        let phy_button = process.transfer_raw(elf_info.stack_top - FRAME_SIZE);
-        let mut user_stack = UserStack::new(phy_button + FRAME_SIZE, elf_info.stack_top);
+        let mut user_stack = UserStack::new(elf_info.stack_top, |addr| {
+            process.access_inner().user_writable_addr(addr)
+        });
         user_stack.push(0).unwrap();
         let argc_ptr = user_stack.push(0).unwrap();
+        process.access_inner().cred.inc_tasks();
 
         let trap_frame = process.trap_frame();
         *trap_frame = TrapFrame::init_for_task(
@@ -1459,7 +2498,8 @@ impl Task {
     /// `tls`用于为子进程创建新的TLS(thread-local storage)值，在flag包含`CLONE_SETTLS`时才会实际产生效果。
     /// `ctid`用于给子进程中的[`set_child_tid`]和[`clear_child_tid`]赋值(分别在flag中包含`CLONE_CHILD_SETTID`和`CLONE_CHILD_CLEARTID`时产生效果)。
     ///
-    /// 成功创建子进程后父进程会返回子进程的TCB。
+    /// 成功创建子进程后父进程会返回子进程的TCB；当同一真实用户的任务数超过 `RLIMIT_NPROC` 的限制或无法分配 tid 时返回 EAGAIN，
+    /// 无法分配内核栈时返回 ENOMEM。
     ///
     /// Note: 当传入的ptid未在父进程地址空间中被分配时，会引发panic。
     pub fn t_clone(
@@ -1470,21 +2510,67 @@ impl Task {
         ptid: usize,
         tls: usize,
         ctid: usize,
-    ) -> Option<Arc<Task>> {
+    ) -> AlienResult<Arc<Task>> {
         warn!(
             "clone: flag:{:?}, sig:{:?}, stack:{:#x}, ptid:{:#x}, tls:{:#x}, ctid:{:#x}",
             flag, sig, stack, ptid, tls, ctid
         );
-        let tid = TidHandle::new()?;
+        // RLIMIT_NPROC 限制的是同一真实用户的任务数量，拥有 CAP_SYS_RESOURCE 或 CAP_SYS_ADMIN 能力时不受限制
+        let (count, nproc, privileged) = {
+            let inner = self.access_inner();
+            let cred = &inner.cred;
+            (
+                cred.task_count(),
+                inner.resource_limits.cur(RLimitRes::Nproc),
+                cred.has_cap(Capabilities::SYS_RESOURCE) || cred.has_cap(Capabilities::SYS_ADMIN),
+            )
+        };
+        if !privileged && count as u64 >= nproc {
+            return Err(LinuxErrno::EAGAIN);
+        }
         let mut inner = self.inner.lock();
+        let tid = TidHandle::new().ok_or(LinuxErrno::EAGAIN)?;
+        // 线程与其线程组位于同一个 pid 命名空间，其余任务位于创建者的子进程 pid 命名空间中
+        let ns = inner.ns.copy(flag)?;
+        let pid_ns = if flag.contains(CloneFlags::CLONE_THREAD) {
+            if !Arc::ptr_eq(&ns.pid_for_children, self.pid_namespace()) {
+                return Err(LinuxErrno::EINVAL);
+            }
+            self.pid_namespace().clone()
+        } else {
+            ns.pid_for_children.clone()
+        };
+        let ns_pid = PidHandle::new(&pid_ns, tid.0)?;
         let address_space = if flag.contains(CloneFlags::CLONE_VM) {
             // to create thread
             inner.address_space.clone()
         } else {
             // to create process
+            // 共享文件映射的页由子进程在缺页时重新映射，设置了 MADV_DONTFORK 的区域不被子进程继承
+            let skip = inner
+                .mmap
+                .regions()
+                .filter(|region| region.page_cache().is_some() || region.dont_fork)
+                .map(|region| region.start..region.start + region.map_len)
+                .collect::<Vec<_>>();
+            // 大页拆分为普通的页后才能写时复制
+            split_huge_pages(&inner.address_space, 0..usize::MAX);
             let address_space =
-                build_cow_address_space(&mut inner.address_space.lock(), inner.shm.clone());
-            Arc::new(Mutex::new(address_space))
+                build_cow_address_space(&mut inner.address_space.lock(), inner.shm.clone(), &skip)?;
+            let address_space = Arc::new(Mutex::new(address_space));
+            fork_swapped_pages(&inner.address_space, &address_space);
+            inner
+                .mmap
+                .regions()
+                .filter(|region| region.dont_fork)
+                .for_each(|region| {
+                    release_swapped_pages(
+                        &address_space,
+                        region.start..region.start + region.map_len,
+                    )
+                });
+            register_address_space(tid.0, &inner.name, &address_space);
+            address_space
         };
 
         let fd_table = if flag.contains(CloneFlags::CLONE_FILES) {
@@ -1505,7 +2591,7 @@ impl Task {
             Some(Arc::downgrade(self))
         };
 
-        let k_stack = Stack::new(USER_KERNEL_STACK_SIZE / FRAME_SIZE)?;
+        let k_stack = Stack::new(USER_KERNEL_STACK_SIZE / FRAME_SIZE).ok_or(LinuxErrno::ENOMEM)?;
         let k_stack_top = k_stack.top();
         let pid = if flag.contains(CloneFlags::CLONE_THREAD) {
             self.pid
@@ -1514,7 +2600,7 @@ impl Task {
         };
         let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new()));
         // 注册线程-信号对应关系
-        global_register_signals(tid.0, signal_receivers.clone());
+        global_register_signals(tid.0, pid, signal_receivers.clone());
         // map the thread trap_context if clone_vm
         let (trap_context, thread_num) = if flag.contains(CloneFlags::CLONE_VM) {
             let thread_num = inner.threads.insert(()).unwrap() + 1;
@@ -1545,14 +2631,15 @@ impl Task {
             trap_context.update_tp(tls);
         }
 
-        // 检查是否在父任务地址中写入 tid
+        // 检查是否在父任务地址中写入 tid，写入的是其在父任务的 pid 命名空间中的 pid
         if flag.contains(CloneFlags::CLONE_PARENT_SETTID) {
             // 有可能这个地址是 lazy alloc 的，需要先检查
             let res = inner.address_space.lock().query(VirtAddr::from(ptid));
             if res.is_ok() {
                 let (physical, _, _) = res.unwrap();
+                let parent_pid = self.pid_namespace().pid_of(tid.0).unwrap_or(0);
                 unsafe {
-                    *(physical.as_usize() as *mut i32) = tid.0 as i32;
+                    *(physical.as_usize() as *mut i32) = parent_pid as i32;
                 }
             } else {
                 panic!("clone: ptid is not mapped")
@@ -1562,7 +2649,7 @@ impl Task {
         let ctid_value = if flag.contains(CloneFlags::CLONE_CHILD_SETTID)
             || flag.contains(CloneFlags::CLONE_CHILD_CLEARTID)
         {
-            tid.0
+            ns_pid.pid()
         } else {
             0
         };
@@ -1577,7 +2664,6 @@ impl Task {
             }
         }
         if stack != 0 {
-            assert!(flag.contains(CloneFlags::CLONE_VM));
             // set the sp of the new process
             trap_context.regs()[2] = stack;
         }
@@ -1587,6 +2673,7 @@ impl Task {
             tid,
             kernel_stack: k_stack,
             pid,
+            ns_pid,
             inner: Mutex::new(TaskInner {
                 name: inner.name.clone(),
                 threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -1602,7 +2689,11 @@ impl Task {
                 timer: TaskTimer::default(),
                 exit_code: 0,
                 heap,
-                mmap: inner.mmap.clone(),
+                mmap: if flag.contains(CloneFlags::CLONE_VM) {
+                    inner.mmap.clone()
+                } else {
+                    inner.mmap.fork()
+                },
                 signal_handlers,
                 signal_receivers,
                 set_child_tid: if flag.contains(CloneFlags::CLONE_CHILD_SETTID) {
@@ -1626,16 +2717,34 @@ impl Task {
                 },
                 unmask: 0o022,
                 stack: inner.stack.clone(),
+                resource_limits: inner.resource_limits.clone(),
+                cred: inner.cred.clone(),
                 need_wait: 0,
+                mlockall: MlockallFlags::empty(),
+                // 共享地址空间的线程不继承备用栈，fork 产生的子进程继承
+                sig_alt_stack: if flag.contains(CloneFlags::CLONE_VM) {
+                    SignalStack::default()
+                } else {
+                    inner.sig_alt_stack
+                },
+                personality: inner.personality,
+                auxv: inner.auxv.clone(),
+                ptrace: PtraceState::default(),
+                vfork_done: None,
+                ns,
             }),
             send_sigchld_when_exit: sig == SignalNumber::SIGCHLD,
         };
+        inner.cred.inc_tasks();
         let task = Arc::new(task);
         if !flag.contains(CloneFlags::CLONE_PARENT) {
             inner.children.push(task.clone());
         }
+        if !flag.contains(CloneFlags::CLONE_THREAD) {
+            vfs::proc::add_process(task.pid);
+        }
         error!("create a task success");
-        Some(task)
+        Ok(task)
     }
 
     /// 用于执行一个可执行文件，供sys_exec调用。
@@ -1644,38 +2753,79 @@ impl Task {
     /// `elf_data`用于传入从对应文件处读入的文件数据，用于构造elf_info。
     /// `args`用于指明启动可执行文件时要传入的参数。
     /// `env`用于指明相关环境变量。
+    /// `cred`用于指明执行该文件后进程的用户凭证(已经处理了 setuid/setgid 位)。
     ///
-    /// 成功执行则返回OK(())；否则返回错误码(isize)。
+    /// 成功执行则返回OK(())；无法解析文件时返回 `ENOEXEC`，参数与环境变量无法放入新程序的用户栈时返回 `E2BIG`，
+    /// 此时进程不受影响。之后写入用户栈只会因为物理页不足而失败并返回 `ENOMEM`，
+    /// 此时原来的地址空间已经被替换，调用者需要终止该进程。
     pub fn exec(
         &self,
         name: &str,
         elf_data: &[u8],
         args: Vec<String>,
         env: Vec<String>,
-    ) -> Result<(), isize> {
-        let mut args = args;
-        let elf_info = build_elf_address_space(elf_data, &mut args, name);
-        if elf_info.is_err() {
-            return Err(-1);
+        cred: Credentials,
+    ) -> AlienResult<()> {
+        let env = if env.is_empty() {
+            let envp = vec![
+                "LD_LIBRARY_PATH=/:/tests:/bin",
+                "PS1=\x1b[1m\x1b[32mAlien\x1b[0m:\x1b[1m\x1b[34m\\w\x1b[0m\\$ \0",
+                "PATH=/bin:/sbin:/usr/bin:/tests",
+                "UB_BINDIR=./",
+            ]
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+            envp
+        } else {
+            env
+        };
+        // 在替换原来的地址空间之前检查参数与环境变量能否放入新程序的用户栈，此时失败进程仍然可以返回
+        let (layout, stack_limit) = {
+            let inner = self.access_inner();
+            let stack_limit = inner.resource_limits.cur(RLimitRes::Stack);
+            (
+                VaLayout::new(inner.personality),
+                min(stack_limit, USER_STACK_MAX_SIZE as u64) as usize / 4,
+            )
+        };
+        if exec_stack_size(name, &args, &env) > stack_limit {
+            return Err(LinuxErrno::E2BIG);
         }
-        let elf_info = elf_info.unwrap();
+        let elf_info = build_elf_address_space(elf_data, name, layout).map_err(|e| match e {
+            ELFError::NoMemory => LinuxErrno::ENOMEM,
+            _ => LinuxErrno::ENOEXEC,
+        })?;
+        let shared = self.leave_shared_address_space();
         let mut inner = self.inner.lock();
         assert_eq!(inner.thread_number, 0);
         let name = elf_info.name;
         let address_space = elf_info.address_space;
+        // write back the shared file mappings of the old program
+        // vfork 创建的子进程的原地址空间仍被父进程使用
+        if !shared {
+            inner.release_shared_mappings();
+            unlock_range(&inner.address_space, 0..usize::MAX);
+            release_all_huge_pages(&inner.address_space);
+        }
         // reset the address space
         inner.address_space = Arc::new(Mutex::new(address_space));
+        register_address_space(self.pid, &name, &inner.address_space);
         // reset the heap
         inner.heap = Arc::new(Mutex::new(HeapInfo::new(
             elf_info.heap_bottom,
             elf_info.heap_bottom,
         )));
-        // reset the mmap
-        inner.mmap = MMapInfo::new();
+        // reset the mmap, the interpreter lives in the mmap area
+        inner.mmap = MMapInfo::new(elf_info.mmap_base, elf_info.stack_top - USER_STACK_MAX_SIZE);
+        if let Some(range) = elf_info.interp_range.clone() {
+            inner.mmap.reserve_range(range);
+        }
+        inner.mlockall = MlockallFlags::empty();
+        inner.sig_alt_stack = SignalStack::default();
         // set the name of the process
         inner.name = name.to_string();
-        // reset time record
-        inner.statistical_data.clear();
+        // 执行新程序不重置已经使用的 CPU 时间，否则可以通过反复 exec 绕过 RLIMIT_CPU
         // close file which contains FD_CLOEXEC flag
         // now we delete all fd
         // inner.fd_table =
@@ -1684,91 +2834,76 @@ impl Task {
         inner.signal_receivers.lock().clear();
         inner.timer.clear();
         inner.stack = elf_info.stack_top - USER_STACK_SIZE..elf_info.stack_top;
-        let env = if env.is_empty() {
-            let envp = vec![
-                "LD_LIBRARY_PATH=/:/tests:/bin",
-                "PS1=\x1b[1m\x1b[32mAlien\x1b[0m:\x1b[1m\x1b[34m\\w\x1b[0m\\$ \0",
-                "PATH=/bin:/sbin:/usr/bin:/tests",
-                "UB_BINDIR=./",
-            ]
-            .iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
-            envp
-        } else {
-            env
-        };
-        // we need make sure the args and env size is less than 4KB
-        let phy_button = inner.transfer_raw(elf_info.stack_top - FRAME_SIZE);
-        let mut user_stack = UserStack::new(phy_button + FRAME_SIZE, elf_info.stack_top);
+        // 以 setuid/setgid 程序运行时，需要告知动态链接器进入安全模式
+        let secure = cred.euid != cred.ruid || cred.egid != cred.rgid;
+        let (uid, euid, gid, egid) = (cred.ruid, cred.euid, cred.rgid, cred.egid);
+        inner.cred = cred;
+        // 参数与环境变量可以占用多页，用户栈在写入时按需拓展
+        let mut user_stack =
+            UserStack::new(elf_info.stack_top, |addr| inner.user_writable_addr(addr));
         // push env to the top of stack of the process
         // we have push '\0' into the env string,so we don't need to push it again
         let envv = env
             .iter()
             .rev()
-            .map(|env| user_stack.push_str(env).unwrap())
-            .collect::<Vec<usize>>();
+            .map(|env| user_stack.push_str(env))
+            .collect::<AlienResult<Vec<usize>>>()?;
         // push the args to the top of stack of the process
         // we have push '\0' into the arg string,so we don't need to push it again
         let argcv = args
             .iter()
             .rev()
-            .map(|arg| user_stack.push_str(arg).unwrap())
-            .collect::<Vec<usize>>();
+            .map(|arg| user_stack.push_str(arg))
+            .collect::<AlienResult<Vec<usize>>>()?;
         // push padding to the top of stack of the process
-        user_stack.align_to(8).unwrap();
-        let random_ptr = user_stack.push_bytes(&[0u8; 16]).unwrap();
+        user_stack.align_to(8)?;
+        let mut random = [0u8; 16];
+        get_random_bytes(&mut random);
+        let random_ptr = user_stack.push_bytes(&random)?;
         // padding
-        user_stack.push_bytes(&[0u8; 8]).unwrap();
+        user_stack.push_bytes(&[0u8; 8])?;
         // push aux
-        let platform = user_stack.push_str("riscv").unwrap();
-
-        let ex_path = user_stack.push_str(&name).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(platform).unwrap();
-        user_stack.push(AT_PLATFORM).unwrap();
-        user_stack.push(ex_path).unwrap();
-        user_stack.push(AT_EXECFN).unwrap();
-        user_stack.push(elf_info.ph_num).unwrap();
-        user_stack.push(AT_PHNUM).unwrap();
-        user_stack.push(FRAME_SIZE).unwrap();
-        user_stack.push(AT_PAGESZ).unwrap();
-
-        user_stack.push(elf_info.bias).unwrap();
-        user_stack.push(AT_BASE).unwrap();
-        user_stack.push(elf_info.entry).unwrap();
-        user_stack.push(AT_ENTRY).unwrap();
-        user_stack.push(elf_info.ph_entry_size).unwrap();
-        user_stack.push(AT_PHENT).unwrap();
-        user_stack.push(elf_info.ph_drift).unwrap();
-        user_stack.push(AT_PHDR).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_GID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_EGID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_UID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_EUID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_SECURE).unwrap();
-        user_stack.push(random_ptr).unwrap();
-        user_stack.push(AT_RANDOM).unwrap();
+        let platform = user_stack.push_str("riscv")?;
+
+        let ex_path = user_stack.push_str(&name)?;
+        // 辅助向量按照地址从低到高的顺序排列
+        let auxv = vec![
+            (AT_RANDOM, random_ptr),
+            (AT_SECURE, secure as usize),
+            (AT_EUID, euid as usize),
+            (AT_UID, uid as usize),
+            (AT_EGID, egid as usize),
+            (AT_GID, gid as usize),
+            (AT_PHDR, elf_info.ph_drift),
+            (AT_PHENT, elf_info.ph_entry_size),
+            (AT_ENTRY, elf_info.program_entry),
+            (AT_BASE, elf_info.interp_base),
+            (AT_PAGESZ, FRAME_SIZE),
+            (AT_PHNUM, elf_info.ph_num),
+            (AT_EXECFN, ex_path),
+            (AT_PLATFORM, platform),
+        ];
+        user_stack.push(0)?;
+        for (key, value) in auxv.iter().rev() {
+            user_stack.push(*value)?;
+            user_stack.push(*key)?;
+        }
 
-        user_stack.push(0).unwrap();
+        user_stack.push(0)?;
         // push the env addr to the top of stack of the process
-        envv.iter().for_each(|env| {
-            user_stack.push(*env).unwrap();
-        });
-        user_stack.push(0).unwrap();
+        for env_ptr in envv {
+            user_stack.push(env_ptr)?;
+        }
+        user_stack.push(0)?;
         // push the args addr to the top of stack of the process
-        argcv.iter().enumerate().for_each(|(_i, arg)| {
-            user_stack.push(*arg).unwrap();
-        });
+        for arg_ptr in argcv {
+            user_stack.push(arg_ptr)?;
+        }
         // push the argc to the top of stack of the process
         let argc = args.len();
-        let argc_ptr = user_stack.push(argc).unwrap();
+        let argc_ptr = user_stack.push(argc)?;
         let user_sp = argc_ptr;
+        inner.auxv = auxv;
         warn!("args:{:?}, env:{:?}, user_sp: {:#x}", args, env, user_sp);
         let (physical, _, _) = inner
             .address_space
@@ -1787,3 +2922,18 @@ impl Task {
         Ok(())
     }
 }
+
+/// 估算执行新程序时放入用户栈的数据的总大小，包括参数、环境变量、程序路径、辅助向量以及指向它们的指针数组
+fn exec_stack_size(name: &str, args: &[String], env: &[String]) -> usize {
+    // 每个字符串都按 8 字节对齐
+    let strings = args
+        .iter()
+        .chain(env.iter())
+        .map(|s| (s.len() + 7) & !7)
+        .sum::<usize>();
+    // 程序路径、平台名称、16 字节的随机数与填充
+    let fixed = ((name.len() + 8) & !7) + 8 + 16 + 8 + 8;
+    // argc、以 0 结尾的参数与环境变量的指针数组，以及至多 16 项的辅助向量
+    let pointers = (1 + args.len() + 1 + env.len() + 1 + 2 * 16 + 1) * size_of::<usize>();
+    strings + fixed + pointers
+}
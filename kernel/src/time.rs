@@ -13,14 +13,17 @@
 use constants::{
     sys::TimeVal,
     time::{ClockId, TimerType},
-    LinuxErrno,
+    AlienResult, LinuxErrno,
 };
 use log::{info, warn};
 use platform::{config::CLOCK_FREQ, set_timer};
 use syscall_table::syscall_func;
 use timer::{read_timer, ITimerVal, TimeNow, TimeSpec, Times};
 
-use crate::task::{current_task, do_suspend, StatisticalData};
+use crate::{
+    mm::uaccess::UserPtr,
+    task::{current_task, do_suspend, StatisticalData},
+};
 
 /// 每秒包含的 时间片 数，每隔一个时间片，就会产生一个时钟中断
 const TICKS_PER_SEC: usize = 10;
@@ -49,12 +52,11 @@ pub fn set_next_trigger_in_kernel() {
 ///
 /// Reference: [get_time_of_day](https://man7.org/linux/man-pages/man2/gettimeofday.2.html)
 #[syscall_func(169)]
-pub fn get_time_of_day(tv: *mut u8) -> isize {
+pub fn get_time_of_day(tv: *mut u8) -> AlienResult<isize> {
     let time = TimeVal::now();
     let process = current_task().unwrap();
-    let tv = process.transfer_raw_ptr(tv as *mut TimeVal);
-    *tv = time;
-    0
+    process.write_user(UserPtr::<TimeVal>::new(tv as usize), &time)?;
+    Ok(0)
 }
 
 /// 一个系统调用函数，获取当前进程在用户态/内核态下运行的时间、最后一次运行在用户态/内核态下的时间等，
@@ -62,12 +64,12 @@ pub fn get_time_of_day(tv: *mut u8) -> isize {
 ///
 /// Reference: [times](https://man7.org/linux/man-pages/man2/times.2.html)
 #[syscall_func(153)]
-pub fn times(tms: *mut u8) -> isize {
+pub fn times(tms: *mut u8) -> AlienResult<isize> {
     let mut task = current_task().unwrap().access_inner();
     let statistic_data = task.statistical_data();
     let time = times_from_process_data(statistic_data);
-    task.copy_to_user(&time, tms as *mut Times);
-    0
+    task.write_user(UserPtr::<Times>::new(tms as usize), &time)?;
+    Ok(0)
 }
 
 /// 从一个 [`StatisticalData`] 结构 (一般为 task 的 statistical_data 字段) 得到一个 `Times` 变量
@@ -86,11 +88,9 @@ pub fn times_from_process_data(data: &StatisticalData) -> Times {
 ///
 /// Reference: [nanosleep](https://man7.org/linux/man-pages/man2/nanosleep.2.html)
 #[syscall_func(101)]
-pub fn nanosleep(req: *mut u8, _: *mut u8) -> isize {
+pub fn nanosleep(req: *mut u8, _: *mut u8) -> AlienResult<isize> {
     let task = current_task().unwrap().clone();
-    let mut time = TimeSpec::new(0, 0);
-    task.access_inner()
-        .copy_from_user(req as *const TimeSpec, &mut time);
+    let time = task.read_user(UserPtr::<TimeSpec>::new(req as usize))?;
     warn!("nanosleep: {:?}", time);
     let end_time = read_timer() + time.to_clock();
     loop {
@@ -103,10 +103,10 @@ pub fn nanosleep(req: *mut u8, _: *mut u8) -> isize {
         let task_inner = task.access_inner();
         let receiver = task_inner.signal_receivers.lock();
         if receiver.have_signal() {
-            return LinuxErrno::EINTR as isize;
+            return Err(LinuxErrno::EINTR);
         }
     }
-    0
+    Ok(0)
 }
 
 /// 一个系统调用函数，可以根据输入的时钟类型`clock_id`来获取当前的时间，获取的时间将存储在`tp`所指向的[`TimeSpec`]结构处。
@@ -116,19 +116,19 @@ pub fn nanosleep(req: *mut u8, _: *mut u8) -> isize {
 ///
 /// Reference: [clock_get_time](https://www.man7.org/linux/man-pages/man3/clock_gettime.3.html)
 #[syscall_func(113)]
-pub fn clock_get_time(clock_id: usize, tp: *mut u8) -> isize {
+pub fn clock_get_time(clock_id: usize, tp: *mut u8) -> AlienResult<isize> {
     let id = ClockId::from_raw(clock_id).unwrap();
     let task = current_task().unwrap();
     match id {
         ClockId::Monotonic | ClockId::Realtime | ClockId::ProcessCputimeId => {
             let time = TimeSpec::now();
-            task.access_inner().copy_to_user(&time, tp as *mut TimeSpec)
+            task.write_user(UserPtr::<TimeSpec>::new(tp as usize), &time)?;
         }
         _ => {
             panic!("clock_get_time: clock_id {:?} not supported", id);
         }
     }
-    0
+    Ok(0)
 }
 
 /// 当发生时钟中断时，`trap_handler` 会调用该函数检查所有计时器队列中的计时器，并唤醒等待在这些计时器上的进程
@@ -142,16 +142,15 @@ pub fn check_timer_queue() {}
 /// 函数执行成功则返回0。
 /// Reference: [getitimer](https://man7.org/linux/man-pages/man2/setitimer.2.html)
 #[syscall_func(102)]
-pub fn getitimer(_which: usize, current_value: usize) -> isize {
+pub fn getitimer(_which: usize, current_value: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let timer = &task.access_inner().timer;
+    let timer = task.access_inner().get_timer();
     let itimer = ITimerVal {
         it_interval: timer.timer_interval,
         it_value: timer.timer_remained.into(),
     };
-    task.access_inner()
-        .copy_to_user(&itimer, current_value as *mut ITimerVal);
-    0
+    task.write_user(UserPtr::<ITimerVal>::new(current_value), &itimer)?;
+    Ok(0)
 }
 
 /// 一个系统调用函数，用于将当前进程的定时器设置为`current_value`指向的[`ITimerVal`]结构处，
@@ -164,7 +163,7 @@ pub fn getitimer(_which: usize, current_value: usize) -> isize {
 /// 函数执行正确则返回0。
 /// Reference: [setitimer](https://man7.org/linux/man-pages/man2/setitimer.2.html)
 #[syscall_func(103)]
-pub fn setitimer(which: usize, current_value: usize, old_value: usize) -> isize {
+pub fn setitimer(which: usize, current_value: usize, old_value: usize) -> AlienResult<isize> {
     let which = TimerType::try_from(which).unwrap();
     assert_ne!(which, TimerType::NONE);
     info!(
@@ -178,16 +177,13 @@ pub fn setitimer(which: usize, current_value: usize, old_value: usize) -> isize
             it_interval: timer.timer_interval.into(),
             it_value: timer.timer_remained.into(),
         };
-        task.access_inner()
-            .copy_to_user(&itimer, old_value as *mut ITimerVal);
+        task.write_user(UserPtr::<ITimerVal>::new(old_value), &itimer)?;
     }
     assert_ne!(current_value, 0);
-    let mut itimer = ITimerVal::default();
-    task.access_inner()
-        .copy_from_user(current_value as *const ITimerVal, &mut itimer);
+    let itimer = task.read_user(UserPtr::<ITimerVal>::new(current_value))?;
     info!("setitimer: itimer {:x?}", itimer);
     task.access_inner().set_timer(itimer, which);
-    0
+    Ok(0)
 }
 
 /// 一个系统调用函数，可以根据输入的时钟类型`clock_id`来获取该时钟分辨率(精度)，获取的精度将存储在`res`所指向的[`TimeSpec`]结构处。
@@ -195,7 +191,7 @@ pub fn setitimer(which: usize, current_value: usize, old_value: usize) -> isize
 ///
 /// Reference: [clock_getres](https://www.man7.org/linux/man-pages/man3/clock_getres.3.html)
 #[syscall_func(114)]
-pub fn clock_getres(id: usize, res: usize) -> isize {
+pub fn clock_getres(id: usize, res: usize) -> AlienResult<isize> {
     let id = ClockId::from_raw(id).unwrap();
     info!("clock_getres: id {:?} ,res {:#x}", id, res);
     let task = current_task().unwrap();
@@ -208,9 +204,8 @@ pub fn clock_getres(id: usize, res: usize) -> isize {
             panic!("clock_get_time: clock_id {:?} not supported", id);
         }
     };
-    task.access_inner()
-        .copy_to_user(&time_res, res as *mut TimeSpec);
-    0
+    task.write_user(UserPtr::<TimeSpec>::new(res), &time_res)?;
+    Ok(0)
 }
 
 /// 一个系统调用函数，如`nanosleep`一样，暂停本进程直到一段时间后结束，但`clock_nanosleep`可以根据传入的`clock_id`来指定使用的时钟类型。
@@ -222,7 +217,12 @@ pub fn clock_getres(id: usize, res: usize) -> isize {
 ///
 /// Reference: [times](https://man7.org/linux/man-pages/man2/times.2.html)
 #[syscall_func(115)]
-pub fn clock_nanosleep(clock_id: usize, flags: usize, req: usize, remain: usize) -> isize {
+pub fn clock_nanosleep(
+    clock_id: usize,
+    flags: usize,
+    req: usize,
+    remain: usize,
+) -> AlienResult<isize> {
     const TIMER_ABSTIME: usize = 1;
     let id = ClockId::from_raw(clock_id).unwrap();
     info!(
@@ -232,10 +232,8 @@ pub fn clock_nanosleep(clock_id: usize, flags: usize, req: usize, remain: usize)
     match id {
         ClockId::Monotonic => {
             assert_eq!(flags, TIMER_ABSTIME);
-            let mut target_time = TimeSpec::new(0, 0);
             let task = current_task().unwrap().clone();
-            task.access_inner()
-                .copy_from_user(req as *const TimeSpec, &mut target_time);
+            let target_time = task.read_user(UserPtr::<TimeSpec>::new(req))?;
             let end_time = target_time.to_clock();
 
             loop {
@@ -249,7 +247,7 @@ pub fn clock_nanosleep(clock_id: usize, flags: usize, req: usize, remain: usize)
                 let task_inner = task.access_inner();
                 let receiver = task_inner.signal_receivers.lock();
                 if receiver.have_signal() {
-                    return LinuxErrno::EINTR.into();
+                    return Err(LinuxErrno::EINTR);
                 }
             }
         }
@@ -257,5 +255,5 @@ pub fn clock_nanosleep(clock_id: usize, flags: usize, req: usize, remain: usize)
             panic!("clock_nanotime: clock_id {:?} not supported", id);
         }
     }
-    0
+    Ok(0)
 }
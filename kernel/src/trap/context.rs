@@ -99,4 +99,17 @@ impl TrapFrame {
     pub fn regs(&mut self) -> &mut [usize] {
         &mut self.x
     }
+
+    /// 按照 riscv64 Linux 的 `struct user_regs_struct` 的布局获取寄存器，依次为 pc 与 x1 ~ x31
+    pub fn user_regs(&self) -> [usize; 32] {
+        let mut regs = self.x;
+        regs[0] = self.sepc;
+        regs
+    }
+
+    /// 按照 `struct user_regs_struct` 的布局设置 pc 与 x1 ~ x31，x0 保持为 0
+    pub fn set_user_regs(&mut self, regs: &[usize; 32]) {
+        self.sepc = regs[0];
+        self.x[1..].copy_from_slice(&regs[1..]);
+    }
 }
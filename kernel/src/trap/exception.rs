@@ -1,16 +1,22 @@
 //! Alien 中对于内部异常的处理
 //!
-//! 目前包括系统调用异常处理 [`syscall_exception_handler`]、页错误异常处理 [`page_exception_handler`] (包括
+//! 目前包括系统调用异常处理 [`syscall_exception_handler`]、断点异常处理 [`breakpoint_exception_handler`]、页错误异常处理 [`page_exception_handler`] (包括
 //! 指令页错误异常处理 [`instruction_page_fault_exception_handler`]、 加载页错误异常处理[`load_page_fault_exception_handler`]、
 //! 储存页错误异常处理 [`store_page_fault_exception_handler`]) 和 文件读入异常处理 [`trap_common_read_file`]。
 use alloc::sync::Arc;
 
 use arch::interrupt_enable;
-use constants::{AlienError, AlienResult};
+use constants::{signal::SignalNumber, AlienError, AlienResult};
 use riscv::register::scause::{Exception, Trap};
 use vfs::kfile::File;
 
-use crate::task::{current_task, current_trap_frame};
+use crate::{
+    ipc::{
+        send_signal_info,
+        sigqueue::{SignalInfo, TRAP_BRKPT, TRAP_TRACE},
+    },
+    task::{current_task, current_trap_frame, ptrace_step_done, ptrace_syscall_stop},
+};
 
 /// 系统调用异常处理
 pub fn syscall_exception_handler() {
@@ -19,11 +25,13 @@ pub fn syscall_exception_handler() {
     // jump to next instruction anyway
     let mut cx = current_trap_frame();
     cx.update_sepc();
+    let task = current_task().unwrap();
+    // 被跟踪的进程在系统调用的入口停止，跟踪者可能修改系统调用号与参数
+    ptrace_syscall_stop(task);
     // get system call return value
     let parameters = cx.parameters();
     let syscall_name = constants::syscall_name(parameters[0]);
 
-    let task = current_task().unwrap();
     let p_name = task.get_name();
     let tid = task.get_tid();
     let pid = task.get_pid();
@@ -45,15 +53,20 @@ pub fn syscall_exception_handler() {
         );
     }
 
-    let result = invoke_call_id!(
-        parameters[0],
-        parameters[1],
-        parameters[2],
-        parameters[3],
-        parameters[4],
-        parameters[5],
-        parameters[6]
-    );
+    // 跟踪者将系统调用号修改为 -1 时跳过该系统调用，返回值保持为跟踪者设置的 a0
+    let result = if parameters[0] == usize::MAX {
+        parameters[1] as isize
+    } else {
+        invoke_call_id!(
+            parameters[0],
+            parameters[1],
+            parameters[2],
+            parameters[3],
+            parameters[4],
+            parameters[5],
+            parameters[6]
+        )
+    };
     let result = Some(result);
     // cx is changed during sys_exec, so we have to call it again
     cx = current_trap_frame();
@@ -69,6 +82,22 @@ pub fn syscall_exception_handler() {
         );
     }
     cx.update_res(result.unwrap() as usize);
+    ptrace_syscall_stop(task);
+}
+
+/// 断点异常处理
+///
+/// 单步执行插入的断点被触发时恢复原来的指令，向进程发送 SIGTRAP(`TRAP_TRACE`)；
+/// 其它 `ebreak` 指令向进程发送 SIGTRAP(`TRAP_BRKPT`)，此时 sepc 仍然指向该指令。
+pub fn breakpoint_exception_handler(sepc: usize) {
+    let task = current_task().unwrap();
+    let code = if ptrace_step_done(task, sepc) {
+        TRAP_TRACE
+    } else {
+        TRAP_BRKPT
+    };
+    let info = SignalInfo::fault(SignalNumber::SIGTRAP as usize, code, sepc);
+    let _ = send_signal_info(task.get_tid() as usize, info);
 }
 
 /// 页异常处理，会根据不同的异常类型，分发至指令页错误异常处理 [`instruction_page_fault_exception_handler`]、
@@ -23,7 +23,13 @@ use riscv::register::{
 };
 
 use crate::{
-    ipc::{send_signal, signal_handler, signal_return, solve_futex_wait},
+    ipc::{
+        force_sig_fault, send_signal, signal_handler, signal_return,
+        sigqueue::{SignalInfo, BUS_ADRERR, SEGV_ACCERR, SEGV_MAPERR},
+        solve_futex_wait,
+    },
+    mm::oom::oom_kill,
+    random::add_interrupt_randomness,
     task::{current_task, current_trap_frame, current_user_token, do_exit, do_suspend},
     time::{check_timer_queue, set_next_trigger_in_kernel},
 };
@@ -46,6 +52,7 @@ extern "C" {
 /// set the reg a0 = trap_cx_ptr, reg a1 = phy addr of usr page table,
 /// finally, jump to new addr of __restore asm function
 pub fn trap_return() -> ! {
+    oom_kill();
     signal_handler();
     interrupt_disable();
     set_user_trap_entry();
@@ -113,6 +120,9 @@ impl TrapHandler for Trap {
             Trap::Exception(Exception::UserEnvCall) => {
                 exception::syscall_exception_handler();
             }
+            Trap::Exception(Exception::Breakpoint) => {
+                exception::breakpoint_exception_handler(sepc);
+            }
             Trap::Exception(Exception::StoreFault)
             | Trap::Exception(Exception::LoadFault)
             | Trap::Exception(Exception::InstructionFault)
@@ -121,8 +131,8 @@ impl TrapHandler for Trap {
                     "[User] {:?} in application,stval:{:#x?} sepc:{:#x?}",
                     self, stval, sepc
                 );
-                let task = current_task().unwrap();
-                send_signal(task.get_tid() as usize, SignalNumber::SIGSEGV as usize)
+                let info = SignalInfo::fault(SignalNumber::SIGSEGV as usize, SEGV_ACCERR, stval);
+                force_sig_fault(info);
             }
             Trap::Exception(Exception::StorePageFault)
             | Trap::Exception(Exception::LoadPageFault) => {
@@ -142,10 +152,17 @@ impl TrapHandler for Trap {
                     if err == AlienError::EAGAIN {
                         // println!("thread need wait");
                         do_suspend();
+                    } else if err == AlienError::ENOMEM {
+                        // 内存不足时无法为该地址分配物理页，重新执行该指令只会再次失败
+                        let info =
+                            SignalInfo::fault(SignalNumber::SIGBUS as usize, BUS_ADRERR, stval);
+                        force_sig_fault(info);
                     } else if err == AlienError::ETMP {
                         do_exit(-1);
                     } else {
-                        send_signal(tid as usize, SignalNumber::SIGSEGV as usize)
+                        let info =
+                            SignalInfo::fault(SignalNumber::SIGSEGV as usize, SEGV_MAPERR, stval);
+                        force_sig_fault(info);
                     }
                 }
             }
@@ -164,21 +181,27 @@ impl TrapHandler for Trap {
                 }
 
                 let res = exception::page_exception_handler(self.clone(), stval);
-                if res.is_err() {
+                if let Err(err) = res {
                     error!(
                         "[User] {:?} in application,stval:{:#x?} sepc:{:#x?}",
                         self, stval, sepc
                     );
-                    let task = current_task().unwrap();
-                    send_signal(task.get_tid() as usize, SignalNumber::SIGSEGV as usize)
+                    let info = if err == AlienError::ENOMEM {
+                        SignalInfo::fault(SignalNumber::SIGBUS as usize, BUS_ADRERR, stval)
+                    } else {
+                        SignalInfo::fault(SignalNumber::SIGSEGV as usize, SEGV_MAPERR, stval)
+                    };
+                    force_sig_fault(info);
                 }
             }
             Trap::Interrupt(Interrupt::SupervisorTimer) => {
                 trace!("[User] timer interrupt");
+                add_interrupt_randomness(sepc);
                 interrupt::timer_interrupt_handler();
             }
             Trap::Interrupt(Interrupt::SupervisorExternal) => {
                 trace!("external interrupt");
+                add_interrupt_randomness(sepc);
                 external_interrupt_handler();
             }
             _ => {
@@ -197,6 +220,7 @@ impl TrapHandler for Trap {
         match self {
             Trap::Interrupt(Interrupt::SupervisorTimer) => {
                 trace!("[kernel] timer interrupt");
+                add_interrupt_randomness(sepc);
                 record_irq(1);
                 check_timer_queue();
                 solve_futex_wait();
@@ -219,6 +243,7 @@ impl TrapHandler for Trap {
                 )
             }
             Trap::Interrupt(Interrupt::SupervisorExternal) => {
+                add_interrupt_randomness(sepc);
                 external_interrupt_handler();
             }
             _ => {
@@ -260,10 +285,17 @@ pub fn user_trap_vector() {
 }
 
 /// 用于检查进程的计时器是否超时。如果超时则会重置计时器，并按照计时器类型向进程发送信号。
+///
+/// 同时检查进程使用的 CPU 时间是否超出了 `RLIMIT_CPU` 的限制，超出时发送 SIGXCPU 或 SIGKILL。
 pub fn check_task_timer_expired() {
     let task = current_task().unwrap();
     let timer_expired = task.access_inner().check_timer_expired();
     let tid = task.get_tid() as usize;
+    let cpu_limit_signal = task.access_inner().check_cpu_limit();
+    if let Some(signal) = cpu_limit_signal {
+        warn!("task {} exceeds cpu limit, send {:?}", tid, signal);
+        send_signal(tid, signal as usize);
+    }
     if timer_expired.is_some() {
         error!("timer expired: {:?}", timer_expired);
         let timer_type = timer_expired.unwrap();
@@ -14,6 +14,8 @@ pub const FLAG: &str = r"
 pub const FRAME_SIZE: usize = 0x1000;
 /// 物理页大小的位数
 pub const FRAME_BITS: usize = 12;
+/// 大页(Sv39 中的 megapage)大小
+pub const HUGE_PAGE_SIZE: usize = 0x20_0000;
 /// 内核启动栈大小
 pub const STACK_SIZE: usize = 1024 * 64;
 /// 内核启动栈大小的位数
@@ -32,8 +34,18 @@ pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - FRAME_SIZE;
 
 /// app内核栈大小
 pub const USER_KERNEL_STACK_SIZE: usize = 0x1000 * 2;
-/// app用户栈大小
+/// app用户栈的初始大小
 pub const USER_STACK_SIZE: usize = 0x50_000;
+/// app用户栈可以增长到的最大大小，实际大小还受到 RLIMIT_STACK 的限制
+pub const USER_STACK_MAX_SIZE: usize = 0x1000_0000;
+/// app用户栈的栈顶位置，位于 Sv39 用户地址空间的顶端，之上保留一个隔离页
+pub const USER_STACK_TOP: usize = 0x40_0000_0000 - FRAME_SIZE;
+
+/// 路径名的最大长度，包含结尾的 '\0'
+pub const PATH_MAX: usize = 4096;
+/// execve 的单个参数或环境变量的最大长度，包含结尾的 '\0'。
+/// 参数与环境变量的总大小(包括指针数组)还不能超过 RLIMIT_STACK 的 1/4
+pub const MAX_ARG_STRLEN: usize = FRAME_SIZE * 32;
 
 /// pipe缓冲区大小
 pub const PIPE_BUF: usize = 65536;
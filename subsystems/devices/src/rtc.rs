@@ -58,7 +58,9 @@ impl VfsFile for RTCDevice {
         match cmd {
             TeletypeCommand::RTC_RD_TIME => {
                 let time = self.device.read_time();
-                shim::copy_data_to_task(&time, arg as *mut RtcTime);
+                if !shim::copy_data_to_task(&time, arg as *mut RtcTime) {
+                    return Err(VfsError::Invalid);
+                }
             }
             _ => return Err(VfsError::Invalid),
         }
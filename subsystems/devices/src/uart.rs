@@ -48,6 +48,15 @@ impl UARTDevice {
     }
 }
 
+/// ioctl 与用户态之间复制数据的结果，用户地址无效时返回 `VfsError::Invalid`
+fn copy_result(copied: bool) -> VfsResult<usize> {
+    if copied {
+        Ok(0)
+    } else {
+        Err(VfsError::Invalid)
+    }
+}
+
 impl VfsFile for UARTDevice {
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         // read util \r and transform to \n
@@ -101,31 +110,29 @@ impl VfsFile for UARTDevice {
         let cmd = TeletypeCommand::try_from(cmd).unwrap();
         return match cmd {
             TeletypeCommand::TCGETS | TeletypeCommand::TCGETA => {
-                shim::copy_data_to_task(&io.termios, arg as *mut Termios);
-                Ok(0)
+                copy_result(shim::copy_data_to_task(&io.termios, arg as *mut Termios))
             }
             TeletypeCommand::TCSETS | TeletypeCommand::TCSETSW | TeletypeCommand::TCSETSF => {
-                shim::copy_data_from_task(arg as *const Termios, &mut io.termios);
-                Ok(0)
-            }
-            TeletypeCommand::TIOCGPGRP => {
-                let word = shim::transfer_ptr_mut(arg as *mut u32);
-                *word = io.foreground_pgid;
-                Ok(0)
-            }
-            TeletypeCommand::TIOCSPGRP => {
-                let word = shim::transfer_ptr(arg as *const u32);
-                io.foreground_pgid = *word;
-                Ok(0)
+                copy_result(shim::copy_data_from_task(
+                    arg as *const Termios,
+                    &mut io.termios,
+                ))
             }
+            TeletypeCommand::TIOCGPGRP => copy_result(shim::copy_data_to_task(
+                &io.foreground_pgid,
+                arg as *mut u32,
+            )),
+            TeletypeCommand::TIOCSPGRP => copy_result(shim::copy_data_from_task(
+                arg as *const u32,
+                &mut io.foreground_pgid,
+            )),
             TeletypeCommand::TIOCGWINSZ => {
-                shim::copy_data_to_task(&io.winsize, arg as *mut WinSize);
-                Ok(0)
-            }
-            TeletypeCommand::TIOCSWINSZ => {
-                shim::copy_data_from_task(arg as *const WinSize, &mut io.winsize);
-                Ok(0)
+                copy_result(shim::copy_data_to_task(&io.winsize, arg as *mut WinSize))
             }
+            TeletypeCommand::TIOCSWINSZ => copy_result(shim::copy_data_from_task(
+                arg as *const WinSize,
+                &mut io.winsize,
+            )),
             _ => {
                 unimplemented!("ioctl cmd: {:?}", cmd)
             }
@@ -102,6 +102,9 @@ impl BlockDevice for GenericBlockDevice {
             if !cache_lock.contains(&page_id) {
                 let device = &self.device;
                 let cache = alloc_frames(1);
+                if cache.is_null() {
+                    return Err(LinuxErrno::ENOMEM);
+                }
                 let mut cache = FrameTracker::new(cache as usize);
                 let start_block = page_id * PAGE_CACHE_SIZE / 512;
                 let end_block = start_block + PAGE_CACHE_SIZE / 512;
@@ -141,6 +144,9 @@ impl BlockDevice for GenericBlockDevice {
             if !cache_lock.contains(&page_id) {
                 let device = &self.device;
                 let cache = alloc_frames(1);
+                if cache.is_null() {
+                    return Err(LinuxErrno::ENOMEM);
+                }
                 let mut cache = FrameTracker::new(cache as usize);
                 let start_block = page_id * PAGE_CACHE_SIZE / 512;
                 let end_block = start_block + PAGE_CACHE_SIZE / 512;
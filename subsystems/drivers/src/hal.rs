@@ -6,9 +6,13 @@ use virtio_drivers::{BufferDirection, Hal, PhysAddr};
 pub struct HalImpl;
 
 unsafe impl Hal for HalImpl {
+    /// 内存不足时返回为 0 的物理地址，virtio 驱动会将其视为 DMA 错误，使当前的请求失败
     fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
         let start = alloc_frames(pages);
-        (start as usize, NonNull::new(start).unwrap())
+        match NonNull::new(start) {
+            Some(vaddr) => (start as usize, vaddr),
+            None => (0, NonNull::dangling()),
+        }
     }
 
     unsafe fn dma_dealloc(paddr: PhysAddr, _vaddr: NonNull<u8>, pages: usize) -> i32 {
@@ -25,6 +25,7 @@ pub(super) fn relocate_removable_data() {
         let size = end - start;
         let np = (size + FRAME_SIZE - 1) / FRAME_SIZE;
         let frame_start = crate::alloc_frames(np);
+        assert!(!frame_start.is_null(), "no memory for initrd data");
         // copy data
         unsafe {
             core::ptr::copy_nonoverlapping(start as *const u8, frame_start, size);
@@ -4,15 +4,16 @@ use core::{
     ops::{Deref, DerefMut},
 };
 
-use config::{FRAME_BITS, FRAME_SIZE};
+use config::{FRAME_BITS, FRAME_SIZE, HUGE_PAGE_SIZE};
 use ksync::Mutex;
-use log::trace;
+use log::{trace, warn};
 use page_table::{
     addr::{PhysAddr, VirtAddr},
     table::PagingIf,
 };
 use pager::{PageAllocator, PageAllocatorExt};
 use platform::println;
+use spin::Once;
 
 use crate::manager::FRAME_REF_MANAGER;
 
@@ -21,6 +22,33 @@ pub static FRAME_ALLOCATOR: Mutex<pager::Bitmap<0>> = Mutex::new(pager::Bitmap::
 #[cfg(feature = "pager_buddy")]
 pub static FRAME_ALLOCATOR: Mutex<pager::Zone<12>> = Mutex::new(pager::Zone::new());
 
+/// 物理页不足时回收页面后重新分配的最大次数
+const RECLAIM_RETRY: usize = 8;
+
+/// 物理页不足时调用的回收函数，参数为需要的页数，返回实际回收的页数
+static RECLAIMER: Once<fn(usize) -> usize> = Once::new();
+
+/// 注册物理页回收函数。注册后物理页不足时分配器会先尝试回收页面，而不是直接失败
+pub fn register_reclaimer(reclaimer: fn(usize) -> usize) {
+    RECLAIMER.call_once(|| reclaimer);
+}
+
+/// 分配 `count` 个连续的物理页，返回起始页号。物理页不足时回收页面后重试
+fn alloc_pages_or_reclaim(count: usize) -> Option<usize> {
+    for _ in 0..RECLAIM_RETRY {
+        // 回收页面时需要释放物理页，不能持有分配器的锁
+        let page = FRAME_ALLOCATOR.lock().alloc_pages(count, FRAME_SIZE);
+        if let Ok(page) = page {
+            return Some(page);
+        }
+        let reclaimed = RECLAIMER.get().map_or(0, |reclaim| reclaim(count));
+        if reclaimed == 0 {
+            return None;
+        }
+    }
+    FRAME_ALLOCATOR.lock().alloc_pages(count, FRAME_SIZE).ok()
+}
+
 pub fn init_frame_allocator(start: usize, end: usize) {
     let page_start = start / FRAME_SIZE;
     let page_end = end / FRAME_SIZE;
@@ -35,15 +63,17 @@ pub fn init_frame_allocator(start: usize, end: usize) {
         .expect("init frame allocator failed");
 }
 
+/// 分配 `num` 个连续的物理页，物理页不足时返回空指针
 #[no_mangle]
 pub fn alloc_frames(num: usize) -> *mut u8 {
     // assert_eq!(num.next_power_of_two(), num);
-    let start_page = FRAME_ALLOCATOR
-        .lock()
-        .alloc_pages(num, FRAME_SIZE)
-        .expect("alloc frame failed");
-    let start_addr = start_page << FRAME_BITS;
-    start_addr as *mut u8
+    match alloc_pages_or_reclaim(num) {
+        Some(start_page) => (start_page << FRAME_BITS) as *mut u8,
+        None => {
+            warn!("alloc {} frames failed", num);
+            core::ptr::null_mut()
+        }
+    }
 }
 
 #[no_mangle]
@@ -104,24 +134,53 @@ impl DerefMut for FrameTracker {
     }
 }
 
-pub fn alloc_frame_trackers(count: usize) -> FrameTracker {
+/// 分配 `count` 个连续的物理页，物理页不足时返回 `None`
+pub fn alloc_frame_trackers(count: usize) -> Option<FrameTracker> {
+    let frame = match alloc_pages_or_reclaim(count) {
+        Some(frame) => frame,
+        None => {
+            warn!("alloc {} frames failed", count);
+            return None;
+        }
+    };
+    trace!("alloc frame [{}] start page: {:#x}", count, frame);
+    for i in 0..count {
+        let refs = FRAME_REF_MANAGER.lock().add_ref(frame + i);
+        assert_eq!(refs, 1)
+    }
+    Some(FrameTracker::new(frame, count))
+}
+
+/// 物理页分配器能否分配大页。
+///
+/// 只有伙伴系统(`pager_buddy`)能够高效地分配对齐的连续物理页，默认的位图分配器(`pager_bitmap`)不支持大页。
+pub const HUGE_FRAME_SUPPORTED: bool = cfg!(feature = "pager_buddy");
+
+/// 分配一个大页，即 [`HUGE_PAGE_SIZE`] 大小、物理地址按其对齐的连续物理页。物理页不足时直接失败，不会回收页面
+///
+/// 分配器不支持大页([`HUGE_FRAME_SUPPORTED`] 为 `false`)时总是返回 `None`，调用者应当退回到普通的页。
+pub fn alloc_huge_frame_trackers() -> Option<FrameTracker> {
+    if !HUGE_FRAME_SUPPORTED {
+        return None;
+    }
+    let count = HUGE_PAGE_SIZE / FRAME_SIZE;
     let frame = FRAME_ALLOCATOR
         .lock()
-        .alloc_pages(count, FRAME_SIZE)
-        .expect(format!("alloc {} frame failed", count).as_str());
-    trace!("alloc frame [{}] start page: {:#x}", count, frame);
+        .alloc_pages(count, HUGE_PAGE_SIZE)
+        .ok()?;
+    trace!("alloc huge frame start page: {:#x}", frame);
     for i in 0..count {
         let refs = FRAME_REF_MANAGER.lock().add_ref(frame + i);
         assert_eq!(refs, 1)
     }
-    FrameTracker::new(frame, count)
+    Some(FrameTracker::new(frame, count))
 }
 
 pub struct VmmPageAllocator;
 
 impl PagingIf for VmmPageAllocator {
     fn alloc_frame() -> Option<PhysAddr> {
-        let frame = alloc_frame_trackers(1);
+        let frame = alloc_frame_trackers(1)?;
         let start_addr = frame.start();
         forget(frame);
         Some(PhysAddr::from(start_addr))
@@ -136,7 +195,7 @@ impl PagingIf for VmmPageAllocator {
     }
 
     fn alloc_contiguous_frames(size: usize) -> Option<PhysAddr> {
-        let frames = alloc_frame_trackers(size);
+        let frames = alloc_frame_trackers(size)?;
         let start_addr = frames.start();
         forget(frames);
         Some(PhysAddr::from(start_addr))
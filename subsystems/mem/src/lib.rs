@@ -13,7 +13,10 @@ mod heap;
 mod manager;
 mod vmm;
 
-pub use frame::{alloc_frame_trackers, alloc_frames, free_frames, FrameTracker, VmmPageAllocator};
+pub use frame::{
+    alloc_frame_trackers, alloc_frames, alloc_huge_frame_trackers, free_frames, register_reclaimer,
+    FrameTracker, VmmPageAllocator, HUGE_FRAME_SUPPORTED,
+};
 pub use manager::FRAME_REF_MANAGER;
 pub use vmm::{kernel_pgd, kernel_satp, kernel_space, map_region_to_kernel, query_kernel_space};
 
@@ -30,6 +30,16 @@ impl FrameRefManager {
             1
         }
     }
+    /// 只增加已经被分配的物理页的引用计数，不由物理页分配器管理的页(如设备内存)返回 false
+    pub fn try_add_ref(&mut self, id: usize) -> bool {
+        match self.record.get_mut(&id) {
+            Some(count) => {
+                *count += 1;
+                true
+            }
+            None => false,
+        }
+    }
     pub fn dec_ref(&mut self, id: usize) -> Option<usize> {
         if let Some(count) = self.record.get_mut(&id) {
             *count -= 1;
@@ -1,7 +1,7 @@
 #![no_std]
 extern crate alloc;
 
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, sync::Arc};
 
 use downcast_rs::{impl_downcast, DowncastSync};
 use spin::Once;
@@ -20,54 +20,22 @@ pub trait KTaskShim: Send + Sync {
     fn put_task(&self, task: Arc<dyn KTask>);
     fn suspend(&self);
     fn schedule_now(&self, task: Arc<dyn KTask>);
-    fn transfer_ptr_raw(&self, ptr: usize) -> usize;
-    fn transfer_buf_raw(&self, src: usize, size: usize) -> Vec<&mut [u8]>;
+    /// Copy `src` to the address `dst` of the current task, return false if the address is invalid.
+    fn copy_to_user(&self, dst: usize, src: &[u8]) -> bool;
+    /// Copy data at the address `src` of the current task to `dst`, return false if the address is invalid.
+    fn copy_from_user(&self, src: usize, dst: &mut [u8]) -> bool;
 }
 
 impl dyn KTaskShim {
-    fn copy_data_to_task<T: 'static + Copy>(&self, src: *const T, dst: *mut T) {
+    fn copy_data_to_task<T: 'static + Copy>(&self, src: *const T, dst: *mut T) -> bool {
         let size = core::mem::size_of::<T>();
-        let bufs = self.transfer_buf_raw(dst as usize, size);
         let src = unsafe { core::slice::from_raw_parts(src as *const u8, size) };
-        let mut start = 0;
-        for buffer in bufs {
-            let len = if start + buffer.len() > size {
-                size - start
-            } else {
-                buffer.len()
-            };
-            unsafe {
-                core::ptr::copy_nonoverlapping(src.as_ptr().add(start), buffer.as_mut_ptr(), len);
-            }
-            start += len;
-        }
+        self.copy_to_user(dst as usize, src)
     }
-    fn copy_data_from_task<T: 'static + Copy>(&self, src: *const T, dst: *mut T) {
+    fn copy_data_from_task<T: 'static + Copy>(&self, src: *const T, dst: *mut T) -> bool {
         let size = core::mem::size_of::<T>();
-        let bufs = self.transfer_buf_raw(src as usize, size);
         let dst = unsafe { core::slice::from_raw_parts_mut(dst as *mut u8, size) };
-        let mut start = 0;
-        for buffer in bufs {
-            let len = if start + buffer.len() > size {
-                size - start
-            } else {
-                buffer.len()
-            };
-            unsafe {
-                core::ptr::copy_nonoverlapping(buffer.as_ptr(), dst.as_mut_ptr().add(start), len);
-            }
-            start += len;
-        }
-    }
-    fn transfer_ptr_mut<T>(&self, ptr: *mut T) -> &'static mut T {
-        let ptr = ptr as usize;
-        let ptr = self.transfer_ptr_raw(ptr);
-        unsafe { &mut *(ptr as *mut T) }
-    }
-    fn transfer_ptr<T>(&self, ptr: *const T) -> &'static T {
-        let ptr = ptr as usize;
-        let ptr = self.transfer_ptr_raw(ptr);
-        unsafe { &*(ptr as *const T) }
+        self.copy_from_user(src as usize, dst)
     }
 }
 
@@ -117,30 +85,18 @@ pub fn schedule_now(task: Arc<dyn KTask>) {
         .schedule_now(task);
 }
 #[cfg(feature = "lib")]
-pub fn copy_data_to_task<T: 'static + Copy>(src: *const T, dst: *mut T) {
-    KTASK_SHIM
-        .get()
-        .expect("ktask_shim not initialized")
-        .copy_data_to_task(src, dst);
-}
-#[cfg(feature = "lib")]
-pub fn copy_data_from_task<T: 'static + Copy>(src: *const T, dst: *mut T) {
-    KTASK_SHIM
-        .get()
-        .expect("ktask_shim not initialized")
-        .copy_data_from_task(src, dst);
-}
-#[cfg(feature = "lib")]
-pub fn transfer_ptr_mut<T>(ptr: *mut T) -> &'static mut T {
+/// Copy data to the current task, return false if `dst` is not a valid user address.
+pub fn copy_data_to_task<T: 'static + Copy>(src: *const T, dst: *mut T) -> bool {
     KTASK_SHIM
         .get()
         .expect("ktask_shim not initialized")
-        .transfer_ptr_mut(ptr)
+        .copy_data_to_task(src, dst)
 }
 #[cfg(feature = "lib")]
-pub fn transfer_ptr<T>(ptr: *const T) -> &'static T {
+/// Copy data from the current task, return false if `src` is not a valid user address.
+pub fn copy_data_from_task<T: 'static + Copy>(src: *const T, dst: *mut T) -> bool {
     KTASK_SHIM
         .get()
         .expect("ktask_shim not initialized")
-        .transfer_ptr(ptr)
+        .copy_data_from_task(src, dst)
 }
@@ -15,12 +15,17 @@ use vfscore::{
     utils::{VfsFileStat, VfsNodeType, VfsPollEvents},
 };
 
-use crate::system_root_fs;
+use crate::{
+    page_cache::{close_page_cache, open_page_cache, PageCache},
+    system_root_fs,
+};
 
 pub struct KernelFile {
     pos: Mutex<u64>,
     open_flag: Mutex<OpenFlags>,
     dentry: Arc<dyn VfsDentry>,
+    /// 普通文件的页缓存，与映射该文件的进程共享
+    page_cache: Option<Arc<Mutex<PageCache>>>,
 }
 
 impl Debug for KernelFile {
@@ -40,10 +45,12 @@ impl KernelFile {
         } else {
             0
         };
+        let page_cache = dentry.inode().ok().and_then(open_page_cache);
         Self {
             pos: Mutex::new(pos),
             open_flag: Mutex::new(open_flag),
             dentry,
+            page_cache,
         }
     }
 }
@@ -87,6 +94,10 @@ pub trait File: DowncastSync + Debug {
     fn poll(&self, _event: PollEvents) -> AlienResult<PollEvents> {
         Err(LinuxErrno::ENOSYS)
     }
+    /// 文件的页缓存，只有普通文件才有页缓存
+    fn page_cache(&self) -> Option<Arc<Mutex<PageCache>>> {
+        None
+    }
 }
 
 impl_downcast!(sync  File);
@@ -120,6 +131,9 @@ impl File for KernelFile {
             return Err(LinuxErrno::EPERM);
         }
         drop(open_flag);
+        if let Some(cache) = &self.page_cache {
+            return cache.lock().read_at(offset, buf);
+        }
         let inode = self.dentry.inode()?;
         let read = inode.read_at(offset, buf)?;
         Ok(read)
@@ -133,6 +147,9 @@ impl File for KernelFile {
         if !open_flag.contains(OpenFlags::O_WRONLY) && !open_flag.contains(OpenFlags::O_RDWR) {
             return Err(LinuxErrno::EPERM);
         }
+        if let Some(cache) = &self.page_cache {
+            return cache.lock().write_at(offset, buf);
+        }
         let inode = self.dentry.inode()?;
         let write = inode.write_at(offset, buf)?;
         Ok(write)
@@ -239,9 +256,11 @@ impl File for KernelFile {
             return Err(LinuxErrno::EINVAL);
         }
         let dt = self.dentry();
-        VfsPath::new(system_root_fs(), dt)
-            .truncate(len)
-            .map_err(Into::into)
+        VfsPath::new(system_root_fs(), dt).truncate(len)?;
+        if let Some(cache) = &self.page_cache {
+            cache.lock().truncate(len);
+        }
+        Ok(())
     }
     fn is_readable(&self) -> bool {
         let open_flag = self.open_flag.lock();
@@ -264,6 +283,10 @@ impl File for KernelFile {
             .map(|e| PollEvents::from_bits_truncate(e.bits()));
         res.map_err(Into::into)
     }
+
+    fn page_cache(&self) -> Option<Arc<Mutex<PageCache>>> {
+        self.page_cache.clone()
+    }
 }
 
 fn vfsnodetype2dirent64(ty: VfsNodeType) -> DirentType {
@@ -272,6 +295,9 @@ fn vfsnodetype2dirent64(ty: VfsNodeType) -> DirentType {
 
 impl Drop for KernelFile {
     fn drop(&mut self) {
+        if let Some(cache) = &self.page_cache {
+            close_page_cache(cache);
+        }
         let _ = self.flush();
         let _ = self.fsync();
     }
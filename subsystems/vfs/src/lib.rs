@@ -25,6 +25,7 @@ pub mod dev;
 mod extffi;
 mod initrd;
 pub mod kfile;
+pub mod page_cache;
 pub mod pipefs;
 pub mod proc;
 pub mod ram;
@@ -0,0 +1,429 @@
+//! inode 级别的页缓存。
+//!
+//! 普通文件的读写(`KernelFile::read_at/write_at`)与 MAP_SHARED 文件映射的缺页处理共用同一份缓存页，
+//! 因此多个进程映射同一个文件时可以看到彼此的修改，`write` 写入的数据也能立即反映到映射中。
+//!
+//! 通过 `write` 写入的数据会同时写回文件(写穿)，而通过可写映射对缓存页的修改只能在
+//! `msync`、`munmap` 或进程退出时写回(写回)。一个 inode 的缓存在最后一个打开它的文件关闭时写回并释放。
+//!
+//! 物理内存不足时，没有被映射且没有未写回修改的缓存页可以按照最近访问的先后顺序被回收，见 [`reclaim_clean_pages`]。
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use config::FRAME_SIZE;
+use constants::{AlienResult, LinuxErrno};
+use ksync::Mutex;
+use mem::{alloc_frame_trackers, FrameTracker};
+use spin::Lazy;
+use vfscore::{inode::VfsInode, utils::VfsNodeType};
+
+/// 所有 inode 的页缓存，以 inode 的地址为键
+static PAGE_CACHES: Lazy<Mutex<BTreeMap<usize, Arc<Mutex<PageCache>>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 访问时钟，每次访问缓存页时递增，用于确定缓存页最近被访问的先后顺序
+static ACCESS_CLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// 一个缓存页
+struct CachePage {
+    frame: FrameTracker,
+    /// 页中有效数据的长度，超出部分位于文件末尾之后
+    valid: usize,
+    /// 页中有尚未写回文件的修改
+    dirty: bool,
+    /// 映射了该页的次数，被映射的页不能被释放
+    maps: usize,
+    /// 以可写方式映射了该页的次数，只要存在可写映射，该页就可能被修改
+    writable_maps: usize,
+    /// 最近一次访问该页时的访问时钟
+    last_access: usize,
+}
+
+impl CachePage {
+    /// 该页可以被直接丢弃
+    fn reclaimable(&self) -> bool {
+        self.maps == 0 && !self.dirty
+    }
+}
+
+/// 一个 inode 的页缓存
+pub struct PageCache {
+    inode: Arc<dyn VfsInode>,
+    /// 页号 -> 缓存页
+    pages: BTreeMap<usize, CachePage>,
+    /// 打开该 inode 的文件数
+    users: usize,
+}
+
+impl PageCache {
+    /// 获取页号为 `index` 的缓存页，不在缓存中时从文件中读入
+    fn page(&mut self, index: usize) -> AlienResult<&mut CachePage> {
+        if !self.pages.contains_key(&index) {
+            let mut frame = alloc_frame_trackers(1).ok_or(LinuxErrno::ENOMEM)?;
+            frame.fill(0);
+            let valid = self
+                .inode
+                .read_at((index * FRAME_SIZE) as u64, &mut frame[..])?;
+            self.pages.insert(
+                index,
+                CachePage {
+                    frame,
+                    valid,
+                    dirty: false,
+                    maps: 0,
+                    writable_maps: 0,
+                    last_access: 0,
+                },
+            );
+        }
+        let page = self.pages.get_mut(&index).unwrap();
+        page.last_access = ACCESS_CLOCK.fetch_add(1, Ordering::Relaxed);
+        Ok(page)
+    }
+
+    /// 从缓存中读取文件 `offset` 处的数据，返回读取的字节数
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> AlienResult<usize> {
+        let mut offset = offset as usize;
+        let mut count = 0;
+        while count < buf.len() {
+            let page_offset = offset % FRAME_SIZE;
+            let page = self.page(offset / FRAME_SIZE)?;
+            if page.valid <= page_offset {
+                break;
+            }
+            let len = min(page.valid - page_offset, buf.len() - count);
+            buf[count..count + len].copy_from_slice(&page.frame[page_offset..page_offset + len]);
+            count += len;
+            offset += len;
+            if page.valid < FRAME_SIZE {
+                // 到达文件末尾
+                break;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 向文件 `offset` 处写入数据，并同步更新已缓存的页，返回写入的字节数
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> AlienResult<usize> {
+        let write = self.inode.write_at(offset, buf)?;
+        let start = offset as usize;
+        let end = start + write;
+        let mut offset = start;
+        while offset < end {
+            let index = offset / FRAME_SIZE;
+            let page_offset = offset % FRAME_SIZE;
+            let len = min(FRAME_SIZE - page_offset, end - offset);
+            // 只更新已经缓存的页，未缓存的页在下次访问时会从文件中读入
+            if let Some(page) = self.pages.get_mut(&index) {
+                let src = offset - start;
+                page.frame[page_offset..page_offset + len].copy_from_slice(&buf[src..src + len]);
+                page.valid = page.valid.max(page_offset + len);
+            }
+            offset += len;
+        }
+        // 写入位置之前的页现在完全位于文件之内，其中原先位于文件末尾之后的部分为空洞
+        for (_, page) in self.pages.range_mut(..start / FRAME_SIZE) {
+            page.valid = FRAME_SIZE;
+        }
+        Ok(write)
+    }
+
+    /// 文件大小被修改为 `len` 字节后，更新缓存页的有效长度，并丢弃超出文件末尾的缓存
+    pub fn truncate(&mut self, len: u64) {
+        let len = len as usize;
+        self.pages.retain(|index, page| {
+            let start = index * FRAME_SIZE;
+            // 仍被映射的页不能释放，只将其内容清空
+            if start >= len && page.maps == 0 {
+                return false;
+            }
+            let valid = min(FRAME_SIZE, len.saturating_sub(start));
+            if valid < page.valid {
+                page.frame[valid..].fill(0);
+            }
+            page.valid = valid;
+            true
+        });
+    }
+
+    /// 将页号为 `index` 的页映射到进程地址空间中，返回该页的物理地址
+    ///
+    /// 缓存页的生命周期由页缓存管理，映射它的页表不能释放该页。
+    pub fn map_page(&mut self, index: usize, writable: bool) -> AlienResult<usize> {
+        let page = self.page(index)?;
+        page.maps += 1;
+        if writable {
+            page.writable_maps += 1;
+        }
+        Ok(page.frame.start())
+    }
+
+    /// 页号为 `index` 的页是否在缓存中
+    pub fn contains_page(&self, index: usize) -> bool {
+        self.pages.contains_key(&index)
+    }
+
+    /// 预先将页号 `[index, index + count)` 范围内的页读入缓存，读到文件末尾时停止
+    pub fn readahead(&mut self, index: usize, count: usize) -> AlienResult<()> {
+        for index in index..index + count {
+            if self.page(index)?.valid < FRAME_SIZE {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 解除一次对页号为 `index` 的页的映射，可写映射期间该页可能被修改，因此将其标记为脏页
+    pub fn unmap_page(&mut self, index: usize, writable: bool) {
+        if let Some(page) = self.pages.get_mut(&index) {
+            page.maps = page.maps.saturating_sub(1);
+            if writable {
+                page.writable_maps = page.writable_maps.saturating_sub(1);
+                page.dirty = true;
+            }
+        }
+    }
+
+    /// 将文件 `[offset, offset + len)` 范围内被修改过的缓存页写回文件
+    ///
+    /// 仍被可写映射的页随时可能被修改，因此总是会被写回。
+    pub fn sync_range(&mut self, offset: u64, len: usize) -> AlienResult<()> {
+        let start = offset as usize / FRAME_SIZE;
+        let end = (offset as usize + len + FRAME_SIZE - 1) / FRAME_SIZE;
+        for (index, page) in self.pages.range_mut(start..end) {
+            if !page.dirty && page.writable_maps == 0 {
+                continue;
+            }
+            if page.valid > 0 {
+                self.inode
+                    .write_at((index * FRAME_SIZE) as u64, &page.frame[..page.valid])?;
+            }
+            page.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// 将所有被修改过的缓存页写回文件
+    pub fn sync_all(&mut self) -> AlienResult<()> {
+        self.sync_range(0, usize::MAX - FRAME_SIZE)
+    }
+}
+
+/// 打开一个 inode 时调用，返回其页缓存，缓存不存在时创建。不是普通文件时返回 `None`。
+pub fn open_page_cache(inode: Arc<dyn VfsInode>) -> Option<Arc<Mutex<PageCache>>> {
+    if inode.inode_type() != VfsNodeType::File {
+        return None;
+    }
+    let key = Arc::as_ptr(&inode) as *const () as usize;
+    let mut caches = PAGE_CACHES.lock();
+    let cache = caches.entry(key).or_insert_with(|| {
+        Arc::new(Mutex::new(PageCache {
+            inode,
+            pages: BTreeMap::new(),
+            users: 0,
+        }))
+    });
+    cache.lock().users += 1;
+    Some(cache.clone())
+}
+
+/// 关闭一个 inode 时调用。最后一个使用者关闭时，将修改写回文件并释放缓存。
+pub fn close_page_cache(cache: &Arc<Mutex<PageCache>>) {
+    let mut caches = PAGE_CACHES.lock();
+    let mut inner = cache.lock();
+    inner.users -= 1;
+    if inner.users == 0 {
+        let _ = inner.sync_all();
+        let key = Arc::as_ptr(&inner.inode) as *const () as usize;
+        caches.remove(&key);
+    }
+}
+
+/// 回收至多 `count` 个最久未被访问的干净缓存页，返回回收的页数
+///
+/// 该函数在分配物理页失败时被调用，调用者可能持有某个页缓存的锁，因此这里不会等待任何锁，被占用的缓存会被跳过。
+pub fn reclaim_clean_pages(count: usize) -> usize {
+    let caches = match PAGE_CACHES.try_lock() {
+        Some(caches) => caches,
+        None => return 0,
+    };
+    let mut candidates = Vec::new();
+    for (key, cache) in caches.iter() {
+        if let Some(cache) = cache.try_lock() {
+            candidates.extend(
+                cache
+                    .pages
+                    .iter()
+                    .filter(|(_, page)| page.reclaimable())
+                    .map(|(index, page)| (page.last_access, *key, *index)),
+            );
+        }
+    }
+    candidates.sort_unstable();
+    let mut reclaimed = 0;
+    for (_, key, index) in candidates.into_iter().take(count) {
+        if let Some(mut cache) = caches[&key].try_lock() {
+            if cache
+                .pages
+                .get(&index)
+                .map_or(false, |page| page.reclaimable())
+            {
+                cache.pages.remove(&index);
+                reclaimed += 1;
+            }
+        }
+    }
+    reclaimed
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        alloc::{alloc_zeroed, Layout},
+        collections::BTreeMap,
+        sync::Arc,
+        vec,
+        vec::Vec,
+    };
+    use core::{
+        cmp::min,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use config::FRAME_SIZE;
+    use ksync::Mutex;
+    use mem::{FrameTracker, FRAME_REF_MANAGER};
+    use vfscore::{
+        error::VfsError,
+        file::VfsFile,
+        inode::{InodeAttr, VfsInode},
+        superblock::VfsSuperBlock,
+        utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+        VfsResult,
+    };
+
+    use crate::page_cache::{CachePage, PageCache};
+
+    /// 保存在内存中的文件，记录写入文件的次数
+    struct MemFile {
+        data: Mutex<Vec<u8>>,
+        writes: AtomicUsize,
+    }
+
+    impl VfsFile for MemFile {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+            let data = self.data.lock();
+            let offset = min(offset as usize, data.len());
+            let len = min(buf.len(), data.len() - offset);
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+            Ok(len)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+            let mut data = self.data.lock();
+            let end = offset as usize + buf.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[offset as usize..end].copy_from_slice(buf);
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            Ok(buf.len())
+        }
+    }
+
+    impl VfsInode for MemFile {
+        fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+            Err(VfsError::NoSys)
+        }
+        fn node_perm(&self) -> VfsNodePerm {
+            VfsNodePerm::empty()
+        }
+        fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+            Ok(())
+        }
+
+        fn get_attr(&self) -> VfsResult<VfsFileStat> {
+            Ok(VfsFileStat::default())
+        }
+
+        fn inode_type(&self) -> VfsNodeType {
+            VfsNodeType::File
+        }
+    }
+
+    /// 将文件的第 `index` 页读入缓存
+    ///
+    /// 测试中没有初始化物理页分配器，缓存页使用按页对齐的堆内存，并多持有一个引用使其不会被释放回分配器。
+    fn cache_page(cache: &mut PageCache, index: usize) {
+        let layout = Layout::from_size_align(FRAME_SIZE, FRAME_SIZE).unwrap();
+        let addr = unsafe { alloc_zeroed(layout) } as usize;
+        let mut manager = FRAME_REF_MANAGER.lock();
+        manager.add_ref(addr / FRAME_SIZE);
+        manager.add_ref(addr / FRAME_SIZE);
+        drop(manager);
+        let mut frame = FrameTracker::from_addr(addr, 1);
+        let valid = cache
+            .inode
+            .read_at((index * FRAME_SIZE) as u64, &mut frame[..])
+            .unwrap();
+        let page = CachePage {
+            frame,
+            valid,
+            dirty: false,
+            maps: 0,
+            writable_maps: 0,
+            last_access: 0,
+        };
+        cache.pages.insert(index, page);
+    }
+
+    #[test]
+    pub fn test_page_cache_write_back() {
+        let file = Arc::new(MemFile {
+            data: Mutex::new(vec![b'a'; FRAME_SIZE + 100]),
+            writes: AtomicUsize::new(0),
+        });
+        let mut cache = PageCache {
+            inode: file.clone(),
+            pages: BTreeMap::new(),
+            users: 1,
+        };
+        cache_page(&mut cache, 0);
+        cache_page(&mut cache, 1);
+        assert_eq!(cache.pages[&1].valid, 100);
+
+        // 可写映射期间的修改只有在同步时才会写回文件
+        cache.map_page(0, true).unwrap();
+        cache.pages.get_mut(&0).unwrap().frame[0] = b'b';
+        assert_eq!(file.data.lock()[0], b'a');
+        cache.sync_range(0, FRAME_SIZE).unwrap();
+        assert_eq!(file.data.lock()[0], b'b');
+        assert_eq!(file.writes.load(Ordering::SeqCst), 1);
+
+        // 解除可写映射后该页为脏页，写回一次后不再写回
+        cache.pages.get_mut(&0).unwrap().frame[1] = b'b';
+        cache.unmap_page(0, true);
+        assert!(cache.pages[&0].dirty);
+        cache.sync_all().unwrap();
+        assert_eq!(file.data.lock()[1], b'b');
+        assert_eq!(file.writes.load(Ordering::SeqCst), 2);
+        cache.sync_all().unwrap();
+        assert_eq!(file.writes.load(Ordering::SeqCst), 2);
+
+        // write 写穿到文件并更新已缓存的页，缓存页不会因此变脏
+        let offset = FRAME_SIZE + 50;
+        assert_eq!(cache.write_at(offset as u64, b"cc").unwrap(), 2);
+        assert_eq!(&file.data.lock()[offset..offset + 2], b"cc");
+        assert_eq!(file.writes.load(Ordering::SeqCst), 3);
+        assert!(!cache.pages[&1].dirty);
+        cache.sync_all().unwrap();
+        assert_eq!(file.writes.load(Ordering::SeqCst), 3);
+
+        let mut buf = [0; 100];
+        assert_eq!(cache.read_at(offset as u64, &mut buf).unwrap(), 50);
+        assert_eq!(&buf[..3], b"cca");
+    }
+}
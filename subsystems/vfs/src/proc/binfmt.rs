@@ -0,0 +1,78 @@
+use alloc::{string::String, sync::Arc};
+use core::cmp::min;
+
+use spin::Once;
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+/// 内核提供的 binfmt_misc 操作，由内核在初始化时注册
+pub struct BinfmtMiscOps {
+    /// 读取 `status` 或某条规则对应的文件，文件不存在时返回 `None`
+    pub read: fn(&str) -> Option<String>,
+    /// 写入 `register`、`status` 或某条规则对应的文件
+    pub write: fn(&str, &[u8]) -> VfsResult<()>,
+}
+
+static BINFMT_MISC_OPS: Once<BinfmtMiscOps> = Once::new();
+
+/// 注册 `/proc/sys/fs/binfmt_misc` 目录下文件的读写操作
+pub fn register_binfmt_misc_ops(ops: BinfmtMiscOps) {
+    BINFMT_MISC_OPS.call_once(|| ops);
+}
+
+/// `/proc/sys/fs/binfmt_misc` 目录下的文件，读写操作都交给内核处理
+pub struct BinfmtMiscFile {
+    name: String,
+}
+
+impl BinfmtMiscFile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+        }
+    }
+}
+
+impl VfsFile for BinfmtMiscFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let ops = BINFMT_MISC_OPS.get().ok_or(VfsError::NoSys)?;
+        let content = (ops.read)(&self.name).ok_or(VfsError::NoEntry)?;
+        let content = content.as_bytes();
+        let offset = min(offset as usize, content.len());
+        let len = min(buf.len(), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let ops = BINFMT_MISC_OPS.get().ok_or(VfsError::NoSys)?;
+        (ops.write)(&self.name, buf)?;
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for BinfmtMiscFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat::default())
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
@@ -1,21 +1,38 @@
+mod binfmt;
 mod filesystem;
 mod interrupt;
 mod mem;
 mod mounts;
+mod process;
+mod sysctl;
 
-use alloc::sync::Arc;
+use alloc::{string::ToString, sync::Arc};
 use core::ops::Index;
 
+use binfmt::BinfmtMiscFile;
+pub use binfmt::{register_binfmt_misc_ops, BinfmtMiscOps};
 use dynfs::DynFsDirInode;
 use filesystem::SystemSupportFS;
 use interrupt::InterruptRecord;
 use mem::MemInfo;
 use mounts::MountInfo;
-use vfscore::{dentry::VfsDentry, error::VfsError, fstype::VfsFsType, path::VfsPath};
+use process::ProcessFile;
+pub use process::{register_process_file_provider, ProcessFileKind};
+use spin::Once;
+use sysctl::SysctlFile;
+pub use sysctl::SysctlOps;
+use vfscore::{
+    dentry::VfsDentry, error::VfsError, fstype::VfsFsType, inode::VfsInode, path::VfsPath,
+};
 
 use crate::{CommonFsProviderImpl, FS};
 pub type ProcFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()>>;
 
+/// procfs 的根目录，用于在进程创建和退出时添加和删除 `/proc/<pid>` 目录
+static PROC_ROOT: Once<Arc<ProcFsDirInodeImpl>> = Once::new();
+/// `/proc/sys/fs/binfmt_misc` 目录，用于在注册和删除规则时添加和删除对应的文件
+static BINFMT_MISC_DIR: Once<Arc<ProcFsDirInodeImpl>> = Once::new();
+
 ///
 /// ```bash
 /// |
@@ -23,6 +40,18 @@ pub type ProcFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()
 /// |-- interrupts
 /// |-- mounts
 /// |-- filesystems
+/// |-- sys
+///     |-- kernel
+///         |-- randomize_va_space
+///     |-- fs
+///         |-- binfmt_misc
+///             |-- register
+///             |-- status
+///             |-- <rule>
+/// |-- <pid>
+///     |-- maps
+///     |-- status
+///     |-- statm
 /// ```
 // todo!(use ramfs instead of dynfs)
 pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
@@ -49,6 +78,29 @@ pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     root_inode
         .add_dir_manually("self", "r-xr-xr-x".into())
         .unwrap();
+    PROC_ROOT.call_once(|| root_inode.clone());
+
+    let binfmt_misc = ["sys", "fs", "binfmt_misc"]
+        .iter()
+        .fold(root_inode.clone(), |dir, name| {
+            dir.add_dir_manually(name, "r-xr-xr-x".into()).unwrap();
+            sub_dir(&dir, name).unwrap()
+        });
+    binfmt_misc
+        .add_file_manually(
+            "register",
+            Arc::new(BinfmtMiscFile::new("register")),
+            "-w-------".into(),
+        )
+        .unwrap();
+    binfmt_misc
+        .add_file_manually(
+            "status",
+            Arc::new(BinfmtMiscFile::new("status")),
+            "rw-r--r--".into(),
+        )
+        .unwrap();
+    BINFMT_MISC_DIR.call_once(|| binfmt_misc);
 
     let path = VfsPath::new(root_dt.clone(), root_dt.clone());
     let ramfs = FS.lock().index("ramfs").clone();
@@ -64,3 +116,76 @@ pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
 
     root_dt
 }
+
+/// 进程创建时调用，创建 `/proc/<pid>` 目录
+pub fn add_process(pid: usize) {
+    let root = match PROC_ROOT.get() {
+        Some(root) => root,
+        None => return,
+    };
+    let name = pid.to_string();
+    // pid 被复用时目录可能已经存在
+    let _ = root.remove_manually(&name);
+    let dir = root
+        .add_dir_manually(&name, "r-xr-xr-x".into())
+        .and_then(|_| sub_dir(root, &name));
+    if let Ok(dir) = dir {
+        for kind in ProcessFileKind::ALL {
+            let file = Arc::new(ProcessFile::new(pid, kind));
+            let _ = dir.add_file_manually(kind.name(), file, "r--r--r--".into());
+        }
+    }
+}
+
+/// 进程退出时调用，删除 `/proc/<pid>` 目录
+pub fn remove_process(pid: usize) {
+    if let Some(root) = PROC_ROOT.get() {
+        let name = pid.to_string();
+        if let Ok(dir) = sub_dir(root, &name) {
+            for kind in ProcessFileKind::ALL {
+                let _ = dir.remove_manually(kind.name());
+            }
+        }
+        let _ = root.remove_manually(&name);
+    }
+}
+
+/// 查找目录 `dir` 下名为 `name` 的子目录
+fn sub_dir(dir: &Arc<ProcFsDirInodeImpl>, name: &str) -> Result<Arc<ProcFsDirInodeImpl>, VfsError> {
+    dir.lookup(name).and_then(|dir| {
+        dir.downcast_arc::<ProcFsDirInodeImpl>()
+            .map_err(|_| VfsError::Invalid)
+    })
+}
+
+/// 创建 `/proc/sys/<dir>/<name>` 文件，读写操作由 `ops` 处理，`dir` 不存在时一并创建
+pub fn register_sysctl(dir: &str, name: &str, ops: SysctlOps) {
+    let sys = match PROC_ROOT.get().map(|root| sub_dir(root, "sys")) {
+        Some(Ok(sys)) => sys,
+        _ => return,
+    };
+    if sub_dir(&sys, dir).is_err() {
+        let _ = sys.add_dir_manually(dir, "r-xr-xr-x".into());
+    }
+    if let Ok(dir) = sub_dir(&sys, dir) {
+        let _ = dir.add_file_manually(name, Arc::new(SysctlFile::new(ops)), "rw-r--r--".into());
+    }
+}
+
+/// 注册 binfmt_misc 规则时调用，创建 `/proc/sys/fs/binfmt_misc/<name>` 文件
+pub fn add_binfmt_misc_entry(name: &str) {
+    if let Some(dir) = BINFMT_MISC_DIR.get() {
+        let _ = dir.add_file_manually(
+            name,
+            Arc::new(BinfmtMiscFile::new(name)),
+            "rw-r--r--".into(),
+        );
+    }
+}
+
+/// 删除 binfmt_misc 规则时调用，删除 `/proc/sys/fs/binfmt_misc/<name>` 文件
+pub fn remove_binfmt_misc_entry(name: &str) {
+    if let Some(dir) = BINFMT_MISC_DIR.get() {
+        let _ = dir.remove_manually(name);
+    }
+}
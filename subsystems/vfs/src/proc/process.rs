@@ -0,0 +1,100 @@
+use alloc::{string::String, sync::Arc};
+use core::cmp::min;
+
+use spin::Once;
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+/// `/proc/<pid>` 目录下的文件
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProcessFileKind {
+    /// 进程的内存映射
+    Maps = 0,
+    /// 进程的状态与内存使用情况
+    Status = 1,
+    /// 以页为单位的内存使用情况
+    Statm = 2,
+}
+
+impl ProcessFileKind {
+    pub const ALL: [ProcessFileKind; 3] = [
+        ProcessFileKind::Maps,
+        ProcessFileKind::Status,
+        ProcessFileKind::Statm,
+    ];
+
+    /// 文件名
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessFileKind::Maps => "maps",
+            ProcessFileKind::Status => "status",
+            ProcessFileKind::Statm => "statm",
+        }
+    }
+}
+
+/// 生成 `/proc/<pid>` 目录下各个文件内容的函数，由内核在初始化时注册
+static PROVIDERS: [Once<fn(usize) -> Option<String>>; 3] = [Once::new(), Once::new(), Once::new()];
+
+/// 注册生成 `/proc/<pid>` 目录下 `kind` 文件内容的函数，进程不存在时该函数返回 `None`
+pub fn register_process_file_provider(
+    kind: ProcessFileKind,
+    provider: fn(usize) -> Option<String>,
+) {
+    PROVIDERS[kind as usize].call_once(|| provider);
+}
+
+/// `/proc/<pid>` 目录下的文件，每次读取时根据进程当前的状态生成内容
+pub struct ProcessFile {
+    pid: usize,
+    kind: ProcessFileKind,
+}
+
+impl ProcessFile {
+    pub fn new(pid: usize, kind: ProcessFileKind) -> Self {
+        Self { pid, kind }
+    }
+
+    fn content(&self) -> VfsResult<String> {
+        let provider = PROVIDERS[self.kind as usize].get().ok_or(VfsError::NoSys)?;
+        provider(self.pid).ok_or(VfsError::NoEntry)
+    }
+}
+
+impl VfsFile for ProcessFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content()?;
+        let content = content.as_bytes();
+        let offset = min(offset as usize, content.len());
+        let len = min(buf.len(), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        Ok(len)
+    }
+}
+
+impl VfsInode for ProcessFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        // 与 Linux 相同，文件大小为 0，内容只能通过读取得到
+        Ok(VfsFileStat::default())
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
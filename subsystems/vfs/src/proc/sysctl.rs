@@ -0,0 +1,66 @@
+use alloc::{string::String, sync::Arc};
+use core::cmp::min;
+
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+/// 内核提供的 `/proc/sys` 参数的读写操作
+pub struct SysctlOps {
+    /// 读取参数的当前值
+    pub read: fn() -> String,
+    /// 写入参数的新值
+    pub write: fn(&[u8]) -> VfsResult<()>,
+}
+
+/// `/proc/sys` 目录下的参数文件，读写操作都交给内核处理
+pub struct SysctlFile {
+    ops: SysctlOps,
+}
+
+impl SysctlFile {
+    pub fn new(ops: SysctlOps) -> Self {
+        Self { ops }
+    }
+}
+
+impl VfsFile for SysctlFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.ops.read)();
+        let content = content.as_bytes();
+        let offset = min(offset as usize, content.len());
+        let len = min(buf.len(), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        (self.ops.write)(buf)?;
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for SysctlFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat::default())
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}